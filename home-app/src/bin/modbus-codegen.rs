@@ -0,0 +1,83 @@
+//! 根据寄存器表生成固件的 modbus_params.h/.c 和上位机的 rust 类型
+//!
+//! ```text
+//! modbus-codegen                                  # 内置的 modbus-rtu-example, 输出到当前目录
+//! modbus-codegen my_device.toml --out main/
+//! modbus-codegen my_device.toml --stdout h        # 只打印头文件
+//! ```
+//!
+//! 地址重叠或未对齐的字段会导致生成失败, 不会写入任何文件
+
+use std::{fs, path::PathBuf, process};
+
+use home_app::{
+    data::{codegen::generate, register_map::RegisterMap},
+    resource::error::{AppError, Result},
+};
+
+const USAGE: &str = "用法: modbus-codegen [寄存器表] [选项]
+  寄存器表            文件 (toml 或 json) 或表名, 默认 modbus-rtu-example
+  --out <目录>        输出目录, 默认当前目录
+  --stdout <h|c|rs>   只打印一个文件, 不写入";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let mut map_arg = None;
+    let mut out = PathBuf::from(".");
+    let mut stdout = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| AppError::Error(format!("{} 缺少参数\n{}", arg, USAGE)))
+        };
+        match arg.as_str() {
+            "--out" => out = PathBuf::from(value()?),
+            "--stdout" => stdout = Some(value()?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ if !arg.starts_with('-') && map_arg.is_none() => map_arg = Some(arg.clone()),
+            _ => return Err(AppError::Error(format!("未知参数 {}\n{}", arg, USAGE))),
+        }
+    }
+
+    let map = load_map(map_arg.as_deref().unwrap_or("modbus-rtu-example"))?;
+    let generated = generate(&map)?;
+    if let Some(extension) = stdout {
+        let (_, text) = generated
+            .files()
+            .into_iter()
+            .find(|(name, _)| name.rsplit('.').next() == Some(extension.as_str()))
+            .ok_or_else(|| AppError::Error(format!("未知的文件类型 {}\n{}", extension, USAGE)))?;
+        print!("{}", text);
+        return Ok(());
+    }
+    fs::create_dir_all(&out)?;
+    for (name, text) in generated.files() {
+        let path = out.join(name);
+        fs::write(&path, text)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// 寄存器表可以是文件路径, 或内置和用户目录中的表名
+fn load_map(name: &str) -> Result<RegisterMap> {
+    if std::path::Path::new(name).is_file() {
+        return RegisterMap::load(name);
+    }
+    RegisterMap::library()
+        .into_iter()
+        .find(|m| m.name == name)
+        .ok_or_else(|| AppError::RegisterMap(format!("找不到寄存器表: {}", name)))
+}
@@ -0,0 +1,162 @@
+//! Modbus 从站模拟器, 不需要开发板就可以调试 home-app 的 Modbus 功能
+//!
+//! 默认在虚拟串口上模拟 modbus-rtu-example 固件:
+//!
+//! ```text
+//! modbus-sim                              # pty, 地址 1
+//! modbus-sim --tcp 5020 --slave 2
+//! modbus-sim --serial /dev/ttyUSB1 --baud 9600
+//! modbus-sim --ascii                      # pty, ascii 帧格式
+//! modbus-sim --map my_device.toml --config sim.toml
+//! ```
+//!
+//! 配置文件示例, 输入寄存器 input_data0 按正弦变化, input_data4 回显主站写入的 holding_data4:
+//!
+//! ```toml
+//! slave = 1
+//! listen = { Tcp = { port = 5020 } }
+//!
+//! [[scripts]]
+//! field = "input_data0"
+//! kind = "sine"
+//! amplitude = 1.0
+//! offset = 5.0
+//! period = 10.0
+//!
+//! [[scripts]]
+//! field = "input_data4"
+//! kind = "echo"
+//! source = "holding_data4"
+//! ```
+
+use std::{process, thread, time::Duration};
+
+use home_app::{
+    data::register_map::RegisterMap,
+    resource::error::{AppError, Result},
+    service::modbus::{
+        rtu::{SerialConfig, SerialMode},
+        simulator::{Simulator, SimulatorConfig},
+        slave::SlaveListen,
+    },
+};
+
+const USAGE: &str = "用法: modbus-sim [选项]
+  --config <文件>     模拟器配置 (toml), 包括监听方式和脚本
+  --map <文件|名称>   寄存器表, 默认 modbus-rtu-example
+  --slave <地址>      从站地址, 默认 1
+  --pty               在虚拟串口上应答 (默认)
+  --serial <串口>     在已有的串口上应答
+  --baud <波特率>     --serial 的波特率, 默认 115200
+  --ascii             串口和虚拟串口使用 ascii 帧格式, 不支持 tcp
+  --tcp <端口>        Modbus TCP";
+
+fn main() {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "INFO");
+    }
+    tracing_subscriber::fmt::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let mut config = SimulatorConfig::default();
+    let mut map_arg = None;
+    let mut baud = None;
+    let mut ascii = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| AppError::Error(format!("{} 缺少参数\n{}", arg, USAGE)))
+        };
+        match arg.as_str() {
+            "--config" => {
+                let path = value()?;
+                config = SimulatorConfig::from_toml(&std::fs::read_to_string(path)?)?;
+            }
+            "--map" => map_arg = Some(value()?),
+            "--slave" => config.slave = parse(&value()?)?,
+            "--pty" => config.listen = SlaveListen::Pty,
+            "--serial" => {
+                config.listen = SlaveListen::Serial(SerialConfig {
+                    port: value()?,
+                    ..Default::default()
+                })
+            }
+            "--baud" => baud = Some(parse(&value()?)?),
+            "--ascii" => ascii = true,
+            "--tcp" => {
+                config.listen = SlaveListen::Tcp {
+                    port: parse(&value()?)?,
+                }
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(AppError::Error(format!("未知参数 {}\n{}", arg, USAGE))),
+        }
+    }
+    if let Some(baud) = baud {
+        match &mut config.listen {
+            SlaveListen::Serial(serial) => serial.baud_rate = baud,
+            _ => {
+                return Err(AppError::Error(format!(
+                    "--baud 只能与 --serial 一起使用\n{}",
+                    USAGE
+                )))
+            }
+        }
+    }
+    if ascii {
+        match &mut config.listen {
+            SlaveListen::Pty | SlaveListen::PtyAscii => config.listen = SlaveListen::PtyAscii,
+            SlaveListen::Serial(serial) => serial.mode = SerialMode::Ascii,
+            SlaveListen::Tcp { .. } => {
+                return Err(AppError::Error(format!(
+                    "--ascii 不能与 --tcp 一起使用\n{}",
+                    USAGE
+                )))
+            }
+        }
+    }
+    if let Some(map) = map_arg {
+        config.map = map;
+    }
+
+    let map = load_map(&config.map)?;
+    let simulator = Simulator::start(&config, map)?;
+    println!(
+        "{} 从站 #{} 已启动: {}",
+        simulator.map().name,
+        config.slave,
+        simulator.label()
+    );
+    while simulator.is_running() {
+        thread::sleep(Duration::from_millis(500));
+    }
+    Err(AppError::Error("从站已停止".to_string()))
+}
+
+/// 寄存器表可以是文件路径, 或内置和用户目录中的表名
+fn load_map(name: &str) -> Result<RegisterMap> {
+    if std::path::Path::new(name).is_file() {
+        return RegisterMap::load(name);
+    }
+    RegisterMap::library()
+        .into_iter()
+        .find(|m| m.name == name)
+        .ok_or_else(|| AppError::RegisterMap(format!("找不到寄存器表: {}", name)))
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T> {
+    text.parse()
+        .map_err(|_| AppError::Error(format!("无效的数值: {}", text)))
+}
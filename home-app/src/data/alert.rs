@@ -0,0 +1,498 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::device::Device;
+
+/// 最多保存的通知数量
+const MAX_HISTORY: usize = 1000;
+/// 弹出通知的显示时间
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// 告警级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 3] = [Severity::Info, Severity::Warning, Severity::Critical];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Info => "信息",
+            Severity::Warning => "警告",
+            Severity::Critical => "严重",
+        }
+    }
+}
+
+/// 告警条件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AlertCondition {
+    /// 高于阈值
+    Above(f64),
+    /// 低于阈值
+    Below(f64),
+    /// 超出范围 [min, max]
+    OutsideRange(f64, f64),
+    /// 超过 N 分钟没有更新
+    NoUpdate(u64),
+    /// 变化率超过阈值, 单位: 每分钟
+    RateOfChange(f64),
+}
+
+impl AlertCondition {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AlertCondition::Above(_) => "高于",
+            AlertCondition::Below(_) => "低于",
+            AlertCondition::OutsideRange(_, _) => "超出范围",
+            AlertCondition::NoUpdate(_) => "无更新",
+            AlertCondition::RateOfChange(_) => "变化率",
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            AlertCondition::Above(v) => format!("> {}", v),
+            AlertCondition::Below(v) => format!("< {}", v),
+            AlertCondition::OutsideRange(min, max) => format!("∉ [{}, {}]", min, max),
+            AlertCondition::NoUpdate(minutes) => format!("{} 分钟无更新", minutes),
+            AlertCondition::RateOfChange(rate) => format!("|Δ| > {}/分钟", rate),
+        }
+    }
+}
+
+/// 一条告警规则, 作用在设备的一个字段上
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: u64,
+    pub device_id: String,
+    pub field: String,
+    pub condition: AlertCondition,
+    pub severity: Severity,
+    /// 回差, 值需要回到阈值以内 hysteresis 的距离, 告警才解除
+    pub hysteresis: f64,
+    /// 冷却时间, 单位: 秒, 在冷却时间内不会重复触发
+    pub cooldown: u64,
+    pub enabled: bool,
+}
+
+impl AlertRule {
+    /// 根据当前值判断: Some(true) 触发, Some(false) 解除, None 保持不变
+    fn check_value(&self, value: f64, rate: Option<f64>, active: bool) -> Option<bool> {
+        let h = self.hysteresis.abs();
+        let (trigger, clear) = match self.condition {
+            AlertCondition::Above(t) => (value > t, value < t - h),
+            AlertCondition::Below(t) => (value < t, value > t + h),
+            AlertCondition::OutsideRange(min, max) => (
+                value < min || value > max,
+                value >= min + h && value <= max - h,
+            ),
+            AlertCondition::RateOfChange(r) => {
+                let rate = rate?.abs();
+                (rate > r, rate < r - h)
+            }
+            // 在 on_device_update 中处理
+            AlertCondition::NoUpdate(_) => (false, true),
+        };
+        if !active && trigger {
+            Some(true)
+        } else if active && clear {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// 一条通知
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: u64,
+    pub rule_id: u64,
+    pub device_id: String,
+    pub field: String,
+    pub severity: Severity,
+    pub message: String,
+    pub time: DateTime<Local>,
+    pub acknowledged: bool,
+}
+
+/// 告警规则的运行状态
+#[derive(Debug, Default)]
+struct RuleState {
+    active: bool,
+    last_fired: Option<Instant>,
+    last_update: Option<Instant>,
+    last_value: Option<(f64, Instant)>,
+}
+
+/// 告警规则 和 通知历史
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AlertManager {
+    rules: Vec<AlertRule>,
+    history: Vec<Notification>,
+    next_id: u64,
+    #[serde(skip)]
+    states: HashMap<u64, RuleState>,
+    /// 正在弹出显示的通知
+    #[serde(skip)]
+    toasts: Vec<(u64, Instant)>,
+}
+
+impl AlertManager {
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    pub fn rules_mut(&mut self) -> &mut Vec<AlertRule> {
+        &mut self.rules
+    }
+
+    pub fn add_rule(&mut self, mut rule: AlertRule) {
+        rule.id = self.next_id();
+        self.rules.push(rule);
+    }
+
+    pub fn remove_rule(&mut self, id: u64) {
+        self.rules.retain(|r| r.id != id);
+        self.states.remove(&id);
+    }
+
+    /// 规则当前是否处于告警状态
+    pub fn is_active(&self, rule_id: u64) -> bool {
+        self.states.get(&rule_id).map_or(false, |s| s.active)
+    }
+
+    pub fn history(&self) -> &[Notification] {
+        &self.history
+    }
+
+    /// 未确认的通知数量
+    pub fn unacknowledged(&self) -> usize {
+        self.history.iter().filter(|n| !n.acknowledged).count()
+    }
+
+    pub fn acknowledge(&mut self, id: u64) {
+        if let Some(n) = self.history.iter_mut().find(|n| n.id == id) {
+            n.acknowledged = true;
+        }
+        self.toasts.retain(|(toast, _)| *toast != id);
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        for n in self.history.iter_mut() {
+            n.acknowledged = true;
+        }
+        self.toasts.clear();
+    }
+
+    /// 清除通知
+    pub fn clear(&mut self, id: u64) {
+        self.history.retain(|n| n.id != id);
+        self.toasts.retain(|(toast, _)| *toast != id);
+    }
+
+    /// 清除所有已确认的通知
+    pub fn clear_acknowledged(&mut self) {
+        self.history.retain(|n| !n.acknowledged);
+    }
+
+    /// 需要弹出显示的通知
+    pub fn toasts(&mut self) -> Vec<Notification> {
+        let now = Instant::now();
+        self.toasts.retain(|(_, t)| now - *t < TOAST_DURATION);
+        self.toasts
+            .iter()
+            .filter_map(|(id, _)| self.history.iter().find(|n| n.id == *id))
+            .cloned()
+            .collect()
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// 设备上报状态后调用, 只检查这条消息中上报的字段
+    pub fn on_device_update(&mut self, device: &Device, fields: &Map<String, Value>) {
+        self.update_at(device, fields, Instant::now());
+    }
+
+    fn update_at(&mut self, device: &Device, fields: &Map<String, Value>, now: Instant) {
+        let rules: Vec<AlertRule> = self
+            .rules
+            .iter()
+            .filter(|r| r.enabled && r.device_id == device.id && fields.contains_key(&r.field))
+            .cloned()
+            .collect();
+
+        for rule in rules {
+            let state = self.states.entry(rule.id).or_default();
+            state.last_update = Some(now);
+            // 字段有更新即解除, 不需要是数值
+            if let AlertCondition::NoUpdate(_) = rule.condition {
+                state.active = false;
+                continue;
+            }
+            let value = match fields.get(&rule.field).and_then(value_as_f64) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let rate = state.last_value.and_then(|(last, t)| {
+                let minutes = (now - t).as_secs_f64() / 60.0;
+                (minutes > 0.0).then(|| (value - last) / minutes)
+            });
+            state.last_value = Some((value, now));
+
+            match rule.check_value(value, rate, state.active) {
+                Some(true) => {
+                    let message = format!(
+                        "{}.{} = {} ({})",
+                        device.name,
+                        rule.field,
+                        value,
+                        rule.condition.describe()
+                    );
+                    self.fire(&rule, message, now);
+                }
+                Some(false) => {
+                    state.active = false;
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// 定时调用, 检查 "无更新" 类的规则
+    pub fn check_timeouts(&mut self) {
+        self.check_timeouts_at(Instant::now());
+    }
+
+    fn check_timeouts_at(&mut self, now: Instant) {
+        let rules: Vec<AlertRule> = self
+            .rules
+            .iter()
+            .filter(|r| r.enabled && matches!(r.condition, AlertCondition::NoUpdate(_)))
+            .cloned()
+            .collect();
+
+        for rule in rules {
+            let minutes = match rule.condition {
+                AlertCondition::NoUpdate(minutes) => minutes,
+                _ => continue,
+            };
+            // 规则刚添加, 或应用刚启动时, 从现在开始计时
+            let state = self.states.entry(rule.id).or_default();
+            let last_update = *state.last_update.get_or_insert(now);
+            if !state.active && now - last_update > Duration::from_secs(minutes.saturating_mul(60))
+            {
+                let message = format!(
+                    "{}.{} {}",
+                    rule.device_id,
+                    rule.field,
+                    rule.condition.describe()
+                );
+                self.fire(&rule, message, now);
+            }
+        }
+    }
+
+    fn fire(&mut self, rule: &AlertRule, message: String, now: Instant) {
+        let state = self.states.entry(rule.id).or_default();
+        state.active = true;
+        if let Some(last_fired) = state.last_fired {
+            if now - last_fired < Duration::from_secs(rule.cooldown) {
+                return;
+            }
+        }
+        state.last_fired = Some(now);
+
+        tracing::warn!("告警[{}]: {}", rule.severity.label(), message);
+        let id = self.next_id();
+        self.history.push(Notification {
+            id,
+            rule_id: rule.id,
+            device_id: rule.device_id.clone(),
+            field: rule.field.clone(),
+            severity: rule.severity,
+            message,
+            time: Local::now(),
+            acknowledged: false,
+        });
+        if self.history.len() > MAX_HISTORY {
+            let n = self.history.len() - MAX_HISTORY;
+            self.history.drain(..n);
+        }
+        self.toasts.push((id, now));
+    }
+}
+
+/// 数值或布尔值都可以参与比较
+fn value_as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn manager(condition: AlertCondition, hysteresis: f64, cooldown: u64) -> AlertManager {
+        let mut manager = AlertManager::default();
+        manager.add_rule(AlertRule {
+            id: 0,
+            device_id: "sensor".to_string(),
+            field: "temp".to_string(),
+            condition,
+            severity: Severity::Warning,
+            hysteresis,
+            cooldown,
+            enabled: true,
+        });
+        manager
+    }
+
+    fn update(manager: &mut AlertManager, value: Value, now: Instant) {
+        let fields = json!({ "temp": value }).as_object().unwrap().clone();
+        manager.update_at(&Device::new("sensor"), &fields, now);
+    }
+
+    fn rule_id(manager: &AlertManager) -> u64 {
+        manager.rules()[0].id
+    }
+
+    #[test]
+    fn threshold_hysteresis() {
+        let mut m = manager(AlertCondition::Above(30.0), 2.0, 0);
+        let id = rule_id(&m);
+        let t = Instant::now();
+        update(&mut m, json!(29.0), t);
+        assert!(!m.is_active(id));
+        update(&mut m, json!(31.0), t);
+        assert!(m.is_active(id));
+        assert_eq!(m.history().len(), 1);
+        // 回到阈值以下但还在回差以内, 保持告警, 不重复通知
+        update(&mut m, json!(29.0), t);
+        update(&mut m, json!(31.0), t);
+        assert!(m.is_active(id));
+        assert_eq!(m.history().len(), 1);
+        update(&mut m, json!(27.5), t);
+        assert!(!m.is_active(id));
+        update(&mut m, json!(30.5), t);
+        assert_eq!(m.history().len(), 2);
+        assert_eq!(m.unacknowledged(), 2);
+
+        let mut m = manager(AlertCondition::OutsideRange(10.0, 20.0), 1.0, 0);
+        let id = rule_id(&m);
+        update(&mut m, json!(9.0), t);
+        assert!(m.is_active(id));
+        update(&mut m, json!(10.5), t);
+        assert!(m.is_active(id));
+        update(&mut m, json!(11.0), t);
+        assert!(!m.is_active(id));
+        // 布尔值和字符串也可以比较, 其它类型忽略
+        update(&mut m, json!("21"), t);
+        assert!(m.is_active(id));
+        update(&mut m, json!(null), t);
+        assert!(m.is_active(id));
+    }
+
+    #[test]
+    fn cooldown() {
+        let mut m = manager(AlertCondition::Below(0.5), 0.0, 60);
+        let t = Instant::now();
+        update(&mut m, json!(false), t);
+        update(&mut m, json!(true), t + Duration::from_secs(10));
+        update(&mut m, json!(false), t + Duration::from_secs(20));
+        assert_eq!(m.history().len(), 1);
+        update(&mut m, json!(true), t + Duration::from_secs(70));
+        update(&mut m, json!(false), t + Duration::from_secs(80));
+        assert_eq!(m.history().len(), 2);
+    }
+
+    #[test]
+    fn rate_of_change() {
+        let mut m = manager(AlertCondition::RateOfChange(5.0), 1.0, 0);
+        let id = rule_id(&m);
+        let t = Instant::now();
+        // 第一个值没有变化率
+        update(&mut m, json!(100.0), t);
+        assert!(!m.is_active(id));
+        update(&mut m, json!(102.0), t + Duration::from_secs(60));
+        assert!(!m.is_active(id));
+        // 30 秒下降 3, 即每分钟 6
+        update(&mut m, json!(99.0), t + Duration::from_secs(90));
+        assert!(m.is_active(id));
+        update(&mut m, json!(103.5), t + Duration::from_secs(150));
+        assert!(m.is_active(id));
+        update(&mut m, json!(105.0), t + Duration::from_secs(210));
+        assert!(!m.is_active(id));
+        // 同一时刻的两个值不计算变化率
+        update(&mut m, json!(200.0), t + Duration::from_secs(210));
+        assert!(!m.is_active(id));
+    }
+
+    #[test]
+    fn no_update() {
+        let mut m = manager(AlertCondition::NoUpdate(2), 0.0, 0);
+        let id = rule_id(&m);
+        let t = Instant::now();
+        // 从第一次检查开始计时
+        m.check_timeouts_at(t);
+        m.check_timeouts_at(t + Duration::from_secs(119));
+        assert!(!m.is_active(id));
+        m.check_timeouts_at(t + Duration::from_secs(121));
+        assert!(m.is_active(id));
+        m.check_timeouts_at(t + Duration::from_secs(200));
+        assert_eq!(m.history().len(), 1);
+        // 字段有更新即解除, 不需要是数值
+        update(&mut m, json!({ "x": 1 }), t + Duration::from_secs(210));
+        assert!(!m.is_active(id));
+        m.check_timeouts_at(t + Duration::from_secs(300));
+        assert!(!m.is_active(id));
+        m.check_timeouts_at(t + Duration::from_secs(331));
+        assert_eq!(m.history().len(), 2);
+
+        // 很大的分钟数不会溢出
+        let mut m = manager(AlertCondition::NoUpdate(u64::MAX), 0.0, 0);
+        m.check_timeouts_at(t);
+        m.check_timeouts_at(t + Duration::from_secs(3600));
+        assert!(m.history().is_empty());
+    }
+
+    #[test]
+    fn other_devices_and_disabled_rules() {
+        let mut m = manager(AlertCondition::Above(0.0), 0.0, 0);
+        let t = Instant::now();
+        let fields = json!({ "temp": 1 }).as_object().unwrap().clone();
+        m.update_at(&Device::new("other"), &fields, t);
+        assert!(m.history().is_empty());
+        m.rules_mut()[0].enabled = false;
+        update(&mut m, json!(1), t);
+        assert!(m.history().is_empty());
+        m.rules_mut()[0].enabled = true;
+        update(&mut m, json!(1), t);
+        assert_eq!(m.history().len(), 1);
+        let id = m.history()[0].id;
+        m.acknowledge(id);
+        assert_eq!(m.unacknowledged(), 0);
+        m.clear_acknowledged();
+        assert!(m.history().is_empty());
+    }
+}
@@ -1,5 +1,684 @@
-use crate::service::mqtt_server::MqttServer;
+use std::{sync::Arc, time::Duration};
 
-struct AppData {
-    mqtt_server: MqttServer,
-}
\ No newline at end of file
+use parking_lot::Mutex;
+
+use crate::{
+    data::{
+        alert::AlertManager,
+        audit::{AuditAction, AuditLog},
+        command_queue::{Command, CommandQueue, CommandTarget},
+        device::{device_topic, device_topic_filter, DeviceCommand, DeviceRegistry},
+        elf_symbols::FirmwareSymbols,
+        history::{HistoryConfig, HistoryStore},
+        modbus_device::ModbusDevice,
+        recipe::Recipe,
+        register_map::RegisterMap,
+        shadow::ShadowStore,
+        storage::persistence::{app_dir, Persistence},
+    },
+    resource::{
+        defines::topics::{AVAILABILITY, SET, STATE},
+        error::{AppError, Result},
+    },
+    service::{
+        modbus::{
+            mqtt_gateway::{GatewayConfig, MqttGateway},
+            pdu::Request,
+            recipe::{RecipeJob, RecipeTarget, RecipeTask, TargetState, TargetStatus},
+            recorder::HistoryRecorder,
+            scanner::ScanConfig,
+            scheduler::{ModbusScheduler, Priority},
+            simulator::{Simulator, SimulatorConfig},
+            sniffer::SnifferConfig,
+            tcp_gateway::{TcpGateway, TcpGatewayConfig},
+        },
+        mqtt_client::{MqttClient, MqttConfig},
+        mqtt_server::MqttServer,
+        serial_monitor::{SerialMonitor, SerialMonitorConfig},
+        spawn_ticker, RepaintSignal,
+    },
+};
+
+/// 从离线队列开始的配方下载
+struct QueuedRecipe {
+    device: String,
+    recipe: String,
+    job: RecipeJob,
+}
+
+/// 应用的共享数据, 各个页面通过 Arc<RwLock<AppData>> 访问
+pub struct AppData {
+    pub devices: DeviceRegistry,
+    pub alerts: AlertManager,
+    pub shadows: ShadowStore,
+    pub command_queue: CommandQueue,
+    pub audit: AuditLog,
+    pub modbus_devices: Vec<ModbusDevice>,
+    /// 所有页面和服务共用的 Modbus 总线
+    pub modbus: ModbusScheduler,
+    /// 内置和用户目录中的寄存器表
+    pub register_maps: Vec<RegisterMap>,
+    /// 寄存器表的参数配方
+    pub recipes: Vec<Recipe>,
+    /// 从站上线后开始下载的排队配方
+    queued_recipes: Vec<QueuedRecipe>,
+    pub simulator_config: SimulatorConfig,
+    /// 应用内运行的从站模拟器
+    pub simulator: Option<Simulator>,
+    pub scan_config: ScanConfig,
+    pub sniffer_config: SnifferConfig,
+    pub gateway_config: GatewayConfig,
+    /// Modbus 到 mqtt 的网关
+    pub gateway: Option<MqttGateway>,
+    pub tcp_gateway_config: TcpGatewayConfig,
+    /// Modbus TCP 到串口总线的网关
+    pub tcp_gateway: Option<TcpGateway>,
+    /// 轮询值的历史记录, 记录服务和趋势页面共用
+    pub history: Arc<Mutex<HistoryStore>>,
+    pub history_config: HistoryConfig,
+    pub history_recorder: Option<HistoryRecorder>,
+    pub serial_monitor_config: SerialMonitorConfig,
+    /// ESP32 的串口日志, 切换页面时保持打开
+    pub serial_monitor: Option<SerialMonitor>,
+    /// 串口监视器解析回溯用的固件符号
+    pub firmware_symbols: Option<FirmwareSymbols>,
+    pub mqtt_client: MqttClient,
+    pub mqtt_server: MqttServer,
+    persistence: Persistence,
+    /// 有未保存的修改, 到了自动保存的时间才转换为 json
+    changed: bool,
+    repaint: RepaintSignal,
+}
+
+impl AppData {
+    /// 从配置文件加载
+    pub fn load() -> Self {
+        let persistence = Persistence::default();
+        let devices = persistence.get_value("devices").unwrap_or_default();
+        let alerts = persistence.get_value("alerts").unwrap_or_default();
+        let shadows = persistence.get_value("shadows").unwrap_or_default();
+        let command_queue = persistence.get_value("command_queue").unwrap_or_default();
+        let mqtt_config: MqttConfig = persistence.get_value("mqtt_client").unwrap_or_default();
+        let mqtt_server = persistence.get_value("mqtt_server").unwrap_or_default();
+        let operator = persistence
+            .get_value("operator")
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        let audit = AuditLog::open(app_dir().join("audit.jsonl"), operator);
+        let modbus_devices: Vec<ModbusDevice> = persistence
+            .get_value("modbus_devices")
+            .unwrap_or_else(|| vec![ModbusDevice::default()]);
+        let modbus =
+            ModbusScheduler::new(persistence.get_value("modbus_buses").unwrap_or_default());
+        let recipes = persistence.get_value("recipes").unwrap_or_default();
+        let simulator_config = persistence.get_value("simulator").unwrap_or_default();
+        let scan_config = persistence.get_value("bus_scan").unwrap_or_default();
+        let sniffer_config = persistence.get_value("sniffer").unwrap_or_default();
+        let gateway_config = persistence.get_value("mqtt_gateway").unwrap_or_default();
+        let tcp_gateway_config = persistence.get_value("tcp_gateway").unwrap_or_default();
+        let history_config: HistoryConfig = persistence.get_value("history").unwrap_or_default();
+        let serial_monitor_config: SerialMonitorConfig =
+            persistence.get_value("serial_monitor").unwrap_or_default();
+        let firmware_symbols = match serial_monitor_config.elf.as_str() {
+            "" => None,
+            elf => FirmwareSymbols::load(elf)
+                .map_err(|e| tracing::warn!("固件符号未加载: {}", e))
+                .ok(),
+        };
+        let history = HistoryStore::open(app_dir().join("history"));
+        if let Err(e) = history.purge(history_config.retention_days) {
+            tracing::warn!("删除过期的历史文件失败: {}", e);
+        }
+        Self {
+            devices,
+            alerts,
+            shadows,
+            command_queue,
+            audit,
+            modbus_devices,
+            modbus,
+            register_maps: RegisterMap::library(),
+            recipes,
+            queued_recipes: vec![],
+            simulator_config,
+            simulator: None,
+            scan_config,
+            sniffer_config,
+            gateway_config,
+            gateway: None,
+            tcp_gateway_config,
+            tcp_gateway: None,
+            history: Arc::new(Mutex::new(history)),
+            history_config,
+            history_recorder: None,
+            serial_monitor_config,
+            serial_monitor: None,
+            firmware_symbols,
+            mqtt_client: MqttClient::new(mqtt_config),
+            mqtt_server,
+            persistence,
+            changed: false,
+            repaint: Arc::new(|| {}),
+        }
+    }
+
+    /// 启动后台服务
+    pub fn start_services(&mut self, repaint: RepaintSignal) {
+        self.repaint = repaint.clone();
+        if let Err(e) = self.mqtt_server.start() {
+            tracing::warn!("mqtt 服务未启动: {}", e);
+        }
+        let subscriptions = vec![
+            device_topic_filter(STATE),
+            device_topic_filter(AVAILABILITY),
+        ];
+        if let Err(e) = self.mqtt_client.start(subscriptions, repaint.clone()) {
+            tracing::error!("mqtt 客户端启动失败: {}", e);
+        }
+        if self.gateway_config.auto_start {
+            if let Err(e) = self.start_gateway() {
+                tracing::error!("modbus 网关启动失败: {}", e);
+            }
+        }
+        if self.tcp_gateway_config.auto_start {
+            if let Err(e) = self.start_tcp_gateway() {
+                tracing::error!("modbus tcp 网关启动失败: {}", e);
+            }
+        }
+        if self.history_config.auto_start {
+            if let Err(e) = self.start_history_recorder() {
+                tracing::error!("modbus 历史记录启动失败: {}", e);
+            }
+        }
+        spawn_ticker(repaint, Duration::from_secs(1));
+    }
+
+    /// 页面启动的后台任务用来请求重绘
+    pub fn repaint_signal(&self) -> RepaintSignal {
+        self.repaint.clone()
+    }
+
+    /// 每帧调用一次, 处理后台服务收到的数据
+    pub fn update(&mut self) {
+        let last_notification = self.alerts.history().last().map(|n| n.id);
+        while let Some(message) = self.mqtt_client.try_recv() {
+            self.changed = true;
+            let update = self
+                .devices
+                .handle_message(&message.topic, &message.payload);
+            let (id, fields) = match update {
+                Some(update) => update,
+                None => continue,
+            };
+            if let Some(device) = self.devices.get(&id) {
+                self.alerts.on_device_update(device, &fields);
+                self.shadows.on_reported(&device.id, &device.reported);
+            }
+        }
+        self.alerts.check_timeouts();
+        if self.alerts.history().last().map(|n| n.id) != last_notification {
+            self.changed = true;
+        }
+        self.flush_command_queue();
+        self.publish_shadow_delta();
+        if self.changed && self.persistence.autosave_due() {
+            self.persist();
+            self.changed = false;
+        }
+        self.persistence.maybe_autosave();
+    }
+
+    /// 页面修改了需要保存的数据, 在下次自动保存时写入文件
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+    }
+
+    /// 向设备发送命令
+    /// 设备离线时, 命令进入离线队列, 设备上线后按顺序发送
+    /// 设备在线时, 命令写入设备影子的 desired, 在设备上报一致的状态之前会定时重发
+    /// ttl 为排队命令的有效期, None 时使用队列的默认有效期
+    pub fn send_command(
+        &mut self,
+        id: &str,
+        command: &DeviceCommand,
+        ttl: Option<u64>,
+    ) -> Result<()> {
+        // 记录命令涉及字段的当前值
+        let old_value = self.devices.get(id).map(|d| {
+            let old: serde_json::Map<String, serde_json::Value> = command
+                .to_fields()
+                .keys()
+                .filter_map(|k| d.reported.get(k).map(|v| (k.clone(), v.clone())))
+                .collect();
+            serde_json::Value::Object(old).to_string()
+        });
+        let offline = self.devices.get(id).map_or(false, |d| !d.online);
+        let action = if offline {
+            AuditAction::CommandQueued
+        } else {
+            AuditAction::Publish
+        };
+        self.audit
+            .record(action, id, old_value, Some(command.to_payload()));
+        self.changed = true;
+
+        if offline {
+            self.command_queue.enqueue(
+                CommandTarget::Mqtt(id.to_string()),
+                Command::Mqtt(command.clone()),
+                ttl,
+            );
+            // 队列需要立即保存, 避免应用退出时丢失
+            self.save();
+            return Ok(());
+        }
+        self.shadows.set_desired(id, command.to_fields());
+        self.publish_shadow_delta();
+        if self.mqtt_client.is_connected() {
+            Ok(())
+        } else {
+            Err(AppError::MqttClientNotConnected)
+        }
+    }
+
+    /// 删除过期的命令, 并按入队顺序原样发送已上线设备的排队命令
+    /// mqtt 设备上线由 availability 判断, Modbus 从站在轮询收到入队之后的响应时视为上线
+    fn flush_command_queue(&mut self) {
+        self.finish_queued_recipes();
+        if self.command_queue.is_empty() {
+            return;
+        }
+        let mut changed = !self.command_queue.purge_expired().is_empty();
+        for target in self.command_queue.targets() {
+            let ready = match &target {
+                CommandTarget::Mqtt(id) => self.devices.get(id).map_or(false, |d| d.online),
+                // 配方下载结束之前不发送后面的命令
+                CommandTarget::Modbus { device } => {
+                    !self.queued_recipes.iter().any(|r| r.device == *device)
+                        && self
+                            .modbus_device(device)
+                            .and_then(|d| self.modbus_online(d))
+                            .unwrap_or(false)
+                }
+            };
+            if !ready {
+                continue;
+            }
+            while let Some(queued) = self.command_queue.pop(&target) {
+                changed = true;
+                tracing::info!(
+                    "{} 已上线, 发送排队命令: {}",
+                    target.label(),
+                    queued.command.label()
+                );
+                let result =
+                    match (&target, queued.command) {
+                        (CommandTarget::Mqtt(id), Command::Mqtt(command)) => self
+                            .mqtt_client
+                            .publish(&device_topic(id, SET), command.to_payload(), false),
+                        (
+                            CommandTarget::Modbus { device },
+                            Command::Write {
+                                request,
+                                description,
+                            },
+                        ) => self.submit_modbus_write(device, request, description),
+                        (
+                            CommandTarget::Modbus { device },
+                            Command::Recipe {
+                                recipe,
+                                only_changed,
+                            },
+                        ) => {
+                            let result = self.start_queued_recipe(device, recipe, only_changed);
+                            if result.is_ok() {
+                                break;
+                            }
+                            result
+                        }
+                        (target, command) => Err(AppError::Error(format!(
+                            "{} 不支持命令: {}",
+                            target.label(),
+                            command.label()
+                        ))),
+                    };
+                if let Err(e) = result {
+                    tracing::warn!("{} 的排队命令发送失败: {}", target.label(), e);
+                }
+            }
+        }
+        if changed {
+            self.save();
+        }
+    }
+
+    /// 按名称查找 Modbus 设备
+    pub fn modbus_device(&self, name: &str) -> Option<&ModbusDevice> {
+        self.modbus_devices.iter().find(|d| d.name == name)
+    }
+
+    /// 从站最后一次请求是否收到了响应, 总线未打开或还没有请求时返回 None
+    pub fn modbus_online(&self, device: &ModbusDevice) -> Option<bool> {
+        let key = device.transport.bus_key();
+        let (_, bus) = self.modbus.buses().into_iter().find(|(k, _)| *k == key)?;
+        let stats = bus.stats();
+        stats.slaves.get(&device.slave)?.online()
+    }
+
+    /// 从站离线时, 写请求和配方进入离线队列, 从站上线后按顺序发送
+    pub fn queue_modbus_command(
+        &mut self,
+        device: &str,
+        command: Command,
+        old_value: Option<String>,
+        ttl: Option<u64>,
+    ) {
+        self.audit.record(
+            AuditAction::CommandQueued,
+            device,
+            old_value,
+            Some(command.label()),
+        );
+        self.command_queue.enqueue(
+            CommandTarget::Modbus {
+                device: device.to_string(),
+            },
+            command,
+            ttl,
+        );
+        self.save();
+    }
+
+    fn submit_modbus_write(
+        &mut self,
+        device: &str,
+        request: Request,
+        description: String,
+    ) -> Result<()> {
+        let device = self
+            .modbus_device(device)
+            .ok_or_else(|| AppError::Error(format!("找不到 Modbus 设备: {}", device)))?;
+        let label = device.name.clone();
+        self.modbus.bus(&device.transport)?.submit(
+            device.slave,
+            request,
+            Priority::Write,
+            move |result| match result {
+                Ok(_) => tracing::info!("{} 排队的写入已完成: {}", label, description),
+                Err(e) => tracing::warn!("{} 排队的写入失败: {}: {}", label, description, e),
+            },
+        );
+        Ok(())
+    }
+
+    fn start_queued_recipe(
+        &mut self,
+        device: &str,
+        recipe: Recipe,
+        only_changed: bool,
+    ) -> Result<()> {
+        let device = self
+            .modbus_device(device)
+            .ok_or_else(|| AppError::Error(format!("找不到 Modbus 设备: {}", device)))?;
+        let target = RecipeTarget {
+            device: device.name.clone(),
+            slave: device.slave,
+            map: self.device_map(device)?,
+            bus: self.modbus.bus(&device.transport)?,
+        };
+        let name = recipe.name.clone();
+        let task = RecipeTask::Download {
+            recipe,
+            only_changed,
+        };
+        let job = RecipeJob::start(task, vec![target], self.repaint.clone())?;
+        self.queued_recipes.push(QueuedRecipe {
+            device: device.name.clone(),
+            recipe: name,
+            job,
+        });
+        Ok(())
+    }
+
+    /// 记录下载结束的排队配方
+    fn finish_queued_recipes(&mut self) {
+        let (finished, running): (Vec<_>, Vec<_>) = std::mem::take(&mut self.queued_recipes)
+            .into_iter()
+            .partition(|r| r.job.state().finished);
+        self.queued_recipes = running;
+        for queued in finished {
+            let targets = queued.job.state().targets.clone();
+            self.audit_recipe(&queued.recipe, &targets);
+        }
+    }
+
+    /// 记录配方下载每个从站修改的字段和结果
+    pub fn audit_recipe(&mut self, recipe: &str, targets: &[TargetState]) {
+        for target in targets {
+            let changes = target.changes.iter().filter(|c| c.changed());
+            let old: Vec<String> = changes
+                .clone()
+                .map(|c| format!("{}={}", c.field, c.old_text))
+                .collect();
+            let mut new: Vec<String> = changes
+                .map(|c| format!("{}={}", c.field, c.new_text))
+                .collect();
+            if target.status != TargetStatus::Done {
+                new.push(target.status.label());
+            }
+            self.audit.record(
+                AuditAction::RegisterWrite,
+                format!("{} 配方 {}", target.device, recipe),
+                Some(old.join(", ")),
+                Some(new.join(", ")),
+            );
+        }
+        self.changed = true;
+    }
+
+    /// 发送所有到期的影子 delta
+    fn publish_shadow_delta(&mut self) {
+        for (id, delta) in self.shadows.due(&self.devices) {
+            let payload = serde_json::Value::Object(delta).to_string();
+            if let Err(e) = self
+                .mqtt_client
+                .publish(&device_topic(&id, SET), payload, false)
+            {
+                tracing::warn!("向 {} 发送影子 delta 失败: {}", id, e);
+            }
+        }
+    }
+
+    /// 向分组的所有成员发送命令, 返回发送失败的设备
+    pub fn send_group_command(
+        &mut self,
+        group: &str,
+        command: &DeviceCommand,
+        ttl: Option<u64>,
+    ) -> Vec<String> {
+        let members: Vec<String> = match self.devices.group(group) {
+            Some(group) => group.members.iter().cloned().collect(),
+            None => return vec![],
+        };
+        let mut failed = vec![];
+        for id in members {
+            if let Err(e) = self.send_command(&id, command, ttl) {
+                tracing::error!("向 {} 发送命令失败: {}", id, e);
+                failed.push(id);
+            }
+        }
+        failed
+    }
+
+    /// 记录配置修改
+    pub fn audit_config(
+        &mut self,
+        target: impl Into<String>,
+        old_value: Option<String>,
+        new_value: Option<String>,
+    ) {
+        self.audit
+            .record(AuditAction::ConfigChange, target, old_value, new_value);
+        self.changed = true;
+    }
+
+    /// 取消排队的命令
+    pub fn cancel_queued(&mut self, id: u64) {
+        if let Some(queued) = self.command_queue.cancel(id) {
+            self.audit.record(
+                AuditAction::CommandCancelled,
+                queued.target.label(),
+                Some(queued.command.label()),
+                None,
+            );
+            self.save();
+        }
+    }
+
+    /// 取消设备的所有排队命令
+    pub fn cancel_all_queued(&mut self, target: &CommandTarget) {
+        let count = self.command_queue.cancel_all(target);
+        if count > 0 {
+            self.audit.record(
+                AuditAction::CommandCancelled,
+                target.label(),
+                Some(format!("{} 条命令", count)),
+                None,
+            );
+            self.save();
+        }
+    }
+
+    /// 按名称查找寄存器表
+    pub fn register_map(&self, name: &str) -> Option<&RegisterMap> {
+        self.register_maps.iter().find(|m| m.name == name)
+    }
+
+    /// 设备使用的寄存器表, 已应用设备的字节顺序
+    pub fn device_map(&self, device: &ModbusDevice) -> Result<RegisterMap> {
+        self.register_map(&device.map)
+            .map(|map| device.resolve_map(map))
+            .ok_or_else(|| {
+                AppError::RegisterMap(format!("{} 找不到寄存器表: {}", device.name, device.map))
+            })
+    }
+
+    /// 按当前配置启动模拟器, 已启动时先停止
+    pub fn start_simulator(&mut self) -> Result<()> {
+        self.simulator = None;
+        let map = self
+            .register_map(&self.simulator_config.map)
+            .cloned()
+            .ok_or_else(|| {
+                AppError::RegisterMap(format!("找不到寄存器表: {}", self.simulator_config.map))
+            })?;
+        self.simulator = Some(Simulator::start(&self.simulator_config, map)?);
+        Ok(())
+    }
+
+    /// 按当前配置启动 Modbus 网关, 已启动时先停止
+    pub fn start_gateway(&mut self) -> Result<()> {
+        self.gateway = None;
+        let selected = &self.gateway_config.devices;
+        let mut devices = vec![];
+        for device in self.modbus_devices.iter() {
+            if !selected.is_empty() && !selected.contains(&device.name) {
+                continue;
+            }
+            devices.push((device.clone(), self.device_map(device)?));
+        }
+        self.gateway = Some(MqttGateway::start(
+            &self.gateway_config,
+            self.mqtt_client.config(),
+            &self.modbus,
+            devices,
+            self.repaint.clone(),
+        )?);
+        Ok(())
+    }
+
+    /// 按当前配置启动 Modbus TCP 网关, 已启动时先停止
+    pub fn start_tcp_gateway(&mut self) -> Result<()> {
+        self.tcp_gateway = None;
+        self.tcp_gateway = Some(TcpGateway::start(
+            &self.tcp_gateway_config,
+            &self.modbus,
+            self.repaint.clone(),
+        )?);
+        Ok(())
+    }
+
+    /// 按当前配置启动历史记录, 已启动时先停止
+    pub fn start_history_recorder(&mut self) -> Result<()> {
+        self.history_recorder = None;
+        let mut devices = vec![];
+        for device in self.modbus_devices.iter() {
+            match self.history_config.fields.get(&device.name) {
+                Some(fields) if !fields.is_empty() => {
+                    devices.push((device.clone(), self.device_map(device)?, fields.clone()))
+                }
+                _ => {}
+            }
+        }
+        self.history_recorder = Some(HistoryRecorder::start(
+            devices,
+            &self.modbus,
+            self.history.clone(),
+            self.repaint.clone(),
+        )?);
+        Ok(())
+    }
+
+    /// 按当前配置打开串口监视器, 已打开时先关闭
+    pub fn start_serial_monitor(&mut self) -> Result<()> {
+        self.serial_monitor = None;
+        self.serial_monitor = Some(SerialMonitor::start(
+            &self.serial_monitor_config,
+            self.repaint.clone(),
+        )?);
+        Ok(())
+    }
+
+    /// 加载串口监视器配置的固件 ELF
+    pub fn load_firmware_symbols(&mut self) -> Result<()> {
+        self.firmware_symbols = None;
+        self.firmware_symbols = Some(FirmwareSymbols::load(&self.serial_monitor_config.elf)?);
+        Ok(())
+    }
+
+    fn persist(&mut self) {
+        self.persistence.set_value("operator", &self.audit.user);
+        self.persistence.set_value("devices", &self.devices);
+        self.persistence.set_value("alerts", &self.alerts);
+        self.persistence.set_value("shadows", &self.shadows);
+        self.persistence
+            .set_value("command_queue", &self.command_queue);
+        self.persistence
+            .set_value("modbus_devices", &self.modbus_devices);
+        self.persistence
+            .set_value("modbus_buses", &self.modbus.configs());
+        self.persistence.set_value("recipes", &self.recipes);
+        self.persistence
+            .set_value("simulator", &self.simulator_config);
+        self.persistence.set_value("bus_scan", &self.scan_config);
+        self.persistence.set_value("sniffer", &self.sniffer_config);
+        self.persistence
+            .set_value("mqtt_gateway", &self.gateway_config);
+        self.persistence
+            .set_value("tcp_gateway", &self.tcp_gateway_config);
+        self.persistence.set_value("history", &self.history_config);
+        self.persistence
+            .set_value("serial_monitor", &self.serial_monitor_config);
+        self.persistence
+            .set_value("mqtt_client", self.mqtt_client.config());
+        self.persistence.set_value("mqtt_server", &self.mqtt_server);
+    }
+
+    /// 立即保存到文件
+    pub fn save(&mut self) {
+        self.persist();
+        self.changed = false;
+        self.persistence.save();
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::resource::error::Result;
+
+/// 操作类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditAction {
+    /// 向设备发布命令
+    Publish,
+    /// 设备离线, 命令进入队列
+    CommandQueued,
+    /// 取消排队的命令
+    CommandCancelled,
+    /// 修改配置
+    ConfigChange,
+    /// 写 modbus 线圈或寄存器
+    RegisterWrite,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 5] = [
+        AuditAction::Publish,
+        AuditAction::CommandQueued,
+        AuditAction::CommandCancelled,
+        AuditAction::ConfigChange,
+        AuditAction::RegisterWrite,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AuditAction::Publish => "发布命令",
+            AuditAction::CommandQueued => "命令排队",
+            AuditAction::CommandCancelled => "取消命令",
+            AuditAction::ConfigChange => "修改配置",
+            AuditAction::RegisterWrite => "写寄存器",
+        }
+    }
+}
+
+/// 一条操作记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Local>,
+    /// 操作人
+    pub user: String,
+    pub action: AuditAction,
+    /// 操作对象, 如设备id, 配置项
+    pub target: String,
+    /// 修改前的值, 未知时为 None
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+impl AuditEntry {
+    /// 是否包含搜索的文本, 不区分大小写
+    pub fn contains(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let fields = [
+            Some(self.user.as_str()),
+            Some(self.action.label()),
+            Some(self.target.as_str()),
+            self.old_value.as_deref(),
+            self.new_value.as_deref(),
+        ];
+        fields
+            .into_iter()
+            .flatten()
+            .any(|s| s.to_lowercase().contains(&query))
+    }
+}
+
+/// 审计日志的查询条件
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub query: String,
+    pub action: Option<AuditAction>,
+    /// 开始日期(包含)
+    pub from: Option<NaiveDate>,
+    /// 结束日期(包含)
+    pub to: Option<NaiveDate>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let date = entry.time.date_naive();
+        self.from.map_or(true, |from| date >= from)
+            && self.to.map_or(true, |to| date <= to)
+            && self.action.map_or(true, |action| entry.action == action)
+            && (self.query.is_empty() || entry.contains(&self.query))
+    }
+}
+
+/// 操作审计日志, 每行一条 json 记录, 只追加不修改
+pub struct AuditLog {
+    path: PathBuf,
+    /// 当前操作人
+    pub user: String,
+    entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    /// 打开日志文件, 读取已有的记录
+    pub fn open(path: impl Into<PathBuf>, user: String) -> Self {
+        let path = path.into();
+        let mut entries = vec![];
+        if let Ok(file) = File::open(&path) {
+            for (i, line) in BufReader::new(file).lines().enumerate() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(&line) {
+                    Ok(entry) => entries.push(entry),
+                    Err(e) => tracing::warn!("审计日志第 {} 行无法解析: {}", i + 1, e),
+                }
+            }
+        }
+        tracing::info!("audit log: {:?}, {} 条记录", &path, entries.len());
+        Self {
+            path,
+            user,
+            entries,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    /// 记录一次操作
+    pub fn record(
+        &mut self,
+        action: AuditAction,
+        target: impl Into<String>,
+        old_value: Option<String>,
+        new_value: Option<String>,
+    ) {
+        let entry = AuditEntry {
+            time: Local::now(),
+            user: self.user.clone(),
+            action,
+            target: target.into(),
+            old_value,
+            new_value,
+        };
+        if let Err(e) = self.append(&entry) {
+            tracing::error!("写入审计日志失败: {}", e);
+        }
+        self.entries.push(entry);
+    }
+
+    fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    pub fn search<'a>(&'a self, filter: &'a AuditFilter) -> impl Iterator<Item = &'a AuditEntry> {
+        self.entries.iter().filter(move |e| filter.matches(e))
+    }
+}
+
+/// 导出为 csv 文件
+pub fn export_csv<'a>(
+    entries: impl Iterator<Item = &'a AuditEntry>,
+    path: impl AsRef<Path>,
+) -> Result<usize> {
+    let escape = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    let mut file = File::create(path)?;
+    let mut count = 0;
+    let mut content = String::from("时间,操作人,操作,对象,原值,新值\n");
+    for entry in entries {
+        content.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            entry.time.format("%Y-%m-%d %H:%M:%S"),
+            escape(&entry.user),
+            escape(entry.action.label()),
+            escape(&entry.target),
+            escape(entry.old_value.as_deref().unwrap_or("")),
+            escape(entry.new_value.as_deref().unwrap_or("")),
+        ));
+        count += 1;
+    }
+    file.write_all(content.as_bytes())?;
+    Ok(count)
+}
@@ -0,0 +1,1175 @@
+//! 根据寄存器表生成固件和上位机代码
+//!
+//! - modbus_params.h: 每个数据区一个 packed 结构体, HOLD_OFFSET/INPUT_OFFSET 宏和起始地址
+//! - modbus_params.c: 结构体实例, setup_reg_data() 和 setup_reg_descriptors()
+//! - modbus_params.rs: 与结构体对应的 rust 类型, 按寄存器解码和编码
+//!
+//! 结构体直接映射 esp-modbus 的寄存器存储: 寄存器之间的空隙填充 reserved 成员, 不注册描述符,
+//! 主站访问时从站返回异常. 地址重叠, 32 和 64 位值不在偶数寄存器, 线圈数组不按字节对齐时生成失败
+
+use std::fmt::Write;
+
+use crate::resource::error::{AppError, Result};
+
+use super::register_map::{Area, DataType, Field, Initial, Order, RegisterMap};
+
+/// 生成的文件内容
+#[derive(Debug, Clone)]
+pub struct Generated {
+    pub header: String,
+    pub source: String,
+    pub rust: String,
+}
+
+impl Generated {
+    /// (文件名, 内容)
+    pub fn files(&self) -> [(&'static str, &str); 3] {
+        [
+            ("modbus_params.h", self.header.as_str()),
+            ("modbus_params.c", self.source.as_str()),
+            ("modbus_params.rs", self.rust.as_str()),
+        ]
+    }
+}
+
+pub fn generate(map: &RegisterMap) -> Result<Generated> {
+    map.validate()?;
+    let layouts = Area::ALL
+        .into_iter()
+        .map(|area| AreaLayout::new(area, map.fields(area)))
+        .collect::<Result<Vec<_>>>()?;
+    let layouts: Vec<AreaLayout> = layouts.into_iter().flatten().collect();
+    Ok(Generated {
+        header: header(map, &layouts),
+        source: source(map, &layouts),
+        rust: rust(map, &layouts),
+    })
+}
+
+fn error<T>(message: String) -> Result<T> {
+    Err(AppError::RegisterMap(message))
+}
+
+/// 结构体中的一个成员
+enum Member<'a> {
+    Field(&'a Field),
+    /// 同一个寄存器中的位段, 按起始位排序
+    Bits {
+        address: u16,
+        fields: Vec<&'a Field>,
+    },
+    /// 寄存器表中没有定义的地址
+    Reserved {
+        address: u16,
+        len: u16,
+    },
+}
+
+impl Member<'_> {
+    fn address(&self) -> u16 {
+        match self {
+            Member::Field(field) => field.address,
+            Member::Bits { address, .. } | Member::Reserved { address, .. } => *address,
+        }
+    }
+
+    fn len(&self) -> u16 {
+        match self {
+            Member::Field(field) => field.len(),
+            Member::Bits { .. } => 1,
+            Member::Reserved { len, .. } => *len,
+        }
+    }
+
+    /// c 结构体中的成员名
+    fn name(&self) -> String {
+        match self {
+            Member::Field(field) => field.name.clone(),
+            Member::Bits { address, .. } => format!("reg_{}", address),
+            Member::Reserved { address, .. } => format!("reserved_{}", address),
+        }
+    }
+}
+
+/// 一个数据区的结构体布局
+struct AreaLayout<'a> {
+    area: Area,
+    /// 结构体第一个成员的地址
+    start: u16,
+    members: Vec<Member<'a>>,
+}
+
+impl<'a> AreaLayout<'a> {
+    /// 没有字段的数据区不生成结构体
+    fn new(area: Area, fields: &'a [Field]) -> Result<Option<Self>> {
+        let mut fields: Vec<&Field> = fields.iter().collect();
+        fields.sort_by_key(|f| (f.address, bit_range(f).0));
+        let first = match fields.first() {
+            Some(first) => first.address,
+            None => return Ok(None),
+        };
+        // 线圈按字节存储, 结构体从字节边界开始
+        let start = if area.is_bit() { first & !7 } else { first };
+
+        let mut members: Vec<Member> = vec![];
+        let mut cursor = start as u32;
+        for field in fields {
+            check_name(&field.name)?;
+            if let Some(Member::Bits { address, fields }) = members.last_mut() {
+                if *address == field.address && matches!(field.data_type, DataType::Bits { .. }) {
+                    let previous = fields[fields.len() - 1];
+                    if bit_range(field).0 < bit_range(previous).1 {
+                        return error(format!("{} 与 {} 的位段重叠", previous.name, field.name));
+                    }
+                    fields.push(field);
+                    continue;
+                }
+            }
+            if (field.address as u32) < cursor {
+                let previous = members.last().map(|m| m.name()).unwrap_or_default();
+                return error(format!(
+                    "{}: {} 与 {} 地址重叠",
+                    area.label(),
+                    previous,
+                    field.name
+                ));
+            }
+            check_alignment(area, start, field)?;
+            if field.address as u32 > cursor {
+                members.push(Member::Reserved {
+                    address: cursor as u16,
+                    len: (field.address as u32 - cursor) as u16,
+                });
+            }
+            members.push(match field.data_type {
+                DataType::Bits { .. } => Member::Bits {
+                    address: field.address,
+                    fields: vec![field],
+                },
+                _ => Member::Field(field),
+            });
+            cursor = field.end();
+        }
+        Ok(Some(Self {
+            area,
+            start,
+            members,
+        }))
+    }
+
+    fn names(&self) -> AreaNames {
+        match self.area {
+            Area::Coils => AreaNames {
+                c_type: "coil_reg_params",
+                rust_type: "CoilRegParams",
+                param: "MB_PARAM_COIL",
+                start: "MB_REG_COILS_START",
+                offset: "",
+            },
+            Area::DiscreteInputs => AreaNames {
+                c_type: "discrete_reg_params",
+                rust_type: "DiscreteRegParams",
+                param: "MB_PARAM_DISCRETE",
+                start: "MB_REG_DISCRETE_INPUT_START",
+                offset: "",
+            },
+            Area::InputRegisters => AreaNames {
+                c_type: "input_reg_params",
+                rust_type: "InputRegParams",
+                param: "MB_PARAM_INPUT",
+                start: "MB_REG_INPUT_START",
+                offset: "INPUT_OFFSET",
+            },
+            Area::HoldingRegisters => AreaNames {
+                c_type: "holding_reg_params",
+                rust_type: "HoldingRegParams",
+                param: "MB_PARAM_HOLDING",
+                start: "MB_REG_HOLDING_START",
+                offset: "HOLD_OFFSET",
+            },
+        }
+    }
+
+    /// 寄存器(或位)数量
+    fn len(&self) -> u32 {
+        self.members.last().map_or(0, |m| {
+            m.address() as u32 + m.len() as u32 - self.start as u32
+        })
+    }
+
+    /// 不包含 reserved 的连续成员, 每段注册一个描述符
+    fn runs(&self) -> Vec<&[Member<'a>]> {
+        self.members
+            .split(|m| matches!(m, Member::Reserved { .. }))
+            .filter(|run| !run.is_empty())
+            .collect()
+    }
+}
+
+struct AreaNames {
+    c_type: &'static str,
+    rust_type: &'static str,
+    param: &'static str,
+    start: &'static str,
+    offset: &'static str,
+}
+
+/// 位段的 [起始位, 结束位), 其它类型为 [0, 16)
+fn bit_range(field: &Field) -> (u8, u8) {
+    match field.data_type {
+        DataType::Bits { bit, width } => (bit, bit + width),
+        _ => (0, 16),
+    }
+}
+
+/// 字段名需要同时是合法的 c 和 rust 标识符
+fn check_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let reserved = C_KEYWORDS.contains(&name) || ["self", "Self", "crate", "super"].contains(&name);
+    if !valid || reserved {
+        return error(format!("{}: 不能作为 c 或 rust 的标识符", name));
+    }
+    Ok(())
+}
+
+fn check_alignment(area: Area, start: u16, field: &Field) -> Result<()> {
+    match field.data_type {
+        // 单个线圈是 1 位的位域, 数组映射为 uint8_t, 需要按字节对齐
+        DataType::Bool if field.count > 1 && (field.address % 8 != 0 || field.count % 8 != 0) => {
+            error(format!(
+                "{}: {}数组的地址和长度需要是 8 的倍数",
+                field.name,
+                area.label()
+            ))
+        }
+        _ if field.data_type.is_multi_register() && (field.address - start) % 2 != 0 => {
+            error(format!(
+                "{}: 32 和 64 位值需要从结构体的偶数寄存器开始, 地址 {} 未对齐",
+                field.name, field.address
+            ))
+        }
+        DataType::Bits { .. } if field.count > 1 => {
+            error(format!("{}: 不支持位段数组", field.name))
+        }
+        DataType::Bits { .. } if field.order().byte_swap() => {
+            error(format!("{}: 位段不支持寄存器内字节交换", field.name))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// esp32 是小端, esp-modbus 按大端发送每个寄存器, 所以 32 和 64 位值在寄存器中低字在前(CDAB),
+/// 字符串在寄存器中低字节在前. 其它字节序的字段不能直接映射为 c 类型, 按寄存器数组保存
+fn is_native(field: &Field) -> bool {
+    let order = field.order();
+    match field.data_type {
+        DataType::Str { .. } => order.byte_swap(),
+        _ if field.data_type.is_multi_register() => order == Order::Cdab,
+        _ => !order.byte_swap(),
+    }
+}
+
+fn c_type(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Bool => "uint8_t",
+        DataType::U16 | DataType::Bits { .. } => "uint16_t",
+        DataType::I16 => "int16_t",
+        DataType::U32 => "uint32_t",
+        DataType::I32 => "int32_t",
+        DataType::F32 => "float",
+        DataType::U64 => "uint64_t",
+        DataType::I64 => "int64_t",
+        DataType::F64 => "double",
+        DataType::Str { .. } => "char",
+    }
+}
+
+fn rust_type(data_type: DataType) -> String {
+    let ty = match data_type {
+        DataType::Bool => "bool",
+        DataType::U16 | DataType::Bits { .. } => "u16",
+        DataType::I16 => "i16",
+        DataType::U32 => "u32",
+        DataType::I32 => "i32",
+        DataType::F32 => "f32",
+        DataType::U64 => "u64",
+        DataType::I64 => "i64",
+        DataType::F64 => "f64",
+        DataType::Str { .. } => return format!("[u8; {}]", data_type.size() as u32 * 2),
+    };
+    ty.to_string()
+}
+
+/// 字段的注释: 地址, 类型, 换算和单位
+fn describe(field: &Field) -> String {
+    let mut text = format!("{} {}", field.address, field.data_type.label());
+    if field.count > 1 {
+        let _ = write!(text, " x {}", field.count);
+    }
+    if field.scale != 1.0 || field.offset != 0.0 {
+        let _ = write!(
+            text,
+            ", 工程值 = 原始值 * {} + {}",
+            field.scale, field.offset
+        );
+    }
+    if !field.unit.is_empty() {
+        let _ = write!(text, ", 单位 {}", field.unit);
+    }
+    if !is_native(field) {
+        text.push_str(", 字节序与 esp32 不同, 按寄存器保存");
+    }
+    if !field.description.is_empty() {
+        let _ = write!(text, ", {}", field.description);
+    }
+    // 多行的说明合并为一行, 否则换行后的内容不在注释中
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn banner(map: &RegisterMap, comment: &str) -> String {
+    format!(
+        "{} 由 modbus-codegen 根据寄存器表 {} 生成, 不要手动修改\n",
+        comment, map.name
+    )
+}
+
+fn header(map: &RegisterMap, layouts: &[AreaLayout]) -> String {
+    let mut out = banner(map, "//");
+    out.push_str("#pragma once\n\n#include <stddef.h>\n#include <stdint.h>\n\n");
+
+    for layout in layouts {
+        let names = layout.names();
+        let _ = writeln!(out, "#pragma pack(push, 1)\ntypedef struct\n{{");
+        let mut bit = layout.start as u32;
+        for member in layout.members.iter() {
+            if layout.area.is_bit() {
+                write_bit_member(&mut out, member, &mut bit);
+            } else {
+                write_register_member(&mut out, member);
+            }
+        }
+        let _ = writeln!(out, "}} {}_t;\n#pragma pack(pop)\n", names.c_type);
+    }
+
+    for layout in layouts.iter().filter(|l| !l.area.is_bit()) {
+        let names = layout.names();
+        let _ = writeln!(
+            out,
+            "#define {}(field) ((uint16_t)(offsetof({}_t, field) >> 1))",
+            names.offset, names.c_type
+        );
+    }
+    out.push('\n');
+    for layout in layouts {
+        let names = layout.names();
+        let _ = writeln!(out, "#define {} (0x{:04X})", names.start, layout.start);
+        if layout.area.is_bit() {
+            continue;
+        }
+        for (i, run) in layout.runs().into_iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "#define {}_AREA{} ({} + {}({}))",
+                names.start,
+                i,
+                names.start,
+                names.offset,
+                run[0].name()
+            );
+        }
+    }
+    out.push('\n');
+    for layout in layouts {
+        let names = layout.names();
+        let _ = writeln!(out, "extern {}_t {};", names.c_type, names.c_type);
+    }
+    out.push_str("\nvoid setup_reg_data(void);\nvoid setup_reg_descriptors(void);\n");
+    out
+}
+
+/// 线圈: 单个线圈为 1 位的位域, 数组每 8 个为一个字节, 空隙为匿名位域
+fn write_bit_member(out: &mut String, member: &Member, bit: &mut u32) {
+    match member {
+        Member::Field(field) if field.count == 1 => {
+            let _ = writeln!(out, "    uint8_t {}:1; // {}", field.name, describe(field));
+        }
+        Member::Field(field) if field.count == 8 => {
+            let _ = writeln!(out, "    uint8_t {}; // {}", field.name, describe(field));
+        }
+        Member::Field(field) => {
+            let _ = writeln!(
+                out,
+                "    uint8_t {}[{}]; // {}",
+                field.name,
+                field.count / 8,
+                describe(field)
+            );
+        }
+        Member::Reserved { len, .. } => {
+            let mut remaining = *len as u32;
+            while remaining > 0 {
+                let width = remaining.min(8 - *bit % 8);
+                let _ = writeln!(out, "    uint8_t :{};", width);
+                remaining -= width;
+                *bit += width;
+            }
+            return;
+        }
+        Member::Bits { .. } => unreachable!("线圈中没有位段"),
+    }
+    *bit += member.len() as u32;
+}
+
+fn write_register_member(out: &mut String, member: &Member) {
+    match member {
+        Member::Field(field) if is_native(field) => {
+            let mut array = if field.count > 1 {
+                format!("[{}]", field.count)
+            } else {
+                String::new()
+            };
+            if let DataType::Str { .. } = field.data_type {
+                let _ = write!(array, "[{}]", field.data_type.size() as u32 * 2);
+            }
+            let _ = writeln!(
+                out,
+                "    {} {}{}; // {}",
+                c_type(field.data_type),
+                field.name,
+                array,
+                describe(field)
+            );
+        }
+        Member::Field(field) => {
+            let _ = writeln!(
+                out,
+                "    uint16_t {}[{}]; // {}",
+                field.name,
+                field.len(),
+                describe(field)
+            );
+        }
+        Member::Bits { address, fields } => {
+            let _ = writeln!(out, "    struct\n    {{");
+            let mut next = 0;
+            for field in fields {
+                let (bit, end) = bit_range(field);
+                if bit > next {
+                    let _ = writeln!(out, "        uint16_t :{};", bit - next);
+                }
+                let _ = writeln!(
+                    out,
+                    "        uint16_t {}:{}; // {}",
+                    field.name,
+                    end - bit,
+                    describe(field)
+                );
+                next = end;
+            }
+            if next < 16 {
+                let _ = writeln!(out, "        uint16_t :{};", 16 - next);
+            }
+            let _ = writeln!(out, "    }} reg_{}; // {}", address, address);
+        }
+        Member::Reserved { address, len } => {
+            let _ = writeln!(
+                out,
+                "    uint16_t reserved_{}[{}]; // {} ~ {}, 未定义",
+                address,
+                len,
+                address,
+                *address as u32 + *len as u32 - 1
+            );
+        }
+    }
+}
+
+fn source(map: &RegisterMap, layouts: &[AreaLayout]) -> String {
+    let mut out = banner(map, "//");
+    out.push_str(
+        "#include <math.h>\n#include <string.h>\n\n#include \"esp_err.h\"\n#include \"mbcontroller.h\"\n#include \"modbus_params.h\"\n\n",
+    );
+    for layout in layouts {
+        let names = layout.names();
+        let _ = writeln!(out, "{}_t {} = {{ 0 }};", names.c_type, names.c_type);
+    }
+
+    out.push_str("\n// 寄存器表中的 initial\nvoid setup_reg_data(void)\n{\n");
+    for layout in layouts {
+        let instance = layout.names().c_type;
+        for member in layout.members.iter() {
+            let fields = match member {
+                Member::Field(field) => vec![*field],
+                Member::Bits { fields, .. } => fields.clone(),
+                Member::Reserved { .. } => continue,
+            };
+            for field in fields {
+                let prefix = match member {
+                    Member::Bits { address, .. } => format!("{}.reg_{}.", instance, address),
+                    _ => format!("{}.", instance),
+                };
+                write_initial(&mut out, &prefix, field);
+            }
+        }
+    }
+    out.push_str("}\n\n");
+
+    out.push_str("// 每段连续的字段注册一个描述符, 空隙中的地址由协议栈返回异常\n");
+    out.push_str(
+        "void setup_reg_descriptors(void)\n{\n    mb_register_area_descriptor_t reg_area;\n",
+    );
+    for layout in layouts {
+        let names = layout.names();
+        let _ = writeln!(out);
+        if layout.area.is_bit() {
+            let _ = writeln!(out, "    reg_area.type = {};", names.param);
+            let _ = writeln!(out, "    reg_area.start_offset = {};", names.start);
+            let _ = writeln!(out, "    reg_area.address = (void *)&{};", names.c_type);
+            let _ = writeln!(out, "    reg_area.size = sizeof({});", names.c_type);
+            let _ = writeln!(
+                out,
+                "    ESP_ERROR_CHECK(mbc_slave_set_descriptor(reg_area));"
+            );
+            continue;
+        }
+        for (i, run) in layout.runs().into_iter().enumerate() {
+            let len: u32 = run.iter().map(|m| m.len() as u32).sum();
+            let _ = writeln!(out, "    reg_area.type = {};", names.param);
+            let _ = writeln!(
+                out,
+                "    reg_area.start_offset = {}_AREA{};",
+                names.start, i
+            );
+            let _ = writeln!(
+                out,
+                "    reg_area.address = (void *)&{}.{};",
+                names.c_type,
+                run[0].name()
+            );
+            let _ = writeln!(out, "    reg_area.size = {}; // {} 个寄存器", len * 2, len);
+            let _ = writeln!(
+                out,
+                "    ESP_ERROR_CHECK(mbc_slave_set_descriptor(reg_area));"
+            );
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// 初始值按 scale 和 offset 转换为原始值
+fn write_initial(out: &mut String, prefix: &str, field: &Field) {
+    let values = match &field.initial {
+        Some(initial) => initial.values(field.count),
+        None => return,
+    };
+    let raw = |value: f64| (value - field.offset) / field.scale;
+    if field.data_type == DataType::Bool {
+        if field.count == 1 {
+            let _ = writeln!(out, "    {}{} = {};", prefix, field.name, values[0] as u8);
+            return;
+        }
+        for (i, byte) in values.chunks(8).enumerate() {
+            let byte = byte
+                .iter()
+                .enumerate()
+                .fold(0u8, |b, (bit, v)| b | (((*v != 0.0) as u8) << bit));
+            let index = if field.count == 8 {
+                String::new()
+            } else {
+                format!("[{}]", i)
+            };
+            let _ = writeln!(
+                out,
+                "    {}{}{} = 0x{:02X};",
+                prefix, field.name, index, byte
+            );
+        }
+        return;
+    }
+    if let (DataType::Str { .. }, Some(Initial::Text(text))) = (field.data_type, &field.initial) {
+        write_text_initial(out, prefix, field, text);
+        return;
+    }
+    if !is_native(field) {
+        let raw = match field.encode(&values, &[]) {
+            Ok(raw) => raw,
+            Err(_) => return,
+        };
+        for (i, word) in raw.iter().enumerate() {
+            let _ = writeln!(out, "    {}{}[{}] = 0x{:04X};", prefix, field.name, i, word);
+        }
+        return;
+    }
+    let literal = |value: f64| match field.data_type {
+        DataType::F32 => float_literal(format!("{}", raw(value) as f32), "f"),
+        DataType::F64 => float_literal(format!("{}", raw(value)), ""),
+        _ => format!("{}", raw(value).round() as i64),
+    };
+    if field.count == 1 {
+        let _ = writeln!(
+            out,
+            "    {}{} = {};",
+            prefix,
+            field.name,
+            literal(values[0])
+        );
+    } else if values.iter().all(|v| *v == values[0]) {
+        let _ = writeln!(
+            out,
+            "    for (int i = 0; i < {}; i++)\n    {{\n        {}{}[i] = {};\n    }}",
+            field.count,
+            prefix,
+            field.name,
+            literal(values[0])
+        );
+    } else {
+        for (i, value) in values.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "    {}{}[{}] = {};",
+                prefix,
+                field.name,
+                i,
+                literal(*value)
+            );
+        }
+    }
+}
+
+/// 浮点数的字面量, NaN 和无穷大使用 math.h 中的宏
+fn float_literal(text: String, suffix: &str) -> String {
+    match text.as_str() {
+        "NaN" => "NAN".to_string(),
+        "inf" => "INFINITY".to_string(),
+        "-inf" => "-INFINITY".to_string(),
+        _ if text.contains(['.', 'e']) => format!("{}{}", text, suffix),
+        _ => format!("{}.0{}", text, suffix),
+    }
+}
+
+/// 字符串的初始值, 数组的每个元素相同
+fn write_text_initial(out: &mut String, prefix: &str, field: &Field, text: &str) {
+    if !is_native(field) {
+        let raw = match field.encode_text(&vec![text.to_string(); field.count as usize]) {
+            Ok(raw) => raw,
+            Err(_) => return,
+        };
+        for (i, word) in raw.iter().enumerate() {
+            let _ = writeln!(out, "    {}{}[{}] = 0x{:04X};", prefix, field.name, i, word);
+        }
+        return;
+    }
+    if text.len() > field.data_type.size() as usize * 2 {
+        return;
+    }
+    let literal = c_string(text);
+    if field.count == 1 {
+        let _ = writeln!(
+            out,
+            "    memcpy({}{}, {}, {});",
+            prefix,
+            field.name,
+            literal,
+            text.len()
+        );
+    } else {
+        let _ = writeln!(
+            out,
+            "    for (int i = 0; i < {}; i++)\n    {{\n        memcpy({}{}[i], {}, {});\n    }}",
+            field.count,
+            prefix,
+            field.name,
+            literal,
+            text.len()
+        );
+    }
+}
+
+/// c 字符串字面量, 非 ascii 字节使用八进制转义
+fn c_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            0x20..=0x7E => literal.push(byte as char),
+            _ => {
+                let _ = write!(literal, "\\{:03o}", byte);
+            }
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn rust(map: &RegisterMap, layouts: &[AreaLayout]) -> String {
+    let mut out = banner(map, "//!");
+    for layout in layouts {
+        let names = layout.names();
+        let (unit, source) = if layout.area.is_bit() {
+            ("bool", "bits")
+        } else {
+            ("u16", "regs")
+        };
+        let fields: Vec<&Field> = layout
+            .members
+            .iter()
+            .flat_map(|m| match m {
+                Member::Field(field) => vec![*field],
+                Member::Bits { fields, .. } => fields.clone(),
+                Member::Reserved { .. } => vec![],
+            })
+            .collect();
+
+        let _ = writeln!(
+            out,
+            "\n/// {}, 地址 {} ~ {}\n#[derive(Debug, Clone, Copy, PartialEq)]\npub struct {} {{",
+            layout.area.label(),
+            layout.start,
+            layout.start as u32 + layout.len() - 1,
+            names.rust_type
+        );
+        for field in fields.iter() {
+            let ty = if field.count > 1 {
+                format!("[{}; {}]", rust_type(field.data_type), field.count)
+            } else {
+                rust_type(field.data_type)
+            };
+            let _ = writeln!(out, "    /// {}", describe(field));
+            let _ = writeln!(out, "    pub {}: {},", rust_ident(&field.name), ty);
+        }
+        let _ = writeln!(out, "}}\n\nimpl {} {{", names.rust_type);
+        let _ = writeln!(out, "    pub const START: u16 = {};", layout.start);
+        let _ = writeln!(out, "    pub const LEN: u16 = {};\n", layout.len());
+
+        let _ = writeln!(out, "    /// 从 START 开始的 LEN 个值");
+        let _ = writeln!(
+            out,
+            "    pub fn decode({}: &[{}]) -> Option<Self> {{",
+            source, unit
+        );
+        let _ = writeln!(
+            out,
+            "        if {}.len() < Self::LEN as usize {{\n            return None;\n        }}",
+            source
+        );
+        let _ = writeln!(out, "        Some(Self {{");
+        for field in fields.iter() {
+            let index = Index::new(field, layout.start);
+            let value = if field.count > 1 {
+                format!("std::array::from_fn(|i| {})", decode_expr(field, &index))
+            } else {
+                decode_expr(field, &index)
+            };
+            let _ = writeln!(out, "            {}: {},", rust_ident(&field.name), value);
+        }
+        let _ = writeln!(out, "        }})\n    }}\n");
+
+        let _ = writeln!(out, "    /// 空隙中的地址为 0");
+        let _ = writeln!(out, "    pub fn encode(&self) -> Vec<{}> {{", unit);
+        let default = if layout.area.is_bit() { "false" } else { "0" };
+        let _ = writeln!(
+            out,
+            "        let mut {} = vec![{}; Self::LEN as usize];",
+            source, default
+        );
+        for field in fields.iter() {
+            let index = Index::new(field, layout.start);
+            let name = rust_ident(&field.name);
+            if field.count > 1 {
+                let _ = writeln!(out, "        for i in 0..{} {{", field.count);
+                for line in encode_lines(field, &index, &format!("self.{}[i]", name)) {
+                    let _ = writeln!(out, "            {}", line);
+                }
+                let _ = writeln!(out, "        }}");
+            } else {
+                for line in encode_lines(field, &index, &format!("self.{}", name)) {
+                    let _ = writeln!(out, "        {}", line);
+                }
+            }
+        }
+        let _ = writeln!(out, "        {}\n    }}\n}}", source);
+    }
+    out
+}
+
+/// 字段在生成的 decode/encode 中的下标, 数组元素的下标包含循环变量 i
+struct Index {
+    start: u16,
+    size: u16,
+    array: bool,
+}
+
+impl Index {
+    fn new(field: &Field, layout_start: u16) -> Self {
+        Self {
+            start: field.address - layout_start,
+            size: field.data_type.size(),
+            array: field.count > 1,
+        }
+    }
+
+    /// 元素中第 word 个寄存器
+    fn at(&self, word: u16) -> String {
+        let start = self.start + word;
+        if !self.array {
+            return start.to_string();
+        }
+        let element = if self.size == 1 {
+            "i".to_string()
+        } else {
+            format!("i * {}", self.size)
+        };
+        if start == 0 {
+            element
+        } else {
+            format!("{} + {}", start, element)
+        }
+    }
+
+    /// 多寄存器数值的每个寄存器, 高字在前
+    fn words(&self, field: &Field) -> Vec<String> {
+        let mut words: Vec<String> = (0..self.size).map(|word| self.at(word)).collect();
+        if field.order().word_swap() {
+            words.reverse();
+        }
+        words
+    }
+
+    /// 元素中下标为 expr 的寄存器, 用于字符串
+    fn offset(&self, expr: &str) -> String {
+        match self.at(0).as_str() {
+            "0" => expr.to_string(),
+            start => format!("{} + {}", start, expr),
+        }
+    }
+}
+
+fn reg_expr(field: &Field, index: &str) -> String {
+    if field.order().byte_swap() {
+        format!("regs[{}].swap_bytes()", index)
+    } else {
+        format!("regs[{}]", index)
+    }
+}
+
+fn decode_expr(field: &Field, index: &Index) -> String {
+    let combine = |ty: &str| {
+        let words = index.words(field);
+        words
+            .iter()
+            .enumerate()
+            .map(|(k, word)| match 16 * (words.len() - 1 - k) {
+                0 => format!("{} as {}", reg_expr(field, word), ty),
+                shift => format!("({} as {}) << {}", reg_expr(field, word), ty, shift),
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    };
+    match field.data_type {
+        DataType::Bool => format!("bits[{}]", index.at(0)),
+        DataType::U16 => reg_expr(field, &index.at(0)),
+        DataType::I16 => format!("{} as i16", reg_expr(field, &index.at(0))),
+        DataType::U32 => combine("u32"),
+        DataType::I32 => format!("({}) as i32", combine("u32")),
+        DataType::F32 => format!("f32::from_bits({})", combine("u32")),
+        DataType::U64 => combine("u64"),
+        DataType::I64 => format!("({}) as i64", combine("u64")),
+        DataType::F64 => format!("f64::from_bits({})", combine("u64")),
+        DataType::Str { .. } => {
+            let bytes = if field.order().byte_swap() {
+                "to_le_bytes"
+            } else {
+                "to_be_bytes"
+            };
+            format!(
+                "std::array::from_fn(|b| regs[{}].{}()[b % 2])",
+                index.offset("b / 2"),
+                bytes
+            )
+        }
+        DataType::Bits { bit: 0, width } => {
+            format!("{} & 0x{:X}", reg_expr(field, &index.at(0)), mask(width))
+        }
+        DataType::Bits { bit, width } => format!(
+            "({} >> {}) & 0x{:X}",
+            reg_expr(field, &index.at(0)),
+            bit,
+            mask(width)
+        ),
+    }
+}
+
+fn encode_lines(field: &Field, index: &Index, value: &str) -> Vec<String> {
+    let swap = |expr: &str| match field.order().byte_swap() {
+        false => expr.to_string(),
+        true if expr.contains(' ') => format!("({}).swap_bytes()", expr),
+        true => format!("{}.swap_bytes()", expr),
+    };
+    let split = |bits: String| {
+        let words = index.words(field);
+        let mut lines = vec![format!("let value = {};", bits)];
+        for (k, word) in words.iter().enumerate() {
+            let expr = match 16 * (words.len() - 1 - k) {
+                0 => "value as u16".to_string(),
+                shift => format!("(value >> {}) as u16", shift),
+            };
+            lines.push(format!("regs[{}] = {};", word, swap(&expr)));
+        }
+        lines
+    };
+    let at = index.at(0);
+    match field.data_type {
+        DataType::Bool => vec![format!("bits[{}] = {};", at, value)],
+        DataType::U16 => vec![format!("regs[{}] = {};", at, swap(value))],
+        DataType::I16 => vec![format!(
+            "regs[{}] = {};",
+            at,
+            swap(&format!("{} as u16", value))
+        )],
+        DataType::U32 => split(value.to_string()),
+        DataType::I32 => split(format!("{} as u32", value)),
+        DataType::F32 => split(format!("{}.to_bits()", value)),
+        DataType::U64 => split(value.to_string()),
+        DataType::I64 => split(format!("{} as u64", value)),
+        DataType::F64 => split(format!("{}.to_bits()", value)),
+        DataType::Str { .. } => {
+            let bytes = if field.order().byte_swap() {
+                "from_le_bytes"
+            } else {
+                "from_be_bytes"
+            };
+            vec![
+                format!("for (b, pair) in {}.chunks(2).enumerate() {{", value),
+                format!(
+                    "    regs[{}] = u16::{}([pair[0], pair[1]]);",
+                    index.offset("b"),
+                    bytes
+                ),
+                "}".to_string(),
+            ]
+        }
+        DataType::Bits { bit: 0, width } => {
+            vec![format!("regs[{}] |= {} & 0x{:X};", at, value, mask(width))]
+        }
+        DataType::Bits { bit, width } => vec![format!(
+            "regs[{}] |= ({} & 0x{:X}) << {};",
+            at,
+            value,
+            mask(width),
+            bit
+        )],
+    }
+}
+
+fn mask(width: u8) -> u32 {
+    (1u32 << width) - 1
+}
+
+fn rust_ident(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+const C_KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while",
+];
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "dyn", "fn", "impl", "in", "let", "loop", "match", "mod",
+    "move", "mut", "pub", "ref", "trait", "type", "unsafe", "use", "where", "yield",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 不经过 from_toml 的检查, 直接交给 generate
+    fn map(fields: &str) -> RegisterMap {
+        toml::from_str(&format!("name = \"test\"\n{}", fields)).unwrap()
+    }
+
+    fn generate_error(fields: &str) -> String {
+        match generate(&map(fields)) {
+            Err(AppError::RegisterMap(message)) => message,
+            result => panic!("{:?}", result.map(|g| g.header)),
+        }
+    }
+
+    /// 结构体成员: (名称, 起始位, 位数), 匿名位域的名称为空
+    fn members(header: &str, c_type: &str) -> Vec<(String, u32, u32)> {
+        let end = header
+            .find(&format!("}} {};", c_type))
+            .unwrap_or_else(|| panic!("{} 不存在", c_type));
+        let start = header[..end].rfind('{').unwrap() + 1;
+        let mut offset = 0;
+        let mut members = vec![];
+        for line in header[start..end].lines() {
+            let line = line
+                .split("//")
+                .next()
+                .unwrap()
+                .trim()
+                .trim_end_matches(';');
+            let (ty, declarator) = match line.split_once(' ') {
+                Some(split) => split,
+                None => continue,
+            };
+            let unit = match ty {
+                "uint8_t" => 8,
+                "uint16_t" => 16,
+                "float" => 32,
+                ty => panic!("未知类型 {}", ty),
+            };
+            let (name, bits) = match declarator.split_once(':') {
+                Some((name, width)) => (name, width.parse().unwrap()),
+                None => match declarator.split_once('[') {
+                    Some((name, len)) => (
+                        name,
+                        unit * len.trim_end_matches(']').parse::<u32>().unwrap(),
+                    ),
+                    None => (declarator, unit),
+                },
+            };
+            members.push((name.trim().to_string(), offset, bits));
+            offset += bits;
+        }
+        members
+    }
+
+    #[test]
+    fn example_matches_modbus_params_h() {
+        let expected = include_str!("../../../modbus-rtu-example/main/modbus_params.h");
+        let firmware = include_str!("../../../modbus-rtu-example/main/modbus_rtu_example_main.c");
+        let generated = generate(&RegisterMap::modbus_rtu_example()).unwrap();
+
+        for c_type in [
+            "coil_reg_params_t",
+            "discrete_reg_params_t",
+            "input_reg_params_t",
+            "holding_reg_params_t",
+        ] {
+            let expected = members(expected, c_type);
+            let generated = members(&generated.header, c_type);
+            let size = |members: &[(String, u32, u32)]| {
+                members.last().map_or(0, |(_, offset, bits)| offset + bits)
+            };
+            assert_eq!(size(&generated), size(&expected), "{}", c_type);
+            for member in expected {
+                let found = generated
+                    .iter()
+                    .find(|(_, offset, _)| *offset == member.1)
+                    .unwrap_or_else(|| panic!("{}: 缺少 {:?}", c_type, member));
+                // 固件中 input 的 data 数组不在寄存器表中, 生成为 reserved
+                if !found.0.starts_with("reserved_") {
+                    assert_eq!(found.0, member.0, "{}", c_type);
+                }
+                assert_eq!(found.2, member.2, "{}: {}", c_type, member.0);
+            }
+        }
+
+        // 固件中手写的宏
+        for line in firmware.lines().filter(|l| l.contains("_OFFSET(field)")) {
+            assert!(generated.header.contains(line), "{}", line);
+        }
+        assert!(generated.header.contains(
+            "#define MB_REG_INPUT_START_AREA1 (MB_REG_INPUT_START + INPUT_OFFSET(input_data4))"
+        ));
+        assert!(generated
+            .source
+            .contains("    holding_reg_params.holding_data0 = 1.34f;"));
+        assert!(generated
+            .source
+            .contains("    coil_reg_params.coils_port0 = 0x55;"));
+        assert!(generated
+            .source
+            .contains("reg_area.size = 332; // 166 个寄存器"));
+    }
+
+    #[test]
+    fn reject_overlap_and_misalignment() {
+        let message = generate_error(
+            "[[holding_registers]]\nname = \"a\"\naddress = 0\ntype = \"u32\"\n\
+             [[holding_registers]]\nname = \"b\"\naddress = 1\ntype = \"u16\"",
+        );
+        assert!(message.contains("a 与 b 地址重叠"), "{}", message);
+
+        let message = generate_error(
+            "[[input_registers]]\nname = \"a\"\naddress = 0\ntype = \"u16\"\n\
+             [[input_registers]]\nname = \"b\"\naddress = 1\ntype = \"f32\"",
+        );
+        assert!(message.contains("地址 1 未对齐"), "{}", message);
+
+        // 结构体从第一个字段开始, 第一个字段在奇数地址时没有问题
+        let map = map("[[input_registers]]\nname = \"a\"\naddress = 1\ntype = \"f32\"");
+        assert!(generate(&map).is_ok());
+
+        let message =
+            generate_error("[[coils]]\nname = \"a\"\naddress = 4\ntype = \"bool\"\ncount = 8");
+        assert!(message.contains("8 的倍数"), "{}", message);
+
+        let message = generate_error(
+            "[[holding_registers]]\nname = \"a\"\naddress = 0\ntype = \"bits\"\nbit = 0\nwidth = 4\n\
+             [[holding_registers]]\nname = \"b\"\naddress = 0\ntype = \"bits\"\nbit = 2\nwidth = 4",
+        );
+        assert!(message.contains("重叠"), "{}", message);
+
+        let message =
+            generate_error("[[holding_registers]]\nname = \"int\"\naddress = 0\ntype = \"u16\"");
+        assert!(message.contains("标识符"), "{}", message);
+    }
+
+    #[test]
+    fn non_finite_initial_values() {
+        let generated = generate(&map(
+            "[[holding_registers]]\nname = \"a\"\naddress = 0\ntype = \"f32\"\norder = \"cdab\"\ninitial = nan\n\
+             [[holding_registers]]\nname = \"b\"\naddress = 2\ntype = \"f32\"\norder = \"cdab\"\ninitial = [inf, -inf]\ncount = 2\n\
+             [[holding_registers]]\nname = \"c\"\naddress = 6\ntype = \"f64\"\norder = \"cdab\"\ninitial = -inf\n\
+             [[holding_registers]]\nname = \"d\"\naddress = 10\ntype = \"f32\"\norder = \"cdab\"\ninitial = 1e39\n\
+             [[holding_registers]]\nname = \"e\"\naddress = 12\ntype = \"f64\"\norder = \"cdab\"\ninitial = 3",
+        ))
+        .unwrap();
+        let source = &generated.source;
+        assert!(source.contains("#include <math.h>"));
+        assert!(source.contains("holding_reg_params.a = NAN;"), "{}", source);
+        assert!(source.contains("holding_reg_params.b[0] = INFINITY;"));
+        assert!(source.contains("holding_reg_params.b[1] = -INFINITY;"));
+        assert!(source.contains("holding_reg_params.c = -INFINITY;"));
+        // 超出 f32 范围
+        assert!(source.contains("holding_reg_params.d = INFINITY;"));
+        assert!(source.contains("holding_reg_params.e = 3.0;"));
+        assert!(!source.contains("inf") && !source.contains("NaN"));
+    }
+
+    #[test]
+    fn multi_line_description() {
+        let generated = generate(&map(
+            "[[holding_registers]]\nname = \"a\"\naddress = 0\ntype = \"u16\"\nunit = \"°C\"\n\
+             description = \"\"\"\n设定温度\r\n范围 0 ~ 100\n\n\"\"\"",
+        ))
+        .unwrap();
+        assert!(generated
+            .header
+            .contains("    uint16_t a; // 0 u16, 单位 °C, 设定温度 范围 0 ~ 100\n"));
+        assert!(generated
+            .rust
+            .contains("    /// 0 u16, 单位 °C, 设定温度 范围 0 ~ 100\n    pub a: u16,"));
+    }
+}
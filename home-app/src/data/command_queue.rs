@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::service::modbus::pdu::Request;
+
+use super::{device::DeviceCommand, recipe::Recipe};
+
+/// 命令的目标
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CommandTarget {
+    /// mqtt 设备, 参数为设备id
+    Mqtt(String),
+    /// Modbus 从站, 参数为设备名称
+    Modbus { device: String },
+}
+
+impl CommandTarget {
+    pub fn label(&self) -> String {
+        match self {
+            CommandTarget::Mqtt(id) => format!("mqtt: {}", id),
+            CommandTarget::Modbus { device } => format!("modbus: {}", device),
+        }
+    }
+}
+
+/// 排队的命令内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Command {
+    /// 发送到 mqtt 设备 set 主题的命令
+    Mqtt(DeviceCommand),
+    /// 写 Modbus 寄存器, description 用于显示, 如 "setpoint = 25"
+    Write {
+        request: Request,
+        description: String,
+    },
+    /// 下载配方, 从站上线后读取当前值再写入
+    Recipe { recipe: Recipe, only_changed: bool },
+}
+
+impl Command {
+    pub fn label(&self) -> String {
+        match self {
+            Command::Mqtt(command) => command.to_payload(),
+            Command::Write { description, .. } => format!("写入 {}", description),
+            Command::Recipe { recipe, .. } => format!("下载配方 {}", recipe.name),
+        }
+    }
+}
+
+/// 排队等待设备上线的命令
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedCommand {
+    pub id: u64,
+    pub target: CommandTarget,
+    pub command: Command,
+    pub created: DateTime<Local>,
+    /// 过期时间, 过期后不再发送
+    pub expires: DateTime<Local>,
+}
+
+impl QueuedCommand {
+    pub fn is_expired(&self, now: DateTime<Local>) -> bool {
+        now >= self.expires
+    }
+}
+
+/// 离线命令队列, 每个设备一个队列, 设备上线后按顺序发送
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandQueue {
+    queues: BTreeMap<CommandTarget, VecDeque<QueuedCommand>>,
+    next_id: u64,
+    /// 默认的命令有效期, 单位: 秒
+    pub default_ttl: u64,
+}
+
+impl Default for CommandQueue {
+    fn default() -> Self {
+        Self {
+            queues: BTreeMap::new(),
+            next_id: 0,
+            default_ttl: 3600,
+        }
+    }
+}
+
+impl CommandQueue {
+    /// 命令入队, ttl 为 None 时使用默认有效期
+    pub fn enqueue(&mut self, target: CommandTarget, command: Command, ttl: Option<u64>) -> u64 {
+        self.next_id += 1;
+        let created = Local::now();
+        let ttl = ttl.unwrap_or(self.default_ttl);
+        let queued = QueuedCommand {
+            id: self.next_id,
+            target: target.clone(),
+            command,
+            created,
+            expires: created + Duration::seconds(ttl as i64),
+        };
+        tracing::info!(
+            "{} 离线, 命令入队: {}",
+            target.label(),
+            queued.command.label()
+        );
+        self.queues.entry(target).or_default().push_back(queued);
+        self.next_id
+    }
+
+    /// 取消一条命令
+    pub fn cancel(&mut self, id: u64) -> Option<QueuedCommand> {
+        let mut cancelled = None;
+        for queue in self.queues.values_mut() {
+            if let Some(pos) = queue.iter().position(|c| c.id == id) {
+                cancelled = queue.remove(pos);
+                break;
+            }
+        }
+        self.queues.retain(|_, q| !q.is_empty());
+        cancelled
+    }
+
+    /// 取消设备的所有命令
+    pub fn cancel_all(&mut self, target: &CommandTarget) -> usize {
+        self.queues.remove(target).map_or(0, |q| q.len())
+    }
+
+    /// 修改一条命令的有效期, 从当前时间开始计算
+    pub fn set_ttl(&mut self, id: u64, ttl: u64) -> Option<&QueuedCommand> {
+        let queued = self.queues.values_mut().flatten().find(|c| c.id == id)?;
+        queued.expires = Local::now() + Duration::seconds(ttl as i64);
+        Some(queued)
+    }
+
+    /// 删除过期的命令
+    pub fn purge_expired(&mut self) -> Vec<QueuedCommand> {
+        let now = Local::now();
+        let mut expired = vec![];
+        for queue in self.queues.values_mut() {
+            let (keep, drop): (VecDeque<_>, VecDeque<_>) =
+                queue.drain(..).partition(|c| !c.is_expired(now));
+            *queue = keep;
+            expired.extend(drop);
+        }
+        self.queues.retain(|_, q| !q.is_empty());
+        for c in expired.iter() {
+            tracing::warn!("{} 的命令已过期: {}", c.target.label(), c.command.label());
+        }
+        expired
+    }
+
+    /// 取出设备最早入队的未过期命令
+    pub fn pop(&mut self, target: &CommandTarget) -> Option<QueuedCommand> {
+        let now = Local::now();
+        let queue = self.queues.get_mut(target)?;
+        let mut next = None;
+        while let Some(queued) = queue.pop_front() {
+            if !queued.is_expired(now) {
+                next = Some(queued);
+                break;
+            }
+        }
+        if queue.is_empty() {
+            self.queues.remove(target);
+        }
+        next
+    }
+
+    pub fn targets(&self) -> Vec<CommandTarget> {
+        self.queues.keys().cloned().collect()
+    }
+
+    pub fn queue(&self, target: &CommandTarget) -> impl Iterator<Item = &QueuedCommand> {
+        self.queues.get(target).into_iter().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::resource::defines::topics::{AVAILABILITY, DEVICE_PREFIX, STATE};
+
+/// 设备 topic: home/<设备id>/<sub>
+pub fn device_topic(id: &str, sub: &str) -> String {
+    format!("{}/{}/{}", DEVICE_PREFIX, id, sub)
+}
+
+/// 设备 topic 的订阅通配符: home/+/<sub>
+pub fn device_topic_filter(sub: &str) -> String {
+    format!("{}/+/{}", DEVICE_PREFIX, sub)
+}
+
+/// 解析设备 topic, 得到 (设备id, 子topic)
+pub fn parse_device_topic(topic: &str) -> Option<(&str, &str)> {
+    let mut parts = topic.splitn(3, '/');
+    if parts.next()? != DEVICE_PREFIX {
+        return None;
+    }
+    let id = parts.next()?;
+    let sub = parts.next()?;
+    if id.is_empty() {
+        return None;
+    }
+    Some((id, sub))
+}
+
+/// 设备类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeviceKind {
+    Light,
+    Switch,
+    Sensor,
+    Other,
+}
+
+impl DeviceKind {
+    pub const ALL: [DeviceKind; 4] = [
+        DeviceKind::Light,
+        DeviceKind::Switch,
+        DeviceKind::Sensor,
+        DeviceKind::Other,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeviceKind::Light => "灯",
+            DeviceKind::Switch => "开关",
+            DeviceKind::Sensor => "传感器",
+            DeviceKind::Other => "其他",
+        }
+    }
+}
+
+/// 下发给设备的命令
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeviceCommand {
+    /// 打开/关闭
+    Power(bool),
+    /// 设置某个字段
+    Set(String, Value),
+}
+
+impl DeviceCommand {
+    /// 命令对应的 json 字段
+    pub fn to_fields(&self) -> Map<String, Value> {
+        let mut fields = Map::new();
+        match self {
+            DeviceCommand::Power(on) => {
+                fields.insert("power".into(), Value::Bool(*on));
+            }
+            DeviceCommand::Set(field, value) => {
+                fields.insert(field.clone(), value.clone());
+            }
+        }
+        fields
+    }
+
+    /// 发布到 home/<设备id>/set 的内容
+    pub fn to_payload(&self) -> String {
+        Value::Object(self.to_fields()).to_string()
+    }
+}
+
+/// 一个 mqtt 设备
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    /// 设备id, 即 topic 中的 <设备id>
+    pub id: String,
+    /// 显示名称
+    pub name: String,
+    pub kind: DeviceKind,
+    /// 所在房间
+    pub room: Option<String>,
+    /// 设备是否在线, 来自 availability topic
+    #[serde(skip)]
+    pub online: bool,
+    /// 设备最后一次上报的状态
+    #[serde(skip)]
+    pub reported: Map<String, Value>,
+}
+
+impl Device {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: id.to_string(),
+            kind: DeviceKind::Other,
+            room: None,
+            online: false,
+            reported: Map::new(),
+        }
+    }
+
+    /// 开关状态, 未上报时为 None
+    pub fn power(&self) -> Option<bool> {
+        self.reported.get("power").and_then(Value::as_bool)
+    }
+
+    /// 设备是否支持开关
+    pub fn switchable(&self) -> bool {
+        matches!(self.kind, DeviceKind::Light | DeviceKind::Switch) || self.power().is_some()
+    }
+}
+
+/// 设备分组
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceGroup {
+    pub name: String,
+    pub members: BTreeSet<String>,
+}
+
+/// 分组的聚合状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupState {
+    AllOn,
+    PartiallyOn,
+    AllOff,
+    /// 没有可开关的成员, 或成员都未上报状态
+    Unknown,
+}
+
+impl GroupState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            GroupState::AllOn => "全部打开",
+            GroupState::PartiallyOn => "部分打开",
+            GroupState::AllOff => "全部关闭",
+            GroupState::Unknown => "未知",
+        }
+    }
+}
+
+/// 设备列表按房间的过滤条件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomFilter {
+    All,
+    Unassigned,
+    Room(String),
+}
+
+impl RoomFilter {
+    pub fn matches(&self, device: &Device) -> bool {
+        match self {
+            RoomFilter::All => true,
+            RoomFilter::Unassigned => device.room.is_none(),
+            RoomFilter::Room(room) => device.room.as_ref() == Some(room),
+        }
+    }
+}
+
+/// 所有设备, 房间 和 分组
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceRegistry {
+    devices: BTreeMap<String, Device>,
+    rooms: Vec<String>,
+    groups: Vec<DeviceGroup>,
+}
+
+impl DeviceRegistry {
+    pub fn devices(&self) -> impl Iterator<Item = &Device> {
+        self.devices.values()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Device> {
+        self.devices.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Device> {
+        self.devices.get_mut(id)
+    }
+
+    /// 获取设备, 不存在时自动添加
+    pub fn get_or_insert(&mut self, id: &str) -> &mut Device {
+        self.devices
+            .entry(id.to_string())
+            .or_insert_with(|| Device::new(id))
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Device> {
+        for group in self.groups.iter_mut() {
+            group.members.remove(id);
+        }
+        self.devices.remove(id)
+    }
+
+    pub fn filtered<'a>(&'a self, filter: &'a RoomFilter) -> impl Iterator<Item = &'a Device> {
+        self.devices.values().filter(move |d| filter.matches(d))
+    }
+
+    pub fn rooms(&self) -> &[String] {
+        &self.rooms
+    }
+
+    /// 添加房间, 房间名重复时返回 false
+    pub fn add_room(&mut self, name: &str) -> bool {
+        let name = name.trim();
+        if name.is_empty() || self.rooms.iter().any(|r| r == name) {
+            return false;
+        }
+        self.rooms.push(name.to_string());
+        true
+    }
+
+    /// 删除房间, 房间内的设备变为未分配
+    pub fn remove_room(&mut self, name: &str) {
+        self.rooms.retain(|r| r != name);
+        for device in self.devices.values_mut() {
+            if device.room.as_deref() == Some(name) {
+                device.room = None;
+            }
+        }
+    }
+
+    /// 把设备分配到房间, room 为 None 时取消分配
+    pub fn assign_room(&mut self, id: &str, room: Option<&str>) {
+        if let Some(room) = room {
+            if !self.rooms.iter().any(|r| r == room) {
+                return;
+            }
+        }
+        if let Some(device) = self.devices.get_mut(id) {
+            device.room = room.map(str::to_string);
+        }
+    }
+
+    pub fn groups(&self) -> &[DeviceGroup] {
+        &self.groups
+    }
+
+    pub fn group(&self, name: &str) -> Option<&DeviceGroup> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// 添加分组, 分组名重复时返回 false
+    pub fn add_group(&mut self, name: &str) -> bool {
+        let name = name.trim();
+        if name.is_empty() || self.group(name).is_some() {
+            return false;
+        }
+        self.groups.push(DeviceGroup {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        true
+    }
+
+    pub fn remove_group(&mut self, name: &str) {
+        self.groups.retain(|g| g.name != name);
+    }
+
+    /// 一个设备可以属于多个分组
+    pub fn add_to_group(&mut self, group: &str, id: &str) {
+        if !self.devices.contains_key(id) {
+            return;
+        }
+        if let Some(group) = self.groups.iter_mut().find(|g| g.name == group) {
+            group.members.insert(id.to_string());
+        }
+    }
+
+    pub fn remove_from_group(&mut self, group: &str, id: &str) {
+        if let Some(group) = self.groups.iter_mut().find(|g| g.name == group) {
+            group.members.remove(id);
+        }
+    }
+
+    /// 分组成员的聚合开关状态
+    pub fn group_state(&self, name: &str) -> GroupState {
+        let group = match self.group(name) {
+            Some(group) => group,
+            None => return GroupState::Unknown,
+        };
+        let (mut on, mut off) = (0, 0);
+        for power in group
+            .members
+            .iter()
+            .filter_map(|id| self.devices.get(id))
+            .filter_map(Device::power)
+        {
+            if power {
+                on += 1;
+            } else {
+                off += 1;
+            }
+        }
+        match (on, off) {
+            (0, 0) => GroupState::Unknown,
+            (_, 0) => GroupState::AllOn,
+            (0, _) => GroupState::AllOff,
+            _ => GroupState::PartiallyOn,
+        }
+    }
+
+    /// 处理设备的 mqtt 消息, 返回消息对应的设备id 和消息中上报的字段
+    pub fn handle_message(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+    ) -> Option<(String, Map<String, Value>)> {
+        let (id, sub) = parse_device_topic(topic)?;
+        let fields = match sub {
+            STATE => {
+                let state: Map<String, Value> = match serde_json::from_slice(payload) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        tracing::warn!("设备 {} 的状态无法解析: {}", id, e);
+                        return None;
+                    }
+                };
+                let device = self.get_or_insert(id);
+                // 能上报状态, 说明设备在线
+                device.online = true;
+                device.reported.extend(state.clone());
+                state
+            }
+            AVAILABILITY => {
+                let device = self.get_or_insert(id);
+                device.online = payload == b"online";
+                Map::new()
+            }
+            _ => return None,
+        };
+        Some((id.to_string(), fields))
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use addr2line::{
+    gimli::{self, EndianArcSlice, RunTimeEndian},
+    object::{self, Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind},
+    Context,
+};
+use parking_lot::Mutex;
+
+use crate::resource::error::{AppError, Result};
+
+type Reader = EndianArcSlice<RunTimeEndian>;
+
+/// 地址对应的源码位置, 内联展开的函数是单独的一帧
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolFrame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// 被内联到下一帧的函数中
+    pub inlined: bool,
+}
+
+impl SymbolFrame {
+    /// "file:line", 没有行号信息时为 "??:?"
+    pub fn location(&self) -> String {
+        format!(
+            "{}:{}",
+            self.file.as_deref().unwrap_or("??"),
+            self.line.map_or_else(|| "?".to_string(), |l| l.to_string())
+        )
+    }
+}
+
+/// 固件 ELF 的调试信息, 把回溯中的地址解析为函数, 文件和行号, 与 addr2line -pfiaC 相同
+///
+/// 没有 DWARF 行号信息时只用符号表给出函数名
+pub struct FirmwareSymbols {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// addr2line 的 Context 在第一次查询时才解析编译单元, 内部不是 Sync
+    context: Mutex<Context<Reader>>,
+    /// 代码段的地址范围, 之外的地址不查询
+    code: Vec<Range<u64>>,
+    /// 按地址排列的函数符号: 起始地址, 长度, 名称
+    functions: Vec<(u64, u64, String)>,
+    cache: Mutex<HashMap<u32, Vec<SymbolFrame>>>,
+}
+
+impl FirmwareSymbols {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        let error = |e: &dyn std::fmt::Display| {
+            AppError::Error(format!("{} 不是有效的 ELF 文件: {}", path.display(), e))
+        };
+        let file = object::File::parse(&*data).map_err(|e| error(&e))?;
+        let endian = if file.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let dwarf = gimli::Dwarf::load(|id| -> std::result::Result<Reader, gimli::Error> {
+            let data = file
+                .section_by_name(id.name())
+                .and_then(|section| section.uncompressed_data().ok())
+                .unwrap_or(Cow::Borrowed(&[]));
+            Ok(EndianArcSlice::new(Arc::from(&*data), endian))
+        })
+        .map_err(|e| error(&e))?;
+        let context = Context::from_dwarf(dwarf).map_err(|e| error(&e))?;
+
+        let code = file
+            .sections()
+            .filter(|s| s.kind() == SectionKind::Text)
+            .map(|s| s.address()..s.address() + s.size())
+            .collect();
+        let mut functions: Vec<(u64, u64, String)> = file
+            .symbols()
+            .filter(|s| s.kind() == SymbolKind::Text && s.size() > 0)
+            .filter_map(|s| Some((s.address(), s.size(), s.name().ok()?.to_string())))
+            .collect();
+        functions.sort();
+        if functions.is_empty() && file.section_by_name(".debug_info").is_none() {
+            return Err(AppError::Error(format!(
+                "{} 没有符号和调试信息",
+                path.display()
+            )));
+        }
+        tracing::info!(
+            "已加载固件符号 {}: {} 个函数",
+            path.display(),
+            functions.len()
+        );
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified,
+            context: Mutex::new(context),
+            code,
+            functions,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 文件在加载后被重新编译
+    pub fn is_outdated(&self) -> bool {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        modified != self.modified
+    }
+
+    /// 地址所在的函数, 最内层的内联函数在前; 不在代码段中时为空
+    /// 返回地址按 address - 1 查询, 与 gdb 相同, 否则函数末尾的调用会解析到下一行或下一个函数
+    pub fn resolve(&self, address: u32, return_address: bool) -> Vec<SymbolFrame> {
+        let address = if return_address {
+            address.saturating_sub(1)
+        } else {
+            address
+        };
+        if let Some(frames) = self.cache.lock().get(&address) {
+            return frames.clone();
+        }
+        if !self.code.iter().any(|r| r.contains(&(address as u64))) {
+            return vec![];
+        }
+        let mut frames = self.dwarf_frames(address as u64).unwrap_or_default();
+        if let Some(name) = self.symbol(address as u64) {
+            match frames.last_mut() {
+                Some(frame) if frame.function.is_none() => frame.function = Some(name),
+                Some(_) => {}
+                None => frames.push(SymbolFrame {
+                    function: Some(name),
+                    file: None,
+                    line: None,
+                    inlined: false,
+                }),
+            }
+        }
+        self.cache.lock().insert(address, frames.clone());
+        frames
+    }
+
+    fn dwarf_frames(&self, address: u64) -> std::result::Result<Vec<SymbolFrame>, gimli::Error> {
+        let context = self.context.lock();
+        let mut iter = context.find_frames(address)?;
+        let mut frames = vec![];
+        while let Some(frame) = iter.next()? {
+            let function = frame
+                .function
+                .as_ref()
+                .and_then(|f| f.demangle().ok())
+                .map(|name| name.into_owned());
+            let (file, line) = match &frame.location {
+                Some(location) => (location.file.map(str::to_string), location.line),
+                None => (None, None),
+            };
+            frames.push(SymbolFrame {
+                function,
+                file,
+                line,
+                inlined: true,
+            });
+        }
+        if let Some(last) = frames.last_mut() {
+            last.inlined = false;
+        }
+        Ok(frames)
+    }
+
+    fn symbol(&self, address: u64) -> Option<String> {
+        let index = self
+            .functions
+            .partition_point(|(start, _, _)| *start <= address);
+        let (start, size, name) = self.functions.get(index.checked_sub(1)?)?;
+        (address < start + size).then(|| name.clone())
+    }
+}
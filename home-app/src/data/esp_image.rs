@@ -0,0 +1,606 @@
+use std::{fs, path::Path};
+
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    data::partition_table::{PartitionTable, DEFAULT_TABLE_OFFSET, TABLE_LEN, TYPE_APP},
+    resource::error::{AppError, Result},
+};
+
+/// 镜像头的第一个字节
+const IMAGE_MAGIC: u8 = 0xe9;
+/// 镜像头 8 字节加扩展头 16 字节
+const HEADER_LEN: usize = 24;
+const SEGMENT_HEADER_LEN: usize = 8;
+const CHECKSUM_SEED: u8 = 0xef;
+/// esp_app_desc_t 的魔数, 位于第一个段的开头
+const APP_DESC_MAGIC: u32 = 0xabcd_5432;
+const APP_DESC_LEN: usize = 256;
+
+fn hex<S: Serializer>(value: &u32, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{:08x}", value))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 镜像中的一个段, 启动时被 bootloader 加载或映射到 load_address
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageSegment {
+    #[serde(serialize_with = "hex")]
+    pub load_address: u32,
+    pub length: u32,
+    /// 段数据在文件中的位置
+    #[serde(serialize_with = "hex")]
+    pub file_offset: u32,
+    /// 地址所在的存储区域, 如 IROM, DRAM
+    pub region: &'static str,
+}
+
+/// 应用的 esp_app_desc_t, 由 ESP-IDF 在编译时生成
+#[derive(Debug, Clone, Serialize)]
+pub struct AppDescription {
+    pub project_name: String,
+    pub version: String,
+    pub idf_version: String,
+    /// 编译日期和时间, 如 "Mar 18 2022 10:21:07"
+    pub compile_time: String,
+    pub secure_version: u32,
+    /// 生成镜像的 ELF 文件的 SHA256
+    pub elf_sha256: String,
+}
+
+/// 一个 ESP-IDF 镜像: 应用或 bootloader
+#[derive(Debug, Clone, Serialize)]
+pub struct EspImage {
+    /// 镜像在文件中的位置, 合并镜像中为 flash 地址
+    #[serde(serialize_with = "hex")]
+    pub offset: u32,
+    /// 合并镜像中的分区名称
+    pub name: String,
+    pub chip_id: u16,
+    pub chip: &'static str,
+    /// 芯片版本, 如 "v0.3"
+    pub min_chip_revision: String,
+    pub max_chip_revision: String,
+    pub flash_mode: &'static str,
+    pub flash_size: &'static str,
+    pub flash_frequency: &'static str,
+    #[serde(serialize_with = "hex")]
+    pub entry_point: u32,
+    pub segments: Vec<ImageSegment>,
+    pub app: Option<AppDescription>,
+    /// 镜像的长度, 包括校验和与 SHA256
+    pub size: u32,
+    pub checksum: u8,
+    pub checksum_valid: bool,
+    pub hash_appended: bool,
+    pub hash: Option<String>,
+    pub hash_valid: Option<bool>,
+}
+
+impl EspImage {
+    /// 解析 data 开头的镜像
+    pub fn parse(data: &[u8], offset: u32, name: &str) -> Result<Self> {
+        let invalid = |message: &str| AppError::Error(format!("0x{:x}: {}", offset, message));
+        if data.len() < HEADER_LEN || data[0] != IMAGE_MAGIC {
+            return Err(invalid("不是 ESP 镜像, 魔数不是 0xE9"));
+        }
+        let segment_count = data[1] as usize;
+        let chip_id = u16_at(data, 12);
+        let mut segments = vec![];
+        let mut app = None;
+        let mut checksum = CHECKSUM_SEED;
+        let mut pos = HEADER_LEN;
+        for index in 0..segment_count {
+            if pos + SEGMENT_HEADER_LEN > data.len() {
+                return Err(invalid(&format!("第 {} 个段超出文件末尾", index)));
+            }
+            let load_address = u32_at(data, pos);
+            let length = u32_at(data, pos + 4);
+            pos += SEGMENT_HEADER_LEN;
+            let segment = data
+                .get(pos..pos + length as usize)
+                .ok_or_else(|| invalid(&format!("第 {} 个段超出文件末尾", index)))?;
+            if index == 0 {
+                app = AppDescription::parse(segment);
+            }
+            checksum = segment.iter().fold(checksum, |c, b| c ^ b);
+            segments.push(ImageSegment {
+                load_address,
+                length,
+                file_offset: offset + pos as u32,
+                region: memory_region(chip_id, load_address),
+            });
+            pos += length as usize;
+        }
+        // 校验和在 16 字节对齐的最后一个字节
+        pos += 15 - pos % 16;
+        let stored = *data.get(pos).ok_or_else(|| invalid("校验和超出文件末尾"))?;
+        pos += 1;
+
+        let hash_appended = data[23] == 1;
+        let (hash, hash_valid) = if hash_appended {
+            let stored = data
+                .get(pos..pos + 32)
+                .ok_or_else(|| invalid("SHA256 超出文件末尾"))?;
+            let calculated = Sha256::digest(&data[..pos]);
+            pos += 32;
+            (Some(to_hex(stored)), Some(calculated.as_slice() == stored))
+        } else {
+            (None, None)
+        };
+
+        Ok(Self {
+            offset,
+            name: name.to_string(),
+            chip_id,
+            chip: chip_name(chip_id),
+            min_chip_revision: chip_revision(u16_at(data, 15)),
+            max_chip_revision: chip_revision(u16_at(data, 17)),
+            flash_mode: flash_mode(data[2]),
+            flash_size: flash_size(data[3] >> 4),
+            flash_frequency: flash_frequency(chip_id, data[3] & 0x0f),
+            entry_point: u32_at(data, 4),
+            segments,
+            app,
+            size: pos as u32,
+            checksum: stored,
+            checksum_valid: stored == checksum,
+            hash_appended,
+            hash,
+            hash_valid,
+        })
+    }
+
+    /// 校验和与 SHA256 都正确
+    pub fn is_valid(&self) -> bool {
+        self.checksum_valid && self.hash_valid != Some(false)
+    }
+}
+
+impl AppDescription {
+    fn parse(segment: &[u8]) -> Option<Self> {
+        if segment.len() < APP_DESC_LEN || u32_at(segment, 0) != APP_DESC_MAGIC {
+            return None;
+        }
+        Some(Self {
+            project_name: c_string(&segment[48..80]),
+            version: c_string(&segment[16..48]),
+            idf_version: c_string(&segment[112..144]),
+            compile_time: format!(
+                "{} {}",
+                c_string(&segment[96..112]),
+                c_string(&segment[80..96])
+            ),
+            secure_version: u32_at(segment, 4),
+            elf_sha256: to_hex(&segment[144..176]),
+        })
+    }
+}
+
+/// 打开的固件文件, 单个镜像或 esptool merge_bin 生成的合并镜像
+#[derive(Debug, Clone, Serialize)]
+pub struct FirmwareFile {
+    pub path: String,
+    pub file_size: u64,
+    /// 合并镜像: 包含 bootloader, 分区表和应用
+    pub merged: bool,
+    pub images: Vec<EspImage>,
+    /// 无法解析的镜像
+    pub errors: Vec<String>,
+}
+
+impl FirmwareFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        let mut file = Self {
+            path: path.display().to_string(),
+            file_size: data.len() as u64,
+            merged: false,
+            images: vec![],
+            errors: vec![],
+        };
+        let partitions = app_partitions(&data);
+        if partitions.is_empty() {
+            file.images.push(EspImage::parse(&data, 0, "")?);
+            return Ok(file);
+        }
+
+        file.merged = true;
+        // ESP32 和 ESP32-S2 的 bootloader 在 0x1000, 其它芯片在 0x0
+        if let Some(offset) = [0, 0x1000]
+            .into_iter()
+            .find(|offset| data.get(*offset) == Some(&IMAGE_MAGIC))
+        {
+            file.push(EspImage::parse(
+                &data[offset..],
+                offset as u32,
+                "bootloader",
+            ));
+        }
+        for (name, offset) in partitions {
+            match data.get(offset as usize) {
+                Some(&IMAGE_MAGIC) => {
+                    file.push(EspImage::parse(&data[offset as usize..], offset, &name));
+                }
+                // 没有写入的 OTA 分区
+                Some(_) => {}
+                None => file
+                    .errors
+                    .push(format!("{}: 分区 0x{:x} 超出文件末尾", name, offset)),
+            }
+        }
+        Ok(file)
+    }
+
+    fn push(&mut self, image: Result<EspImage>) {
+        match image {
+            Ok(image) => self.images.push(image),
+            Err(e) => self.errors.push(e.to_string()),
+        }
+    }
+
+    /// 导出为 json 文件
+    pub fn export_json(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// 合并镜像中 0x8000 处的分区表里的应用分区: 名称, 地址
+fn app_partitions(data: &[u8]) -> Vec<(String, u32)> {
+    let start = DEFAULT_TABLE_OFFSET as usize;
+    let table = data
+        .get(start..start + TABLE_LEN)
+        .and_then(|table| PartitionTable::from_binary(table).ok())
+        .unwrap_or_default();
+    table
+        .partitions
+        .into_iter()
+        .filter(|p| p.kind == TYPE_APP)
+        .map(|p| (p.name, p.offset))
+        .collect()
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+/// 以 0 结尾的字符串
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+pub fn chip_name(chip_id: u16) -> &'static str {
+    match chip_id {
+        0 => "ESP32",
+        2 => "ESP32-S2",
+        5 => "ESP32-C3",
+        9 => "ESP32-S3",
+        12 => "ESP32-C2",
+        13 => "ESP32-C6",
+        16 => "ESP32-H2",
+        _ => "未知芯片",
+    }
+}
+
+/// 版本号为 主版本 * 100 + 次版本, 0xffff 表示不限制
+fn chip_revision(revision: u16) -> String {
+    match revision {
+        0xffff => "不限".to_string(),
+        _ => format!("v{}.{}", revision / 100, revision % 100),
+    }
+}
+
+fn flash_mode(mode: u8) -> &'static str {
+    match mode {
+        0 => "QIO",
+        1 => "QOUT",
+        2 => "DIO",
+        3 => "DOUT",
+        4 => "FAST_READ",
+        5 => "SLOW_READ",
+        _ => "未知",
+    }
+}
+
+fn flash_size(size: u8) -> &'static str {
+    match size {
+        0 => "1MB",
+        1 => "2MB",
+        2 => "4MB",
+        3 => "8MB",
+        4 => "16MB",
+        5 => "32MB",
+        6 => "64MB",
+        7 => "128MB",
+        _ => "未知",
+    }
+}
+
+/// flash 频率的编码与芯片有关, 与 esptool 相同
+fn flash_frequency(chip_id: u16, frequency: u8) -> &'static str {
+    match (chip_id, frequency) {
+        (12, 0x0) => "30MHz",
+        (12, 0x1) => "20MHz",
+        (12, 0x2) => "15MHz",
+        (12, 0xf) => "60MHz",
+        (13, 0x0) => "80MHz",
+        (13, 0x2) => "20MHz",
+        (16, 0x0) => "24MHz",
+        (16, 0x1) => "16MHz",
+        (16, 0x2) => "12MHz",
+        (16, 0xf) => "48MHz",
+        (_, 0x0) => "40MHz",
+        (_, 0x1) => "26MHz",
+        (_, 0x2) => "20MHz",
+        (_, 0xf) => "80MHz",
+        _ => "未知",
+    }
+}
+
+/// 各芯片的存储区域, 与 esptool 的 MEMORY_MAP 相同
+fn memory_map(chip_id: u16) -> &'static [(u32, u32, &'static str)] {
+    match chip_id {
+        0 => &[
+            (0x3f40_0000, 0x3f80_0000, "DROM"),
+            (0x3ff8_0000, 0x3ff8_2000, "RTC_DRAM"),
+            (0x3ffa_e000, 0x4000_0000, "DRAM"),
+            (0x4007_0000, 0x400a_0000, "IRAM"),
+            (0x400c_0000, 0x400c_2000, "RTC_IRAM"),
+            (0x400d_0000, 0x4040_0000, "IROM"),
+            (0x5000_0000, 0x5000_2000, "RTC_DATA"),
+        ],
+        2 => &[
+            (0x3f00_0000, 0x3ff8_0000, "DROM"),
+            (0x3ff9_e000, 0x3ffa_0000, "RTC_DRAM"),
+            (0x3ffb_0000, 0x4000_0000, "DRAM"),
+            (0x4002_0000, 0x4007_0000, "IRAM"),
+            (0x4007_0000, 0x4007_2000, "RTC_IRAM"),
+            (0x4008_0000, 0x40b8_0000, "IROM"),
+            (0x5000_0000, 0x5000_2000, "RTC_DATA"),
+        ],
+        5 => &[
+            (0x3c00_0000, 0x3c80_0000, "DROM"),
+            (0x3fc8_0000, 0x3fce_0000, "DRAM"),
+            (0x4037_c000, 0x403e_0000, "IRAM"),
+            (0x4200_0000, 0x4280_0000, "IROM"),
+            (0x5000_0000, 0x5000_2000, "RTC_RAM"),
+        ],
+        9 => &[
+            (0x3c00_0000, 0x3e00_0000, "DROM"),
+            (0x3fc8_8000, 0x3fd0_0000, "DRAM"),
+            (0x4037_0000, 0x403e_0000, "IRAM"),
+            (0x4200_0000, 0x4400_0000, "IROM"),
+            (0x5000_0000, 0x5000_2000, "RTC_DATA"),
+            (0x600f_e000, 0x6010_0000, "RTC_RAM"),
+        ],
+        12 => &[
+            (0x3c00_0000, 0x3c40_0000, "DROM"),
+            (0x3fca_0000, 0x3fce_0000, "DRAM"),
+            (0x4037_c000, 0x403c_0000, "IRAM"),
+            (0x4200_0000, 0x4240_0000, "IROM"),
+        ],
+        13 | 16 => &[
+            (0x4080_0000, 0x4088_0000, "SRAM"),
+            (0x4200_0000, 0x4280_0000, "IROM"),
+            (0x4280_0000, 0x4300_0000, "DROM"),
+            (0x5000_0000, 0x5000_4000, "LP_RAM"),
+        ],
+        _ => &[],
+    }
+}
+
+fn memory_region(chip_id: u16, address: u32) -> &'static str {
+    memory_map(chip_id)
+        .iter()
+        .find(|(start, end, _)| (*start..*end).contains(&address))
+        .map_or("", |(_, _, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::partition_table::{Partition, TYPE_DATA};
+
+    /// 与 esptool elf2image 相同格式的 ESP32-C3 镜像
+    fn image(segments: &[(u32, Vec<u8>)], hash: bool) -> Vec<u8> {
+        let mut data = vec![IMAGE_MAGIC, segments.len() as u8, 2, 0x2f];
+        data.extend_from_slice(&0x4038_0080u32.to_le_bytes());
+        // 扩展头: wp_pin, 驱动强度, chip_id, 芯片版本, 保留, hash_appended
+        data.extend_from_slice(&[0xee, 0, 0, 0]);
+        data.extend_from_slice(&5u16.to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(&0xffffu16.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.push(hash as u8);
+        assert_eq!(data.len(), HEADER_LEN);
+
+        let mut checksum = CHECKSUM_SEED;
+        for (address, segment) in segments {
+            data.extend_from_slice(&address.to_le_bytes());
+            data.extend_from_slice(&(segment.len() as u32).to_le_bytes());
+            data.extend_from_slice(segment);
+            checksum = segment.iter().fold(checksum, |c, b| c ^ b);
+        }
+        data.resize(data.len() + 15 - data.len() % 16, 0);
+        data.push(checksum);
+        if hash {
+            let digest = Sha256::digest(&data);
+            data.extend_from_slice(&digest);
+        }
+        data
+    }
+
+    fn app_desc() -> Vec<u8> {
+        let mut desc = vec![0u8; APP_DESC_LEN];
+        desc[..4].copy_from_slice(&APP_DESC_MAGIC.to_le_bytes());
+        desc[4..8].copy_from_slice(&2u32.to_le_bytes());
+        let mut text = |pos: usize, value: &str| {
+            desc[pos..pos + value.len()].copy_from_slice(value.as_bytes());
+        };
+        text(16, "v1.2.3");
+        text(48, "modbus-rtu-example");
+        text(80, "10:21:07");
+        text(96, "Mar 18 2022");
+        text(112, "v4.4");
+        desc[144..176].copy_from_slice(&[0xab; 32]);
+        desc
+    }
+
+    fn app_image() -> Vec<u8> {
+        image(
+            &[
+                (0x3c00_0020, app_desc()),
+                (0x3fc8_a000, vec![1, 2, 3, 4]),
+                (0x4200_0020, (0..100).collect()),
+            ],
+            true,
+        )
+    }
+
+    #[test]
+    fn parse_header_and_segments() {
+        let data = app_image();
+        let image = EspImage::parse(&data, 0, "").unwrap();
+        assert_eq!(image.chip, "ESP32-C3");
+        assert_eq!(image.flash_mode, "DIO");
+        assert_eq!(image.flash_size, "4MB");
+        assert_eq!(image.flash_frequency, "80MHz");
+        assert_eq!(image.min_chip_revision, "v0.3");
+        assert_eq!(image.max_chip_revision, "不限");
+        assert_eq!(image.entry_point, 0x4038_0080);
+
+        let segments: Vec<(u32, u32, u32, &str)> = image
+            .segments
+            .iter()
+            .map(|s| (s.load_address, s.length, s.file_offset, s.region))
+            .collect();
+        assert_eq!(
+            segments,
+            vec![
+                (0x3c00_0020, 256, 32, "DROM"),
+                (0x3fc8_a000, 4, 296, "DRAM"),
+                (0x4200_0020, 100, 308, "IROM"),
+            ]
+        );
+
+        let app = image.app.as_ref().unwrap();
+        assert_eq!(app.project_name, "modbus-rtu-example");
+        assert_eq!(app.version, "v1.2.3");
+        assert_eq!(app.idf_version, "v4.4");
+        assert_eq!(app.compile_time, "Mar 18 2022 10:21:07");
+        assert_eq!(app.secure_version, 2);
+        assert_eq!(app.elf_sha256, "ab".repeat(32));
+
+        assert_eq!(image.size as usize, data.len());
+        assert!(image.checksum_valid);
+        assert!(image.hash_appended);
+        assert_eq!(image.hash_valid, Some(true));
+        assert_eq!(
+            image.hash.as_deref(),
+            Some(&*to_hex(&data[data.len() - 32..]))
+        );
+        assert!(image.is_valid());
+    }
+
+    #[test]
+    fn detect_corruption() {
+        let mut data = app_image();
+        // 第三个段的数据
+        data[310] ^= 0x01;
+        let image = EspImage::parse(&data, 0, "").unwrap();
+        assert!(!image.checksum_valid);
+        assert_eq!(image.hash_valid, Some(false));
+        assert!(!image.is_valid());
+
+        // 没有 SHA256 时只检查校验和
+        let data = image_without_hash();
+        let image = EspImage::parse(&data, 0, "").unwrap();
+        assert!(image.checksum_valid);
+        assert_eq!(image.hash, None);
+        assert!(image.app.is_none());
+        assert_eq!(image.size as usize, data.len());
+
+        let data = app_image();
+        assert!(EspImage::parse(&data[..200], 0, "").is_err());
+        assert!(EspImage::parse(&data[..data.len() - 1], 0, "").is_err());
+        let mut bad_magic = data.clone();
+        bad_magic[0] = 0xea;
+        assert!(EspImage::parse(&bad_magic, 0, "").is_err());
+    }
+
+    fn image_without_hash() -> Vec<u8> {
+        image(&[(0x4037_c000, vec![0x55; 17])], false)
+    }
+
+    #[test]
+    fn open_merged_image() {
+        let bootloader = image_without_hash();
+        let app = app_image();
+        let table = PartitionTable {
+            partitions: vec![
+                Partition {
+                    name: "nvs".into(),
+                    kind: TYPE_DATA,
+                    subtype: 0x02,
+                    offset: 0x9000,
+                    size: 0x6000,
+                    encrypted: false,
+                    readonly: false,
+                },
+                Partition {
+                    name: "factory".into(),
+                    kind: TYPE_APP,
+                    subtype: 0x00,
+                    offset: 0x10000,
+                    size: 0x100000,
+                    encrypted: false,
+                    readonly: false,
+                },
+                Partition {
+                    name: "ota_0".into(),
+                    kind: TYPE_APP,
+                    subtype: 0x10,
+                    offset: 0x110000,
+                    size: 0x100000,
+                    encrypted: false,
+                    readonly: false,
+                },
+            ],
+        };
+        let mut data = vec![0xff; 0x10000];
+        data[..bootloader.len()].copy_from_slice(&bootloader);
+        data[0x8000..0x8000 + TABLE_LEN].copy_from_slice(&table.to_binary().unwrap());
+        data.extend_from_slice(&app);
+
+        let path = std::env::temp_dir().join(format!("esp-image-test-{}.bin", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let file = FirmwareFile::open(&path);
+        fs::remove_file(&path).unwrap();
+        let file = file.unwrap();
+        assert!(file.merged);
+        let images: Vec<(&str, u32, bool)> = file
+            .images
+            .iter()
+            .map(|i| (i.name.as_str(), i.offset, i.is_valid()))
+            .collect();
+        assert_eq!(
+            images,
+            vec![("bootloader", 0, true), ("factory", 0x10000, true)]
+        );
+        assert_eq!(file.images[1].segments[0].file_offset, 0x10000 + 32);
+        assert_eq!(file.errors.len(), 1);
+        assert!(file.errors[0].contains("ota_0"));
+    }
+}
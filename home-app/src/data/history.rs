@@ -0,0 +1,476 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::resource::error::Result;
+
+/// 内存中保留的时长, 更早的值只保存在文件中
+const MEMORY_HOURS: i64 = 24;
+/// 每个序列在内存中的最大点数
+const MAX_POINTS: usize = 1_000_000;
+
+/// 历史记录的参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// 设备名称 -> 记录的字段名称
+    pub fields: BTreeMap<String, Vec<String>>,
+    /// 历史文件保留的天数
+    pub retention_days: u32,
+    /// 趋势图显示的序列
+    pub trends: Vec<String>,
+    pub auto_start: bool,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            fields: BTreeMap::new(),
+            retention_days: 7,
+            trends: vec![],
+            auto_start: false,
+        }
+    }
+}
+
+/// 序列名称: "设备/字段", 数组元素为 "设备/字段[序号]"
+pub fn series_name(device: &str, field: &str) -> String {
+    format!("{}/{}", device, field)
+}
+
+/// 一次轮询得到的一个设备的值, 历史文件中每行一条
+#[derive(Debug, Serialize, Deserialize)]
+struct HistoryRecord {
+    /// 毫秒时间戳
+    t: i64,
+    d: String,
+    v: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryPoint {
+    /// 毫秒时间戳
+    pub time: i64,
+    pub value: f64,
+}
+
+/// 一段时间内的统计, 用于缩小后的趋势图
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    /// 时间段的中点
+    pub time: i64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub count: usize,
+}
+
+/// 轮询值的历史记录
+///
+/// 按天保存为 jsonl 文件, 只追加不修改. 最近 24 小时的值同时保存在内存中, 供趋势图使用
+pub struct HistoryStore {
+    dir: PathBuf,
+    series: BTreeMap<String, VecDeque<HistoryPoint>>,
+    /// 当前写入的文件, 跨天时切换
+    file: Option<(NaiveDate, File)>,
+}
+
+impl HistoryStore {
+    /// 打开历史目录, 读取最近 24 小时的记录
+    pub fn open(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        if let Err(e) = fs::create_dir_all(&dir) {
+            tracing::error!("无法创建历史目录 {:?}: {}", dir, e);
+        }
+        let mut store = Self {
+            dir,
+            series: BTreeMap::new(),
+            file: None,
+        };
+        let since = (Local::now() - Duration::hours(MEMORY_HOURS)).timestamp_millis();
+        let today = Local::now().date_naive();
+        for date in [today - Duration::days(1), today] {
+            store.load(date, since);
+        }
+        tracing::info!("history: {:?}, {} 个序列", &store.dir, store.series.len());
+        store
+    }
+
+    fn load(&mut self, date: NaiveDate, since: i64) {
+        let path = self.path(date);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return,
+        };
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<HistoryRecord>(&line) {
+                Ok(record) if record.t >= since => self.insert(&record),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("{:?} 第 {} 行无法解析: {}", path, i + 1, e),
+            }
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, date: NaiveDate) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", date.format("%Y-%m-%d")))
+    }
+
+    /// 有记录的序列, 按名称排列
+    pub fn series_names(&self) -> impl Iterator<Item = &str> {
+        self.series.keys().map(|s| s.as_str())
+    }
+
+    /// 记录一个设备一次轮询的值, 键为字段名称
+    ///
+    /// NaN 和无穷大在 json 中写为 null, 无法再读取, 因此跳过
+    pub fn record(
+        &mut self,
+        device: &str,
+        time: DateTime<Local>,
+        mut values: BTreeMap<String, f64>,
+    ) {
+        values.retain(|_, value| value.is_finite());
+        if values.is_empty() {
+            return;
+        }
+        let record = HistoryRecord {
+            t: time.timestamp_millis(),
+            d: device.to_string(),
+            v: values,
+        };
+        if let Err(e) = self.append(time.date_naive(), &record) {
+            tracing::error!("写入历史记录失败: {}", e);
+        }
+        self.insert(&record);
+    }
+
+    fn append(&mut self, date: NaiveDate, record: &HistoryRecord) -> Result<()> {
+        if self.file.as_ref().map(|(d, _)| *d) != Some(date) {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.path(date))?;
+            self.file = Some((date, file));
+        }
+        if let Some((_, file)) = &mut self.file {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, record: &HistoryRecord) {
+        let since = record.t - Duration::hours(MEMORY_HOURS).num_milliseconds();
+        for (field, value) in record.v.iter() {
+            let points = self
+                .series
+                .entry(series_name(&record.d, field))
+                .or_default();
+            let point = HistoryPoint {
+                time: record.t,
+                value: *value,
+            };
+            match points.back() {
+                Some(last) if last.time > record.t => {
+                    let index = points.partition_point(|p| p.time <= record.t);
+                    points.insert(index, point);
+                }
+                _ => points.push_back(point),
+            }
+            while points.len() > MAX_POINTS || points.front().map_or(false, |p| p.time < since) {
+                points.pop_front();
+            }
+        }
+    }
+
+    /// 时间段 [from, to] 内的点
+    pub fn points(&self, series: &str, from: i64, to: i64) -> Vec<HistoryPoint> {
+        let points = match self.series.get(series) {
+            Some(points) => points,
+            None => return vec![],
+        };
+        let start = points.partition_point(|p| p.time < from);
+        let end = points.partition_point(|p| p.time <= to);
+        points.range(start..end).copied().collect()
+    }
+
+    /// time 时或之前的最后一个点
+    pub fn value_at(&self, series: &str, time: i64) -> Option<HistoryPoint> {
+        let points = self.series.get(series)?;
+        let index = points.partition_point(|p| p.time <= time);
+        index.checked_sub(1).and_then(|i| points.get(i)).copied()
+    }
+
+    /// 把时间段分成 count 份, 统计每份的最小, 最大和平均值, 没有值的时间段跳过
+    /// 点数不超过 count 时每个点一份
+    pub fn buckets(&self, series: &str, from: i64, to: i64, count: usize) -> Vec<Bucket> {
+        let points = self.points(series, from, to);
+        if points.len() <= count.max(1) {
+            return points
+                .into_iter()
+                .map(|p| Bucket {
+                    time: p.time,
+                    min: p.value,
+                    max: p.value,
+                    mean: p.value,
+                    count: 1,
+                })
+                .collect();
+        }
+        let width = ((to - from) as f64 / count as f64).max(1.0);
+        let mut buckets: Vec<Bucket> = vec![];
+        let mut current = None;
+        for point in points {
+            let index = ((point.time - from) as f64 / width) as i64;
+            match buckets.last_mut() {
+                Some(bucket) if current == Some(index) => {
+                    bucket.min = bucket.min.min(point.value);
+                    bucket.max = bucket.max.max(point.value);
+                    bucket.mean += point.value;
+                    bucket.count += 1;
+                }
+                _ => {
+                    current = Some(index);
+                    buckets.push(Bucket {
+                        time: from + ((index as f64 + 0.5) * width) as i64,
+                        min: point.value,
+                        max: point.value,
+                        mean: point.value,
+                        count: 1,
+                    });
+                }
+            }
+        }
+        for bucket in buckets.iter_mut() {
+            bucket.mean /= bucket.count as f64;
+        }
+        buckets
+    }
+
+    /// 删除超过保留天数的历史文件, 返回删除的文件数
+    pub fn purge(&self, retention_days: u32) -> Result<usize> {
+        let oldest = Local::now().date_naive() - Duration::days(retention_days.max(1) as i64);
+        let mut count = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let date = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok());
+            if date.map_or(false, |date| date < oldest) {
+                fs::remove_file(&path)?;
+                count += 1;
+            }
+        }
+        if count > 0 {
+            tracing::info!("删除了 {} 个过期的历史文件", count);
+        }
+        Ok(count)
+    }
+}
+
+/// 导出时间段内的多个序列为 csv 文件, 每个时间一行, 每个序列一列, 返回行数
+pub fn export_csv(
+    store: &HistoryStore,
+    series: &[String],
+    from: i64,
+    to: i64,
+    path: impl AsRef<Path>,
+) -> Result<usize> {
+    let escape = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    let mut rows: BTreeMap<i64, Vec<Option<f64>>> = BTreeMap::new();
+    for (i, name) in series.iter().enumerate() {
+        for point in store.points(name, from, to) {
+            rows.entry(point.time)
+                .or_insert_with(|| vec![None; series.len()])[i] = Some(point.value);
+        }
+    }
+
+    let mut content = String::from("时间");
+    for name in series {
+        content.push(',');
+        content.push_str(&escape(name));
+    }
+    content.push('\n');
+    for (time, values) in rows.iter() {
+        let time = Local.timestamp_millis_opt(*time).single();
+        if let Some(time) = time {
+            content.push_str(&time.format("%Y-%m-%d %H:%M:%S%.3f").to_string());
+        }
+        for value in values {
+            content.push(',');
+            if let Some(value) = value {
+                content.push_str(&value.to_string());
+            }
+        }
+        content.push('\n');
+    }
+    File::create(path)?.write_all(content.as_bytes())?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> HistoryStore {
+        let dir =
+            std::env::temp_dir().join(format!("history-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        HistoryStore::open(dir)
+    }
+
+    fn values(values: &[(&str, f64)]) -> BTreeMap<String, f64> {
+        values.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn record_and_reload() {
+        let mut store = temp_store("reload");
+        let now = Local::now();
+        let t = now.timestamp_millis();
+        store.record("dev", now, values(&[("a", 1.5), ("b", f64::NAN)]));
+        store.record(
+            "dev",
+            now + Duration::milliseconds(10),
+            values(&[("a", f64::INFINITY), ("b", 2.0)]),
+        );
+        // 全部为非有限值时不写入
+        store.record("dev", now, values(&[("c", f64::NEG_INFINITY)]));
+        // 乱序的记录按时间插入
+        store.record(
+            "dev",
+            now - Duration::milliseconds(10),
+            values(&[("a", 0.5)]),
+        );
+
+        let expected_a = vec![
+            HistoryPoint {
+                time: t - 10,
+                value: 0.5,
+            },
+            HistoryPoint {
+                time: t,
+                value: 1.5,
+            },
+        ];
+        let expected_b = vec![HistoryPoint {
+            time: t + 10,
+            value: 2.0,
+        }];
+        assert_eq!(store.points("dev/a", t - 100, t + 100), expected_a);
+        assert_eq!(store.points("dev/b", t - 100, t + 100), expected_b);
+        assert_eq!(store.series_names().collect::<Vec<_>>(), ["dev/a", "dev/b"]);
+
+        let dir = store.dir().to_path_buf();
+        drop(store);
+        let store = HistoryStore::open(&dir);
+        assert_eq!(store.points("dev/a", t - 100, t + 100), expected_a);
+        assert_eq!(store.points("dev/b", t - 100, t + 100), expected_b);
+        assert_eq!(store.value_at("dev/a", t + 5), Some(expected_a[1]));
+        assert_eq!(store.value_at("dev/a", t - 11), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn buckets() {
+        let mut store = temp_store("buckets");
+        let now = Local::now();
+        let from = now.timestamp_millis();
+        for (offset, value) in [(0, 1.0), (10, 3.0), (20, 2.0), (70, 5.0), (90, 7.0)] {
+            store.record(
+                "dev",
+                now + Duration::milliseconds(offset),
+                values(&[("a", value)]),
+            );
+        }
+        // 100 ms 分成 4 份, 第三份没有值
+        let buckets = store.buckets("dev/a", from, from + 100, 4);
+        assert_eq!(
+            buckets,
+            vec![
+                Bucket {
+                    time: from + 12,
+                    min: 1.0,
+                    max: 3.0,
+                    mean: 2.0,
+                    count: 3,
+                },
+                Bucket {
+                    time: from + 62,
+                    min: 5.0,
+                    max: 5.0,
+                    mean: 5.0,
+                    count: 1,
+                },
+                Bucket {
+                    time: from + 87,
+                    min: 7.0,
+                    max: 7.0,
+                    mean: 7.0,
+                    count: 1,
+                },
+            ]
+        );
+        // 点数不超过份数时每个点一份
+        let buckets = store.buckets("dev/a", from, from + 100, 5);
+        assert_eq!(buckets.len(), 5);
+        assert!(buckets.iter().all(|b| b.count == 1 && b.min == b.max));
+        assert_eq!(buckets[3].time, from + 70);
+        assert!(store.buckets("dev/b", from, from + 100, 4).is_empty());
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn export() {
+        let mut store = temp_store("export");
+        let now = Local::now();
+        let t = now.timestamp_millis();
+        store.record("dev", now, values(&[("a", 1.5), ("b", -2.0)]));
+        store.record(
+            "dev",
+            now + Duration::milliseconds(500),
+            values(&[("a", 3.0)]),
+        );
+        store.record("dev", now + Duration::seconds(10), values(&[("a", 4.0)]));
+        let path = store.dir().join("export.csv");
+        let series = [
+            "dev/a".to_string(),
+            "dev/\"b\"".to_string(),
+            "dev/b".to_string(),
+        ];
+        assert_eq!(export_csv(&store, &series, t, t + 1000, &path).unwrap(), 2);
+        let time = |offset| {
+            Local
+                .timestamp_millis_opt(t + offset)
+                .unwrap()
+                .format("%Y-%m-%d %H:%M:%S%.3f")
+                .to_string()
+        };
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!(
+                "时间,\"dev/a\",\"dev/\"\"b\"\"\",\"dev/b\"\n{},1.5,,-2\n{},3,,\n",
+                time(0),
+                time(500)
+            )
+        );
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+}
@@ -0,0 +1,407 @@
+use std::{collections::VecDeque, ops::Range};
+
+use chrono::{DateTime, Local};
+
+/// ESP-IDF 日志级别, 对应 ESP_LOGE 到 ESP_LOGV
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Verbose,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 5] = [
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Verbose,
+    ];
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'E' => Some(LogLevel::Error),
+            'W' => Some(LogLevel::Warn),
+            'I' => Some(LogLevel::Info),
+            'D' => Some(LogLevel::Debug),
+            'V' => Some(LogLevel::Verbose),
+            _ => None,
+        }
+    }
+
+    pub fn letter(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "E",
+            LogLevel::Warn => "W",
+            LogLevel::Info => "I",
+            LogLevel::Debug => "D",
+            LogLevel::Verbose => "V",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "错误",
+            LogLevel::Warn => "警告",
+            LogLevel::Info => "信息",
+            LogLevel::Debug => "调试",
+            LogLevel::Verbose => "详细",
+        }
+    }
+}
+
+/// 串口输出的一行
+///
+/// ESP-IDF 日志行的格式为 "I (1234) example: Turning the LED ON!",
+/// 时间戳是启动后的毫秒数, 开启 CONFIG_LOG_TIMESTAMP_SOURCE_SYSTEM 时为 "12:34:56.789".
+/// 其它输出 (ROM 启动信息, printf, panic) 没有级别和标签
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    /// 从 0 开始的行号
+    pub index: u64,
+    /// 主机收到这一行的时间
+    pub host_time: DateTime<Local>,
+    pub level: Option<LogLevel>,
+    /// 设备的时间戳
+    pub timestamp: String,
+    pub tag: String,
+    /// 日志行的消息, 其它输出的整行, 已去掉 ANSI 颜色
+    pub message: String,
+    /// 消息中代码地址的位置, 可能是回溯地址
+    pub addresses: Vec<Range<usize>>,
+}
+
+impl LogLine {
+    pub fn parse(index: u64, host_time: DateTime<Local>, text: &str) -> Self {
+        let text = strip_ansi(text);
+        let (level, timestamp, tag, message) = match split_log(&text) {
+            Some((level, timestamp, tag, message)) => (
+                Some(level),
+                timestamp.to_string(),
+                tag.to_string(),
+                message.to_string(),
+            ),
+            None => (None, String::new(), String::new(), text.clone()),
+        };
+        let addresses = code_addresses(&message);
+        Self {
+            index,
+            host_time,
+            level,
+            timestamp,
+            tag,
+            message,
+            addresses,
+        }
+    }
+
+    /// 是否包含搜索的文本, 不区分大小写
+    pub fn contains(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.message.to_lowercase().contains(&query) || self.tag.to_lowercase().contains(&query)
+    }
+
+    /// 恢复为设备输出的格式
+    pub fn text(&self) -> String {
+        match self.level {
+            Some(level) => format!(
+                "{} ({}) {}: {}",
+                level.letter(),
+                self.timestamp,
+                self.tag,
+                self.message
+            ),
+            None => self.message.clone(),
+        }
+    }
+}
+
+/// 拆分 "I (1234) tag: message"
+fn split_log(text: &str) -> Option<(LogLevel, &str, &str, &str)> {
+    let mut chars = text.chars();
+    let level = LogLevel::from_char(chars.next()?)?;
+    let rest = text[1..].strip_prefix(" (")?;
+    let (timestamp, rest) = rest.split_once(") ")?;
+    if timestamp.is_empty()
+        || !timestamp
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == ':' || c == '.')
+    {
+        return None;
+    }
+    let (tag, message) = rest.split_once(": ").or_else(|| rest.split_once(':'))?;
+    if tag.is_empty() {
+        return None;
+    }
+    Some((level, timestamp, tag, message))
+}
+
+/// 去掉 ANSI 转义序列, ESP-IDF 用它给日志级别着色, 如 "\x1b[0;32m"
+pub fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            result.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'[') {
+            chars.next();
+            // 参数和中间字节, 直到 0x40..=0x7E 的结束字节
+            for c in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    result
+}
+
+/// ESP32 系列的代码地址范围: ROM, IRAM 和 flash 映射的指令空间
+pub fn is_code_address(address: u32) -> bool {
+    (0x4000_0000..0x4400_0000).contains(&address)
+}
+
+/// 文本中的 0x 开头的 8 位十六进制代码地址
+/// panic 时的 "Backtrace: 0x400d1234:0x3ffb1230" 和 RISC-V 的 "MEPC    : 0x42005c1a" 都会被找到
+pub fn code_addresses(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut ranges = vec![];
+    let mut i = 0;
+    while i + 10 <= bytes.len() {
+        let boundary = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+        let end = i + 10;
+        if boundary
+            && bytes[i] == b'0'
+            && (bytes[i + 1] == b'x' || bytes[i + 1] == b'X')
+            && bytes[i + 2..end].iter().all(u8::is_ascii_hexdigit)
+            && bytes.get(end).map_or(true, |b| !b.is_ascii_hexdigit())
+        {
+            let address = u32::from_str_radix(&text[i + 2..end], 16).unwrap_or(0);
+            if is_code_address(address) {
+                ranges.push(i..end);
+            }
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    ranges
+}
+
+/// panic 输出的开始和结束, RISC-V 芯片在寄存器之后输出栈内容, 其中的数值不是回溯地址
+const PANIC_START: [&str; 2] = ["Guru Meditation Error", "abort() was called"];
+const PANIC_END: [&str; 3] = ["Stack memory", "ELF file SHA256", "Rebooting"];
+
+/// 最近一次 panic 输出中带有代码地址的行, 从 "Guru Meditation Error" 到栈内容或重启之前
+pub fn last_panic(lines: &VecDeque<LogLine>) -> Vec<&LogLine> {
+    let start = match lines
+        .iter()
+        .rposition(|l| PANIC_START.iter().any(|s| l.message.contains(s)))
+    {
+        Some(start) => start,
+        None => return vec![],
+    };
+    lines
+        .iter()
+        .skip(start)
+        .take_while(|l| !PANIC_END.iter().any(|s| l.message.contains(s)))
+        .filter(|l| !l.addresses.is_empty())
+        .collect()
+}
+
+/// 地址前面的寄存器名称, 如 "MEPC    : 0x42005c1a" 中的 MEPC, 回溯中后面的地址为空
+pub fn address_label<'a>(text: &'a str, range: &Range<usize>) -> &'a str {
+    let before = text[..range.start].trim_end();
+    let before = before.strip_suffix(':').unwrap_or(before).trim_end();
+    match before.rsplit(char::is_whitespace).next() {
+        Some(word) if !word.starts_with("0x") => word,
+        _ => "",
+    }
+}
+
+/// 回溯中第一个地址是发生异常的 PC, 之后的地址和寄存器 RA 都是返回地址
+/// 返回地址指向调用指令的下一条指令, 解析源码位置时需要减 1
+pub fn is_return_address(text: &str, range: &Range<usize>) -> bool {
+    if address_label(text, range) == "RA" {
+        return true;
+    }
+    match text[..range.start].find("Backtrace:") {
+        Some(start) => code_addresses(&text[start..])
+            .first()
+            .map_or(false, |first| start + first.start != range.start),
+        None => false,
+    }
+}
+
+/// 把串口收到的字节分成行, 兼容 \r\n, 不完整的 utf-8 留到下一次
+#[derive(Debug, Default)]
+pub struct LineSplitter {
+    buffer: Vec<u8>,
+}
+
+impl LineSplitter {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut lines = vec![];
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            lines.push(decode(&line));
+        }
+        lines
+    }
+
+    /// 一段时间没有新数据时取出不完整的行, 如等待输入的提示符
+    pub fn flush(&mut self) -> Option<String> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let line = decode(&self.buffer);
+        self.buffer.clear();
+        Some(line)
+    }
+}
+
+fn decode(line: &[u8]) -> String {
+    String::from_utf8_lossy(line)
+        .trim_end_matches(['\r', '\n'])
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn return_addresses(text: &str) -> Vec<(&str, bool)> {
+        code_addresses(text)
+            .iter()
+            .map(|range| {
+                (
+                    &text[range.start..range.end],
+                    is_return_address(text, range),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn backtrace_return_addresses() {
+        let text = "Backtrace: 0x400d1234:0x3ffb1230 0x400d5678:0x3ffb1250 0x40085abc:0x3ffb1270";
+        assert_eq!(
+            return_addresses(text),
+            vec![
+                ("0x400d1234", false),
+                ("0x400d5678", true),
+                ("0x40085abc", true)
+            ]
+        );
+        assert_eq!(
+            return_addresses("MEPC    : 0x42005c1a  RA      : 0x42005c0e  SP      : 0x3fc8f3d0"),
+            vec![("0x42005c1a", false), ("0x42005c0e", true)]
+        );
+        assert_eq!(
+            return_addresses("PC      : 0x400d1234  PS      : 0x00060b30"),
+            vec![("0x400d1234", false)]
+        );
+    }
+
+    fn parse(text: &str) -> LogLine {
+        LogLine::parse(0, Local::now(), text)
+    }
+
+    #[test]
+    fn parse_coloured_lines() {
+        let line = parse("\x1b[0;32mI (1234) example: Turning the LED ON!\x1b[0m");
+        assert_eq!(line.level, Some(LogLevel::Info));
+        assert_eq!(line.timestamp, "1234");
+        assert_eq!(line.tag, "example");
+        assert_eq!(line.message, "Turning the LED ON!");
+        assert_eq!(line.text(), "I (1234) example: Turning the LED ON!");
+
+        let line =
+            parse("\x1b[0;31mE (12:34:56.789) MB_PORT_COMMON: mb_port_event_get: error\x1b[0m");
+        assert_eq!(line.level, Some(LogLevel::Error));
+        assert_eq!(line.timestamp, "12:34:56.789");
+        assert_eq!(line.tag, "MB_PORT_COMMON");
+        assert_eq!(line.message, "mb_port_event_get: error");
+
+        let line = parse("\x1b[1;33mW (50) wifi:no AP found\x1b[0m");
+        assert_eq!(line.level, Some(LogLevel::Warn));
+        assert_eq!(
+            (line.tag.as_str(), line.message.as_str()),
+            ("wifi", "no AP found")
+        );
+    }
+
+    #[test]
+    fn parse_other_output() {
+        for text in [
+            "ESP-ROM:esp32c3-api1-20210207",
+            "I (abc) tag: not a timestamp",
+            "Info (12) tag: message",
+            "I (12) : empty tag",
+            "",
+        ] {
+            let line = parse(text);
+            assert_eq!(line.level, None, "{}", text);
+            assert_eq!(line.message, text);
+        }
+        let line = parse("Guru Meditation Error: Core  0 panic'ed (Load access fault)");
+        assert!(line.contains("guru"));
+        assert!(line.addresses.is_empty());
+    }
+
+    #[test]
+    fn strip_escape_sequences() {
+        assert_eq!(strip_ansi("\x1b[0;32mI\x1b[0m \x1b[1m(1)\x1b[K"), "I (1)");
+        assert_eq!(strip_ansi("plain"), "plain");
+        assert_eq!(strip_ansi("trailing \x1b"), "trailing ");
+    }
+
+    #[test]
+    fn find_code_addresses() {
+        let text = "MEPC    : 0x42005c1a  MTVAL   : 0x00000000 x0x40000000 0x400d12345";
+        let ranges = code_addresses(text);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(&text[ranges[0].clone()], "0x42005c1a");
+        assert_eq!(address_label(text, &ranges[0]), "MEPC");
+    }
+
+    #[test]
+    fn find_last_panic() {
+        let lines: VecDeque<LogLine> = [
+            "Guru Meditation Error: Core  0 panic'ed (old)",
+            "MEPC    : 0x42000000",
+            "I (10) boot: ok",
+            "Guru Meditation Error: Core  0 panic'ed (Load access fault)",
+            "MEPC    : 0x42005c1a  RA      : 0x42005c0e",
+            "Stack memory:",
+            "3fc8f3d0: 0x42005c0e 0x00000000",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, text)| LogLine::parse(i as u64, Local::now(), text))
+        .collect();
+        let panic: Vec<u64> = last_panic(&lines).iter().map(|l| l.index).collect();
+        assert_eq!(panic, vec![4]);
+    }
+
+    #[test]
+    fn split_lines() {
+        let mut splitter = LineSplitter::default();
+        assert_eq!(
+            splitter.push(b"I (1) a: x\r\nI (2) b: "),
+            vec!["I (1) a: x".to_string()]
+        );
+        // 中 = e4 b8 ad, 拆在两次读取中
+        assert_eq!(splitter.push(b"\xe4\xb8"), Vec::<String>::new());
+        assert_eq!(splitter.push(b"\xad\n"), vec!["I (2) b: 中".to_string()]);
+        assert!(splitter.push(b"> ").is_empty());
+        assert_eq!(splitter.flush().as_deref(), Some("> "));
+        assert_eq!(splitter.flush(), None);
+    }
+}
@@ -0,0 +1,1070 @@
+use std::{fmt, fs, path::Path};
+
+use crate::{
+    data::sdkconfig::Sdkconfig,
+    resource::error::{AppError, Result},
+};
+
+/// 默认值引用其它选项时的最大深度, 防止循环引用
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolType {
+    Bool,
+    Int,
+    Hex,
+    String,
+}
+
+impl SymbolType {
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "bool" | "tristate" => Some(SymbolType::Bool),
+            "int" => Some(SymbolType::Int),
+            "hex" => Some(SymbolType::Hex),
+            "string" => Some(SymbolType::String),
+            _ => None,
+        }
+    }
+
+    /// 没有设置也没有默认值时的值
+    fn empty_value(&self) -> String {
+        match self {
+            SymbolType::Bool => "n".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// depends on, if 和 default ... if 中的表达式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// 选项名称, y/n 和数字等常量
+    Symbol(String),
+    /// 引号中的字符串
+    Literal(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Equal(Box<Expr>, Box<Expr>),
+    NotEqual(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn and(a: Option<Expr>, b: Option<Expr>) -> Option<Expr> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(Expr::And(Box::new(a), Box::new(b))),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Or(_, _) => 1,
+            Expr::And(_, _) => 2,
+            _ => 3,
+        }
+    }
+
+    /// 出现的选项名称
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            Expr::Symbol(name) if !is_constant(name) => vec![name.as_str()],
+            Expr::Symbol(_) | Expr::Literal(_) => vec![],
+            Expr::Not(e) => e.symbols(),
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Equal(a, b) | Expr::NotEqual(a, b) => {
+                let mut symbols = a.symbols();
+                symbols.extend(b.symbols());
+                symbols
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let child = |f: &mut fmt::Formatter<'_>, e: &Expr| {
+            if e.precedence() < self.precedence() {
+                write!(f, "({})", e)
+            } else {
+                write!(f, "{}", e)
+            }
+        };
+        match self {
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::Literal(text) => write!(f, "\"{}\"", text),
+            Expr::Not(e) if e.precedence() < 3 => write!(f, "!({})", e),
+            Expr::Not(e) => write!(f, "!{}", e),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                child(f, a)?;
+                write!(
+                    f,
+                    " {} ",
+                    if let Expr::And(_, _) = self {
+                        "&&"
+                    } else {
+                        "||"
+                    }
+                )?;
+                child(f, b)
+            }
+            Expr::Equal(a, b) => write!(f, "{} = {}", a, b),
+            Expr::NotEqual(a, b) => write!(f, "{} != {}", a, b),
+        }
+    }
+}
+
+/// y, n, m 和数字是常量, 不是选项
+fn is_constant(word: &str) -> bool {
+    matches!(word, "y" | "n" | "m") || word.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultValue {
+    /// 常量或选项名称
+    pub value: Expr,
+    pub condition: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range {
+    pub min: Expr,
+    pub max: Expr,
+    pub condition: Option<Expr>,
+}
+
+/// config 或 menuconfig 定义的选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolType,
+    /// 没有提示的选项不能由用户设置
+    pub prompt: Option<String>,
+    pub defaults: Vec<DefaultValue>,
+    pub ranges: Vec<Range>,
+    /// 自身和所在 menu, if, choice 的依赖
+    pub depends: Option<Expr>,
+    pub help: String,
+    /// 所在 choice 的序号
+    pub choice: Option<usize>,
+}
+
+/// choice 中只能选择一个选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub prompt: String,
+    /// 默认选择的选项名称
+    pub defaults: Vec<DefaultValue>,
+    pub options: Vec<String>,
+    pub depends: Option<Expr>,
+    pub help: String,
+}
+
+/// 菜单树中的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Menu {
+        title: String,
+        depends: Option<Expr>,
+        children: Vec<Node>,
+    },
+    /// symbols 中的序号
+    Symbol(usize),
+    /// choices 中的序号
+    Choice(usize),
+    Comment(String),
+}
+
+/// 检查结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub error: bool,
+    pub symbol: String,
+    pub message: String,
+}
+
+/// 正在解析的 menu, if 或 choice
+struct Block {
+    keyword: &'static str,
+    title: String,
+    choice: Option<usize>,
+    depends: Option<Expr>,
+    children: Vec<Node>,
+}
+
+/// 当前属性所属的项
+#[derive(Clone, Copy)]
+enum Entry {
+    Symbol(usize),
+    Choice(usize),
+    Menu,
+    Other,
+}
+
+/// Kconfig 文件, 支持 ESP-IDF 项目 Kconfig.projbuild 常用的子集:
+/// menu, config, menuconfig, choice, if, comment, 属性 prompt, default, range, depends on, help
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Kconfig {
+    pub symbols: Vec<Symbol>,
+    pub choices: Vec<Choice>,
+    pub nodes: Vec<Node>,
+    /// 不支持而跳过的行
+    pub warnings: Vec<String>,
+}
+
+impl Kconfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut kconfig = Kconfig::default();
+        let mut blocks = vec![Block {
+            keyword: "",
+            title: String::new(),
+            choice: None,
+            depends: None,
+            children: vec![],
+        }];
+        let mut entry = Entry::Other;
+        let lines = join_lines(text);
+        let mut i = 0;
+        while i < lines.len() {
+            let (number, line) = &lines[i];
+            i += 1;
+            let error = |message: String| AppError::Error(format!("第 {} 行: {}", number, message));
+            let tokens = tokenize(line).map_err(error)?;
+            let (keyword, args) = match tokens.split_first() {
+                Some((Token::Word(keyword), args)) => (keyword.as_str(), args),
+                Some(_) => return Err(error(format!("无法解析 {}", line.trim()))),
+                None => continue,
+            };
+            let block = blocks.last_mut().unwrap();
+            match keyword {
+                "mainmenu" => {}
+                "menu" => {
+                    blocks.push(Block {
+                        keyword: "menu",
+                        title: string_arg(args).map_err(error)?,
+                        choice: None,
+                        depends: None,
+                        children: vec![],
+                    });
+                    entry = Entry::Menu;
+                }
+                "if" => {
+                    blocks.push(Block {
+                        keyword: "if",
+                        title: String::new(),
+                        choice: None,
+                        depends: Some(parse_expr(args).map_err(error)?),
+                        children: vec![],
+                    });
+                    entry = Entry::Other;
+                }
+                "choice" => {
+                    kconfig.choices.push(Choice {
+                        prompt: String::new(),
+                        defaults: vec![],
+                        options: vec![],
+                        depends: None,
+                        help: String::new(),
+                    });
+                    let index = kconfig.choices.len() - 1;
+                    blocks.push(Block {
+                        keyword: "choice",
+                        title: String::new(),
+                        choice: Some(index),
+                        depends: None,
+                        children: vec![],
+                    });
+                    entry = Entry::Choice(index);
+                }
+                "endmenu" | "endif" | "endchoice" => {
+                    let expected = &keyword[3..];
+                    if blocks.len() < 2 || blocks.last().unwrap().keyword != expected {
+                        return Err(error(format!("{} 没有对应的 {}", keyword, expected)));
+                    }
+                    let block = blocks.pop().unwrap();
+                    kconfig.add_depends(&block.children, &block.depends);
+                    let parent = &mut blocks.last_mut().unwrap().children;
+                    match block.keyword {
+                        "menu" => parent.push(Node::Menu {
+                            title: block.title,
+                            depends: block.depends,
+                            children: block.children,
+                        }),
+                        "choice" => parent.push(Node::Choice(block.choice.unwrap())),
+                        _ => parent.extend(block.children),
+                    }
+                    entry = Entry::Other;
+                }
+                "config" | "menuconfig" => {
+                    let name = match args.first() {
+                        Some(Token::Word(name)) => name.clone(),
+                        _ => return Err(error("config 后面需要名称".to_string())),
+                    };
+                    let choice = block.choice;
+                    kconfig.symbols.push(Symbol {
+                        name: name.clone(),
+                        kind: SymbolType::Bool,
+                        prompt: None,
+                        defaults: vec![],
+                        ranges: vec![],
+                        depends: None,
+                        help: String::new(),
+                        choice,
+                    });
+                    let index = kconfig.symbols.len() - 1;
+                    match choice {
+                        Some(choice) => kconfig.choices[choice].options.push(name),
+                        None => block.children.push(Node::Symbol(index)),
+                    }
+                    entry = Entry::Symbol(index);
+                }
+                "comment" => {
+                    block
+                        .children
+                        .push(Node::Comment(string_arg(args).map_err(error)?));
+                    entry = Entry::Other;
+                }
+                "source" | "rsource" | "osource" | "orsource" => {
+                    kconfig
+                        .warnings
+                        .push(format!("第 {} 行: 不支持 {}, 已跳过", number, keyword));
+                }
+                "help" | "---help---" => {
+                    let indent = indentation(line);
+                    let mut help = vec![];
+                    let mut help_indent = None;
+                    while i < lines.len() {
+                        let (_, next) = &lines[i];
+                        if next.trim().is_empty() {
+                            help.push("");
+                            i += 1;
+                            continue;
+                        }
+                        let next_indent = indentation(next);
+                        let limit = *help_indent.get_or_insert(next_indent);
+                        if next_indent <= indent || next_indent < limit {
+                            break;
+                        }
+                        help.push(next.trim());
+                        i += 1;
+                    }
+                    let help = help.join("\n").trim().to_string();
+                    match entry {
+                        Entry::Symbol(index) => kconfig.symbols[index].help = help,
+                        Entry::Choice(index) => kconfig.choices[index].help = help,
+                        _ => {}
+                    }
+                }
+                _ => {
+                    let symbol = match entry {
+                        Entry::Symbol(index) => Some(&mut kconfig.symbols[index]),
+                        _ => None,
+                    };
+                    let choice = match entry {
+                        Entry::Choice(index) => Some(&mut kconfig.choices[index]),
+                        _ => None,
+                    };
+                    match (keyword, symbol, choice) {
+                        (kind, Some(symbol), _) if SymbolType::parse(kind).is_some() => {
+                            symbol.kind = SymbolType::parse(kind).unwrap();
+                            if !args.is_empty() {
+                                symbol.prompt = Some(string_arg(args).map_err(error)?);
+                            }
+                        }
+                        ("bool" | "tristate", None, Some(choice)) => {
+                            if !args.is_empty() {
+                                choice.prompt = string_arg(args).map_err(error)?;
+                            }
+                        }
+                        ("prompt", Some(symbol), _) => {
+                            symbol.prompt = Some(string_arg(args).map_err(error)?)
+                        }
+                        ("prompt", None, Some(choice)) => {
+                            choice.prompt = string_arg(args).map_err(error)?
+                        }
+                        (
+                            "default" | "def_bool" | "def_int" | "def_hex" | "def_string",
+                            symbol,
+                            choice,
+                        ) => {
+                            let default = parse_default(args).map_err(error)?;
+                            if let Some(symbol) = symbol {
+                                if let Some(kind) = keyword.strip_prefix("def_") {
+                                    symbol.kind = SymbolType::parse(kind).unwrap_or(symbol.kind);
+                                }
+                                symbol.defaults.push(default);
+                            } else if let Some(choice) = choice {
+                                choice.defaults.push(default);
+                            }
+                        }
+                        ("range", Some(symbol), _) => {
+                            symbol.ranges.push(parse_range(args).map_err(error)?)
+                        }
+                        ("depends", symbol, choice) => {
+                            let expr = match args.split_first() {
+                                Some((Token::Word(on), expr)) if on == "on" => {
+                                    parse_expr(expr).map_err(error)?
+                                }
+                                _ => return Err(error("depends 后面需要 on".to_string())),
+                            };
+                            if let Some(symbol) = symbol {
+                                symbol.depends = Expr::and(symbol.depends.take(), Some(expr));
+                            } else if let Some(choice) = choice {
+                                choice.depends = Expr::and(choice.depends.take(), Some(expr));
+                            } else if let Entry::Menu = entry {
+                                block.depends = Expr::and(block.depends.take(), Some(expr));
+                            }
+                        }
+                        ("select" | "imply" | "option" | "visible" | "optional", _, _) => {}
+                        _ => kconfig.warnings.push(format!(
+                            "第 {} 行: 不支持 {}, 已跳过",
+                            number,
+                            line.trim()
+                        )),
+                    }
+                }
+            }
+        }
+        if blocks.len() > 1 {
+            let block = blocks.last().unwrap();
+            return Err(AppError::Error(format!("{} 没有结束", block.keyword)));
+        }
+        kconfig.nodes = blocks.pop().unwrap().children;
+        Ok(kconfig)
+    }
+
+    /// 把 menu 和 if 的依赖加到其中的选项上, 内层的 menu 结束时已经加过自己的依赖
+    fn add_depends(&mut self, nodes: &[Node], depends: &Option<Expr>) {
+        if depends.is_none() {
+            return;
+        }
+        for node in nodes {
+            match node {
+                Node::Menu { children, .. } => self.add_depends(children, depends),
+                Node::Symbol(index) => {
+                    let symbol = &mut self.symbols[*index];
+                    symbol.depends = Expr::and(depends.clone(), symbol.depends.take());
+                }
+                Node::Choice(index) => {
+                    let choice = &mut self.choices[*index];
+                    choice.depends = Expr::and(depends.clone(), choice.depends.take());
+                }
+                Node::Comment(_) => {}
+            }
+        }
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    /// 依赖是否满足
+    pub fn is_active(&self, depends: &Option<Expr>, config: &Sdkconfig) -> bool {
+        self.active(depends, config, 0)
+    }
+
+    fn active(&self, depends: &Option<Expr>, config: &Sdkconfig, depth: usize) -> bool {
+        depends
+            .as_ref()
+            .map_or(true, |e| self.eval(e, config, depth))
+    }
+
+    fn eval(&self, expr: &Expr, config: &Sdkconfig, depth: usize) -> bool {
+        match expr {
+            Expr::Symbol(_) | Expr::Literal(_) => self.term(expr, config, depth) == "y",
+            Expr::Not(e) => !self.eval(e, config, depth),
+            Expr::And(a, b) => self.eval(a, config, depth) && self.eval(b, config, depth),
+            Expr::Or(a, b) => self.eval(a, config, depth) || self.eval(b, config, depth),
+            Expr::Equal(a, b) => self.term(a, config, depth) == self.term(b, config, depth),
+            Expr::NotEqual(a, b) => self.term(a, config, depth) != self.term(b, config, depth),
+        }
+    }
+
+    fn term(&self, expr: &Expr, config: &Sdkconfig, depth: usize) -> String {
+        match expr {
+            Expr::Symbol(name) if is_constant(name) => name.clone(),
+            Expr::Symbol(name) => self.value_at(name, config, depth + 1),
+            Expr::Literal(text) => text.clone(),
+            e => if self.eval(e, config, depth) {
+                "y"
+            } else {
+                "n"
+            }
+            .to_string(),
+        }
+    }
+
+    /// 选项的最终值: 文件中的设置, 否则为满足条件的第一个默认值;
+    /// 依赖不满足时布尔选项为 n, 其它为空. 不在 Kconfig 中的选项取文件中的值
+    pub fn value(&self, name: &str, config: &Sdkconfig) -> String {
+        self.value_at(name, config, 0)
+    }
+
+    fn value_at(&self, name: &str, config: &Sdkconfig, depth: usize) -> String {
+        let symbol = match self.symbol(name) {
+            Some(symbol) => symbol,
+            None => return config.get(name).unwrap_or_default(),
+        };
+        if depth > MAX_DEPTH || !self.active(&symbol.depends, config, depth) {
+            return symbol.kind.empty_value();
+        }
+        if let Some(choice) = symbol.choice {
+            let selected = self.selected_at(choice, config, depth);
+            return if selected.as_deref() == Some(name) {
+                "y"
+            } else {
+                "n"
+            }
+            .to_string();
+        }
+        if symbol.prompt.is_some() {
+            if let Some(value) = config.get(name) {
+                return value;
+            }
+        }
+        self.default_value(&symbol.defaults, config, depth)
+            .unwrap_or_else(|| symbol.kind.empty_value())
+    }
+
+    /// 不考虑文件中的设置时的值
+    pub fn default_of(&self, symbol: &Symbol, config: &Sdkconfig) -> String {
+        self.default_value(&symbol.defaults, config, 0)
+            .unwrap_or_else(|| symbol.kind.empty_value())
+    }
+
+    fn default_value(
+        &self,
+        defaults: &[DefaultValue],
+        config: &Sdkconfig,
+        depth: usize,
+    ) -> Option<String> {
+        defaults
+            .iter()
+            .find(|d| self.active(&d.condition, config, depth))
+            .map(|d| self.term(&d.value, config, depth))
+    }
+
+    /// choice 中选择的选项: 文件中为 y 的选项, 否则为默认选项或第一个可选的选项
+    pub fn selected(&self, choice: usize, config: &Sdkconfig) -> Option<String> {
+        self.selected_at(choice, config, 0)
+    }
+
+    fn selected_at(&self, choice: usize, config: &Sdkconfig, depth: usize) -> Option<String> {
+        let choice = &self.choices[choice];
+        if !self.active(&choice.depends, config, depth) {
+            return None;
+        }
+        let available: Vec<&String> = choice
+            .options
+            .iter()
+            .filter(|name| {
+                self.symbol(name)
+                    .map_or(false, |s| self.active(&s.depends, config, depth + 1))
+            })
+            .collect();
+        let set = available
+            .iter()
+            .find(|name| config.get(name).as_deref() == Some("y"));
+        // choice 的默认值是选项名称, 不是选项的值
+        let default = || {
+            let name = match &choice
+                .defaults
+                .iter()
+                .find(|d| self.active(&d.condition, config, depth))?
+                .value
+            {
+                Expr::Symbol(name) => name,
+                _ => return None,
+            };
+            available.iter().find(|n| **n == name).copied()
+        };
+        set.copied()
+            .or_else(default)
+            .or_else(|| available.first().copied())
+            .cloned()
+    }
+
+    /// 选项当前生效的范围
+    pub fn range(&self, symbol: &Symbol, config: &Sdkconfig) -> Option<(i64, i64)> {
+        let range = symbol
+            .ranges
+            .iter()
+            .find(|r| self.is_active(&r.condition, config))?;
+        let bound = |e: &Expr| parse_number(symbol.kind, &self.term(e, config, 0));
+        Some((bound(&range.min)?, bound(&range.max)?))
+    }
+
+    /// 检查文件中的设置: 数字格式, 范围, 依赖和未知的选项
+    pub fn validate(&self, config: &Sdkconfig) -> Vec<Issue> {
+        let mut issues = vec![];
+        let mut issue = |error: bool, symbol: &str, message: String| {
+            issues.push(Issue {
+                error,
+                symbol: symbol.to_string(),
+                message,
+            })
+        };
+        for symbol in self.symbols.iter() {
+            let name = &symbol.name;
+            let set = config.contains(name);
+            let active = self.is_active(&symbol.depends, config);
+            if set && !active {
+                let depends = symbol.depends.as_ref().map(|d| d.to_string());
+                issue(
+                    false,
+                    name,
+                    format!(
+                        "{}: 依赖 {} 不满足, 设置不会生效",
+                        name,
+                        depends.unwrap_or_default()
+                    ),
+                );
+            }
+            if set && symbol.prompt.is_none() {
+                issue(false, name, format!("{}: 没有提示, 不能由用户设置", name));
+            }
+            if !active {
+                continue;
+            }
+            let value = self.value(name, config);
+            match symbol.kind {
+                SymbolType::Bool if value != "y" && value != "n" => {
+                    issue(true, name, format!("{}: {} 不是 y 或 n", name, value))
+                }
+                SymbolType::Int | SymbolType::Hex if set || !value.is_empty() => {
+                    match parse_number(symbol.kind, &value) {
+                        None => issue(true, name, format!("{}: {} 不是有效的数字", name, value)),
+                        Some(number) => {
+                            if let Some((min, max)) = self.range(symbol, config) {
+                                if number < min || number > max {
+                                    issue(
+                                        true,
+                                        name,
+                                        format!(
+                                            "{}: {} 超出范围 {} - {}",
+                                            name,
+                                            value,
+                                            format_number(symbol.kind, min),
+                                            format_number(symbol.kind, max)
+                                        ),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for name in config.names() {
+            if self.symbol(name).is_none() {
+                issue(false, name, format!("{}: 不在这个 Kconfig 中", name));
+            }
+        }
+        issues.sort_by_key(|issue| !issue.error);
+        issues
+    }
+}
+
+/// int 为十进制, hex 为 0x 开头或不带前缀的十六进制
+pub fn parse_number(kind: SymbolType, text: &str) -> Option<i64> {
+    let text = text.trim();
+    match kind {
+        SymbolType::Hex => {
+            let digits = text
+                .strip_prefix("0x")
+                .or_else(|| text.strip_prefix("0X"))
+                .unwrap_or(text);
+            i64::from_str_radix(digits, 16).ok()
+        }
+        _ => text.parse().ok(),
+    }
+}
+
+pub fn format_number(kind: SymbolType, value: i64) -> String {
+    match kind {
+        SymbolType::Hex => format!("0x{:x}", value),
+        _ => value.to_string(),
+    }
+}
+
+/// 合并以 \ 结尾的行, 保留行号
+fn join_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = vec![];
+    let mut continued = false;
+    for (i, line) in text.lines().enumerate() {
+        let (content, next) = match line.strip_suffix('\\') {
+            Some(content) => (content, true),
+            None => (line, false),
+        };
+        match lines.last_mut() {
+            Some((_, last)) if continued => last.push_str(content),
+            _ => lines.push((i + 1, content.to_string())),
+        }
+        continued = next;
+    }
+    lines
+}
+
+/// 缩进宽度, tab 按 8 个字符计算
+fn indentation(line: &str) -> usize {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width = (width / 8 + 1) * 8,
+            _ => break,
+        }
+    }
+    width
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    String(String),
+    Operator(&'static str),
+}
+
+fn tokenize(line: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '#' => break,
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => text.extend(chars.next()),
+                        Some(q) if q == c => break,
+                        Some(ch) => text.push(ch),
+                        None => return Err("字符串没有结束".to_string()),
+                    }
+                }
+                tokens.push(Token::String(text));
+            }
+            '&' | '|' | '!' | '=' | '(' | ')' => {
+                chars.next();
+                let operator = match (c, chars.peek()) {
+                    ('&', Some('&')) => "&&",
+                    ('|', Some('|')) => "||",
+                    ('!', Some('=')) => "!=",
+                    ('!', _) => "!",
+                    ('=', _) => "=",
+                    ('(', _) => "(",
+                    (')', _) => ")",
+                    _ => return Err(format!("无法识别的符号 {}", c)),
+                };
+                if operator.len() == 2 {
+                    chars.next();
+                }
+                tokens.push(Token::Operator(operator));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || "#\"'&|!=()".contains(ch) {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// menu "标题", comment "文本" 和提示的参数, 忽略后面的 if 条件
+fn string_arg(args: &[Token]) -> std::result::Result<String, String> {
+    match args.first() {
+        Some(Token::String(text)) => Ok(text.clone()),
+        _ => Err("需要引号中的文本".to_string()),
+    }
+}
+
+/// 拆分 "... if 条件"
+fn split_if(args: &[Token]) -> std::result::Result<(&[Token], Option<Expr>), String> {
+    match args
+        .iter()
+        .position(|t| matches!(t, Token::Word(w) if w == "if"))
+    {
+        Some(pos) => Ok((&args[..pos], Some(parse_expr(&args[pos + 1..])?))),
+        None => Ok((args, None)),
+    }
+}
+
+fn parse_default(args: &[Token]) -> std::result::Result<DefaultValue, String> {
+    let (value, condition) = split_if(args)?;
+    Ok(DefaultValue {
+        value: parse_expr(value)?,
+        condition,
+    })
+}
+
+fn parse_range(args: &[Token]) -> std::result::Result<Range, String> {
+    let (bounds, condition) = split_if(args)?;
+    match bounds {
+        [min, max] => Ok(Range {
+            min: parse_expr(std::slice::from_ref(min))?,
+            max: parse_expr(std::slice::from_ref(max))?,
+            condition,
+        }),
+        _ => Err("range 需要最小值和最大值".to_string()),
+    }
+}
+
+fn parse_expr(tokens: &[Token]) -> std::result::Result<Expr, String> {
+    let mut parser = ExprParser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    if parser.pos < tokens.len() {
+        return Err("表达式后面有多余的内容".to_string());
+    }
+    Ok(expr)
+}
+
+/// 表达式的递归下降解析, 优先级: || < && < ! < = !=
+struct ExprParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn accept(&mut self, operator: &'static str) -> bool {
+        let found = self.tokens.get(self.pos) == Some(&Token::Operator(operator));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.accept("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.accept("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> std::result::Result<Expr, String> {
+        if self.accept("!") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> std::result::Result<Expr, String> {
+        if self.accept("(") {
+            let expr = self.or()?;
+            if !self.accept(")") {
+                return Err("缺少 )".to_string());
+            }
+            return Ok(expr);
+        }
+        let left = self.term()?;
+        if self.accept("=") {
+            Ok(Expr::Equal(Box::new(left), Box::new(self.term()?)))
+        } else if self.accept("!=") {
+            Ok(Expr::NotEqual(Box::new(left), Box::new(self.term()?)))
+        } else {
+            Ok(left)
+        }
+    }
+
+    fn term(&mut self) -> std::result::Result<Expr, String> {
+        let expr = match self.tokens.get(self.pos) {
+            Some(Token::Word(word)) => Expr::Symbol(word.clone()),
+            Some(Token::String(text)) => Expr::Literal(text.clone()),
+            _ => return Err("缺少选项或常量".to_string()),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// modbus-rtu-example 固件的配置
+    const KCONFIG: &str = include_str!("../../../modbus-rtu-example/main/Kconfig.projbuild");
+    const DEFAULTS: &str = include_str!("../../../modbus-rtu-example/sdkconfig.defaults");
+
+    fn kconfig() -> Kconfig {
+        Kconfig::parse(KCONFIG).unwrap()
+    }
+
+    /// ESP32-C3 目标的 sdkconfig.defaults
+    fn esp32c3() -> Sdkconfig {
+        let mut config = Sdkconfig::parse(DEFAULTS);
+        config.set_raw("IDF_TARGET_ESP32C3", "y");
+        config
+    }
+
+    #[test]
+    fn parse_project_kconfig() {
+        let kconfig = kconfig();
+        assert!(kconfig.warnings.is_empty(), "{:?}", kconfig.warnings);
+        match &kconfig.nodes[..] {
+            [Node::Menu {
+                title, children, ..
+            }] => {
+                assert_eq!(title, "Example Configuration");
+                assert_eq!(children.len(), 11);
+            }
+            nodes => panic!("{:?}", nodes),
+        }
+        assert_eq!(kconfig.symbols.len(), 13);
+        assert_eq!(kconfig.choices.len(), 2);
+        assert_eq!(
+            kconfig.choices[0].options,
+            vec!["BLINK_LED_GPIO", "BLINK_LED_RMT"]
+        );
+
+        let period = kconfig.symbol("BLINK_PERIOD").unwrap();
+        assert_eq!(period.kind, SymbolType::Int);
+        assert_eq!(period.prompt.as_deref(), Some("Blink period in ms"));
+        assert_eq!(period.help, "Define the blinking period in milliseconds.");
+        let channel = kconfig.symbol("BLINK_LED_RMT_CHANNEL").unwrap();
+        assert_eq!(
+            channel.depends,
+            Some(Expr::Symbol("BLINK_LED_RMT".to_string()))
+        );
+        let gpio = kconfig.symbol("BLINK_GPIO").unwrap();
+        assert_eq!(
+            gpio.defaults[0].condition.as_ref().unwrap().to_string(),
+            "IDF_TARGET_ESP32C3 || IDF_TARGET_ESP32H2"
+        );
+        let rtu = kconfig.symbol("MB_COMM_MODE_RTU").unwrap();
+        assert_eq!(rtu.choice, Some(1));
+    }
+
+    #[test]
+    fn conditional_defaults_and_ranges() {
+        let kconfig = kconfig();
+        let defaults = Sdkconfig::parse(DEFAULTS);
+        assert_eq!(kconfig.value("BLINK_GPIO", &defaults), "5");
+        assert_eq!(kconfig.value("MB_UART_BAUD_RATE", &defaults), "115200");
+        // 没有目标芯片时条件范围都不生效
+        let rxd = kconfig.symbol("MB_UART_RXD").unwrap();
+        assert_eq!(kconfig.range(rxd, &defaults), None);
+
+        let config = esp32c3();
+        assert_eq!(kconfig.value("BLINK_GPIO", &config), "8");
+        assert_eq!(kconfig.range(rxd, &config), Some((0, 34)));
+        let gpio = kconfig.symbol("BLINK_GPIO").unwrap();
+        assert_eq!(kconfig.default_of(gpio, &config), "8");
+        assert_eq!(kconfig.range(gpio, &config), Some((0, 48)));
+    }
+
+    #[test]
+    fn choice_and_depends() {
+        let kconfig = kconfig();
+        let mut config = esp32c3();
+        assert_eq!(
+            kconfig.selected(0, &config).as_deref(),
+            Some("BLINK_LED_RMT")
+        );
+        assert_eq!(kconfig.value("BLINK_LED_RMT", &config), "y");
+        assert_eq!(kconfig.value("BLINK_LED_RMT_CHANNEL", &config), "0");
+
+        config.set_raw("BLINK_LED_GPIO", "y");
+        assert_eq!(
+            kconfig.selected(0, &config).as_deref(),
+            Some("BLINK_LED_GPIO")
+        );
+        assert_eq!(kconfig.value("BLINK_LED_RMT", &config), "n");
+        // 依赖不满足时没有值
+        assert_eq!(kconfig.value("BLINK_LED_RMT_CHANNEL", &config), "");
+
+        // 通信模式的选项依赖 esp-modbus 组件中的选项
+        assert_eq!(kconfig.selected(1, &config), None);
+        config.set_raw("FMB_COMM_MODE_RTU_EN", "y");
+        assert_eq!(
+            kconfig.selected(1, &config).as_deref(),
+            Some("MB_COMM_MODE_RTU")
+        );
+    }
+
+    #[test]
+    fn validate_sdkconfig_defaults() {
+        let kconfig = kconfig();
+        let mut config = esp32c3();
+        let issues = kconfig.validate(&config);
+        assert!(issues.iter().all(|i| !i.error), "{:?}", issues);
+        let warning = |symbol: &str| {
+            issues
+                .iter()
+                .find(|i| i.symbol == symbol)
+                .map(|i| i.message.clone())
+        };
+        assert!(warning("MB_COMM_MODE_RTU")
+            .unwrap()
+            .contains("依赖 FMB_COMM_MODE_RTU_EN 不满足"));
+        assert!(warning("FMB_TIMER_GROUP")
+            .unwrap()
+            .contains("不在这个 Kconfig 中"));
+        assert_eq!(warning("MB_UART_RXD"), None);
+
+        config.set_raw("MB_UART_RXD", "40");
+        config.set_raw("MB_SLAVE_ADDR", "0x10");
+        config.set_raw("BLINK_LED_RMT_CHANNEL", "8");
+        let errors: Vec<String> = kconfig
+            .validate(&config)
+            .into_iter()
+            .filter(|i| i.error)
+            .map(|i| i.message)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "BLINK_LED_RMT_CHANNEL: 8 超出范围 0 - 7",
+                "MB_UART_RXD: 40 超出范围 0 - 34",
+                "MB_SLAVE_ADDR: 0x10 不是有效的数字",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_expressions_and_errors() {
+        let kconfig = Kconfig::parse(
+            "config A\n    bool \"a\"\n\
+             config B\n    hex \"b\"\n    depends on A && !(C = \"x\" || D = 2)\n    range 0x10 0xff\n    default 0x20\n",
+        )
+        .unwrap();
+        let b = kconfig.symbol("B").unwrap();
+        assert_eq!(
+            b.depends.as_ref().unwrap().to_string(),
+            "A && !(C = \"x\" || D = 2)"
+        );
+        let mut config = Sdkconfig::parse("CONFIG_A=y\n");
+        assert_eq!(kconfig.value("B", &config), "0x20");
+        config.set_raw("C", "\"x\"");
+        assert_eq!(kconfig.value("B", &config), "");
+        assert_eq!(parse_number(SymbolType::Hex, "ff"), Some(255));
+        assert_eq!(format_number(SymbolType::Hex, 255), "0xff");
+
+        assert!(Kconfig::parse("config A\n    bool \"a\n")
+            .unwrap_err()
+            .to_string()
+            .contains("第 2 行"));
+    }
+}
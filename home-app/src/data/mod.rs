@@ -1,2 +1,18 @@
+pub mod alert;
+pub mod app_data;
+pub mod audit;
+pub mod codegen;
+pub mod command_queue;
+pub mod device;
+pub mod elf_symbols;
+pub mod esp_image;
+pub mod history;
+pub mod idf_log;
+pub mod kconfig;
+pub mod modbus_device;
+pub mod partition_table;
+pub mod recipe;
+pub mod register_map;
+pub mod sdkconfig;
+pub mod shadow;
 pub mod storage;
-pub mod app_data;
\ No newline at end of file
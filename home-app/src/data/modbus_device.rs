@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::register_map::{Area, Order, RegisterMap},
+    service::modbus::TransportConfig,
+};
+
+/// 一个 Modbus 从站: 连接方式, 从站地址和使用的寄存器表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModbusDevice {
+    pub name: String,
+    pub transport: TransportConfig,
+    pub slave: u8,
+    /// 寄存器表名称
+    pub map: String,
+    /// 轮询间隔, 单位: 毫秒
+    pub poll_interval: u64,
+    /// 寄存器表中未配置字节顺序的 32 和 64 位字段使用的字节顺序
+    #[serde(default)]
+    pub order: Order,
+    /// 按字段名覆盖寄存器表中的字节顺序
+    #[serde(default)]
+    pub field_orders: BTreeMap<String, Order>,
+}
+
+impl Default for ModbusDevice {
+    fn default() -> Self {
+        Self {
+            name: "modbus-rtu-example".into(),
+            transport: TransportConfig::default(),
+            slave: 1,
+            map: "modbus-rtu-example".into(),
+            poll_interval: 1000,
+            order: Order::default(),
+            field_orders: BTreeMap::new(),
+        }
+    }
+}
+
+impl ModbusDevice {
+    /// 应用设备的字节顺序后的寄存器表
+    /// 优先级: 设备的字段配置, 寄存器表的字段配置, 设备的默认值
+    pub fn resolve_map(&self, map: &RegisterMap) -> RegisterMap {
+        let mut map = map.clone();
+        for area in Area::ALL {
+            for field in map.fields_mut(area) {
+                if let Some(order) = self.field_orders.get(&field.name) {
+                    field.order = Some(*order);
+                }
+            }
+        }
+        map.with_default_order(self.order)
+    }
+}
@@ -0,0 +1,736 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+use crate::resource::error::{AppError, Result};
+
+/// 分区表在 flash 中的默认位置, 由 CONFIG_PARTITION_TABLE_OFFSET 修改
+pub const DEFAULT_TABLE_OFFSET: u32 = 0x8000;
+/// 二进制分区表的长度, 包括 MD5 条目和填充的 0xFF
+pub const TABLE_LEN: usize = 0xc00;
+const ENTRY_LEN: usize = 32;
+const ENTRY_MAGIC: [u8; 2] = [0xaa, 0x50];
+const MD5_MAGIC: [u8; 2] = [0xeb, 0xeb];
+const MAX_ENTRIES: usize = TABLE_LEN / ENTRY_LEN - 1;
+/// 应用分区需要按 64K 对齐, 其它分区按 4K 对齐
+const APP_ALIGN: u32 = 0x10000;
+const DATA_ALIGN: u32 = 0x1000;
+const MAX_NAME_LEN: usize = 15;
+
+pub const TYPE_APP: u8 = 0x00;
+pub const TYPE_DATA: u8 = 0x01;
+pub const SUBTYPE_OTA_0: u8 = 0x10;
+pub const SUBTYPE_TEST: u8 = 0x20;
+pub const SUBTYPE_OTADATA: u8 = 0x00;
+pub const SUBTYPE_NVS: u8 = 0x02;
+
+const APP_SUBTYPES: [(&str, u8); 18] = [
+    ("factory", 0x00),
+    ("ota_0", 0x10),
+    ("ota_1", 0x11),
+    ("ota_2", 0x12),
+    ("ota_3", 0x13),
+    ("ota_4", 0x14),
+    ("ota_5", 0x15),
+    ("ota_6", 0x16),
+    ("ota_7", 0x17),
+    ("ota_8", 0x18),
+    ("ota_9", 0x19),
+    ("ota_10", 0x1a),
+    ("ota_11", 0x1b),
+    ("ota_12", 0x1c),
+    ("ota_13", 0x1d),
+    ("ota_14", 0x1e),
+    ("ota_15", 0x1f),
+    ("test", 0x20),
+];
+
+const DATA_SUBTYPES: [(&str, u8); 11] = [
+    ("ota", 0x00),
+    ("phy", 0x01),
+    ("nvs", 0x02),
+    ("coredump", 0x03),
+    ("nvs_keys", 0x04),
+    ("efuse", 0x05),
+    ("undefined", 0x06),
+    ("esphttpd", 0x80),
+    ("fat", 0x81),
+    ("spiffs", 0x82),
+    ("littlefs", 0x83),
+];
+
+/// ESP-IDF 自带的分区表: 名称, csv
+pub const TEMPLATES: [(&str, &str); 2] = [
+    (
+        "单个应用",
+        "nvs, data, nvs, 0x9000, 0x6000,\n\
+         phy_init, data, phy, 0xf000, 0x1000,\n\
+         factory, app, factory, 0x10000, 1M,\n",
+    ),
+    (
+        "两个 OTA",
+        "nvs, data, nvs, 0x9000, 0x4000,\n\
+         otadata, data, ota, 0xd000, 0x2000,\n\
+         phy_init, data, phy, 0xf000, 0x1000,\n\
+         factory, app, factory, 0x10000, 1M,\n\
+         ota_0, app, ota_0, 0x110000, 1M,\n\
+         ota_1, app, ota_1, 0x210000, 1M,\n",
+    ),
+];
+
+/// 类型的已知子类型名称
+pub fn subtypes(kind: u8) -> &'static [(&'static str, u8)] {
+    match kind {
+        TYPE_APP => &APP_SUBTYPES,
+        TYPE_DATA => &DATA_SUBTYPES,
+        _ => &[],
+    }
+}
+
+pub fn type_name(kind: u8) -> String {
+    match kind {
+        TYPE_APP => "app".to_string(),
+        TYPE_DATA => "data".to_string(),
+        _ => format!("0x{:02x}", kind),
+    }
+}
+
+pub fn subtype_name(kind: u8, subtype: u8) -> String {
+    subtypes(kind)
+        .iter()
+        .find(|(_, value)| *value == subtype)
+        .map_or_else(
+            || format!("0x{:02x}", subtype),
+            |(name, _)| name.to_string(),
+        )
+}
+
+/// 分区地址的对齐要求
+pub fn alignment(kind: u8) -> u32 {
+    if kind == TYPE_APP {
+        APP_ALIGN
+    } else {
+        DATA_ALIGN
+    }
+}
+
+/// 向上对齐到类型要求的地址, 超出 32 位地址空间时为 None
+pub fn align_up(address: u32, kind: u8) -> Option<u32> {
+    let align = alignment(kind);
+    Some(address.checked_add(align - 1)? / align * align)
+}
+
+/// 解析 "0x10000", "65536", "64K", "1M"
+pub fn parse_size(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let (number, unit) = match text.chars().last()? {
+        'k' | 'K' => (&text[..text.len() - 1], 1024),
+        'm' | 'M' => (&text[..text.len() - 1], 1024 * 1024),
+        _ => (text, 1),
+    };
+    let number = number.trim();
+    let value = match number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => number.parse().ok()?,
+    };
+    value.checked_mul(unit)
+}
+
+/// 能整除时写为 "1M", "24K", 否则为十六进制
+pub fn format_size(size: u32) -> String {
+    if size > 0 && size % (1024 * 1024) == 0 {
+        format!("{}M", size / (1024 * 1024))
+    } else if size > 0 && size % 1024 == 0 {
+        format!("{}K", size / 1024)
+    } else {
+        format!("0x{:x}", size)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Partition {
+    /// 最长 15 个字符
+    pub name: String,
+    pub kind: u8,
+    pub subtype: u8,
+    pub offset: u32,
+    pub size: u32,
+    pub encrypted: bool,
+    pub readonly: bool,
+}
+
+impl Partition {
+    pub fn end(&self) -> u32 {
+        self.offset.saturating_add(self.size)
+    }
+
+    /// ota_0 到 ota_15 的序号
+    pub fn ota_slot(&self) -> Option<u8> {
+        (self.kind == TYPE_APP && (SUBTYPE_OTA_0..SUBTYPE_TEST).contains(&self.subtype))
+            .then(|| self.subtype - SUBTYPE_OTA_0)
+    }
+
+    fn flags(&self) -> u32 {
+        self.encrypted as u32 | (self.readonly as u32) << 1
+    }
+}
+
+/// flash 的大小和分区表的位置, 从 sdkconfig 中读取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlashLayout {
+    pub flash_size: u32,
+    pub table_offset: u32,
+}
+
+impl Default for FlashLayout {
+    fn default() -> Self {
+        Self {
+            flash_size: 4 * 1024 * 1024,
+            table_offset: DEFAULT_TABLE_OFFSET,
+        }
+    }
+}
+
+impl FlashLayout {
+    /// 读取 CONFIG_ESPTOOLPY_FLASHSIZE 和 CONFIG_PARTITION_TABLE_OFFSET, 没有的项保持默认值
+    pub fn from_sdkconfig(text: &str) -> Self {
+        let mut layout = Self::default();
+        for line in text.lines() {
+            let (key, value) = match line.trim().split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim().trim_matches('"')),
+                None => continue,
+            };
+            match key {
+                "CONFIG_ESPTOOLPY_FLASHSIZE" => {
+                    if let Some(size) = value.strip_suffix('B').and_then(parse_size) {
+                        layout.flash_size = size;
+                    }
+                }
+                "CONFIG_PARTITION_TABLE_OFFSET" => {
+                    if let Some(offset) = parse_size(value) {
+                        layout.table_offset = offset;
+                    }
+                }
+                _ => {}
+            }
+        }
+        layout
+    }
+
+    /// 分区表之后第一个可用的地址
+    pub fn first_free(&self) -> u32 {
+        self.table_offset.saturating_add(DATA_ALIGN)
+    }
+}
+
+/// 检查结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub error: bool,
+    /// 有问题的分区的序号
+    pub partition: Option<usize>,
+    pub message: String,
+}
+
+/// ESP-IDF 分区表, 与 gen_esp32part.py 的 csv 和二进制格式相同
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionTable {
+    pub partitions: Vec<Partition>,
+}
+
+impl PartitionTable {
+    /// 按文件内容识别 csv 或二进制格式
+    pub fn load(path: impl AsRef<Path>, layout: &FlashLayout) -> Result<Self> {
+        let data = fs::read(path)?;
+        if data.starts_with(&ENTRY_MAGIC) {
+            Self::from_binary(&data)
+        } else {
+            Self::from_csv(&String::from_utf8_lossy(&data), layout)
+        }
+    }
+
+    /// 扩展名为 .bin 时保存为二进制, 否则为 csv
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if path.extension().map_or(false, |e| e == "bin") {
+            fs::write(path, self.to_binary()?)?;
+        } else {
+            fs::write(path, self.to_csv())?;
+        }
+        Ok(())
+    }
+
+    /// 解析 csv, 没有地址的分区排在前一个分区之后
+    pub fn from_csv(text: &str, layout: &FlashLayout) -> Result<Self> {
+        let mut partitions = vec![];
+        let mut next = layout.first_free();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| AppError::Error(format!("第 {} 行: {}", i + 1, message));
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() < 5 {
+                return Err(error("至少需要名称, 类型, 子类型, 地址和大小".to_string()));
+            }
+            let kind = match fields[1] {
+                "app" => TYPE_APP,
+                "data" => TYPE_DATA,
+                text => parse_number(text).ok_or_else(|| error(format!("未知类型 {}", text)))?,
+            };
+            let subtype = subtypes(kind)
+                .iter()
+                .find(|(name, _)| *name == fields[2])
+                .map(|(_, value)| *value)
+                .or_else(|| parse_number(fields[2]))
+                .ok_or_else(|| error(format!("未知子类型 {}", fields[2])))?;
+            let offset = match fields[3] {
+                "" => align_up(next, kind).ok_or_else(|| {
+                    error(format!("前一个分区的结束地址 0x{:x} 之后没有空间", next))
+                })?,
+                text => parse_size(text).ok_or_else(|| error(format!("地址 {} 无效", text)))?,
+            };
+            let size =
+                parse_size(fields[4]).ok_or_else(|| error(format!("大小 {} 无效", fields[4])))?;
+            let mut partition = Partition {
+                name: fields[0].to_string(),
+                kind,
+                subtype,
+                offset,
+                size,
+                encrypted: false,
+                readonly: false,
+            };
+            for flag in fields.get(5).unwrap_or(&"").split(':').map(str::trim) {
+                match flag {
+                    "" => {}
+                    "encrypted" => partition.encrypted = true,
+                    "readonly" => partition.readonly = true,
+                    flag => return Err(error(format!("未知标志 {}", flag))),
+                }
+            }
+            next = partition.end();
+            partitions.push(partition);
+        }
+        Ok(Self { partitions })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut text = String::from("# Name,   Type, SubType, Offset,  Size, Flags\n");
+        for p in self.partitions.iter() {
+            let flags: Vec<&str> = [(p.encrypted, "encrypted"), (p.readonly, "readonly")]
+                .into_iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, name)| name)
+                .collect();
+            text.push_str(&format!(
+                "{}, {}, {}, 0x{:x}, {},",
+                p.name,
+                type_name(p.kind),
+                subtype_name(p.kind, p.subtype),
+                p.offset,
+                format_size(p.size),
+            ));
+            if !flags.is_empty() {
+                text.push(' ');
+                text.push_str(&flags.join(":"));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// 解析二进制分区表, 有 MD5 条目时检查 MD5
+    pub fn from_binary(data: &[u8]) -> Result<Self> {
+        let mut partitions = vec![];
+        for (i, entry) in data
+            .chunks_exact(ENTRY_LEN)
+            .take(MAX_ENTRIES + 1)
+            .enumerate()
+        {
+            if entry[..2] == MD5_MAGIC {
+                let calculated = Md5::digest(&data[..i * ENTRY_LEN]);
+                if calculated.as_slice() != &entry[16..] {
+                    return Err(AppError::Error("分区表的 MD5 不正确".to_string()));
+                }
+                break;
+            }
+            if entry[..2] != ENTRY_MAGIC {
+                break;
+            }
+            let name = &entry[12..28];
+            let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            let flags = u32_at(entry, 28);
+            partitions.push(Partition {
+                name: String::from_utf8_lossy(&name[..end]).to_string(),
+                kind: entry[2],
+                subtype: entry[3],
+                offset: u32_at(entry, 4),
+                size: u32_at(entry, 8),
+                encrypted: flags & 1 != 0,
+                readonly: flags & 2 != 0,
+            });
+        }
+        if partitions.is_empty() {
+            return Err(AppError::Error(
+                "不是分区表, 没有 0xAA50 开头的条目".to_string(),
+            ));
+        }
+        Ok(Self { partitions })
+    }
+
+    /// 生成 0xC00 字节的二进制分区表, 以 MD5 条目结束
+    pub fn to_binary(&self) -> Result<Vec<u8>> {
+        if self.partitions.len() > MAX_ENTRIES {
+            return Err(AppError::Error(format!("最多 {} 个分区", MAX_ENTRIES)));
+        }
+        let mut data = Vec::with_capacity(TABLE_LEN);
+        for p in self.partitions.iter() {
+            if p.name.len() > MAX_NAME_LEN {
+                return Err(AppError::Error(format!("分区名称 {} 太长", p.name)));
+            }
+            let mut name = [0u8; 16];
+            name[..p.name.len()].copy_from_slice(p.name.as_bytes());
+            data.extend_from_slice(&ENTRY_MAGIC);
+            data.push(p.kind);
+            data.push(p.subtype);
+            data.extend_from_slice(&p.offset.to_le_bytes());
+            data.extend_from_slice(&p.size.to_le_bytes());
+            data.extend_from_slice(&name);
+            data.extend_from_slice(&p.flags().to_le_bytes());
+        }
+        let md5 = Md5::digest(&data);
+        data.extend_from_slice(&MD5_MAGIC);
+        data.extend_from_slice(&[0xff; 14]);
+        data.extend_from_slice(&md5);
+        data.resize(TABLE_LEN, 0xff);
+        Ok(data)
+    }
+
+    /// 按当前顺序重新分配地址, 从分区表之后开始, 满足对齐要求
+    /// 地址超出 32 位时返回错误, 不修改分区
+    pub fn arrange(&mut self, layout: &FlashLayout) -> Result<()> {
+        let mut offsets = Vec::with_capacity(self.partitions.len());
+        let mut next = layout.first_free();
+        for p in self.partitions.iter() {
+            let offset = align_up(next, p.kind)
+                .filter(|offset| offset.checked_add(p.size).is_some())
+                .ok_or_else(|| AppError::Error(format!("{}: 地址超出 32 位地址空间", p.name)))?;
+            offsets.push(offset);
+            next = offset + p.size;
+        }
+        for (p, offset) in self.partitions.iter_mut().zip(offsets) {
+            p.offset = offset;
+        }
+        Ok(())
+    }
+
+    /// 检查对齐, 重叠, OTA 分区和 flash 大小, 错误在前
+    pub fn validate(&self, layout: &FlashLayout) -> Vec<Issue> {
+        let mut issues = Issues::default();
+        let table_end = layout.first_free();
+        let mut names = BTreeMap::new();
+        for (i, p) in self.partitions.iter().enumerate() {
+            let name = &p.name;
+            if name.is_empty() || name.len() > MAX_NAME_LEN {
+                issues.error(i, format!("{}: 名称需要 1 到 15 个字符", name));
+            }
+            if let Some(first) = names.insert(name.clone(), i) {
+                issues.error(i, format!("{}: 与第 {} 个分区同名", name, first + 1));
+            }
+            let align = alignment(p.kind);
+            if p.offset % align != 0 {
+                issues.error(
+                    i,
+                    format!("{}: 地址 0x{:x} 没有按 0x{:x} 对齐", name, p.offset, align),
+                );
+            }
+            if p.size == 0 || p.size % DATA_ALIGN != 0 {
+                issues.error(i, format!("{}: 大小需要是 4K 的整数倍", name));
+            }
+            if p.offset < table_end {
+                issues.error(
+                    i,
+                    format!(
+                        "{}: 与 bootloader 或 0x{:x} 处的分区表重叠",
+                        name, layout.table_offset
+                    ),
+                );
+            }
+            if p.end() > layout.flash_size {
+                issues.error(
+                    i,
+                    format!(
+                        "{}: 结束地址 0x{:x} 超出 flash 大小 {}",
+                        name,
+                        p.end(),
+                        format_size(layout.flash_size)
+                    ),
+                );
+            }
+            for (j, other) in self.partitions.iter().enumerate().skip(i + 1) {
+                if p.offset < other.end() && other.offset < p.end() {
+                    issues.error(j, format!("{}: 与 {} 重叠", other.name, name));
+                }
+            }
+            if p.kind == TYPE_DATA && p.subtype == SUBTYPE_NVS && p.size < 3 * DATA_ALIGN {
+                issues.warning(i, format!("{}: nvs 分区至少需要 12K", name));
+            }
+        }
+
+        let apps: Vec<usize> = self.indices(|p| p.kind == TYPE_APP);
+        if apps.is_empty() {
+            issues.0.push(Issue {
+                error: true,
+                partition: None,
+                message: "没有应用分区".to_string(),
+            });
+        }
+        let mut subtypes = BTreeMap::new();
+        for i in apps.iter() {
+            let p = &self.partitions[*i];
+            if let Some(first) = subtypes.insert(p.subtype, *i) {
+                let first = &self.partitions[first].name;
+                issues.error(*i, format!("{}: 与 {} 的子类型相同", p.name, first));
+            }
+        }
+
+        // OTA 需要一个 8K 的 otadata 分区记录当前启动的分区
+        let otadata = self.indices(|p| p.kind == TYPE_DATA && p.subtype == SUBTYPE_OTADATA);
+        for (n, i) in otadata.iter().enumerate() {
+            let p = &self.partitions[*i];
+            if n > 0 {
+                issues.error(*i, format!("{}: 只能有一个 otadata 分区", p.name));
+            }
+            if p.size != 2 * DATA_ALIGN {
+                issues.error(*i, format!("{}: otadata 分区需要 8K", p.name));
+            }
+        }
+        let slots = self.indices(|p| p.ota_slot().is_some());
+        match (slots.first(), otadata.first()) {
+            (Some(slot), None) => issues.error(
+                *slot,
+                "有 OTA 分区但没有 otadata 分区, 无法切换".to_string(),
+            ),
+            (None, Some(otadata)) => {
+                issues.warning(*otadata, "有 otadata 分区但没有 OTA 分区".to_string())
+            }
+            _ => {}
+        }
+        if slots.len() == 1 {
+            issues.warning(
+                slots[0],
+                "只有一个 OTA 分区, 升级时没有另一个分区可以写入".to_string(),
+            );
+        }
+        for n in 0..slots.len() {
+            let numbered = slots
+                .iter()
+                .any(|i| self.partitions[*i].ota_slot() == Some(n as u8));
+            if !numbered {
+                issues.warning(
+                    slots[n],
+                    format!("OTA 分区没有从 ota_0 开始连续编号, 缺少 ota_{}", n),
+                );
+                break;
+            }
+        }
+        for i in slots.iter().skip(1) {
+            let (p, first) = (&self.partitions[*i], &self.partitions[slots[0]]);
+            if p.size != first.size {
+                issues.warning(
+                    *i,
+                    format!(
+                        "{}: 大小与 {} 不同, 固件需要能放入最小的 OTA 分区",
+                        p.name, first.name
+                    ),
+                );
+            }
+        }
+
+        let mut issues = issues.0;
+        issues.sort_by_key(|issue| !issue.error);
+        issues
+    }
+
+    fn indices(&self, filter: impl Fn(&Partition) -> bool) -> Vec<usize> {
+        self.partitions
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| filter(p))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Issues(Vec<Issue>);
+
+impl Issues {
+    fn error(&mut self, partition: usize, message: String) {
+        self.0.push(Issue {
+            error: true,
+            partition: Some(partition),
+            message,
+        });
+    }
+
+    fn warning(&mut self, partition: usize, message: String) {
+        self.0.push(Issue {
+            error: false,
+            partition: Some(partition),
+            message,
+        });
+    }
+}
+
+fn parse_number(text: &str) -> Option<u8> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_ota() -> PartitionTable {
+        PartitionTable::from_csv(TEMPLATES[1].1, &FlashLayout::default()).unwrap()
+    }
+
+    #[test]
+    fn align_addresses() {
+        assert_eq!(align_up(0x9000, TYPE_DATA), Some(0x9000));
+        assert_eq!(align_up(0x9001, TYPE_DATA), Some(0xa000));
+        assert_eq!(align_up(0x9000, TYPE_APP), Some(0x10000));
+        assert_eq!(align_up(0xffff_f000, TYPE_DATA), Some(0xffff_f000));
+        assert_eq!(align_up(0xffff_f001, TYPE_DATA), None);
+        assert_eq!(align_up(0xffff_0000, TYPE_APP), Some(0xffff_0000));
+        assert_eq!(align_up(0xffff_0001, TYPE_APP), None);
+        assert_eq!(align_up(u32::MAX, TYPE_DATA), None);
+    }
+
+    #[test]
+    fn parse_csv() {
+        let csv = "# Name, Type, SubType, Offset, Size, Flags\n\
+                   nvs,      data, nvs,     ,        24K,\n\
+                   phy_init, data, phy,     ,        4K,   readonly\n\
+                   factory,  app,  factory, ,        1M,   encrypted:readonly # 注释\n\
+                   storage,  0x40, 0x1,     0x200000, 0x10000\n";
+        let table = PartitionTable::from_csv(csv, &FlashLayout::default()).unwrap();
+        let layout: Vec<(&str, u8, u8, u32, u32)> = table
+            .partitions
+            .iter()
+            .map(|p| (p.name.as_str(), p.kind, p.subtype, p.offset, p.size))
+            .collect();
+        assert_eq!(
+            layout,
+            vec![
+                ("nvs", TYPE_DATA, SUBTYPE_NVS, 0x9000, 0x6000),
+                ("phy_init", TYPE_DATA, 0x01, 0xf000, 0x1000),
+                ("factory", TYPE_APP, 0x00, 0x10000, 0x100000),
+                ("storage", 0x40, 0x01, 0x200000, 0x10000),
+            ]
+        );
+        assert!(table.partitions[1].readonly);
+        assert!(table.partitions[2].encrypted && table.partitions[2].readonly);
+        assert!(table.validate(&FlashLayout::default()).is_empty());
+
+        let error = |csv: &str| {
+            PartitionTable::from_csv(csv, &FlashLayout::default())
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("nvs, data, nvs, 0x9000\n").contains("第 1 行"));
+        assert!(error("\nnvs, data, bad, 0x9000, 4K\n").contains("第 2 行"));
+        assert!(error("nvs, data, nvs, 0x9000, 4K, hidden\n").contains("未知标志"));
+        assert!(error("nvs, data, nvs, 0x9000, 5G\n").contains("大小"));
+    }
+
+    #[test]
+    fn csv_offset_overflow() {
+        let csv = "last, data, nvs, 0xfffff000, 0x1000,\n\
+                   next, data, nvs, , 0x1000,\n";
+        let error = PartitionTable::from_csv(csv, &FlashLayout::default()).unwrap_err();
+        assert!(error.to_string().contains("第 2 行"), "{}", error);
+
+        let mut table = PartitionTable::from_csv(
+            "a, data, nvs, 0x9000, 0xfff00000,\nb, app, factory, 0x10000, 1M,\n",
+            &FlashLayout::default(),
+        )
+        .unwrap();
+        let before = table.clone();
+        assert!(table.arrange(&FlashLayout::default()).is_err());
+        assert_eq!(table, before);
+    }
+
+    #[test]
+    fn arrange_offsets() {
+        let mut table = two_ota();
+        for p in table.partitions.iter_mut() {
+            p.offset = 0;
+        }
+        table.arrange(&FlashLayout::default()).unwrap();
+        let offsets: Vec<u32> = table.partitions.iter().map(|p| p.offset).collect();
+        assert_eq!(
+            offsets,
+            vec![0x9000, 0xd000, 0xf000, 0x10000, 0x110000, 0x210000]
+        );
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let table = two_ota();
+        let data = table.to_binary().unwrap();
+        assert_eq!(data.len(), TABLE_LEN);
+        // 第一个条目: nvs, data, nvs, 0x9000, 0x4000
+        assert_eq!(
+            data[..12],
+            [0xaa, 0x50, 0x01, 0x02, 0x00, 0x90, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00]
+        );
+        assert_eq!(&data[12..16], b"nvs\0");
+
+        let md5_entry = table.partitions.len() * ENTRY_LEN;
+        assert_eq!(data[md5_entry..md5_entry + 2], MD5_MAGIC);
+        assert_eq!(
+            data[md5_entry + 16..md5_entry + 32],
+            Md5::digest(&data[..md5_entry])[..]
+        );
+        assert!(data[md5_entry + ENTRY_LEN..].iter().all(|b| *b == 0xff));
+
+        assert_eq!(PartitionTable::from_binary(&data).unwrap(), table);
+        let csv = table.to_csv();
+        assert_eq!(
+            PartitionTable::from_csv(&csv, &FlashLayout::default()).unwrap(),
+            table
+        );
+
+        let mut corrupted = data.clone();
+        corrupted[4] ^= 0x10;
+        assert!(PartitionTable::from_binary(&corrupted)
+            .unwrap_err()
+            .to_string()
+            .contains("MD5"));
+        assert!(PartitionTable::from_binary(&[0xff; TABLE_LEN]).is_err());
+    }
+
+    #[test]
+    fn flash_layout_from_sdkconfig() {
+        let layout = FlashLayout::from_sdkconfig(
+            "CONFIG_ESPTOOLPY_FLASHSIZE=\"8MB\"\nCONFIG_PARTITION_TABLE_OFFSET=0x10000\n",
+        );
+        assert_eq!(layout.flash_size, 8 * 1024 * 1024);
+        assert_eq!(layout.table_offset, 0x10000);
+        assert_eq!(layout.first_free(), 0x11000);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    data::register_map::{Area, Field, RegisterMap},
+    resource::error::{AppError, Result},
+};
+
+/// 配方中的一个字段, 数组字段的值为 json 数组
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecipeItem {
+    pub field: String,
+    pub value: Value,
+}
+
+/// 配方: 一个寄存器表的一组保持寄存器和线圈的值, 按 items 的顺序下载到从站
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    pub name: String,
+    /// 寄存器表名称
+    pub map: String,
+    #[serde(default)]
+    pub description: String,
+    pub created: DateTime<Local>,
+    pub items: Vec<RecipeItem>,
+}
+
+impl Recipe {
+    pub fn new(name: impl Into<String>, map: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            map: map.into(),
+            description: String::new(),
+            created: Local::now(),
+            items: vec![],
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        let recipe: Recipe = serde_json::from_str(text)?;
+        if recipe.name.is_empty() {
+            return Err(AppError::Error("配方名称为空".to_string()));
+        }
+        Ok(recipe)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| AppError::Error(format!("读取 {} 失败: {}", path.display(), e)))?;
+        Self::from_json(&text)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// 查找每一项对应的字段, 字段必须存在并且可写
+    pub fn resolve(&self, map: &RegisterMap) -> Result<Vec<(Area, Field)>> {
+        self.items
+            .iter()
+            .map(|item| {
+                let (area, field) = map.find(&item.field).ok_or_else(|| {
+                    AppError::RegisterMap(format!("{} 中没有字段 {}", map.name, item.field))
+                })?;
+                if !field.writable(area) {
+                    return Err(AppError::RegisterMap(format!("{} 只读", item.field)));
+                }
+                Ok((area, field.clone()))
+            })
+            .collect()
+    }
+}
+
+/// 可以放入配方的字段: 可写的线圈和保持寄存器, 按地址排列
+pub fn recipe_fields(map: &RegisterMap) -> Vec<(Area, &Field)> {
+    let mut fields = vec![];
+    for area in [Area::Coils, Area::HoldingRegisters] {
+        let mut area_fields: Vec<&Field> = map
+            .fields(area)
+            .iter()
+            .filter(|f| f.writable(area))
+            .collect();
+        area_fields.sort_by_key(|f| f.address);
+        fields.extend(area_fields.into_iter().map(|f| (area, f)));
+    }
+    fields
+}
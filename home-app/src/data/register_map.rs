@@ -0,0 +1,1171 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    data::storage::persistence::app_dir,
+    resource::{
+        defines::register_maps,
+        error::{AppError, Result},
+    },
+    service::modbus::pdu::{
+        Request, MAX_READ_BITS, MAX_READ_REGISTERS, MAX_WRITE_BITS, MAX_WRITE_REGISTERS,
+    },
+};
+
+/// Modbus 的四个数据区
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Area {
+    Coils,
+    DiscreteInputs,
+    InputRegisters,
+    HoldingRegisters,
+}
+
+impl Area {
+    pub const ALL: [Area; 4] = [
+        Area::Coils,
+        Area::DiscreteInputs,
+        Area::InputRegisters,
+        Area::HoldingRegisters,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Area::Coils => "线圈",
+            Area::DiscreteInputs => "离散输入",
+            Area::InputRegisters => "输入寄存器",
+            Area::HoldingRegisters => "保持寄存器",
+        }
+    }
+
+    /// 线圈和离散输入按位寻址
+    pub fn is_bit(&self) -> bool {
+        matches!(self, Area::Coils | Area::DiscreteInputs)
+    }
+
+    /// 主站是否可以写入
+    pub fn writable(&self) -> bool {
+        matches!(self, Area::Coils | Area::HoldingRegisters)
+    }
+
+    /// 一次请求最多读取的数量
+    pub fn max_read(&self) -> u16 {
+        if self.is_bit() {
+            MAX_READ_BITS
+        } else {
+            MAX_READ_REGISTERS
+        }
+    }
+
+    /// 一次请求最多写入的数量
+    pub fn max_write(&self) -> u16 {
+        if self.is_bit() {
+            MAX_WRITE_BITS
+        } else {
+            MAX_WRITE_REGISTERS
+        }
+    }
+
+    pub fn read_request(&self, address: u16, quantity: u16) -> Request {
+        match self {
+            Area::Coils => Request::ReadCoils { address, quantity },
+            Area::DiscreteInputs => Request::ReadDiscreteInputs { address, quantity },
+            Area::InputRegisters => Request::ReadInputRegisters { address, quantity },
+            Area::HoldingRegisters => Request::ReadHoldingRegisters { address, quantity },
+        }
+    }
+
+    /// 写入原始值的请求, 只读的数据区返回 None
+    pub fn write_request(&self, address: u16, raw: Vec<u16>) -> Option<Request> {
+        match (self, raw.len()) {
+            (Area::Coils, 1) => Some(Request::WriteSingleCoil {
+                address,
+                value: raw[0] != 0,
+            }),
+            (Area::Coils, _) => Some(Request::WriteMultipleCoils {
+                address,
+                values: raw.iter().map(|v| *v != 0).collect(),
+            }),
+            (Area::HoldingRegisters, 1) => Some(Request::WriteSingleRegister {
+                address,
+                value: raw[0],
+            }),
+            (Area::HoldingRegisters, _) => Some(Request::WriteMultipleRegisters {
+                address,
+                values: raw,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// 一次读请求覆盖的地址范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadBlock {
+    pub area: Area,
+    pub address: u16,
+    pub quantity: u16,
+}
+
+impl ReadBlock {
+    pub fn request(&self) -> Request {
+        self.area.read_request(self.address, self.quantity)
+    }
+
+    pub fn addresses(&self) -> std::ops::Range<u32> {
+        self.address as u32..self.address as u32 + self.quantity as u32
+    }
+}
+
+/// 数据类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DataType {
+    /// 线圈或离散输入
+    Bool,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    /// 64 位整数转换为工程值时超过 2^53 的部分会丢失精度
+    U64,
+    I64,
+    F64,
+    /// 寄存器中的位段, 从第 bit 位开始, 共 width 位
+    Bits {
+        bit: u8,
+        width: u8,
+    },
+    /// 字符串, 每个寄存器两个字节, length 为字节数, 不足时以 0 填充
+    #[serde(rename = "string")]
+    Str {
+        length: u16,
+    },
+}
+
+impl DataType {
+    pub fn label(&self) -> String {
+        match self {
+            DataType::Bool => "bool".to_string(),
+            DataType::U16 => "u16".to_string(),
+            DataType::I16 => "i16".to_string(),
+            DataType::U32 => "u32".to_string(),
+            DataType::I32 => "i32".to_string(),
+            DataType::F32 => "f32".to_string(),
+            DataType::U64 => "u64".to_string(),
+            DataType::I64 => "i64".to_string(),
+            DataType::F64 => "f64".to_string(),
+            DataType::Bits { bit, width } => {
+                format!("bits[{}..{}]", bit, *bit as u32 + *width as u32)
+            }
+            DataType::Str { length } => format!("string[{}]", length),
+        }
+    }
+
+    /// 一个值占用的寄存器(或位)数量, 最大为 32768
+    pub fn size(&self) -> u16 {
+        match self {
+            DataType::U32 | DataType::I32 | DataType::F32 => 2,
+            DataType::U64 | DataType::I64 | DataType::F64 => 4,
+            DataType::Str { length } => ((*length as u32 + 1) / 2) as u16,
+            _ => 1,
+        }
+    }
+
+    /// 占用多个寄存器的数值, 受字顺序影响
+    pub fn is_multi_register(&self) -> bool {
+        matches!(
+            self,
+            DataType::U32
+                | DataType::I32
+                | DataType::F32
+                | DataType::U64
+                | DataType::I64
+                | DataType::F64
+        )
+    }
+}
+
+/// 多寄存器数值的字节顺序, A 为最高字节
+/// 64 位值按同样的规则扩展, 如 CDAB 对应 GHEFCDAB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    /// 高字在前, 每个寄存器高字节在前, Modbus 的标准顺序
+    Abcd,
+    /// 低字在前, 如 esp-modbus 中直接映射的 float
+    Cdab,
+    /// 高字在前, 寄存器内字节交换
+    Badc,
+    /// 低字在前, 寄存器内字节交换, 即小端
+    Dcba,
+}
+
+impl Default for Order {
+    fn default() -> Self {
+        Order::Abcd
+    }
+}
+
+impl Order {
+    pub const ALL: [Order; 4] = [Order::Abcd, Order::Cdab, Order::Badc, Order::Dcba];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Order::Abcd => "ABCD",
+            Order::Cdab => "CDAB",
+            Order::Badc => "BADC",
+            Order::Dcba => "DCBA",
+        }
+    }
+
+    /// 低位的寄存器在前
+    pub fn word_swap(&self) -> bool {
+        matches!(self, Order::Cdab | Order::Dcba)
+    }
+
+    /// 寄存器内低字节在前
+    pub fn byte_swap(&self) -> bool {
+        matches!(self, Order::Badc | Order::Dcba)
+    }
+}
+
+/// 读写权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    #[serde(rename = "r")]
+    Read,
+    #[serde(rename = "rw")]
+    ReadWrite,
+}
+
+fn default_count() -> u16 {
+    1
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// 字段的初始值, 数组可以只写一个值或每个元素一个值
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Initial {
+    Value(f64),
+    Values(Vec<f64>),
+    /// 字符串字段, 数组的每个元素相同
+    Text(String),
+}
+
+impl Initial {
+    /// 每个数组元素的初始值, 缺少的元素为 0
+    pub fn values(&self, count: u16) -> Vec<f64> {
+        match self {
+            Initial::Value(value) => vec![*value; count as usize],
+            Initial::Text(_) => vec![0.0; count as usize],
+            Initial::Values(values) => {
+                let mut values = values.clone();
+                values.resize(count as usize, 0.0);
+                values
+            }
+        }
+    }
+}
+
+/// 寄存器表中的一个字段
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    /// 起始地址(协议地址, 从 0 开始)
+    pub address: u16,
+    #[serde(flatten)]
+    pub data_type: DataType,
+    /// 数组长度, 1 表示单个值
+    #[serde(default = "default_count")]
+    pub count: u16,
+    /// 字节顺序, 未配置时 32 和 64 位值使用设备的默认值, 其它为 ABCD
+    /// 字符串和 16 位值只受寄存器内字节交换的影响
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Order>,
+    /// 工程值 = 原始值 * scale + offset
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default)]
+    pub unit: String,
+    /// 未配置时, 线圈和保持寄存器可读写, 其它只读
+    #[serde(default)]
+    pub access: Option<Access>,
+    #[serde(default)]
+    pub description: String,
+    /// 从站上电后的值, 用于模拟器
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial: Option<Initial>,
+}
+
+impl Field {
+    pub fn new(name: impl Into<String>, address: u16, data_type: DataType) -> Self {
+        Self {
+            name: name.into(),
+            address,
+            data_type,
+            count: 1,
+            order: None,
+            scale: 1.0,
+            offset: 0.0,
+            unit: String::new(),
+            access: None,
+            description: String::new(),
+            initial: None,
+        }
+    }
+
+    /// 占用的寄存器(或位)数量, 超过 65535 时 validate 返回错误
+    pub fn len(&self) -> u16 {
+        u16::try_from(self.span()).unwrap_or(u16::MAX)
+    }
+
+    /// 未经检查的字段占用的寄存器(或位)数量
+    fn span(&self) -> u32 {
+        self.data_type.size() as u32 * self.count as u32
+    }
+
+    /// 结束地址(不包含)
+    pub fn end(&self) -> u32 {
+        self.address as u32 + self.span()
+    }
+
+    /// 数组的第 index 个元素, 作为单独的字段
+    pub fn element(&self, index: u16) -> Field {
+        let mut element = self.clone();
+        element.address =
+            (self.address as u32 + index as u32 * self.data_type.size() as u32) as u16;
+        element.count = 1;
+        if self.count > 1 {
+            element.name = format!("{}[{}]", self.name, index);
+            element.initial = self.initial.as_ref().map(|initial| match initial {
+                Initial::Text(text) => Initial::Text(text.clone()),
+                _ => Initial::Value(initial.values(self.count)[index as usize]),
+            });
+        }
+        element
+    }
+
+    /// 展开数组, 单个值返回自身
+    pub fn elements(&self) -> impl Iterator<Item = Field> + '_ {
+        (0..self.count).map(move |i| self.element(i))
+    }
+
+    pub fn writable(&self, area: Area) -> bool {
+        area.writable() && self.access.unwrap_or(Access::ReadWrite) == Access::ReadWrite
+    }
+
+    /// 生效的字节顺序
+    pub fn order(&self) -> Order {
+        self.order.unwrap_or_default()
+    }
+
+    /// 字符串字段使用 decode_text 和 encode_text
+    pub fn is_text(&self) -> bool {
+        matches!(self.data_type, DataType::Str { .. })
+    }
+
+    fn check_len(&self, raw: &[u16]) -> Result<()> {
+        if raw.len() != self.len() as usize {
+            return Err(AppError::RegisterMap(format!(
+                "{}: 需要 {} 个寄存器, 实际 {}",
+                self.name,
+                self.len(),
+                raw.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// 原始寄存器(或位)转换为工程值, 每个数组元素一个值
+    pub fn decode(&self, raw: &[u16]) -> Result<Vec<f64>> {
+        self.check_len(raw)?;
+        if self.is_text() {
+            return Err(AppError::RegisterMap(format!("{} 是字符串", self.name)));
+        }
+        let size = self.data_type.size() as usize;
+        Ok(raw
+            .chunks(size)
+            .map(|regs| {
+                let value = self.decode_one(regs);
+                match self.data_type {
+                    DataType::Bool => value,
+                    _ => value * self.scale + self.offset,
+                }
+            })
+            .collect())
+    }
+
+    fn decode_one(&self, regs: &[u16]) -> f64 {
+        match self.data_type {
+            DataType::Bool => (regs[0] != 0) as u8 as f64,
+            DataType::U16 => self.swap_bytes(regs[0]) as f64,
+            DataType::I16 => self.swap_bytes(regs[0]) as i16 as f64,
+            DataType::U32 => self.combine(regs) as u32 as f64,
+            DataType::I32 => self.combine(regs) as u32 as i32 as f64,
+            DataType::F32 => f32::from_bits(self.combine(regs) as u32) as f64,
+            DataType::U64 => self.combine(regs) as f64,
+            DataType::I64 => self.combine(regs) as i64 as f64,
+            DataType::F64 => f64::from_bits(self.combine(regs)),
+            DataType::Bits { bit, width } => {
+                let mask = bit_mask(width);
+                ((self.swap_bytes(regs[0]) >> bit) & mask) as f64
+            }
+            DataType::Str { .. } => 0.0,
+        }
+    }
+
+    /// 工程值转换为原始寄存器(或位)
+    /// 位段需要当前的寄存器值, 只修改对应的位
+    pub fn encode(&self, values: &[f64], current: &[u16]) -> Result<Vec<u16>> {
+        if values.len() != self.count as usize {
+            return Err(AppError::RegisterMap(format!(
+                "{}: 需要 {} 个值, 实际 {}",
+                self.name,
+                self.count,
+                values.len()
+            )));
+        }
+        if self.is_text() {
+            return Err(AppError::RegisterMap(format!("{} 是字符串", self.name)));
+        }
+        let mut raw = Vec::with_capacity(self.len() as usize);
+        for (i, value) in values.iter().enumerate() {
+            let value = match self.data_type {
+                DataType::Bool => *value,
+                _ => (value - self.offset) / self.scale,
+            };
+            match self.data_type {
+                DataType::Bool => raw.push((value != 0.0) as u16),
+                DataType::U16 => raw.push(self.swap_bytes(value.round() as u16)),
+                DataType::I16 => raw.push(self.swap_bytes(value.round() as i16 as u16)),
+                DataType::U32 => raw.extend(self.split(value.round() as u32 as u64, 2)),
+                DataType::I32 => raw.extend(self.split(value.round() as i32 as u32 as u64, 2)),
+                DataType::F32 => raw.extend(self.split((value as f32).to_bits() as u64, 2)),
+                DataType::U64 => raw.extend(self.split(value.round() as u64, 4)),
+                DataType::I64 => raw.extend(self.split(value.round() as i64 as u64, 4)),
+                DataType::F64 => raw.extend(self.split(value.to_bits(), 4)),
+                DataType::Bits { bit, width } => {
+                    let mask = bit_mask(width) << bit;
+                    let old = self.swap_bytes(current.get(i).copied().unwrap_or(0));
+                    let new = (old & !mask) | (((value.round() as u16) << bit) & mask);
+                    raw.push(self.swap_bytes(new));
+                }
+                DataType::Str { .. } => {}
+            }
+        }
+        Ok(raw)
+    }
+
+    /// 字符串字段的值, 去掉末尾的 0 和空格
+    pub fn decode_text(&self, raw: &[u16]) -> Result<Vec<String>> {
+        self.check_len(raw)?;
+        let length = match self.data_type {
+            DataType::Str { length } => length as usize,
+            _ => return Err(AppError::RegisterMap(format!("{} 不是字符串", self.name))),
+        };
+        Ok(raw
+            .chunks(self.data_type.size() as usize)
+            .map(|regs| {
+                let bytes: Vec<u8> = regs
+                    .iter()
+                    .flat_map(|reg| self.swap_bytes(*reg).to_be_bytes())
+                    .take(length)
+                    .collect();
+                String::from_utf8_lossy(&bytes)
+                    .trim_end_matches(['\0', ' '])
+                    .to_string()
+            })
+            .collect())
+    }
+
+    /// 字符串转换为原始寄存器, 每个数组元素一个字符串
+    pub fn encode_text(&self, texts: &[String]) -> Result<Vec<u16>> {
+        let length = match self.data_type {
+            DataType::Str { length } => length as usize,
+            _ => return Err(AppError::RegisterMap(format!("{} 不是字符串", self.name))),
+        };
+        if texts.len() != self.count as usize {
+            return Err(AppError::RegisterMap(format!(
+                "{}: 需要 {} 个值, 实际 {}",
+                self.name,
+                self.count,
+                texts.len()
+            )));
+        }
+        let mut raw = Vec::with_capacity(self.len() as usize);
+        for text in texts {
+            if text.len() > length {
+                return Err(AppError::RegisterMap(format!(
+                    "{}: 字符串超过 {} 字节",
+                    self.name, length
+                )));
+            }
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.resize(self.data_type.size() as usize * 2, 0);
+            raw.extend(
+                bytes
+                    .chunks(2)
+                    .map(|pair| self.swap_bytes(u16::from_be_bytes([pair[0], pair[1]]))),
+            );
+        }
+        Ok(raw)
+    }
+
+    /// 显示用的值, 每个数组元素一个
+    pub fn display(&self, raw: &[u16]) -> Result<Vec<String>> {
+        if self.is_text() {
+            return self.decode_text(raw);
+        }
+        Ok(self
+            .decode(raw)?
+            .into_iter()
+            .map(|v| self.format_value(v))
+            .collect())
+    }
+
+    /// json 表示的值: 布尔, 数字或字符串, 数组字段为 json 数组
+    pub fn to_json(&self, raw: &[u16]) -> Result<Value> {
+        let mut items: Vec<Value> = if self.is_text() {
+            self.decode_text(raw)?
+                .into_iter()
+                .map(Value::String)
+                .collect()
+        } else {
+            self.decode(raw)?
+                .into_iter()
+                .map(|v| self.json_value(v))
+                .collect()
+        };
+        Ok(if self.count == 1 {
+            items.remove(0)
+        } else {
+            Value::Array(items)
+        })
+    }
+
+    /// 工程值转换为 json, 按显示精度取整, 避免 f32 转换带来的多余小数
+    fn json_value(&self, value: f64) -> Value {
+        match self.data_type {
+            DataType::Bool => Value::Bool(value != 0.0),
+            _ => serde_json::from_str(&self.format_value(value))
+                .unwrap_or_else(|_| Value::from(value)),
+        }
+    }
+
+    /// json 值转换为原始寄存器, 数值支持数字, true/false 和 ON/OFF
+    /// 位段只修改对应的位, 需要寄存器的当前值
+    pub fn from_json(&self, value: &Value, current: &[u16]) -> Result<Vec<u16>> {
+        if self.is_text() {
+            let texts = match value {
+                Value::Array(items) => items.iter().map(json_text).collect(),
+                value => vec![json_text(value)],
+            };
+            return self.encode_text(&texts);
+        }
+        let values = match value {
+            Value::Array(items) => items.iter().map(json_number).collect(),
+            value => json_number(value).map(|v| vec![v]),
+        }
+        .filter(|values| values.len() == self.count as usize)
+        .ok_or_else(|| {
+            AppError::RegisterMap(format!(
+                "{} 的值无效, 需要 {} 个值: {}",
+                self.name, self.count, value
+            ))
+        })?;
+        self.encode(&values, current)
+    }
+
+    fn swap_bytes(&self, reg: u16) -> u16 {
+        if self.order().byte_swap() {
+            reg.swap_bytes()
+        } else {
+            reg
+        }
+    }
+
+    /// 多个寄存器组合为一个值, 先按字顺序排列寄存器, 再交换每个寄存器的字节
+    fn combine(&self, regs: &[u16]) -> u64 {
+        let word_swap = self.order().word_swap();
+        let mut value = 0u64;
+        for i in 0..regs.len() {
+            let reg = if word_swap {
+                regs[regs.len() - 1 - i]
+            } else {
+                regs[i]
+            };
+            value = (value << 16) | self.swap_bytes(reg) as u64;
+        }
+        value
+    }
+
+    fn split(&self, value: u64, words: usize) -> Vec<u16> {
+        let mut regs: Vec<u16> = (0..words)
+            .rev()
+            .map(|i| self.swap_bytes((value >> (16 * i)) as u16))
+            .collect();
+        if self.order().word_swap() {
+            regs.reverse();
+        }
+        regs
+    }
+
+    /// 显示用的工程值
+    pub fn format_value(&self, value: f64) -> String {
+        match self.data_type {
+            DataType::Bool => if value != 0.0 { "ON" } else { "OFF" }.to_string(),
+            DataType::F32 | DataType::F64 => format!("{:.3}", value),
+            _ if self.scale.fract() != 0.0 || self.offset.fract() != 0.0 => {
+                format!("{:.3}", value)
+            }
+            _ => format!("{}", value),
+        }
+    }
+
+    /// 所有字节顺序的解码结果, 按可信程度排序, 第一个最可能是正确的
+    /// 用于猜测未知设备的字节顺序, 只支持 32 和 64 位数值
+    pub fn guess_order(&self, raw: &[u16]) -> Vec<OrderGuess> {
+        if !self.data_type.is_multi_register() {
+            return vec![];
+        }
+        let mut guesses: Vec<OrderGuess> = Order::ALL
+            .into_iter()
+            .filter_map(|order| {
+                let mut field = self.clone();
+                field.order = Some(order);
+                let values = field.decode(raw).ok()?;
+                Some(OrderGuess {
+                    order,
+                    score: values
+                        .iter()
+                        .map(|v| plausibility(self.data_type, *v))
+                        .sum(),
+                    value: values[0],
+                })
+            })
+            .collect();
+        // 分数相同时保持 ABCD, CDAB, BADC, DCBA 的顺序
+        guesses.sort_by_key(|guess| std::cmp::Reverse(guess.score));
+        guesses
+    }
+}
+
+/// 按一种字节顺序解码的结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderGuess {
+    pub order: Order,
+    /// 第一个元素的工程值
+    pub value: f64,
+    /// 所有元素的 plausibility 之和
+    pub score: u32,
+}
+
+/// 数值看起来是否合理, 分数越高越合理
+/// 浮点数: 有限, 量级适中, 有效数字少; 整数: 绝对值小
+pub fn plausibility(data_type: DataType, value: f64) -> u32 {
+    if !value.is_finite() {
+        return 0;
+    }
+    if value == 0.0 {
+        return 50;
+    }
+    let magnitude = value.abs().log10();
+    match data_type {
+        DataType::F32 | DataType::F64 => {
+            if !(-4.0..=7.0).contains(&magnitude) {
+                return 1;
+            }
+            // 1.34 比 1.3400000333786 更像是真实的值
+            let text = format!("{}", value as f32);
+            let digits = text.chars().filter(|c| c.is_ascii_digit()).count() as u32;
+            100u32.saturating_sub(digits * 5)
+        }
+        _ => 100u32.saturating_sub((magnitude.max(0.0) * 5.0) as u32),
+    }
+}
+
+fn json_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Bool(on) => Some(*on as u8 as f64),
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => match text.to_ascii_uppercase().as_str() {
+            "ON" | "TRUE" => Some(1.0),
+            "OFF" | "FALSE" => Some(0.0),
+            _ => text.parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+/// 字符串字段的值, 其它 json 值按文本写入
+fn json_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn bit_mask(width: u8) -> u16 {
+    if width >= 16 {
+        u16::MAX
+    } else {
+        (1u16 << width) - 1
+    }
+}
+
+/// 寄存器表, 描述一种从站设备的寄存器布局
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegisterMap {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub coils: Vec<Field>,
+    #[serde(default)]
+    pub discrete_inputs: Vec<Field>,
+    #[serde(default)]
+    pub input_registers: Vec<Field>,
+    #[serde(default)]
+    pub holding_registers: Vec<Field>,
+}
+
+impl RegisterMap {
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let map: RegisterMap =
+            toml::from_str(text).map_err(|e| AppError::RegisterMap(e.to_string()))?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        let map: RegisterMap =
+            serde_json::from_str(text).map_err(|e| AppError::RegisterMap(e.to_string()))?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    /// 未配置字节顺序的 32 和 64 位字段使用 order
+    pub fn with_default_order(mut self, order: Order) -> Self {
+        for area in Area::ALL {
+            for field in self.fields_mut(area) {
+                if field.order.is_none() && field.data_type.is_multi_register() {
+                    field.order = Some(order);
+                }
+            }
+        }
+        self
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).map_err(|e| AppError::RegisterMap(e.to_string()))
+    }
+
+    /// 按扩展名读取 .toml 或 .json 文件
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&text),
+            _ => Self::from_toml(&text),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::to_string_pretty(self)?,
+            _ => self.to_toml()?,
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// 与 modbus-rtu-example 固件的 modbus_params.h 对应的寄存器表
+    pub fn modbus_rtu_example() -> Self {
+        Self::from_toml(register_maps::MODBUS_RTU_EXAMPLE).expect("内置寄存器表格式错误")
+    }
+
+    /// 用户寄存器表的目录
+    pub fn library_dir() -> PathBuf {
+        app_dir().join("register_maps")
+    }
+
+    /// 内置的寄存器表和 library_dir 中的所有寄存器表
+    pub fn library() -> Vec<RegisterMap> {
+        let mut maps = vec![Self::modbus_rtu_example()];
+        let entries = match fs::read_dir(Self::library_dir()) {
+            Ok(entries) => entries,
+            Err(_) => return maps,
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            let supported = matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("toml") | Some("json")
+            );
+            if !supported {
+                continue;
+            }
+            match Self::load(&path) {
+                Ok(map) => maps.push(map),
+                Err(e) => tracing::warn!("无法读取寄存器表 {:?}: {}", path, e),
+            }
+        }
+        maps
+    }
+
+    pub fn fields(&self, area: Area) -> &[Field] {
+        match area {
+            Area::Coils => &self.coils,
+            Area::DiscreteInputs => &self.discrete_inputs,
+            Area::InputRegisters => &self.input_registers,
+            Area::HoldingRegisters => &self.holding_registers,
+        }
+    }
+
+    pub fn fields_mut(&mut self, area: Area) -> &mut Vec<Field> {
+        match area {
+            Area::Coils => &mut self.coils,
+            Area::DiscreteInputs => &mut self.discrete_inputs,
+            Area::InputRegisters => &mut self.input_registers,
+            Area::HoldingRegisters => &mut self.holding_registers,
+        }
+    }
+
+    /// 合并地址连续的字段, 生成最少的读请求
+    /// 不读取字段之间的空隙, 从站对未定义的地址通常返回异常
+    pub fn read_blocks(&self, area: Area) -> Vec<ReadBlock> {
+        let mut ranges: Vec<(u32, u32)> = self
+            .fields(area)
+            .iter()
+            .map(|f| (f.address as u32, f.end()))
+            .collect();
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(u32, u32)> = vec![];
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        let max = area.max_read() as u32;
+        let mut blocks = vec![];
+        for (mut start, end) in merged {
+            while start < end {
+                let quantity = (end - start).min(max);
+                blocks.push(ReadBlock {
+                    area,
+                    address: start as u16,
+                    quantity: quantity as u16,
+                });
+                start += quantity;
+            }
+        }
+        blocks
+    }
+
+    /// 所有数据区的读请求
+    pub fn all_read_blocks(&self) -> Vec<ReadBlock> {
+        Area::ALL
+            .into_iter()
+            .flat_map(|area| self.read_blocks(area))
+            .collect()
+    }
+
+    /// 按名称查找字段
+    pub fn find(&self, name: &str) -> Option<(Area, &Field)> {
+        Area::ALL.into_iter().find_map(|area| {
+            self.fields(area)
+                .iter()
+                .find(|f| f.name == name)
+                .map(|f| (area, f))
+        })
+    }
+
+    /// 检查字段的类型, 地址范围, 重名和重叠
+    /// 同一个寄存器中不重叠的位段是允许的
+    pub fn validate(&self) -> Result<()> {
+        let error = |message: String| Err(AppError::RegisterMap(message));
+        let mut names = std::collections::HashSet::new();
+        for area in Area::ALL {
+            let fields = self.fields(area);
+            for field in fields {
+                if !names.insert(field.name.as_str()) {
+                    return error(format!("字段名重复: {}", field.name));
+                }
+                if field.count == 0 {
+                    return error(format!("{}: 数组长度不能为 0", field.name));
+                }
+                if field.end() > 0x10000 {
+                    return error(format!("{}: 地址超出 65535", field.name));
+                }
+                if field.span() > u16::MAX as u32 {
+                    return error(format!("{}: 占用的寄存器超过 65535", field.name));
+                }
+                if field.scale == 0.0 {
+                    return error(format!("{}: scale 不能为 0", field.name));
+                }
+                match (area.is_bit(), field.data_type) {
+                    (true, DataType::Bool) => {}
+                    (true, _) => {
+                        return error(format!("{}: {} 只支持 bool", field.name, area.label()))
+                    }
+                    (false, DataType::Bool) => {
+                        return error(format!(
+                            "{}: {} 不支持 bool, 请使用 bits",
+                            field.name,
+                            area.label()
+                        ))
+                    }
+                    (false, DataType::Bits { bit, width }) => {
+                        if width == 0 || bit as u32 + width as u32 > 16 {
+                            return error(format!("{}: 位段超出 16 位", field.name));
+                        }
+                    }
+                    (false, DataType::Str { length: 0 }) => {
+                        return error(format!("{}: 字符串长度不能为 0", field.name));
+                    }
+                    (false, _) => {}
+                }
+            }
+
+            for (i, a) in fields.iter().enumerate() {
+                for b in &fields[i + 1..] {
+                    let overlap = (a.address as u32) < b.end() && (b.address as u32) < a.end();
+                    if overlap && !Self::disjoint_bits(a, b) {
+                        return error(format!(
+                            "{}: {} 与 {} 地址重叠",
+                            area.label(),
+                            a.name,
+                            b.name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn disjoint_bits(a: &Field, b: &Field) -> bool {
+        match (a.data_type, b.data_type) {
+            (
+                DataType::Bits {
+                    bit: a_bit,
+                    width: a_width,
+                },
+                DataType::Bits {
+                    bit: b_bit,
+                    width: b_width,
+                },
+            ) => {
+                let a_mask = bit_mask(a_width) << a_bit;
+                let b_mask = bit_mask(b_width) << b_bit;
+                a_mask & b_mask == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(data_type: DataType, order: Order) -> Field {
+        let mut field = Field::new("value", 0, data_type);
+        field.order = Some(order);
+        field
+    }
+
+    #[test]
+    fn decode_and_encode_in_all_orders() {
+        // 1.34f32 = 0x3FAB851F, -2.5f64 = 0xC004000000000000
+        let cases = [
+            (Order::Abcd, [0x3FAB, 0x851F], [0xC004, 0, 0, 0]),
+            (Order::Cdab, [0x851F, 0x3FAB], [0, 0, 0, 0xC004]),
+            (Order::Badc, [0xAB3F, 0x1F85], [0x04C0, 0, 0, 0]),
+            (Order::Dcba, [0x1F85, 0xAB3F], [0, 0, 0, 0x04C0]),
+        ];
+        for (order, f32_raw, f64_raw) in cases {
+            let f = field(DataType::F32, order);
+            assert_eq!(
+                f.decode(&f32_raw).unwrap(),
+                vec![1.34f32 as f64],
+                "{:?}",
+                order
+            );
+            assert_eq!(
+                f.encode(&[1.34], &[]).unwrap(),
+                f32_raw.to_vec(),
+                "{:?}",
+                order
+            );
+
+            let f = field(DataType::F64, order);
+            assert_eq!(f.decode(&f64_raw).unwrap(), vec![-2.5], "{:?}", order);
+            assert_eq!(
+                f.encode(&[-2.5], &[]).unwrap(),
+                f64_raw.to_vec(),
+                "{:?}",
+                order
+            );
+        }
+
+        let f = field(DataType::I32, Order::Cdab);
+        assert_eq!(f.encode(&[-2.0], &[]).unwrap(), vec![0xFFFE, 0xFFFF]);
+        assert_eq!(f.decode(&[0xFFFE, 0xFFFF]).unwrap(), vec![-2.0]);
+        let f = field(DataType::U32, Order::Abcd);
+        assert_eq!(f.decode(&[0x0001, 0x0002]).unwrap(), vec![65538.0]);
+        let f = field(DataType::U64, Order::Dcba);
+        assert_eq!(f.encode(&[258.0], &[]).unwrap(), vec![0x0201, 0, 0, 0]);
+
+        // 16 位值和字符串只受字节交换的影响
+        let f = field(DataType::U16, Order::Cdab);
+        assert_eq!(f.decode(&[0x1234]).unwrap(), vec![0x1234 as f64]);
+        let f = field(DataType::I16, Order::Badc);
+        assert_eq!(f.decode(&[0xFFFE]).unwrap(), vec![-257.0]);
+        assert_eq!(f.encode(&[-257.0], &[]).unwrap(), vec![0xFFFE]);
+    }
+
+    #[test]
+    fn scale_offset_and_arrays() {
+        let mut f = field(DataType::I16, Order::Abcd);
+        f.count = 2;
+        f.scale = 0.1;
+        f.offset = -40.0;
+        let values = f.decode(&[650, 0]).unwrap();
+        assert!((values[0] - 25.0).abs() < 1e-9);
+        assert_eq!(values[1], -40.0);
+        assert_eq!(f.encode(&[25.0, -40.0], &[]).unwrap(), vec![650, 0]);
+        assert!(f.encode(&[25.0], &[]).is_err());
+        assert!(f.decode(&[650]).is_err());
+
+        let element = f.element(1);
+        assert_eq!(element.name, "value[1]");
+        assert_eq!(element.address, 1);
+    }
+
+    #[test]
+    fn bits_keep_other_bits() {
+        let f = field(DataType::Bits { bit: 4, width: 3 }, Order::Abcd);
+        assert_eq!(f.decode(&[0b0101_0000]).unwrap(), vec![5.0]);
+        assert_eq!(f.encode(&[2.0], &[0xFF0F]).unwrap(), vec![0xFF2F]);
+        let f = field(DataType::Bits { bit: 0, width: 8 }, Order::Badc);
+        assert_eq!(f.decode(&[0x12AB]).unwrap(), vec![0x12 as f64]);
+    }
+
+    #[test]
+    fn text_fields() {
+        let f = field(DataType::Str { length: 5 }, Order::Abcd);
+        assert_eq!(f.len(), 3);
+        let raw = f.encode_text(&["abc".to_string()]).unwrap();
+        assert_eq!(raw, vec![0x6162, 0x6300, 0]);
+        assert_eq!(f.decode_text(&raw).unwrap(), vec!["abc".to_string()]);
+        assert!(f.encode_text(&["abcdef".to_string()]).is_err());
+
+        let f = field(DataType::Str { length: 5 }, Order::Badc);
+        assert_eq!(
+            f.encode_text(&["abc".to_string()]).unwrap(),
+            vec![0x6261, 0x0063, 0]
+        );
+    }
+
+    #[test]
+    fn guess_order() {
+        let f = Field::new("value", 0, DataType::F32);
+        let guesses = f.guess_order(&[0x851F, 0x3FAB]);
+        assert_eq!(guesses[0].order, Order::Cdab);
+        assert_eq!(guesses[0].value, 1.34f32 as f64);
+    }
+
+    fn map(fields: Vec<Field>) -> RegisterMap {
+        RegisterMap {
+            name: "test".into(),
+            holding_registers: fields,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validate_fields() {
+        assert!(RegisterMap::modbus_rtu_example().validate().is_ok());
+
+        let ok = Field::new("a", 0, DataType::U16);
+        assert!(map(vec![ok.clone()]).validate().is_ok());
+
+        let mut zero = ok.clone();
+        zero.count = 0;
+        let mut scale = ok.clone();
+        scale.scale = 0.0;
+        let end = Field::new("a", 0xFFFF, DataType::U32);
+        let bits = Field::new("a", 0, DataType::Bits { bit: 12, width: 8 });
+        let text = Field::new("a", 0, DataType::Str { length: 0 });
+        let bool = Field::new("a", 0, DataType::Bool);
+        for field in [zero, scale, end, bits, text, bool] {
+            assert!(map(vec![field.clone()]).validate().is_err(), "{:?}", field);
+        }
+
+        let duplicate = Field::new("a", 10, DataType::U16);
+        assert!(map(vec![ok.clone(), duplicate]).validate().is_err());
+        let overlap = Field::new("b", 0, DataType::F32);
+        assert!(map(vec![overlap, Field::new("c", 1, DataType::U16)])
+            .validate()
+            .is_err());
+
+        // 同一个寄存器中不重叠的位段
+        let low = Field::new("low", 0, DataType::Bits { bit: 0, width: 8 });
+        let high = Field::new("high", 0, DataType::Bits { bit: 8, width: 8 });
+        assert!(map(vec![low.clone(), high]).validate().is_ok());
+        let middle = Field::new("middle", 0, DataType::Bits { bit: 4, width: 8 });
+        assert!(map(vec![low, middle]).validate().is_err());
+
+        let coil = Field::new("coil", 0, DataType::U16);
+        let coils = RegisterMap {
+            name: "test".into(),
+            coils: vec![coil],
+            ..Default::default()
+        };
+        assert!(coils.validate().is_err());
+    }
+
+    #[test]
+    fn validate_large_fields_without_overflow() {
+        let mut text = Field::new("text", 0, DataType::Str { length: u16::MAX });
+        assert_eq!(text.data_type.size(), 32768);
+        text.count = 2;
+        assert_eq!(text.end(), 65536);
+        assert!(map(vec![text.clone()]).validate().is_err());
+        text.count = u16::MAX;
+        assert!(map(vec![text]).validate().is_err());
+
+        let mut array = Field::new("array", 0, DataType::U64);
+        array.count = 0x4000;
+        assert_eq!(array.end(), 0x10000);
+        assert!(map(vec![array.clone()]).validate().is_err());
+        array.count = 0x3FFF;
+        assert_eq!(array.len(), 0xFFFC);
+        assert!(map(vec![array]).validate().is_ok());
+
+        let bits = DataType::Bits {
+            bit: 200,
+            width: 200,
+        };
+        assert_eq!(bits.label(), "bits[200..400]");
+    }
+}
@@ -0,0 +1,200 @@
+use std::{fs, path::Path};
+
+use crate::resource::error::Result;
+
+/// sdkconfig 中选项名称的前缀, Kconfig 中没有
+pub const CONFIG_PREFIX: &str = "CONFIG_";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// CONFIG_NAME=value, 字符串保留引号
+    Value { name: String, value: String },
+    /// # CONFIG_NAME is not set
+    Unset { name: String },
+    /// 注释和空行, 保存时原样写回
+    Other(String),
+}
+
+/// sdkconfig 或 sdkconfig.defaults 文件, 修改时保留注释和顺序
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sdkconfig {
+    lines: Vec<Line>,
+}
+
+impl Sdkconfig {
+    pub fn parse(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim();
+                if let Some(name) = trimmed
+                    .strip_prefix("# ")
+                    .and_then(|s| s.strip_suffix(" is not set"))
+                    .and_then(|s| s.strip_prefix(CONFIG_PREFIX))
+                {
+                    return Line::Unset {
+                        name: name.to_string(),
+                    };
+                }
+                match trimmed
+                    .strip_prefix(CONFIG_PREFIX)
+                    .and_then(|s| s.split_once('='))
+                {
+                    Some((name, value)) => Line::Value {
+                        name: name.trim().to_string(),
+                        value: value.trim().to_string(),
+                    },
+                    None => Line::Other(line.to_string()),
+                }
+            })
+            .collect();
+        Self { lines }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for line in self.lines.iter() {
+            match line {
+                Line::Value { name, value } => {
+                    text.push_str(&format!("{}{}={}", CONFIG_PREFIX, name, value))
+                }
+                Line::Unset { name } => {
+                    text.push_str(&format!("# {}{} is not set", CONFIG_PREFIX, name))
+                }
+                Line::Other(other) => text.push_str(other),
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// 设置的选项名称, 不含前缀
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Value { name, .. } | Line::Unset { name } => Some(name.as_str()),
+            Line::Other(_) => None,
+        })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names().any(|n| n == name)
+    }
+
+    /// 选项的值, 字符串去掉引号, "is not set" 为 "n"
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Value { name: n, value } if n == name => Some(unquote(value)),
+            Line::Unset { name: n } if n == name => Some("n".to_string()),
+            _ => None,
+        })
+    }
+
+    /// 文件中的原始文本, 字符串带引号
+    pub fn get_raw(&self, name: &str) -> Option<String> {
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Value { name: n, value } if n == name => Some(value.clone()),
+            Line::Unset { name: n } if n == name => Some("n".to_string()),
+            _ => None,
+        })
+    }
+
+    /// 设置原始文本, 布尔值 n 写为 "is not set"
+    pub fn set_raw(&mut self, name: &str, value: &str) {
+        let line = if value == "n" {
+            Line::Unset {
+                name: name.to_string(),
+            }
+        } else {
+            Line::Value {
+                name: name.to_string(),
+                value: value.to_string(),
+            }
+        };
+        let existing = self.lines.iter_mut().find(|l| match l {
+            Line::Value { name: n, .. } | Line::Unset { name: n } => n == name,
+            Line::Other(_) => false,
+        });
+        match existing {
+            Some(existing) => *existing = line,
+            None => self.lines.push(line),
+        }
+    }
+
+    /// 设置字符串, 加上引号并转义
+    pub fn set_string(&mut self, name: &str, value: &str) {
+        self.set_raw(name, &quote(value));
+    }
+
+    /// 删除选项, 恢复为 Kconfig 中的默认值
+    pub fn remove(&mut self, name: &str) {
+        self.lines.retain(|l| match l {
+            Line::Value { name: n, .. } | Line::Unset { name: n } => n != name,
+            Line::Other(_) => true,
+        });
+    }
+}
+
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+pub fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(inner) => {
+            let mut result = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => result.extend(chars.next()),
+                    c => result.push(c),
+                }
+            }
+            result
+        }
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULTS: &str = include_str!("../../../modbus-rtu-example/sdkconfig.defaults");
+
+    #[test]
+    fn keep_comments_and_order() {
+        let mut config = Sdkconfig::parse(DEFAULTS);
+        assert_eq!(config.to_text(), DEFAULTS);
+        assert_eq!(config.get("MB_UART_RXD").as_deref(), Some("5"));
+        assert_eq!(config.get("FMB_TIMER_ISR_IN_IRAM").as_deref(), Some("y"));
+        assert_eq!(config.get("MB_UART_CTS"), None);
+
+        config.set_raw("MB_SLAVE_ADDR", "2");
+        config.set_raw("FMB_TIMER_ISR_IN_IRAM", "n");
+        config.set_string("PROJECT_NAME", "say \"hi\"");
+        config.remove("MB_UART_RTS");
+        let text = config.to_text();
+        assert!(text.starts_with("#\n# Modbus configuration\n#\n"));
+        assert!(text.contains("CONFIG_MB_SLAVE_ADDR=2\n"));
+        assert!(text.contains("# CONFIG_FMB_TIMER_ISR_IN_IRAM is not set\n"));
+        assert!(text.ends_with("CONFIG_PROJECT_NAME=\"say \\\"hi\\\"\"\n"));
+        assert!(!text.contains("MB_UART_RTS"));
+
+        let config = Sdkconfig::parse(&text);
+        assert_eq!(config.get("FMB_TIMER_ISR_IN_IRAM").as_deref(), Some("n"));
+        assert_eq!(config.get("PROJECT_NAME").as_deref(), Some("say \"hi\""));
+        assert_eq!(
+            config.get_raw("PROJECT_NAME").as_deref(),
+            Some("\"say \\\"hi\\\"\"")
+        );
+    }
+}
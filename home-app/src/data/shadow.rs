@@ -0,0 +1,356 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::device::DeviceRegistry;
+
+/// 影子中一个字段的同步状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    /// 设备上报的值与期望值一致
+    InSync,
+    /// 等待设备上报, 参数为已发送次数
+    Pending(u32),
+    /// 超过重试次数, 设备仍未同步
+    Failed,
+}
+
+impl SyncState {
+    pub fn label(&self) -> String {
+        match self {
+            SyncState::InSync => "已同步".to_string(),
+            SyncState::Pending(attempts) => format!("同步中({})", attempts),
+            SyncState::Failed => "未同步".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct RetryState {
+    attempts: u32,
+    last_sent: Option<Instant>,
+}
+
+/// 设备影子, 类似 AWS IoT 的设备影子, 保存期望状态和设备上报的状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Shadow {
+    /// ui 命令设置的期望状态
+    pub desired: Map<String, Value>,
+    /// 设备上报的状态
+    pub reported: Map<String, Value>,
+    /// desired 每次修改后加一
+    pub version: u64,
+    #[serde(skip)]
+    retry: RetryState,
+    /// 上一次检查时, 设备是否在线
+    #[serde(skip)]
+    online: bool,
+}
+
+impl Shadow {
+    /// 期望值与上报值不一致的字段
+    pub fn delta(&self) -> Map<String, Value> {
+        self.desired
+            .iter()
+            .filter(|(k, v)| self.reported.get(*k) != Some(v))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    pub fn in_sync(&self) -> bool {
+        self.desired
+            .iter()
+            .all(|(k, v)| self.reported.get(k) == Some(v))
+    }
+
+    /// 影子文档, 格式与 AWS IoT 设备影子相同
+    pub fn document(&self) -> Value {
+        json!({
+            "state": {
+                "desired": self.desired,
+                "reported": self.reported,
+                "delta": self.delta(),
+            },
+            "version": self.version,
+        })
+    }
+}
+
+/// 所有设备的影子
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShadowStore {
+    shadows: BTreeMap<String, Shadow>,
+    /// 重新发送 delta 的间隔, 单位: 秒
+    pub retry_interval: u64,
+    /// 最多发送次数
+    pub max_retries: u32,
+}
+
+impl Default for ShadowStore {
+    fn default() -> Self {
+        Self {
+            shadows: BTreeMap::new(),
+            retry_interval: 5,
+            max_retries: 10,
+        }
+    }
+}
+
+impl ShadowStore {
+    pub fn get(&self, id: &str) -> Option<&Shadow> {
+        self.shadows.get(id)
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        self.shadows.remove(id);
+    }
+
+    /// 字段的同步状态
+    pub fn field_state(&self, id: &str, field: &str) -> SyncState {
+        let shadow = match self.shadows.get(id) {
+            Some(shadow) => shadow,
+            None => return SyncState::InSync,
+        };
+        match shadow.desired.get(field) {
+            Some(desired) if shadow.reported.get(field) != Some(desired) => {
+                if self.exhausted(&shadow.retry) {
+                    SyncState::Failed
+                } else {
+                    SyncState::Pending(shadow.retry.attempts)
+                }
+            }
+            _ => SyncState::InSync,
+        }
+    }
+
+    /// 所有未同步字段的状态
+    pub fn out_of_sync(&self, id: &str) -> Vec<(String, SyncState)> {
+        match self.shadows.get(id) {
+            Some(shadow) => shadow
+                .delta()
+                .keys()
+                .map(|field| (field.clone(), self.field_state(id, field)))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// 修改期望状态, 重新开始计算重试次数
+    pub fn set_desired(&mut self, id: &str, fields: Map<String, Value>) {
+        let shadow = self.shadows.entry(id.to_string()).or_default();
+        shadow.desired.extend(fields);
+        shadow.version += 1;
+        shadow.retry = RetryState::default();
+    }
+
+    /// 放弃所有未同步的期望值
+    pub fn discard_delta(&mut self, id: &str) {
+        if let Some(shadow) = self.shadows.get_mut(id) {
+            let delta = shadow.delta();
+            for field in delta.keys() {
+                shadow.desired.remove(field);
+            }
+            shadow.version += 1;
+            shadow.retry = RetryState::default();
+        }
+    }
+
+    /// 设备上报状态后调用
+    /// 已经同步的期望值随即删除, 之后设备在应用以外的修改 (墙壁开关, 固件) 不会被覆盖
+    pub fn on_reported(&mut self, id: &str, reported: &Map<String, Value>) {
+        let shadow = self.shadows.entry(id.to_string()).or_default();
+        shadow.reported = reported.clone();
+        let len = shadow.desired.len();
+        shadow.desired.retain(|k, v| reported.get(k) != Some(v));
+        if shadow.desired.len() != len {
+            shadow.version += 1;
+        }
+        if shadow.in_sync() {
+            shadow.retry = RetryState::default();
+        }
+    }
+
+    /// 返回需要(重新)发送 delta 的设备
+    /// 离线的设备不发送, 也不计入重试次数; 设备重新上线时, 立即发送, 并重新计算重试次数
+    pub fn due(&mut self, devices: &DeviceRegistry) -> Vec<(String, Map<String, Value>)> {
+        self.due_at(devices, Instant::now())
+    }
+
+    fn due_at(
+        &mut self,
+        devices: &DeviceRegistry,
+        now: Instant,
+    ) -> Vec<(String, Map<String, Value>)> {
+        let interval = Duration::from_secs(self.retry_interval);
+        let max_retries = self.max_retries;
+
+        let mut due = vec![];
+        for (id, shadow) in self.shadows.iter_mut() {
+            // 没有注册的设备不知道是否在线, 照常发送
+            let online = devices.get(id).map_or(true, |d| d.online);
+            if online && !shadow.online {
+                shadow.retry = RetryState::default();
+            }
+            shadow.online = online;
+
+            if !online {
+                continue;
+            }
+            let delta = shadow.delta();
+            if delta.is_empty() || shadow.retry.attempts >= max_retries {
+                continue;
+            }
+            let elapsed = shadow.retry.last_sent.map_or(true, |t| now - t >= interval);
+            if elapsed {
+                shadow.retry.attempts += 1;
+                shadow.retry.last_sent = Some(now);
+                due.push((id.clone(), delta));
+            }
+        }
+        due
+    }
+
+    fn exhausted(&self, retry: &RetryState) -> bool {
+        retry.attempts >= self.max_retries
+            && retry.last_sent.map_or(true, |t| {
+                t.elapsed() >= Duration::from_secs(self.retry_interval)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    /// 模拟设备上报, 与 DeviceRegistry::handle_message 相同, 返回设备当前的全部状态
+    fn report(devices: &mut DeviceRegistry, id: &str, state: Value) -> Map<String, Value> {
+        let device = devices.get_or_insert(id);
+        device.online = true;
+        device.reported.extend(fields(state));
+        device.reported.clone()
+    }
+
+    fn store() -> ShadowStore {
+        ShadowStore {
+            max_retries: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn synced_desired_is_not_republished() {
+        let mut store = store();
+        let mut devices = DeviceRegistry::default();
+        let t = Instant::now();
+        let reported = report(&mut devices, "lamp", json!({ "power": false }));
+        store.on_reported("lamp", &reported);
+
+        store.set_desired("lamp", fields(json!({ "power": true })));
+        assert_eq!(store.field_state("lamp", "power"), SyncState::Pending(0));
+        let due = store.due_at(&devices, t);
+        assert_eq!(
+            due,
+            vec![("lamp".to_string(), fields(json!({ "power": true })))]
+        );
+
+        let reported = report(&mut devices, "lamp", json!({ "power": true }));
+        store.on_reported("lamp", &reported);
+        assert!(store.get("lamp").unwrap().desired.is_empty());
+        assert_eq!(store.field_state("lamp", "power"), SyncState::InSync);
+
+        // 设备重启后重新上线, 期间墙壁开关把灯关了
+        devices.get_mut("lamp").unwrap().online = false;
+        assert!(store
+            .due_at(&devices, t + Duration::from_secs(60))
+            .is_empty());
+        let reported = report(&mut devices, "lamp", json!({ "power": false }));
+        store.on_reported("lamp", &reported);
+        assert!(store
+            .due_at(&devices, t + Duration::from_secs(61))
+            .is_empty());
+        assert!(store.out_of_sync("lamp").is_empty());
+    }
+
+    #[test]
+    fn retry_until_reported() {
+        let mut store = store();
+        let mut devices = DeviceRegistry::default();
+        let t = Instant::now();
+        let reported = report(&mut devices, "fan", json!({ "speed": 1, "power": true }));
+        store.on_reported("fan", &reported);
+        store.set_desired("fan", fields(json!({ "speed": 3, "power": true })));
+
+        // power 已经一致, 只发送 speed
+        let delta = fields(json!({ "speed": 3 }));
+        assert_eq!(store.due_at(&devices, t), vec![("fan".to_string(), delta)]);
+        assert!(store
+            .due_at(&devices, t + Duration::from_secs(1))
+            .is_empty());
+        assert_eq!(store.due_at(&devices, t + Duration::from_secs(5)).len(), 1);
+        assert_eq!(store.due_at(&devices, t + Duration::from_secs(10)).len(), 1);
+        assert!(store
+            .due_at(&devices, t + Duration::from_secs(15))
+            .is_empty());
+        assert_eq!(store.field_state("fan", "speed"), SyncState::Pending(3));
+
+        // 修改期望值后重新计算重试次数
+        store.set_desired("fan", fields(json!({ "speed": 2 })));
+        assert_eq!(store.due_at(&devices, t + Duration::from_secs(20)).len(), 1);
+        let reported = report(&mut devices, "fan", json!({ "speed": 2 }));
+        store.on_reported("fan", &reported);
+        assert!(store.get("fan").unwrap().in_sync());
+        assert!(store
+            .due_at(&devices, t + Duration::from_secs(30))
+            .is_empty());
+    }
+
+    #[test]
+    fn offline_devices_keep_attempts() {
+        let mut store = store();
+        let mut devices = DeviceRegistry::default();
+        let t = Instant::now();
+        let reported = report(&mut devices, "lamp", json!({ "power": false }));
+        store.on_reported("lamp", &reported);
+        devices.get_mut("lamp").unwrap().online = false;
+
+        // 设备重启期间设置的命令, 不发送也不消耗重试次数
+        store.set_desired("lamp", fields(json!({ "power": true })));
+        for i in 0..10 {
+            assert!(store
+                .due_at(&devices, t + Duration::from_secs(i * 5))
+                .is_empty());
+        }
+        assert_eq!(store.field_state("lamp", "power"), SyncState::Pending(0));
+
+        devices.get_mut("lamp").unwrap().online = true;
+        let due = store.due_at(&devices, t + Duration::from_secs(60));
+        assert_eq!(
+            due,
+            vec![("lamp".to_string(), fields(json!({ "power": true })))]
+        );
+
+        // 没有注册的设备照常发送
+        store.set_desired("unknown", fields(json!({ "power": true })));
+        assert_eq!(store.due_at(&devices, t + Duration::from_secs(61)).len(), 1);
+    }
+
+    #[test]
+    fn discard_delta() {
+        let mut store = store();
+        store.set_desired("lamp", fields(json!({ "power": true })));
+        let version = store.get("lamp").unwrap().version;
+        store.discard_delta("lamp");
+        let shadow = store.get("lamp").unwrap();
+        assert!(shadow.desired.is_empty());
+        assert_eq!(shadow.version, version + 1);
+        assert_eq!(shadow.document()["state"]["delta"], json!({}));
+    }
+}
@@ -43,7 +43,7 @@ impl Storage for FileStorage {
 
     fn flush(&mut self) {
         if self.dirty {
-            log::debug!("Persisted to {}", self.json_filepath.display());
+            tracing::debug!("Persisted to {}", self.json_filepath.display());
             let file = std::fs::File::create(&self.json_filepath).unwrap();
             if serde_json::to_writer_pretty(file, &self.kv).is_ok() {
                 self.dirty = false;
@@ -9,6 +9,13 @@ use std::{
     time::{Duration, Instant},
 };
 
+/// 应用数据所在的目录, 即可执行文件所在路径
+pub fn app_dir() -> PathBuf {
+    let mut dir = current_exe().unwrap();
+    dir.pop();
+    dir
+}
+
 /// 在一定的时间间隔后, 如果数据变化, 就保存到文件
 pub struct Persistence {
     storage: Box<dyn Storage>,
@@ -19,8 +26,7 @@ pub struct Persistence {
 impl Default for Persistence {
     /// 默认配置文件路径是可执行文件所在路径
     fn default() -> Self {
-        let mut config_file = current_exe().unwrap();
-        config_file.pop();
+        let mut config_file = app_dir();
         config_file.push(format!("{}.json", APP_NAME));
         tracing::info!("config file: {:?}", &config_file);
 
@@ -63,6 +69,12 @@ impl Persistence {
         self.storage.flush();
     }
 
+    /// 距离上次自动保存已经超过了间隔
+    #[inline(always)]
+    pub fn autosave_due(&self) -> bool {
+        self.last_auto_save.elapsed() > self.auto_save_interval
+    }
+
     #[inline(always)]
     pub fn maybe_autosave(&mut self) {
         let now = Instant::now();
@@ -0,0 +1,5 @@
+pub mod data;
+pub mod resource;
+pub mod service;
+pub mod ui;
+pub mod window;
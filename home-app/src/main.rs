@@ -6,15 +6,11 @@ use epi::egui::vec2;
 use epi::{egui, App, NativeOptions};
 use parking_lot::RwLock;
 // use tracing_log::LogTracer;
-use window::MainWindow;
+use home_app::window::MainWindow;
 use winit::dpi::PhysicalPosition;
 use winit::event::*;
 use winit::event_loop::ControlFlow;
 
-mod resource;
-mod ui;
-mod window;
-
 pub enum CustomEvent {
     RequestRedraw,
 }
@@ -7,3 +7,19 @@ pub mod icons {
 pub mod fonts {
     pub const FONT_CHINESE: &[u8] = include_bytes!("fonts/DroidSansFallbackFull.ttf");
 }
+
+/// 设备的 mqtt topic 约定: home/<设备id>/<子topic>
+pub mod topics {
+    pub const DEVICE_PREFIX: &str = "home";
+    /// 设备上报的状态, json 对象
+    pub const STATE: &str = "state";
+    /// 下发给设备的命令, json 对象
+    pub const SET: &str = "set";
+    /// 设备在线状态, "online" 或 "offline"
+    pub const AVAILABILITY: &str = "availability";
+}
+
+/// 内置的 modbus 寄存器表
+pub mod register_maps {
+    pub const MODBUS_RTU_EXAMPLE: &str = include_str!("config/modbus_rtu_example.toml");
+}
@@ -2,6 +2,8 @@ use image::ImageError;
 use thiserror::Error;
 use winit::window::BadIcon;
 
+use crate::service::modbus::pdu::ExceptionCode;
+
 // #[non_exhaustive]
 #[derive(Error, Debug, Clone, Eq, PartialEq)]
 pub enum AppError {
@@ -11,6 +13,39 @@ pub enum AppError {
     #[error("Mqtt服务未配置")]
     MqttServerNoConfig,
 
+    #[error("Mqtt客户端未启动")]
+    MqttClientNotStarted,
+
+    #[error("Mqtt未连接, 命令将在连接后重发")]
+    MqttClientNotConnected,
+
+    #[error("串口错误: {0}")]
+    Serial(String),
+
+    #[error("Modbus 连接失败: {0}")]
+    ModbusConnection(String),
+
+    #[error("Modbus 响应超时")]
+    ModbusTimeout,
+
+    #[error("Modbus CRC 校验错误: 期望 {expected:04X}, 实际 {actual:04X}")]
+    ModbusCrc { expected: u16, actual: u16 },
+
+    #[error("Modbus LRC 校验错误: 期望 {expected:02X}, 实际 {actual:02X}")]
+    ModbusLrc { expected: u8, actual: u8 },
+
+    #[error("Modbus 异常响应, 功能码 0x{function:02X}: {code}")]
+    ModbusException { function: u8, code: ExceptionCode },
+
+    #[error("Modbus 响应无效: {0}")]
+    ModbusInvalidResponse(String),
+
+    #[error("Modbus 请求无效: {0}")]
+    ModbusInvalidRequest(String),
+
+    #[error("寄存器表错误: {0}")]
+    RegisterMap(String),
+
     #[error("未知错误, 请联系开发人员.")]
     Unknown,
 }
@@ -29,6 +64,30 @@ impl From<ImageError> for AppError {
     }
 }
 
+impl From<rumqttc::ClientError> for AppError {
+    fn from(e: rumqttc::ClientError) -> Self {
+        AppError::Error(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Error(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Error(e.to_string())
+    }
+}
+
+impl From<serialport::Error> for AppError {
+    fn from(e: serialport::Error) -> Self {
+        AppError::Serial(e.to_string())
+    }
+}
+
 impl From<String> for AppError {
     fn from(e: String) -> Self {
         AppError::Error(e)
@@ -1 +1,20 @@
-pub mod mqtt_server;
\ No newline at end of file
+use std::{sync::Arc, time::Duration};
+
+pub mod modbus;
+pub mod mqtt_client;
+pub mod mqtt_server;
+pub mod serial_monitor;
+
+/// 后台服务收到数据后, 通知 ui 重绘
+pub type RepaintSignal = Arc<dyn Fn() + Send + Sync>;
+
+/// 定时请求重绘, 保证定时检查的逻辑(如告警超时)在没有输入事件时也能运行
+pub fn spawn_ticker(repaint: RepaintSignal, interval: Duration) {
+    let builder = std::thread::Builder::new().name("ui-ticker".to_string());
+    builder
+        .spawn(move || loop {
+            std::thread::sleep(interval);
+            repaint();
+        })
+        .ok();
+}
@@ -0,0 +1,208 @@
+use std::{
+    io::{self, ErrorKind},
+    time::{Duration, Instant},
+};
+
+use crate::resource::error::{AppError, Result};
+
+use super::rtu::is_timeout;
+
+/// ascii 帧的最大长度: ':' + 2 * (地址 + 253 字节 pdu + lrc) + CRLF
+pub(super) const MAX_FRAME: usize = 513;
+
+/// LRC, 地址和 pdu 各字节之和的补码
+pub fn lrc(data: &[u8]) -> u8 {
+    data.iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg()
+}
+
+/// 组装 ascii 帧: ':' + 十六进制(从站地址 + pdu + lrc) + CRLF
+pub fn encode_frame(slave: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(pdu.len() + 2);
+    body.push(slave);
+    body.extend_from_slice(pdu);
+    body.push(lrc(&body));
+
+    let mut frame = Vec::with_capacity(body.len() * 2 + 3);
+    frame.push(b':');
+    for byte in body {
+        frame.extend_from_slice(format!("{:02X}", byte).as_bytes());
+    }
+    frame.extend_from_slice(b"\r\n");
+    frame
+}
+
+/// 检查格式和 lrc, 返回 (从站地址, pdu)
+pub fn decode_frame(frame: &[u8]) -> Result<(u8, Vec<u8>)> {
+    let invalid = |message: &str| AppError::ModbusInvalidResponse(message.to_string());
+    let text = frame
+        .strip_prefix(b":")
+        .and_then(|f| f.strip_suffix(b"\r\n"))
+        .ok_or_else(|| invalid("ascii 帧应以 ':' 开始, CRLF 结束"))?;
+    if text.len() < 6 || text.len() % 2 != 0 {
+        return Err(invalid("ascii 帧长度错误"));
+    }
+    let body = text
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| invalid("ascii 帧包含非十六进制字符"))?;
+    let (data, actual) = body.split_at(body.len() - 1);
+    let expected = lrc(data);
+    if expected != actual[0] {
+        return Err(AppError::ModbusLrc {
+            expected,
+            actual: actual[0],
+        });
+    }
+    Ok((data[0], data[1..].to_vec()))
+}
+
+/// 读取一个 ascii 帧, 丢弃 ':' 之前的字节, 收到 LF 时结束
+pub(super) fn read_frame<F>(mut read: F, timeout: Duration) -> Result<Vec<u8>>
+where
+    F: FnMut(&mut [u8], Duration) -> io::Result<usize>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut frame = Vec::with_capacity(MAX_FRAME);
+    let mut buf = [0u8; 1];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            if !frame.is_empty() {
+                tracing::debug!("ascii rx (不完整): {}", String::from_utf8_lossy(&frame));
+            }
+            return Err(AppError::ModbusTimeout);
+        }
+        match read(&mut buf, remaining) {
+            Ok(0) => {}
+            Ok(_) => {
+                let byte = buf[0];
+                if byte == b':' {
+                    frame.clear();
+                }
+                if frame.is_empty() && byte != b':' {
+                    continue;
+                }
+                frame.push(byte);
+                if byte == b'\n' {
+                    return Ok(frame);
+                }
+                if frame.len() > MAX_FRAME {
+                    return Err(AppError::ModbusInvalidResponse("ascii 帧过长".to_string()));
+                }
+            }
+            Err(e) if is_timeout(&e) || e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::modbus::{ModbusMaster, Transport};
+
+    #[test]
+    fn lrc_known_vectors() {
+        // Modbus over serial line 规范中的示例
+        assert_eq!(lrc(&[0xF7, 0x03, 0x13, 0x89, 0x00, 0x0A]), 0x60);
+        assert_eq!(lrc(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x02]), 0xFA);
+        assert_eq!(lrc(&[]), 0x00);
+        assert_eq!(
+            encode_frame(0xF7, &[0x03, 0x13, 0x89, 0x00, 0x0A]),
+            b":F7031389000A60\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let pdu = [0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0xFF, 0xFF];
+        let frame = encode_frame(0x11, &pdu);
+        assert_eq!(decode_frame(&frame).unwrap(), (0x11, pdu.to_vec()));
+        // 小写的十六进制字符也可以解析
+        let lower = frame.to_ascii_lowercase();
+        assert_eq!(decode_frame(&lower).unwrap(), (0x11, pdu.to_vec()));
+    }
+
+    #[test]
+    fn reject_invalid_frames() {
+        assert_eq!(
+            decode_frame(b":010300000002FB\r\n"),
+            Err(AppError::ModbusLrc {
+                expected: 0xFA,
+                actual: 0xFB,
+            })
+        );
+        let invalid =
+            |frame: &[u8]| matches!(decode_frame(frame), Err(AppError::ModbusInvalidResponse(_)));
+        // 奇数个字符
+        assert!(invalid(b":010300000002F\r\n"));
+        assert!(invalid(b":0103FA0\r\n"));
+        // 太短
+        assert!(invalid(b":01FF\r\n"));
+        // 非十六进制字符
+        assert!(invalid(b":01030000000GFA\r\n"));
+        assert!(invalid(b":01030000 002FA\r\n"));
+        // 缺少起始符或 CRLF
+        assert!(invalid(b"010300000002FA\r\n"));
+        assert!(invalid(b":010300000002FA\n"));
+    }
+
+    #[test]
+    fn read_frame_skips_noise() {
+        let mut data = b"\x00xx:01".to_vec();
+        data.extend_from_slice(&encode_frame(1, &[0x83, 0x02]));
+        data.extend_from_slice(b":01");
+        let mut bytes = data.into_iter();
+        let read = |buf: &mut [u8], _| match bytes.next() {
+            Some(byte) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            None => Err(io::Error::new(ErrorKind::TimedOut, "timeout")),
+        };
+        let frame = read_frame(read, Duration::from_millis(100)).unwrap();
+        assert_eq!(frame, encode_frame(1, &[0x83, 0x02]));
+
+        let read = |_: &mut [u8], _| Err(io::Error::new(ErrorKind::TimedOut, "timeout"));
+        assert_eq!(
+            read_frame(read, Duration::from_millis(20)),
+            Err(AppError::ModbusTimeout)
+        );
+    }
+
+    /// 帧在 ascii 总线上被干扰, 第一次返回 lrc 错误
+    struct NoisyTransport {
+        frames: Vec<Vec<u8>>,
+    }
+
+    impl Transport for NoisyTransport {
+        fn transact(&mut self, slave: u8, _pdu: &[u8], _timeout: Duration) -> Result<Vec<u8>> {
+            let (address, pdu) = decode_frame(&self.frames.remove(0))?;
+            assert_eq!(address, slave);
+            Ok(pdu)
+        }
+
+        fn label(&self) -> String {
+            "noisy".to_string()
+        }
+    }
+
+    #[test]
+    fn retry_lrc_errors() {
+        let response = encode_frame(1, &[0x03, 0x02, 0x12, 0x34]);
+        let mut corrupted = response.clone();
+        corrupted[7] = b'5';
+        let transport = NoisyTransport {
+            frames: vec![corrupted, response],
+        };
+        let mut master = ModbusMaster::new(Box::new(transport));
+        assert_eq!(master.read_holding_registers(1, 0, 1), Ok(vec![0x1234]));
+    }
+}
@@ -0,0 +1,255 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::resource::error::{AppError, Result};
+
+pub mod ascii;
+pub mod mqtt_gateway;
+pub mod pdu;
+pub mod poller;
+pub mod recipe;
+pub mod recorder;
+pub mod rtu;
+pub mod scanner;
+pub mod scheduler;
+pub mod simulator;
+pub mod slave;
+pub mod sniffer;
+pub mod tcp;
+pub mod tcp_gateway;
+
+use pdu::{Request, Response};
+use rtu::{RtuTransport, SerialConfig};
+use tcp::{RtuOverTcpTransport, TcpConfig, TcpTransport};
+
+/// 以十六进制显示报文, 如 "01 03 00 00 00 02 C4 0B"
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 传输层, 负责组帧和收发, 请求的编码和解析由 ModbusMaster 完成
+pub trait Transport: Send {
+    /// 发送请求 pdu 并等待响应, 返回响应的 pdu
+    fn transact(&mut self, slave: u8, pdu: &[u8], timeout: Duration) -> Result<Vec<u8>>;
+
+    /// 用于界面和日志显示
+    fn label(&self) -> String;
+}
+
+/// 从站的连接方式
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TransportConfig {
+    /// 串口 rtu 或 ascii, 见 SerialConfig::mode
+    Rtu(SerialConfig),
+    /// Modbus TCP
+    Tcp(TcpConfig),
+    /// 通过串口服务器透传的 rtu 帧
+    RtuOverTcp(TcpConfig),
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        TransportConfig::Rtu(SerialConfig::default())
+    }
+}
+
+impl TransportConfig {
+    pub fn label(&self) -> String {
+        match self {
+            TransportConfig::Rtu(config) => format!(
+                "{} {} {}",
+                config.mode.label().to_lowercase(),
+                config.port,
+                config.baud_rate
+            ),
+            TransportConfig::Tcp(config) => format!("tcp {}:{}", config.host, config.port),
+            TransportConfig::RtuOverTcp(config) => {
+                format!("rtu over tcp {}:{}", config.host, config.port)
+            }
+        }
+    }
+
+    /// 物理总线的标识, 同一个串口或同一个 tcp 地址只能有一个主站
+    pub fn bus_key(&self) -> String {
+        match self {
+            TransportConfig::Rtu(config) => config.port.clone(),
+            TransportConfig::Tcp(config) | TransportConfig::RtuOverTcp(config) => {
+                format!("{}:{}", config.host, config.port)
+            }
+        }
+    }
+
+    /// 打开传输层, tcp 连接在第一次请求时建立
+    pub fn open(&self) -> Result<Box<dyn Transport>> {
+        Ok(match self {
+            TransportConfig::Rtu(config) => Box::new(RtuTransport::open(config.clone())?),
+            TransportConfig::Tcp(config) => Box::new(TcpTransport::new(config.clone())),
+            TransportConfig::RtuOverTcp(config) => {
+                Box::new(RtuOverTcpTransport::new(config.clone()))
+            }
+        })
+    }
+}
+
+/// Modbus 主站, 与传输方式无关
+pub struct ModbusMaster {
+    transport: Box<dyn Transport>,
+    /// 默认的响应超时
+    pub timeout: Duration,
+    /// 默认的重试次数, 不包括第一次请求
+    pub retries: u32,
+}
+
+impl ModbusMaster {
+    pub fn new(transport: Box<dyn Transport>) -> Self {
+        Self {
+            transport,
+            timeout: Duration::from_millis(500),
+            retries: 2,
+        }
+    }
+
+    pub fn open(config: &TransportConfig) -> Result<Self> {
+        Ok(Self::new(config.open()?))
+    }
+
+    pub fn label(&self) -> String {
+        self.transport.label()
+    }
+
+    /// 使用默认的超时和重试次数发送请求
+    pub fn request(&mut self, slave: u8, request: &Request) -> Result<Response> {
+        self.request_with(slave, request, self.timeout, self.retries)
+    }
+
+    /// 发送请求, 超时, 连接错误, crc 或 lrc 错误和无效响应会重试, 异常响应不重试
+    pub fn request_with(
+        &mut self,
+        slave: u8,
+        request: &Request,
+        timeout: Duration,
+        retries: u32,
+    ) -> Result<Response> {
+        let pdu = request.encode()?;
+
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .transport
+                .transact(slave, &pdu, timeout)
+                .and_then(|response| request.decode_response(&response));
+            match result {
+                Err(e) if attempt < retries && Self::retryable(&e) => {
+                    attempt += 1;
+                    tracing::warn!("从站 {} 请求失败, 第 {} 次重试: {}", slave, attempt, e);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// 可以重试的错误
+    pub fn retryable(e: &AppError) -> bool {
+        matches!(
+            e,
+            AppError::ModbusTimeout
+                | AppError::ModbusConnection(_)
+                | AppError::ModbusCrc { .. }
+                | AppError::ModbusLrc { .. }
+                | AppError::ModbusInvalidResponse(_)
+        )
+    }
+
+    /// 01 读线圈
+    pub fn read_coils(&mut self, slave: u8, address: u16, quantity: u16) -> Result<Vec<bool>> {
+        let request = Request::ReadCoils { address, quantity };
+        self.request(slave, &request).map(Self::bits)
+    }
+
+    /// 02 读离散输入
+    pub fn read_discrete_inputs(
+        &mut self,
+        slave: u8,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<bool>> {
+        let request = Request::ReadDiscreteInputs { address, quantity };
+        self.request(slave, &request).map(Self::bits)
+    }
+
+    /// 03 读保持寄存器
+    pub fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        let request = Request::ReadHoldingRegisters { address, quantity };
+        self.request(slave, &request).map(Self::registers)
+    }
+
+    /// 04 读输入寄存器
+    pub fn read_input_registers(
+        &mut self,
+        slave: u8,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        let request = Request::ReadInputRegisters { address, quantity };
+        self.request(slave, &request).map(Self::registers)
+    }
+
+    /// 05 写单个线圈
+    pub fn write_single_coil(&mut self, slave: u8, address: u16, value: bool) -> Result<()> {
+        let request = Request::WriteSingleCoil { address, value };
+        self.request(slave, &request).map(|_| ())
+    }
+
+    /// 06 写单个寄存器
+    pub fn write_single_register(&mut self, slave: u8, address: u16, value: u16) -> Result<()> {
+        let request = Request::WriteSingleRegister { address, value };
+        self.request(slave, &request).map(|_| ())
+    }
+
+    /// 15 写多个线圈
+    pub fn write_multiple_coils(&mut self, slave: u8, address: u16, values: &[bool]) -> Result<()> {
+        let request = Request::WriteMultipleCoils {
+            address,
+            values: values.to_vec(),
+        };
+        self.request(slave, &request).map(|_| ())
+    }
+
+    /// 16 写多个寄存器
+    pub fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        address: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        let request = Request::WriteMultipleRegisters {
+            address,
+            values: values.to_vec(),
+        };
+        self.request(slave, &request).map(|_| ())
+    }
+
+    fn bits(response: Response) -> Vec<bool> {
+        match response {
+            Response::Bits(bits) => bits,
+            _ => vec![],
+        }
+    }
+
+    fn registers(response: Response) -> Vec<u16> {
+        match response {
+            Response::Registers(registers) => registers,
+            _ => vec![],
+        }
+    }
+}
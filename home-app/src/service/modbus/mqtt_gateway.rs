@@ -0,0 +1,721 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use parking_lot::{Mutex, MutexGuard};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    data::{
+        device::{device_topic, device_topic_filter, parse_device_topic},
+        modbus_device::ModbusDevice,
+        register_map::{Area, Field, RegisterMap},
+    },
+    resource::{
+        defines::topics::{AVAILABILITY, SET, STATE},
+        error::{AppError, Result},
+    },
+    service::{mqtt_client::MqttConfig, RepaintSignal},
+};
+
+use super::{
+    pdu::{Request, Response},
+    scheduler::{BusHandle, ModbusScheduler, Priority},
+};
+
+/// 网关自身统计信息的发布间隔
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 发布的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PublishFormat {
+    /// home/<设备>/state 发布 json 对象, 与应用中其它设备的约定相同
+    Json,
+    /// 每个字段一个 topic: home/<设备>/state/<字段>
+    PerField,
+}
+
+impl PublishFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PublishFormat::Json => "JSON",
+            PublishFormat::PerField => "每个字段一个 topic",
+        }
+    }
+}
+
+/// 发布的时机
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PublishMode {
+    /// 字段的值变化时发布
+    OnChange,
+    /// 按固定间隔发布所有字段
+    Periodic,
+}
+
+impl PublishMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PublishMode::OnChange => "变化时",
+            PublishMode::Periodic => "定时",
+        }
+    }
+}
+
+/// 网关参数, 连接的 broker 与应用的 mqtt 客户端相同
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GatewayConfig {
+    /// 网关的 mqtt 客户端 id, 网关的在线状态发布到 home/<id>/availability
+    pub id: String,
+    /// 桥接的 Modbus 设备名称, 为空时桥接所有设备
+    pub devices: Vec<String>,
+    pub format: PublishFormat,
+    pub mode: PublishMode,
+    /// 定时发布的间隔, 单位: 秒
+    pub publish_interval: u64,
+    pub retain: bool,
+    /// 应用启动时自动启动网关
+    pub auto_start: bool,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            id: "modbus-gateway".into(),
+            devices: vec![],
+            format: PublishFormat::Json,
+            mode: PublishMode::OnChange,
+            publish_interval: 10,
+            retain: true,
+            auto_start: false,
+        }
+    }
+}
+
+/// 一个从站的轮询统计
+#[derive(Debug, Clone, Default)]
+pub struct SlaveStats {
+    pub device: String,
+    /// 连接方式和从站地址
+    pub label: String,
+    pub polls: u64,
+    pub failures: u64,
+    /// 连续失败的次数, 成功后清零
+    pub consecutive_failures: u64,
+    /// 最后一次轮询所有读请求的总时间
+    pub latency: Option<Duration>,
+    pub last_poll: Option<DateTime<Local>>,
+    pub last_error: Option<String>,
+    /// 最后一次轮询是否成功
+    pub online: bool,
+    pub publishes: u64,
+    pub writes: u64,
+    pub write_failures: u64,
+}
+
+impl SlaveStats {
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "polls": self.polls,
+            "failures": self.failures,
+            "consecutive_failures": self.consecutive_failures,
+            "latency_ms": self.latency.map(|l| l.as_secs_f64() * 1000.0),
+            "online": self.online,
+            "publishes": self.publishes,
+            "writes": self.writes,
+            "write_failures": self.write_failures,
+            "last_error": self.last_error,
+        })
+    }
+}
+
+/// 网关的运行状态
+#[derive(Debug, Default)]
+pub struct GatewayState {
+    pub connected: bool,
+    /// 按设备顺序
+    pub slaves: Vec<SlaveStats>,
+    /// 某条总线无法打开等错误
+    pub errors: Vec<String>,
+}
+
+/// mqtt 消息中要写入的字段
+type FieldValues = Vec<(String, Value)>;
+
+enum Command {
+    Write { device: usize, values: FieldValues },
+    Stop,
+}
+
+/// Modbus 到 mqtt 的网关
+///
+/// 按寄存器表轮询从站, 把工程值发布到 broker, 并把 home/<设备>/set 的消息转换为写请求
+/// 同一条总线(如同一个串口)上的设备共用一个后台线程, 通过总线调度器与其它页面排队访问总线
+pub struct MqttGateway {
+    id: String,
+    client: Client,
+    buses: Vec<Sender<Command>>,
+    /// 所有设备的 topic id
+    devices: Vec<String>,
+    retain: bool,
+    state: Arc<Mutex<GatewayState>>,
+    running: Arc<AtomicBool>,
+}
+
+impl MqttGateway {
+    pub fn start(
+        config: &GatewayConfig,
+        mqtt: &MqttConfig,
+        scheduler: &ModbusScheduler,
+        devices: Vec<(ModbusDevice, RegisterMap)>,
+        repaint: RepaintSignal,
+    ) -> Result<Self> {
+        if devices.is_empty() {
+            return Err(AppError::Error("没有需要桥接的 Modbus 设备".to_string()));
+        }
+
+        let availability = device_topic(&topic_id(&config.id), AVAILABILITY);
+        let mut options = MqttOptions::new(config.id.clone(), mqtt.host.clone(), mqtt.port);
+        options.set_keep_alive(Duration::from_secs(mqtt.keep_alive));
+        options.set_last_will(LastWill::new(
+            &availability,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        let (client, mut connection) = Client::new(options, 256);
+
+        let state = Arc::new(Mutex::new(GatewayState::default()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        // 按总线分组, 同一条总线上的设备依次轮询
+        let mut groups: BTreeMap<String, Vec<(usize, ModbusDevice, RegisterMap)>> = BTreeMap::new();
+        for (index, (device, map)) in devices.into_iter().enumerate() {
+            state.lock().slaves.push(SlaveStats {
+                device: device.name.clone(),
+                label: format!("{} #{}", device.transport.label(), device.slave),
+                ..Default::default()
+            });
+            groups
+                .entry(device.transport.bus_key())
+                .or_default()
+                .push((index, device, map));
+        }
+
+        let mut routes = HashMap::new();
+        let mut buses = vec![];
+        let mut topic_ids = vec![];
+        for (label, devices) in groups {
+            let (tx, rx) = channel();
+            let handle = scheduler.bus(&devices[0].1.transport);
+            let bus_devices: Vec<BusDevice> = devices
+                .into_iter()
+                .enumerate()
+                .map(|(i, (stats, device, map))| {
+                    let id = topic_id(&device.name);
+                    routes.insert(id.clone(), (tx.clone(), i));
+                    topic_ids.push(id.clone());
+                    BusDevice::new(stats, id, device, map)
+                })
+                .collect();
+            let bus = Bus {
+                label: label.clone(),
+                config: config.clone(),
+                devices: bus_devices,
+                client: client.clone(),
+                state: state.clone(),
+                repaint: repaint.clone(),
+            };
+            thread::Builder::new()
+                .name("modbus-gateway".to_string())
+                .spawn(move || bus.run(rx, handle))?;
+            buses.push(tx);
+        }
+
+        let subscriptions = [
+            device_topic_filter(SET),
+            format!("{}/+", device_topic_filter(SET)),
+        ];
+        let mut sub_client = client.clone();
+        let thread_state = state.clone();
+        let thread_running = running.clone();
+        let stats_topic = device_topic(&topic_id(&config.id), "stats");
+        thread::Builder::new()
+            .name("mqtt-gateway".to_string())
+            .spawn(move || {
+                let mut last_stats = Instant::now();
+                for notification in connection.iter() {
+                    if !thread_running.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    match notification {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            tracing::info!("modbus 网关已连接 mqtt");
+                            thread_state.lock().connected = true;
+                            sub_client
+                                .try_publish(&availability, QoS::AtLeastOnce, true, "online")
+                                .ok();
+                            for topic in subscriptions.iter() {
+                                if let Err(e) = sub_client.try_subscribe(topic, QoS::AtLeastOnce) {
+                                    tracing::error!("订阅 {} 失败: {}", topic, e);
+                                }
+                            }
+                            repaint();
+                        }
+                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                            route_set(&routes, &publish.topic, &publish.payload, &thread_state);
+                        }
+                        Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) => break,
+                        Ok(_) => {}
+                        Err(e) => {
+                            thread_state.lock().connected = false;
+                            repaint();
+                            tracing::warn!("modbus 网关 mqtt 连接错误: {}", e);
+                            thread::sleep(Duration::from_secs(1));
+                        }
+                    }
+                    if last_stats.elapsed() >= STATS_INTERVAL {
+                        last_stats = Instant::now();
+                        let stats: Map<String, Value> = thread_state
+                            .lock()
+                            .slaves
+                            .iter()
+                            .map(|s| (s.device.clone(), s.to_json()))
+                            .collect();
+                        sub_client
+                            .try_publish(
+                                &stats_topic,
+                                QoS::AtMostOnce,
+                                false,
+                                Value::Object(stats).to_string(),
+                            )
+                            .ok();
+                    }
+                }
+                thread_state.lock().connected = false;
+            })?;
+
+        tracing::info!("modbus 网关已启动, {} 条总线", buses.len());
+        Ok(Self {
+            id: config.id.clone(),
+            client,
+            buses,
+            devices: topic_ids,
+            retain: config.retain,
+            state,
+            running,
+        })
+    }
+
+    pub fn state(&self) -> MutexGuard<GatewayState> {
+        self.state.lock()
+    }
+}
+
+impl Drop for MqttGateway {
+    fn drop(&mut self) {
+        for bus in self.buses.iter() {
+            bus.send(Command::Stop).ok();
+        }
+        // 正常停止时主动发布离线状态, 异常退出时由 broker 发布遗嘱
+        for id in self.devices.iter() {
+            self.client
+                .try_publish(
+                    device_topic(id, AVAILABILITY),
+                    QoS::AtLeastOnce,
+                    self.retain,
+                    "offline",
+                )
+                .ok();
+        }
+        self.client
+            .try_publish(
+                device_topic(&topic_id(&self.id), AVAILABILITY),
+                QoS::AtLeastOnce,
+                true,
+                "offline",
+            )
+            .ok();
+        self.client.disconnect().ok();
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// 设备名称中不能出现 topic 的分隔符和通配符, 如扫描得到的 "/dev/ttyUSB0-1"
+pub fn topic_id(name: &str) -> String {
+    name.trim_start_matches('/').replace(['/', '+', '#'], "_")
+}
+
+/// 把 home/<设备>/set 或 home/<设备>/set/<字段> 的消息交给设备所在的总线
+fn route_set(
+    routes: &HashMap<String, (Sender<Command>, usize)>,
+    topic: &str,
+    payload: &[u8],
+    state: &Mutex<GatewayState>,
+) {
+    let (id, sub) = match parse_device_topic(topic) {
+        Some(parsed) => parsed,
+        None => return,
+    };
+    let (tx, device) = match routes.get(id) {
+        Some(route) => route,
+        None => return,
+    };
+    let values = if sub == SET {
+        serde_json::from_slice::<Map<String, Value>>(payload)
+            .map(|object| object.into_iter().collect::<FieldValues>())
+            .map_err(|e| e.to_string())
+    } else if let Some(field) = sub.strip_prefix(SET).and_then(|s| s.strip_prefix('/')) {
+        // 单个字段的值可以是 json, 也可以是 ON/OFF 等文本
+        let text = String::from_utf8_lossy(payload).trim().to_string();
+        let value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        Ok(vec![(field.to_string(), value)])
+    } else {
+        return;
+    };
+    match values {
+        Ok(values) => {
+            tx.send(Command::Write {
+                device: *device,
+                values,
+            })
+            .ok();
+        }
+        Err(e) => {
+            tracing::warn!("{} 的消息无法解析: {}", topic, e);
+            let mut state = state.lock();
+            if let Some(stats) = state.slaves.iter_mut().find(|s| topic_id(&s.device) == id) {
+                stats.write_failures += 1;
+                stats.last_error = Some(format!("写入消息无法解析: {}", e));
+            }
+        }
+    }
+}
+
+/// 一条总线上的一个设备
+struct BusDevice {
+    /// GatewayState::slaves 中的序号
+    stats: usize,
+    id: String,
+    device: ModbusDevice,
+    map: RegisterMap,
+    next_poll: Instant,
+    raw: BTreeMap<(Area, u16), u16>,
+    /// 已发布的值, 用于判断是否变化
+    published: Map<String, Value>,
+    last_publish: Option<Instant>,
+    online: Option<bool>,
+}
+
+impl BusDevice {
+    fn new(stats: usize, id: String, device: ModbusDevice, map: RegisterMap) -> Self {
+        Self {
+            stats,
+            id,
+            device,
+            map,
+            next_poll: Instant::now(),
+            raw: BTreeMap::new(),
+            published: Map::new(),
+            last_publish: None,
+            online: None,
+        }
+    }
+
+    /// 连续地址的原始值
+    fn raw(&self, area: Area, address: u16, len: u16) -> Option<Vec<u16>> {
+        (0..len)
+            .map(|i| self.raw.get(&(area, address.wrapping_add(i))).copied())
+            .collect()
+    }
+
+    /// 所有字段的工程值, 数组字段为 json 数组
+    fn values(&self) -> Map<String, Value> {
+        let mut values = Map::new();
+        for area in Area::ALL {
+            for field in self.map.fields(area) {
+                let raw = match self.raw(area, field.address, field.len()) {
+                    Some(raw) => raw,
+                    None => continue,
+                };
+                if let Ok(value) = field.to_json(&raw) {
+                    values.insert(field.name.clone(), value);
+                }
+            }
+        }
+        values
+    }
+}
+
+/// 按名称查找字段, 数组元素的名称为 "字段[序号]"
+fn find_element(map: &RegisterMap, name: &str) -> Option<(Area, Field)> {
+    if let Some((area, field)) = map.find(name) {
+        return Some((area, field.clone()));
+    }
+    Area::ALL.into_iter().find_map(|area| {
+        map.fields(area)
+            .iter()
+            .flat_map(|f| f.elements())
+            .find(|f| f.name == name)
+            .map(|f| (area, f))
+    })
+}
+
+/// 一条总线的轮询线程
+struct Bus {
+    label: String,
+    config: GatewayConfig,
+    devices: Vec<BusDevice>,
+    client: Client,
+    state: Arc<Mutex<GatewayState>>,
+    repaint: RepaintSignal,
+}
+
+impl Bus {
+    fn run(mut self, rx: Receiver<Command>, bus: Result<BusHandle>) {
+        let bus = match bus {
+            Ok(bus) => bus,
+            Err(e) => {
+                tracing::error!("modbus 网关无法打开 {}: {}", self.label, e);
+                self.state
+                    .lock()
+                    .errors
+                    .push(format!("{}: {}", self.label, e));
+                (self.repaint)();
+                return;
+            }
+        };
+        loop {
+            let next = self
+                .devices
+                .iter()
+                .map(|d| d.next_poll)
+                .min()
+                .unwrap_or_else(Instant::now);
+            match rx.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                Ok(Command::Write { device, values }) => {
+                    self.write(&bus, device, values);
+                    // 写入后立即读取, 发布新的值
+                    self.devices[device].next_poll = Instant::now();
+                    continue;
+                }
+                Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            let now = Instant::now();
+            for i in 0..self.devices.len() {
+                if self.devices[i].next_poll <= now {
+                    self.poll(&bus, i);
+                }
+            }
+            (self.repaint)();
+        }
+        tracing::info!("modbus 网关停止轮询 {}", self.label);
+    }
+
+    fn poll(&mut self, bus: &BusHandle, index: usize) {
+        let device = &mut self.devices[index];
+        let interval = Duration::from_millis(device.device.poll_interval.max(10));
+        device.next_poll = Instant::now() + interval;
+
+        let start = Instant::now();
+        let mut error = None;
+        for block in device.map.all_read_blocks() {
+            match bus.request(device.device.slave, &block.request(), Priority::Poll) {
+                Ok(response) => {
+                    let values = match response {
+                        Response::Bits(bits) => bits.into_iter().map(u16::from).collect(),
+                        Response::Registers(registers) => registers,
+                        _ => vec![],
+                    };
+                    for (address, raw) in block.addresses().zip(values) {
+                        device.raw.insert((block.area, address as u16), raw);
+                    }
+                }
+                Err(e) => {
+                    error = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+        let online = error.is_none();
+
+        {
+            let mut state = self.state.lock();
+            let stats = &mut state.slaves[device.stats];
+            stats.polls += 1;
+            stats.last_poll = Some(Local::now());
+            stats.online = online;
+            if online {
+                stats.consecutive_failures = 0;
+                stats.latency = Some(start.elapsed());
+            } else {
+                stats.failures += 1;
+                stats.consecutive_failures += 1;
+                stats.last_error = error.clone();
+            }
+        }
+        if let Some(e) = &error {
+            tracing::debug!("modbus 网关轮询 {} 失败: {}", device.device.name, e);
+        }
+
+        if device.online != Some(online) {
+            device.online = Some(online);
+            let payload = if online { "online" } else { "offline" };
+            self.publish(device_topic(&self.devices[index].id, AVAILABILITY), payload);
+        }
+        if online {
+            self.publish_values(index);
+        }
+    }
+
+    /// 按配置的格式和时机发布字段的值
+    fn publish_values(&mut self, index: usize) {
+        let device = &self.devices[index];
+        let values = device.values();
+        let due = match self.config.mode {
+            PublishMode::OnChange => false,
+            PublishMode::Periodic => device.last_publish.map_or(true, |last| {
+                last.elapsed() >= Duration::from_secs(self.config.publish_interval.max(1))
+            }),
+        };
+        if self.config.mode == PublishMode::Periodic && !due {
+            return;
+        }
+        let changed: Map<String, Value> = values
+            .iter()
+            .filter(|(name, value)| due || device.published.get(*name) != Some(*value))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+
+        let id = device.id.clone();
+        let mut messages = vec![];
+        match self.config.format {
+            // 保留消息需要完整的状态, 任一字段变化时发布所有字段
+            PublishFormat::Json => messages.push((
+                device_topic(&id, STATE),
+                Value::Object(values.clone()).to_string(),
+            )),
+            PublishFormat::PerField => {
+                for (name, value) in changed.iter() {
+                    messages.push((
+                        format!("{}/{}", device_topic(&id, STATE), name),
+                        value.to_string(),
+                    ));
+                }
+            }
+        }
+        let count = messages.len() as u64;
+        for (topic, payload) in messages {
+            self.publish(topic, payload);
+        }
+
+        let device = &mut self.devices[index];
+        device.published = values;
+        device.last_publish = Some(Instant::now());
+        self.state.lock().slaves[device.stats].publishes += count;
+    }
+
+    fn publish(&mut self, topic: String, payload: impl Into<Vec<u8>>) {
+        if let Err(e) = self
+            .client
+            .publish(&topic, QoS::AtLeastOnce, self.config.retain, payload)
+        {
+            tracing::warn!("modbus 网关发布 {} 失败: {}", topic, e);
+        }
+    }
+
+    fn write(&mut self, bus: &BusHandle, index: usize, values: FieldValues) {
+        for (name, value) in values {
+            let result = self.write_field(bus, index, &name, &value);
+            let device = &self.devices[index];
+            let mut state = self.state.lock();
+            let stats = &mut state.slaves[device.stats];
+            stats.writes += 1;
+            match result {
+                Ok(()) => tracing::info!(
+                    "modbus 网关写入 {}.{} = {}",
+                    device.device.name,
+                    name,
+                    value
+                ),
+                Err(e) => {
+                    tracing::warn!(
+                        "modbus 网关写入 {}.{} 失败: {}",
+                        device.device.name,
+                        name,
+                        e
+                    );
+                    stats.write_failures += 1;
+                    stats.last_error = Some(format!("写入 {} 失败: {}", name, e));
+                }
+            }
+        }
+        (self.repaint)();
+    }
+
+    fn write_field(
+        &mut self,
+        bus: &BusHandle,
+        index: usize,
+        name: &str,
+        value: &Value,
+    ) -> Result<()> {
+        let device = &mut self.devices[index];
+        let (area, field) = find_element(&device.map, name)
+            .ok_or_else(|| AppError::RegisterMap(format!("字段不存在: {}", name)))?;
+        if !field.writable(area) {
+            return Err(AppError::RegisterMap(format!("{} 只读", name)));
+        }
+        let slave = device.device.slave;
+        // 位段只修改对应的位, 需要寄存器的当前值
+        let current = match device.raw(area, field.address, field.len()) {
+            Some(current) => current,
+            None if field.is_text() => vec![],
+            None => match bus.request(
+                slave,
+                &area.read_request(field.address, field.len()),
+                Priority::Write,
+            )? {
+                Response::Registers(registers) => registers,
+                Response::Bits(bits) => bits.into_iter().map(u16::from).collect(),
+                _ => vec![],
+            },
+        };
+        let raw = field.from_json(value, &current)?;
+        Self::write_raw(bus, device, area, &field, raw)
+    }
+
+    /// 写入原始值, 成功后更新缓存, 下次发布时不需要等待轮询
+    fn write_raw(
+        bus: &BusHandle,
+        device: &mut BusDevice,
+        area: Area,
+        field: &Field,
+        raw: Vec<u16>,
+    ) -> Result<()> {
+        let request: Request = area
+            .write_request(field.address, raw.clone())
+            .ok_or_else(|| AppError::RegisterMap(format!("{} 不可写", area.label())))?;
+        bus.request(device.device.slave, &request, Priority::Write)?;
+        for (i, raw) in raw.into_iter().enumerate() {
+            device.raw.insert((area, field.address + i as u16), raw);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,759 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::resource::error::{AppError, Result};
+
+/// 一次最多读取的线圈/离散输入数量
+pub const MAX_READ_BITS: u16 = 2000;
+/// 一次最多读取的寄存器数量
+pub const MAX_READ_REGISTERS: u16 = 125;
+/// 一次最多写入的线圈数量
+pub const MAX_WRITE_BITS: u16 = 1968;
+/// 一次最多写入的寄存器数量
+pub const MAX_WRITE_REGISTERS: u16 = 123;
+
+/// 功能码
+pub mod function {
+    pub const READ_COILS: u8 = 0x01;
+    pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+    pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+    pub const READ_INPUT_REGISTERS: u8 = 0x04;
+    pub const WRITE_SINGLE_COIL: u8 = 0x05;
+    pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+    pub const WRITE_MULTIPLE_COILS: u8 = 0x0F;
+    pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+    pub const REPORT_SERVER_ID: u8 = 0x11;
+
+    /// 功能码的名称, 异常响应的功能码返回原功能码的名称
+    pub fn name(function: u8) -> &'static str {
+        match function & 0x7F {
+            READ_COILS => "读线圈",
+            READ_DISCRETE_INPUTS => "读离散输入",
+            READ_HOLDING_REGISTERS => "读保持寄存器",
+            READ_INPUT_REGISTERS => "读输入寄存器",
+            WRITE_SINGLE_COIL => "写单个线圈",
+            WRITE_SINGLE_REGISTER => "写单个寄存器",
+            WRITE_MULTIPLE_COILS => "写多个线圈",
+            WRITE_MULTIPLE_REGISTERS => "写多个寄存器",
+            REPORT_SERVER_ID => "读从站标识",
+            _ => "未知功能码",
+        }
+    }
+}
+
+/// 从站返回的异常码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionCode {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    ServerDeviceFailure,
+    Acknowledge,
+    ServerDeviceBusy,
+    MemoryParityError,
+    GatewayPathUnavailable,
+    GatewayTargetFailed,
+    Unknown(u8),
+}
+
+impl From<u8> for ExceptionCode {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => ExceptionCode::IllegalFunction,
+            0x02 => ExceptionCode::IllegalDataAddress,
+            0x03 => ExceptionCode::IllegalDataValue,
+            0x04 => ExceptionCode::ServerDeviceFailure,
+            0x05 => ExceptionCode::Acknowledge,
+            0x06 => ExceptionCode::ServerDeviceBusy,
+            0x08 => ExceptionCode::MemoryParityError,
+            0x0A => ExceptionCode::GatewayPathUnavailable,
+            0x0B => ExceptionCode::GatewayTargetFailed,
+            code => ExceptionCode::Unknown(code),
+        }
+    }
+}
+
+impl From<ExceptionCode> for u8 {
+    fn from(code: ExceptionCode) -> Self {
+        match code {
+            ExceptionCode::IllegalFunction => 0x01,
+            ExceptionCode::IllegalDataAddress => 0x02,
+            ExceptionCode::IllegalDataValue => 0x03,
+            ExceptionCode::ServerDeviceFailure => 0x04,
+            ExceptionCode::Acknowledge => 0x05,
+            ExceptionCode::ServerDeviceBusy => 0x06,
+            ExceptionCode::MemoryParityError => 0x08,
+            ExceptionCode::GatewayPathUnavailable => 0x0A,
+            ExceptionCode::GatewayTargetFailed => 0x0B,
+            ExceptionCode::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for ExceptionCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            ExceptionCode::IllegalFunction => "非法功能码",
+            ExceptionCode::IllegalDataAddress => "非法数据地址",
+            ExceptionCode::IllegalDataValue => "非法数据值",
+            ExceptionCode::ServerDeviceFailure => "从站设备故障",
+            ExceptionCode::Acknowledge => "已确认, 正在处理",
+            ExceptionCode::ServerDeviceBusy => "从站设备忙",
+            ExceptionCode::MemoryParityError => "存储奇偶校验错误",
+            ExceptionCode::GatewayPathUnavailable => "网关路径不可用",
+            ExceptionCode::GatewayTargetFailed => "网关目标设备无响应",
+            ExceptionCode::Unknown(code) => return write!(f, "未知异常码 0x{:02X}", code),
+        };
+        f.write_str(text)
+    }
+}
+
+/// 主站请求
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request {
+    ReadCoils { address: u16, quantity: u16 },
+    ReadDiscreteInputs { address: u16, quantity: u16 },
+    ReadHoldingRegisters { address: u16, quantity: u16 },
+    ReadInputRegisters { address: u16, quantity: u16 },
+    WriteSingleCoil { address: u16, value: bool },
+    WriteSingleRegister { address: u16, value: u16 },
+    WriteMultipleCoils { address: u16, values: Vec<bool> },
+    WriteMultipleRegisters { address: u16, values: Vec<u16> },
+}
+
+/// 从站响应
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// 线圈或离散输入的值, 长度与请求的数量相同
+    Bits(Vec<bool>),
+    /// 保持寄存器或输入寄存器的值
+    Registers(Vec<u16>),
+    WriteSingleCoil {
+        address: u16,
+        value: bool,
+    },
+    WriteSingleRegister {
+        address: u16,
+        value: u16,
+    },
+    /// 写多个线圈/寄存器的确认
+    WriteMultiple {
+        address: u16,
+        quantity: u16,
+    },
+}
+
+impl Request {
+    pub fn function_code(&self) -> u8 {
+        match self {
+            Request::ReadCoils { .. } => function::READ_COILS,
+            Request::ReadDiscreteInputs { .. } => function::READ_DISCRETE_INPUTS,
+            Request::ReadHoldingRegisters { .. } => function::READ_HOLDING_REGISTERS,
+            Request::ReadInputRegisters { .. } => function::READ_INPUT_REGISTERS,
+            Request::WriteSingleCoil { .. } => function::WRITE_SINGLE_COIL,
+            Request::WriteSingleRegister { .. } => function::WRITE_SINGLE_REGISTER,
+            Request::WriteMultipleCoils { .. } => function::WRITE_MULTIPLE_COILS,
+            Request::WriteMultipleRegisters { .. } => function::WRITE_MULTIPLE_REGISTERS,
+        }
+    }
+
+    /// 读请求, 不改变从站的状态
+    pub fn is_read(&self) -> bool {
+        matches!(
+            self,
+            Request::ReadCoils { .. }
+                | Request::ReadDiscreteInputs { .. }
+                | Request::ReadHoldingRegisters { .. }
+                | Request::ReadInputRegisters { .. }
+        )
+    }
+
+    /// 检查数量和地址范围, 数量按 usize 比较, 写入的值超过 65535 个时不会截断
+    pub fn validate(&self) -> Result<()> {
+        let (address, quantity, max) = match self {
+            Request::ReadCoils { address, quantity }
+            | Request::ReadDiscreteInputs { address, quantity } => {
+                (*address, *quantity as usize, MAX_READ_BITS)
+            }
+            Request::ReadHoldingRegisters { address, quantity }
+            | Request::ReadInputRegisters { address, quantity } => {
+                (*address, *quantity as usize, MAX_READ_REGISTERS)
+            }
+            Request::WriteSingleCoil { .. } | Request::WriteSingleRegister { .. } => return Ok(()),
+            Request::WriteMultipleCoils { address, values } => {
+                (*address, values.len(), MAX_WRITE_BITS)
+            }
+            Request::WriteMultipleRegisters { address, values } => {
+                (*address, values.len(), MAX_WRITE_REGISTERS)
+            }
+        };
+        if quantity == 0 || quantity > max as usize {
+            return Err(AppError::ModbusInvalidRequest(format!(
+                "数量 {} 超出范围 1..={}",
+                quantity, max
+            )));
+        }
+        if address as usize + quantity > 0x10000 {
+            return Err(AppError::ModbusInvalidRequest(format!(
+                "地址 {} + 数量 {} 超出 65535",
+                address, quantity
+            )));
+        }
+        Ok(())
+    }
+
+    /// 编码为 pdu (功能码 + 数据)
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+        let mut pdu = vec![self.function_code()];
+        match self {
+            Request::ReadCoils { address, quantity }
+            | Request::ReadDiscreteInputs { address, quantity }
+            | Request::ReadHoldingRegisters { address, quantity }
+            | Request::ReadInputRegisters { address, quantity } => {
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&quantity.to_be_bytes());
+            }
+            Request::WriteSingleCoil { address, value } => {
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&coil_value(*value).to_be_bytes());
+            }
+            Request::WriteSingleRegister { address, value } => {
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&value.to_be_bytes());
+            }
+            Request::WriteMultipleCoils { address, values } => {
+                let bytes = pack_bits(values);
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+                pdu.push(bytes.len() as u8);
+                pdu.extend_from_slice(&bytes);
+            }
+            Request::WriteMultipleRegisters { address, values } => {
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+                pdu.push((values.len() * 2) as u8);
+                for value in values {
+                    pdu.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+        Ok(pdu)
+    }
+
+    /// 解析从站返回的 pdu, 并检查是否与请求匹配
+    pub fn decode_response(&self, pdu: &[u8]) -> Result<Response> {
+        let function = *pdu
+            .first()
+            .ok_or_else(|| invalid_response("响应为空".to_string()))?;
+        if function == self.function_code() | 0x80 {
+            let code = *pdu
+                .get(1)
+                .ok_or_else(|| invalid_response("异常响应缺少异常码".to_string()))?;
+            return Err(AppError::ModbusException {
+                function: self.function_code(),
+                code: code.into(),
+            });
+        }
+        if function != self.function_code() {
+            return Err(invalid_response(format!(
+                "功能码不匹配: 请求 0x{:02X}, 响应 0x{:02X}",
+                self.function_code(),
+                function
+            )));
+        }
+
+        let data = &pdu[1..];
+        match self {
+            Request::ReadCoils { quantity, .. } | Request::ReadDiscreteInputs { quantity, .. } => {
+                let bytes = byte_counted(data, (*quantity as usize + 7) / 8)?;
+                let mut bits = unpack_bits(bytes);
+                bits.truncate(*quantity as usize);
+                Ok(Response::Bits(bits))
+            }
+            Request::ReadHoldingRegisters { quantity, .. }
+            | Request::ReadInputRegisters { quantity, .. } => {
+                let bytes = byte_counted(data, *quantity as usize * 2)?;
+                let registers = bytes
+                    .chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .collect();
+                Ok(Response::Registers(registers))
+            }
+            Request::WriteSingleCoil { address, value } => {
+                let (echo_address, echo_value) = address_value(data)?;
+                if echo_address != *address || echo_value != coil_value(*value) {
+                    return Err(invalid_response("写线圈的回显与请求不一致".to_string()));
+                }
+                Ok(Response::WriteSingleCoil {
+                    address: *address,
+                    value: *value,
+                })
+            }
+            Request::WriteSingleRegister { address, value } => {
+                let (echo_address, echo_value) = address_value(data)?;
+                if echo_address != *address || echo_value != *value {
+                    return Err(invalid_response("写寄存器的回显与请求不一致".to_string()));
+                }
+                Ok(Response::WriteSingleRegister {
+                    address: *address,
+                    value: *value,
+                })
+            }
+            Request::WriteMultipleCoils { address, values } => {
+                write_multiple_ack(data, *address, values.len() as u16)
+            }
+            Request::WriteMultipleRegisters { address, values } => {
+                write_multiple_ack(data, *address, values.len() as u16)
+            }
+        }
+    }
+
+    /// 从站解析主站的 pdu, 格式错误时返回对应的异常码
+    pub fn decode(pdu: &[u8]) -> std::result::Result<Request, ExceptionCode> {
+        let function = *pdu.first().ok_or(ExceptionCode::IllegalFunction)?;
+        let data = &pdu[1..];
+        let word = |i: usize| -> std::result::Result<u16, ExceptionCode> {
+            data.get(i..i + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or(ExceptionCode::IllegalDataValue)
+        };
+        let request = match function {
+            function::READ_COILS
+            | function::READ_DISCRETE_INPUTS
+            | function::READ_HOLDING_REGISTERS
+            | function::READ_INPUT_REGISTERS => {
+                let (address, quantity) = (word(0)?, word(2)?);
+                match function {
+                    function::READ_COILS => Request::ReadCoils { address, quantity },
+                    function::READ_DISCRETE_INPUTS => {
+                        Request::ReadDiscreteInputs { address, quantity }
+                    }
+                    function::READ_HOLDING_REGISTERS => {
+                        Request::ReadHoldingRegisters { address, quantity }
+                    }
+                    _ => Request::ReadInputRegisters { address, quantity },
+                }
+            }
+            function::WRITE_SINGLE_COIL => {
+                let value = match word(2)? {
+                    0xFF00 => true,
+                    0x0000 => false,
+                    _ => return Err(ExceptionCode::IllegalDataValue),
+                };
+                Request::WriteSingleCoil {
+                    address: word(0)?,
+                    value,
+                }
+            }
+            function::WRITE_SINGLE_REGISTER => Request::WriteSingleRegister {
+                address: word(0)?,
+                value: word(2)?,
+            },
+            function::WRITE_MULTIPLE_COILS | function::WRITE_MULTIPLE_REGISTERS => {
+                let (address, quantity) = (word(0)?, word(2)?);
+                let count = *data.get(4).ok_or(ExceptionCode::IllegalDataValue)? as usize;
+                let bytes = data
+                    .get(5..5 + count)
+                    .filter(|b| b.len() == data.len() - 5)
+                    .ok_or(ExceptionCode::IllegalDataValue)?;
+                if function == function::WRITE_MULTIPLE_COILS {
+                    if count != (quantity as usize + 7) / 8 {
+                        return Err(ExceptionCode::IllegalDataValue);
+                    }
+                    let mut values = unpack_bits(bytes);
+                    values.truncate(quantity as usize);
+                    Request::WriteMultipleCoils { address, values }
+                } else {
+                    if count != quantity as usize * 2 {
+                        return Err(ExceptionCode::IllegalDataValue);
+                    }
+                    let values = bytes
+                        .chunks_exact(2)
+                        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                        .collect();
+                    Request::WriteMultipleRegisters { address, values }
+                }
+            }
+            _ => return Err(ExceptionCode::IllegalFunction),
+        };
+        // 数量超出范围是非法数据值, 地址超出范围由从站检查
+        match request.validate() {
+            Ok(()) => Ok(request),
+            Err(_) => Err(ExceptionCode::IllegalDataValue),
+        }
+    }
+}
+
+impl Response {
+    /// 从站编码响应 pdu
+    pub fn encode(&self, function: u8) -> Vec<u8> {
+        let mut pdu = vec![function];
+        match self {
+            Response::Bits(bits) => {
+                let bytes = pack_bits(bits);
+                pdu.push(bytes.len() as u8);
+                pdu.extend_from_slice(&bytes);
+            }
+            Response::Registers(registers) => {
+                pdu.push((registers.len() * 2) as u8);
+                for register in registers {
+                    pdu.extend_from_slice(&register.to_be_bytes());
+                }
+            }
+            Response::WriteSingleCoil { address, value } => {
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&coil_value(*value).to_be_bytes());
+            }
+            Response::WriteSingleRegister { address, value } => {
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&value.to_be_bytes());
+            }
+            Response::WriteMultiple { address, quantity } => {
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&quantity.to_be_bytes());
+            }
+        }
+        pdu
+    }
+}
+
+/// 从站的异常响应 pdu
+pub fn exception_pdu(function: u8, code: ExceptionCode) -> Vec<u8> {
+    vec![function | 0x80, code.into()]
+}
+
+/// 单个线圈的值在协议中为 0xFF00 或 0x0000
+fn coil_value(on: bool) -> u16 {
+    if on {
+        0xFF00
+    } else {
+        0x0000
+    }
+}
+
+/// 线圈按低位在前打包为字节
+pub fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0u8; (bits.len() + 7) / 8];
+    for (i, _) in bits.iter().enumerate().filter(|(_, on)| **on) {
+        bytes[i / 8] |= 1 << (i % 8);
+    }
+    bytes
+}
+
+pub fn unpack_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| byte & (1 << i) != 0))
+        .collect()
+}
+
+fn invalid_response(message: String) -> AppError {
+    AppError::ModbusInvalidResponse(message)
+}
+
+/// 读取 "字节数 + 数据" 格式的数据, 并检查长度
+fn byte_counted(data: &[u8], expected: usize) -> Result<&[u8]> {
+    let count = *data
+        .first()
+        .ok_or_else(|| invalid_response("缺少字节数".to_string()))? as usize;
+    if count != expected || data.len() != count + 1 {
+        return Err(invalid_response(format!(
+            "字节数错误: 期望 {}, 实际 {} (数据长度 {})",
+            expected,
+            count,
+            data.len().saturating_sub(1)
+        )));
+    }
+    Ok(&data[1..])
+}
+
+fn address_value(data: &[u8]) -> Result<(u16, u16)> {
+    if data.len() != 4 {
+        return Err(invalid_response(format!("响应长度错误: {}", data.len())));
+    }
+    Ok((
+        u16::from_be_bytes([data[0], data[1]]),
+        u16::from_be_bytes([data[2], data[3]]),
+    ))
+}
+
+fn write_multiple_ack(data: &[u8], address: u16, quantity: u16) -> Result<Response> {
+    let (echo_address, echo_quantity) = address_value(data)?;
+    if echo_address != address || echo_quantity != quantity {
+        return Err(invalid_response(format!(
+            "写入确认不一致: 地址 {}, 数量 {}",
+            echo_address, echo_quantity
+        )));
+    }
+    Ok(Response::WriteMultiple { address, quantity })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 协议规范中的请求示例
+    fn requests() -> Vec<(Request, Vec<u8>)> {
+        vec![
+            (
+                Request::ReadCoils {
+                    address: 0x13,
+                    quantity: 0x13,
+                },
+                vec![0x01, 0x00, 0x13, 0x00, 0x13],
+            ),
+            (
+                Request::ReadDiscreteInputs {
+                    address: 0xC4,
+                    quantity: 0x16,
+                },
+                vec![0x02, 0x00, 0xC4, 0x00, 0x16],
+            ),
+            (
+                Request::ReadHoldingRegisters {
+                    address: 0x6B,
+                    quantity: 3,
+                },
+                vec![0x03, 0x00, 0x6B, 0x00, 0x03],
+            ),
+            (
+                Request::ReadInputRegisters {
+                    address: 0x08,
+                    quantity: 1,
+                },
+                vec![0x04, 0x00, 0x08, 0x00, 0x01],
+            ),
+            (
+                Request::WriteSingleCoil {
+                    address: 0xAC,
+                    value: true,
+                },
+                vec![0x05, 0x00, 0xAC, 0xFF, 0x00],
+            ),
+            (
+                Request::WriteSingleRegister {
+                    address: 0x01,
+                    value: 0x03,
+                },
+                vec![0x06, 0x00, 0x01, 0x00, 0x03],
+            ),
+            (
+                Request::WriteMultipleCoils {
+                    address: 0x13,
+                    values: vec![
+                        true, false, true, true, false, false, true, true, true, false,
+                    ],
+                },
+                vec![0x0F, 0x00, 0x13, 0x00, 0x0A, 0x02, 0xCD, 0x01],
+            ),
+            (
+                Request::WriteMultipleRegisters {
+                    address: 0x01,
+                    values: vec![0x000A, 0x0102],
+                },
+                vec![0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02],
+            ),
+        ]
+    }
+
+    #[test]
+    fn encode_and_decode_requests() {
+        for (request, pdu) in requests() {
+            assert_eq!(request.encode().unwrap(), pdu, "{:?}", request);
+            assert_eq!(Request::decode(&pdu).unwrap(), request);
+        }
+    }
+
+    #[test]
+    fn decode_read_responses() {
+        let request = Request::ReadCoils {
+            address: 0x13,
+            quantity: 0x13,
+        };
+        let bits = match request.decode_response(&[0x01, 0x03, 0xCD, 0x6B, 0x05]) {
+            Ok(Response::Bits(bits)) => bits,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(bits.len(), 19);
+        assert_eq!(
+            &bits[..8],
+            &[true, false, true, true, false, false, true, true]
+        );
+        assert_eq!(&bits[16..], &[true, false, true]);
+
+        let request = Request::ReadHoldingRegisters {
+            address: 0x6B,
+            quantity: 3,
+        };
+        let pdu = [0x03, 0x06, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64];
+        assert_eq!(
+            request.decode_response(&pdu).unwrap(),
+            Response::Registers(vec![0x022B, 0x0000, 0x0064])
+        );
+        // 字节数与请求的数量不一致
+        assert!(matches!(
+            request.decode_response(&[0x03, 0x04, 0x02, 0x2B, 0x00, 0x00]),
+            Err(AppError::ModbusInvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn decode_write_responses() {
+        for (request, pdu) in requests().into_iter().skip(4) {
+            let response = match &request {
+                Request::WriteSingleCoil { .. } | Request::WriteSingleRegister { .. } => pdu,
+                _ => pdu[..5].to_vec(),
+            };
+            assert!(request.decode_response(&response).is_ok(), "{:?}", request);
+        }
+        let request = Request::WriteSingleRegister {
+            address: 1,
+            value: 3,
+        };
+        assert!(request
+            .decode_response(&[0x06, 0x00, 0x01, 0x00, 0x04])
+            .is_err());
+    }
+
+    #[test]
+    fn response_encode_matches_decode() {
+        for (request, _) in requests() {
+            let response = match &request {
+                Request::ReadCoils { quantity, .. }
+                | Request::ReadDiscreteInputs { quantity, .. } => {
+                    Response::Bits((0..*quantity).map(|i| i % 3 == 0).collect())
+                }
+                Request::ReadHoldingRegisters { quantity, .. }
+                | Request::ReadInputRegisters { quantity, .. } => {
+                    Response::Registers((0..*quantity).map(|i| i * 0x101).collect())
+                }
+                Request::WriteSingleCoil { address, value } => Response::WriteSingleCoil {
+                    address: *address,
+                    value: *value,
+                },
+                Request::WriteSingleRegister { address, value } => Response::WriteSingleRegister {
+                    address: *address,
+                    value: *value,
+                },
+                Request::WriteMultipleCoils { address, values } => Response::WriteMultiple {
+                    address: *address,
+                    quantity: values.len() as u16,
+                },
+                Request::WriteMultipleRegisters { address, values } => Response::WriteMultiple {
+                    address: *address,
+                    quantity: values.len() as u16,
+                },
+            };
+            let pdu = response.encode(request.function_code());
+            assert_eq!(request.decode_response(&pdu).unwrap(), response);
+        }
+    }
+
+    #[test]
+    fn exception_responses() {
+        let pdu = exception_pdu(
+            function::READ_HOLDING_REGISTERS,
+            ExceptionCode::IllegalDataAddress,
+        );
+        assert_eq!(pdu, vec![0x83, 0x02]);
+        let request = Request::ReadHoldingRegisters {
+            address: 0,
+            quantity: 1,
+        };
+        assert_eq!(
+            request.decode_response(&pdu),
+            Err(AppError::ModbusException {
+                function: 0x03,
+                code: ExceptionCode::IllegalDataAddress,
+            })
+        );
+        // 其它功能码的异常响应不是这个请求的响应
+        assert!(matches!(
+            request.decode_response(&[0x84, 0x02]),
+            Err(AppError::ModbusInvalidResponse(_))
+        ));
+        assert_eq!(u8::from(ExceptionCode::from(0x0B)), 0x0B);
+        assert_eq!(
+            ExceptionCode::from(0x0B),
+            ExceptionCode::GatewayTargetFailed
+        );
+        assert_eq!(ExceptionCode::from(0x42), ExceptionCode::Unknown(0x42));
+    }
+
+    #[test]
+    fn decode_invalid_requests() {
+        assert_eq!(Request::decode(&[]), Err(ExceptionCode::IllegalFunction));
+        assert_eq!(
+            Request::decode(&[0x2B, 0x0E, 0x01, 0x00]),
+            Err(ExceptionCode::IllegalFunction)
+        );
+        // 数量为 0 或超出一次请求的最大值
+        assert_eq!(
+            Request::decode(&[0x03, 0x00, 0x00, 0x00, 0x00]),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(
+            Request::decode(&[0x03, 0x00, 0x00, 0x00, 0x7E]),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        // 单个线圈只能是 0xFF00 或 0x0000
+        assert_eq!(
+            Request::decode(&[0x05, 0x00, 0x01, 0x12, 0x34]),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        // 字节数与数量不一致
+        assert_eq!(
+            Request::decode(&[0x10, 0x00, 0x01, 0x00, 0x02, 0x02, 0x00, 0x0A]),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(
+            Request::decode(&[0x03, 0x00]),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+    }
+
+    #[test]
+    fn validate_write_lengths() {
+        let registers = |len: usize| Request::WriteMultipleRegisters {
+            address: 0,
+            values: vec![0; len],
+        };
+        let coils = |len: usize| Request::WriteMultipleCoils {
+            address: 0,
+            values: vec![false; len],
+        };
+        assert!(registers(MAX_WRITE_REGISTERS as usize).validate().is_ok());
+        assert!(registers(MAX_WRITE_REGISTERS as usize + 1)
+            .validate()
+            .is_err());
+        assert!(coils(MAX_WRITE_BITS as usize).validate().is_ok());
+        assert!(coils(MAX_WRITE_BITS as usize + 1).validate().is_err());
+        assert!(registers(0).encode().is_err());
+        // 超过 65535 个值时按 u16 计算的数量会被截断为 1
+        assert!(registers(0x10001).encode().is_err());
+        assert!(coils(0x10001).encode().is_err());
+
+        let request = Request::ReadHoldingRegisters {
+            address: 0xFFFF,
+            quantity: 2,
+        };
+        assert!(request.validate().is_err());
+        let request = Request::ReadHoldingRegisters {
+            address: 0xFFFF,
+            quantity: 1,
+        };
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn pack_and_unpack_bits() {
+        let bits = [
+            true, false, true, true, false, false, true, true, true, false,
+        ];
+        assert_eq!(pack_bits(&bits), vec![0xCD, 0x01]);
+        assert_eq!(&unpack_bits(&[0xCD, 0x01])[..10], &bits);
+        assert!(pack_bits(&[]).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Local};
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::{
+    data::register_map::{Area, ReadBlock},
+    resource::error::{AppError, Result},
+    service::RepaintSignal,
+};
+
+use super::{
+    pdu::Request,
+    scheduler::{BusHandle, Priority},
+};
+
+/// 一个地址最后一次读到的值
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub raw: u16,
+    pub time: DateTime<Local>,
+}
+
+/// 轮询结果, 按 (数据区, 地址) 保存
+#[derive(Debug, Default)]
+pub struct PollState {
+    pub samples: BTreeMap<(Area, u16), Sample>,
+    /// 最后一次读取失败的地址和错误
+    pub errors: BTreeMap<(Area, u16), AppError>,
+    pub polls: u64,
+    pub failures: u64,
+    /// 最近一次写入的结果
+    pub last_write: Option<std::result::Result<String, String>>,
+}
+
+impl PollState {
+    /// 连续地址的原始值, 有地址未读到时返回 None
+    pub fn raw(&self, area: Area, address: u16, len: u16) -> Option<Vec<u16>> {
+        (0..len)
+            .map(|i| {
+                self.samples
+                    .get(&(area, address.wrapping_add(i)))
+                    .map(|s| s.raw)
+            })
+            .collect()
+    }
+
+    /// 连续地址中最早的读取时间
+    pub fn read_time(&self, area: Area, address: u16, len: u16) -> Option<DateTime<Local>> {
+        (0..len)
+            .filter_map(|i| self.samples.get(&(area, address.wrapping_add(i))))
+            .map(|s| s.time)
+            .min()
+    }
+
+    /// 连续地址中的第一个错误
+    pub fn error(&self, area: Area, address: u16, len: u16) -> Option<&AppError> {
+        (0..len).find_map(|i| self.errors.get(&(area, address.wrapping_add(i))))
+    }
+
+    /// 写入一个读请求的结果, 失败时记录块中每个地址的错误
+    pub(super) fn update(&mut self, block: &ReadBlock, result: Result<Vec<u16>>) {
+        self.polls += 1;
+        let values = match result {
+            Ok(values) => values,
+            Err(e) => {
+                self.failures += 1;
+                for address in block.addresses() {
+                    self.errors.insert((block.area, address as u16), e.clone());
+                }
+                return;
+            }
+        };
+        let time = Local::now();
+        for (address, raw) in block.addresses().zip(values) {
+            let key = (block.area, address as u16);
+            self.samples.insert(key, Sample { raw, time });
+            self.errors.remove(&key);
+        }
+    }
+}
+
+/// 按固定间隔轮询一个从站, 由总线调度器和其它使用同一总线的请求排队发送
+/// 写请求优先于轮询, 写入后立即读取一次
+pub struct ModbusPoller {
+    label: String,
+    slave: u8,
+    bus: BusHandle,
+    group: u64,
+    state: Arc<Mutex<PollState>>,
+    repaint: RepaintSignal,
+}
+
+impl ModbusPoller {
+    pub fn start(
+        bus: BusHandle,
+        slave: u8,
+        blocks: Vec<ReadBlock>,
+        interval: Duration,
+        repaint: RepaintSignal,
+    ) -> Self {
+        let label = format!("{} #{}", bus.label(), slave);
+        let state = Arc::new(Mutex::new(PollState::default()));
+        let group = bus.add_group(slave, blocks, interval, state.clone(), repaint.clone());
+        Self {
+            label,
+            slave,
+            bus,
+            group,
+            state,
+            repaint,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn state(&self) -> MutexGuard<PollState> {
+        self.state.lock()
+    }
+
+    /// 写请求在下一次轮询之前发送
+    pub fn write(&self, request: Request, description: String) {
+        let state = self.state.clone();
+        let bus = self.bus.clone();
+        let group = self.group;
+        let repaint = self.repaint.clone();
+        self.bus
+            .submit(self.slave, request, Priority::Write, move |result| {
+                state.lock().last_write =
+                    Some(result.map(|_| description).map_err(|e| e.to_string()));
+                bus.refresh_group(group);
+                repaint();
+            });
+    }
+
+    pub fn set_interval(&self, interval: Duration) {
+        self.bus.set_group_interval(self.group, interval);
+    }
+}
+
+impl Drop for ModbusPoller {
+    fn drop(&mut self) {
+        self.bus.remove_group(self.group);
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::{
+    data::{
+        recipe::{recipe_fields, Recipe, RecipeItem},
+        register_map::{Area, Field, RegisterMap},
+    },
+    resource::error::{AppError, Result},
+    service::RepaintSignal,
+};
+
+use super::{
+    pdu::Response,
+    scheduler::{BusHandle, Priority},
+};
+
+/// 配方中一个字段在从站上的当前值和配方值
+#[derive(Debug, Clone)]
+pub struct RecipeChange {
+    pub field: String,
+    pub area: Area,
+    pub address: u16,
+    pub old: Vec<u16>,
+    pub new: Vec<u16>,
+    pub old_text: String,
+    pub new_text: String,
+    /// 已写入并读回校验, 回滚后恢复为 false
+    pub written: bool,
+}
+
+impl RecipeChange {
+    pub fn changed(&self) -> bool {
+        self.old != self.new
+    }
+}
+
+/// 一个从站的执行结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetStatus {
+    Waiting,
+    Running,
+    /// 已读取当前值, 未写入
+    Previewed,
+    Done,
+    /// 写入失败, 已恢复原值
+    RolledBack(String),
+    Failed(String),
+}
+
+impl TargetStatus {
+    pub fn label(&self) -> String {
+        match self {
+            TargetStatus::Waiting => "等待".to_string(),
+            TargetStatus::Running => "执行中".to_string(),
+            TargetStatus::Previewed => "已读取".to_string(),
+            TargetStatus::Done => "完成".to_string(),
+            TargetStatus::RolledBack(e) => format!("已回滚: {}", e),
+            TargetStatus::Failed(e) => format!("失败: {}", e),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, TargetStatus::RolledBack(_) | TargetStatus::Failed(_))
+    }
+}
+
+/// 配方的目标从站, map 已应用设备的字节顺序
+pub struct RecipeTarget {
+    pub device: String,
+    pub slave: u8,
+    pub map: RegisterMap,
+    pub bus: BusHandle,
+}
+
+#[derive(Debug, Clone)]
+pub struct TargetState {
+    pub device: String,
+    pub status: TargetStatus,
+    pub changes: Vec<RecipeChange>,
+}
+
+pub enum RecipeTask {
+    /// 读取从站的当前值, 与配方比较
+    Preview(Recipe),
+    /// 按顺序写入并读回校验, 失败时按相反的顺序恢复已写入的字段
+    Download { recipe: Recipe, only_changed: bool },
+    /// 读取第一个从站所有可写字段的当前值, 生成新的配方
+    Upload { name: String },
+}
+
+#[derive(Debug, Default)]
+pub struct RecipeJobState {
+    pub targets: Vec<TargetState>,
+    pub uploaded: Option<Recipe>,
+    pub finished: bool,
+}
+
+/// 在后台线程中依次处理每个从站
+pub struct RecipeJob {
+    state: Arc<Mutex<RecipeJobState>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl RecipeJob {
+    pub fn start(
+        task: RecipeTask,
+        targets: Vec<RecipeTarget>,
+        repaint: RepaintSignal,
+    ) -> Result<Self> {
+        if targets.is_empty() {
+            return Err(AppError::Error("没有选择从站".to_string()));
+        }
+        let state = Arc::new(Mutex::new(RecipeJobState {
+            targets: targets
+                .iter()
+                .map(|target| TargetState {
+                    device: target.device.clone(),
+                    status: TargetStatus::Waiting,
+                    changes: vec![],
+                })
+                .collect(),
+            ..Default::default()
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let thread_state = state.clone();
+        let thread_cancel = cancel.clone();
+        thread::Builder::new()
+            .name("modbus-recipe".to_string())
+            .spawn(move || {
+                run(&task, &targets, &thread_state, &thread_cancel, &repaint);
+                thread_state.lock().finished = true;
+                repaint();
+            })?;
+        Ok(Self { state, cancel })
+    }
+
+    pub fn state(&self) -> MutexGuard<RecipeJobState> {
+        self.state.lock()
+    }
+
+    /// 下载中取消时回滚已写入的字段
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for RecipeJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn run(
+    task: &RecipeTask,
+    targets: &[RecipeTarget],
+    state: &Mutex<RecipeJobState>,
+    cancel: &AtomicBool,
+    repaint: &RepaintSignal,
+) {
+    for (index, target) in targets.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        state.lock().targets[index].status = TargetStatus::Running;
+        repaint();
+        let status = match task {
+            RecipeTask::Preview(recipe) => match diff(target, recipe, Priority::Request) {
+                Ok(changes) => {
+                    state.lock().targets[index].changes = changes;
+                    TargetStatus::Previewed
+                }
+                Err(e) => TargetStatus::Failed(e.to_string()),
+            },
+            RecipeTask::Download {
+                recipe,
+                only_changed,
+            } => match diff(target, recipe, Priority::Write) {
+                Ok(changes) => {
+                    state.lock().targets[index].changes = changes;
+                    download(target, index, *only_changed, state, cancel, repaint)
+                }
+                Err(e) => TargetStatus::Failed(e.to_string()),
+            },
+            RecipeTask::Upload { name } => {
+                let result = upload(target, name);
+                let mut state = state.lock();
+                match result {
+                    Ok(recipe) => {
+                        state.uploaded = Some(recipe);
+                        TargetStatus::Done
+                    }
+                    Err(e) => TargetStatus::Failed(e.to_string()),
+                }
+            }
+        };
+        tracing::info!("配方 {}: {}", target.device, status.label());
+        state.lock().targets[index].status = status;
+        repaint();
+        if matches!(task, RecipeTask::Upload { .. }) {
+            break;
+        }
+    }
+}
+
+/// 读取配方中每个字段的当前值, 计算要写入的值
+/// 同一个寄存器中的多个位段依次修改, 后面的字段基于前面字段写入后的值
+fn diff(target: &RecipeTarget, recipe: &Recipe, priority: Priority) -> Result<Vec<RecipeChange>> {
+    let fields = recipe.resolve(&target.map)?;
+    let mut cache: BTreeMap<(Area, u16), u16> = BTreeMap::new();
+    let mut changes = vec![];
+    for (item, (area, field)) in recipe.items.iter().zip(fields) {
+        let addresses = || (0..field.len()).map(|i| (area, field.address.wrapping_add(i)));
+        if addresses().any(|key| !cache.contains_key(&key)) {
+            let raw = read_raw(target, area, field.address, field.len(), priority)?;
+            for (key, raw) in addresses().zip(raw) {
+                cache.entry(key).or_insert(raw);
+            }
+        }
+        let old: Vec<u16> = addresses().map(|key| cache[&key]).collect();
+        let new = field.from_json(&item.value, &old)?;
+        for (key, raw) in addresses().zip(new.iter()) {
+            cache.insert(key, *raw);
+        }
+        changes.push(RecipeChange {
+            field: field.name.clone(),
+            area,
+            address: field.address,
+            old_text: display(&field, &old),
+            new_text: display(&field, &new),
+            old,
+            new,
+            written: false,
+        });
+    }
+    Ok(changes)
+}
+
+fn download(
+    target: &RecipeTarget,
+    index: usize,
+    only_changed: bool,
+    state: &Mutex<RecipeJobState>,
+    cancel: &AtomicBool,
+    repaint: &RepaintSignal,
+) -> TargetStatus {
+    let changes = state.lock().targets[index].changes.clone();
+    let mut attempted = vec![];
+    let mut failure = None;
+    for (i, change) in changes.iter().enumerate() {
+        if only_changed && !change.changed() {
+            continue;
+        }
+        if cancel.load(Ordering::Relaxed) {
+            failure = Some("已取消".to_string());
+            break;
+        }
+        // 写入失败时从站可能已经修改了部分寄存器, 也需要恢复
+        attempted.push(i);
+        match write_verified(target, change.area, change.address, &change.new) {
+            Ok(()) => {
+                state.lock().targets[index].changes[i].written = true;
+                repaint();
+            }
+            Err(e) => {
+                failure = Some(format!("{}: {}", change.field, e));
+                break;
+            }
+        }
+    }
+    let failure = match failure {
+        Some(failure) => failure,
+        None => return TargetStatus::Done,
+    };
+
+    tracing::warn!("配方写入 {} 失败, 回滚: {}", target.device, failure);
+    let mut errors = vec![];
+    for i in attempted.into_iter().rev() {
+        let change = &changes[i];
+        match write_verified(target, change.area, change.address, &change.old) {
+            Ok(()) => state.lock().targets[index].changes[i].written = false,
+            Err(e) => errors.push(format!("{}: {}", change.field, e)),
+        }
+    }
+    if errors.is_empty() {
+        TargetStatus::RolledBack(failure)
+    } else {
+        TargetStatus::Failed(format!("{}, 回滚失败: {}", failure, errors.join("; ")))
+    }
+}
+
+fn upload(target: &RecipeTarget, name: &str) -> Result<Recipe> {
+    let mut recipe = Recipe::new(name, &target.map.name);
+    recipe.description = format!("从 {} 上传", target.device);
+    for (area, field) in recipe_fields(&target.map) {
+        let raw = read_raw(target, area, field.address, field.len(), Priority::Request)?;
+        recipe.items.push(RecipeItem {
+            field: field.name.clone(),
+            value: field.to_json(&raw)?,
+        });
+    }
+    Ok(recipe)
+}
+
+/// 写入后读回, 与写入的值比较, 超过一次请求的数量时分多次写入
+fn write_verified(target: &RecipeTarget, area: Area, address: u16, raw: &[u16]) -> Result<()> {
+    for (i, chunk) in raw.chunks(area.max_write() as usize).enumerate() {
+        let address = address.wrapping_add(i as u16 * area.max_write());
+        let request = area
+            .write_request(address, chunk.to_vec())
+            .ok_or_else(|| AppError::RegisterMap(format!("{} 不可写", area.label())))?;
+        target
+            .bus
+            .request(target.slave, &request, Priority::Write)?;
+    }
+    let read = read_raw(target, area, address, raw.len() as u16, Priority::Write)?;
+    if read != raw {
+        return Err(AppError::Error(format!(
+            "读回校验失败: 写入 {:04X?}, 读回 {:04X?}",
+            raw, read
+        )));
+    }
+    Ok(())
+}
+
+/// 读取连续地址, 超过一次请求的数量时分多次读取
+fn read_raw(
+    target: &RecipeTarget,
+    area: Area,
+    address: u16,
+    len: u16,
+    priority: Priority,
+) -> Result<Vec<u16>> {
+    let mut raw = Vec::with_capacity(len as usize);
+    while raw.len() < len as usize {
+        let start = address.wrapping_add(raw.len() as u16);
+        let quantity = (len - raw.len() as u16).min(area.max_read());
+        match target
+            .bus
+            .request(target.slave, &area.read_request(start, quantity), priority)?
+        {
+            Response::Registers(registers) => raw.extend(registers),
+            Response::Bits(bits) => raw.extend(bits.into_iter().map(u16::from)),
+            response => {
+                return Err(AppError::ModbusInvalidResponse(format!(
+                    "不是读响应: {:?}",
+                    response
+                )))
+            }
+        }
+    }
+    Ok(raw)
+}
+
+fn display(field: &Field, raw: &[u16]) -> String {
+    field
+        .display(raw)
+        .map(|values| values.join(", "))
+        .unwrap_or_else(|e| e.to_string())
+}
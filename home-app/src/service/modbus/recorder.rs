@@ -0,0 +1,171 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Local};
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::{
+    data::{
+        history::HistoryStore,
+        modbus_device::ModbusDevice,
+        register_map::{Area, Field, RegisterMap},
+    },
+    resource::error::{AppError, Result},
+    service::RepaintSignal,
+};
+
+use super::{
+    poller::PollState,
+    scheduler::{BusHandle, ModbusScheduler},
+};
+
+/// 一个设备的记录情况
+#[derive(Debug, Clone)]
+pub struct RecorderStats {
+    pub device: String,
+    pub fields: usize,
+    /// 写入历史的轮询次数
+    pub records: u64,
+    pub last_record: Option<DateTime<Local>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct RecorderState {
+    pub devices: Vec<RecorderStats>,
+}
+
+/// 按设备的轮询间隔读取选择的字段, 把工程值写入历史
+///
+/// 读请求作为轮询组加入总线调度器, 与寄存器页面等的轮询合并发送.
+/// 字符串字段不记录, 数组字段的每个元素是一个序列
+pub struct HistoryRecorder {
+    groups: Vec<(BusHandle, u64)>,
+    state: Arc<Mutex<RecorderState>>,
+}
+
+impl HistoryRecorder {
+    /// devices: 设备, 已应用设备字节顺序的寄存器表, 记录的字段名称
+    pub fn start(
+        devices: Vec<(ModbusDevice, RegisterMap, Vec<String>)>,
+        scheduler: &ModbusScheduler,
+        store: Arc<Mutex<HistoryStore>>,
+        repaint: RepaintSignal,
+    ) -> Result<Self> {
+        let mut targets = vec![];
+        for (device, map, names) in devices {
+            let mut fields = vec![];
+            for name in names.iter() {
+                match map.find(name) {
+                    Some((_, field)) if field.is_text() => {}
+                    Some((area, field)) => fields.push((area, field.clone())),
+                    None => {
+                        return Err(AppError::RegisterMap(format!(
+                            "{} 中没有字段 {}",
+                            map.name, name
+                        )))
+                    }
+                }
+            }
+            if !fields.is_empty() {
+                targets.push((device, map, fields));
+            }
+        }
+        if targets.is_empty() {
+            return Err(AppError::Error("没有选择要记录的字段".to_string()));
+        }
+
+        let state = Arc::new(Mutex::new(RecorderState::default()));
+        let mut groups = vec![];
+        for (index, (device, mut map, fields)) in targets.into_iter().enumerate() {
+            state.lock().devices.push(RecorderStats {
+                device: device.name.clone(),
+                fields: fields.len(),
+                records: 0,
+                last_record: None,
+                last_error: None,
+            });
+            // 只读取选择的字段
+            for area in Area::ALL {
+                map.fields_mut(area)
+                    .retain(|f| fields.iter().any(|(a, field)| *a == area && field == f));
+            }
+            let bus = scheduler.bus(&device.transport)?;
+            let poll = Arc::new(Mutex::new(PollState::default()));
+            let on_poll = {
+                let poll = poll.clone();
+                let store = store.clone();
+                let state = state.clone();
+                let repaint = repaint.clone();
+                let name = device.name.clone();
+                Arc::new(move || {
+                    let (time, values, error) = collect(&poll.lock(), &fields);
+                    if let Some(time) = time {
+                        store.lock().record(&name, time, values);
+                    }
+                    let mut state = state.lock();
+                    let stats = &mut state.devices[index];
+                    if time.is_some() {
+                        stats.records += 1;
+                        stats.last_record = time;
+                    }
+                    stats.last_error = error;
+                    drop(state);
+                    repaint();
+                })
+            };
+            let interval = Duration::from_millis(device.poll_interval.max(10));
+            let group = bus.add_group(device.slave, map.all_read_blocks(), interval, poll, on_poll);
+            groups.push((bus, group));
+        }
+        tracing::info!("modbus 历史记录已启动, {} 个设备", groups.len());
+        Ok(Self { groups, state })
+    }
+
+    pub fn state(&self) -> MutexGuard<RecorderState> {
+        self.state.lock()
+    }
+}
+
+impl Drop for HistoryRecorder {
+    fn drop(&mut self) {
+        for (bus, group) in self.groups.iter() {
+            bus.remove_group(*group);
+        }
+    }
+}
+
+/// 本次轮询成功读取的字段的工程值, 读取失败的字段跳过
+fn collect(
+    poll: &PollState,
+    fields: &[(Area, Field)],
+) -> (
+    Option<DateTime<Local>>,
+    BTreeMap<String, f64>,
+    Option<String>,
+) {
+    let mut time = None;
+    let mut values = BTreeMap::new();
+    let mut error = None;
+    for (area, field) in fields {
+        if let Some(e) = poll.error(*area, field.address, field.len()) {
+            error = Some(format!("{}: {}", field.name, e));
+            continue;
+        }
+        let raw = match poll.raw(*area, field.address, field.len()) {
+            Some(raw) => raw,
+            None => continue,
+        };
+        let decoded = match field.decode(&raw) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                error = Some(e.to_string());
+                continue;
+            }
+        };
+        for (element, value) in field.elements().zip(decoded) {
+            values.insert(element.name, value);
+        }
+        time = time.max(poll.read_time(*area, field.address, field.len()));
+    }
+    (time, values, error)
+}
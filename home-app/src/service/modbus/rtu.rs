@@ -0,0 +1,561 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serialport::{ClearBuffer, SerialPort};
+
+use crate::resource::error::{AppError, Result};
+
+use super::{ascii, hex, pdu::function, Transport};
+
+/// 校验位
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+impl From<Parity> for serialport::Parity {
+    fn from(parity: Parity) -> Self {
+        match parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        }
+    }
+}
+
+/// 停止位
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+impl From<StopBits> for serialport::StopBits {
+    fn from(stop_bits: StopBits) -> Self {
+        match stop_bits {
+            StopBits::One => serialport::StopBits::One,
+            StopBits::Two => serialport::StopBits::Two,
+        }
+    }
+}
+
+/// 串口上的帧格式, 对应固件的 CONFIG_MB_COMM_MODE_RTU 和 CONFIG_MB_COMM_MODE_ASCII
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SerialMode {
+    /// 二进制帧, crc 校验, 按 3.5 个字符的静默时间分帧
+    Rtu,
+    /// ':' 开始, 十六进制文本, lrc 校验, CRLF 结束
+    Ascii,
+}
+
+impl Default for SerialMode {
+    fn default() -> Self {
+        SerialMode::Rtu
+    }
+}
+
+impl SerialMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SerialMode::Rtu => "RTU",
+            SerialMode::Ascii => "ASCII",
+        }
+    }
+
+    pub fn encode_frame(&self, slave: u8, pdu: &[u8]) -> Vec<u8> {
+        match self {
+            SerialMode::Rtu => encode_frame(slave, pdu),
+            SerialMode::Ascii => ascii::encode_frame(slave, pdu),
+        }
+    }
+
+    /// 检查校验, 返回 (从站地址, pdu)
+    pub fn decode_frame(&self, frame: &[u8]) -> Result<(u8, Vec<u8>)> {
+        match self {
+            SerialMode::Rtu => decode_frame(frame).map(|(slave, pdu)| (slave, pdu.to_vec())),
+            SerialMode::Ascii => ascii::decode_frame(frame),
+        }
+    }
+
+    /// 日志中显示的报文, ascii 帧本身就是文本
+    pub fn format_frame(&self, frame: &[u8]) -> String {
+        match self {
+            SerialMode::Rtu => hex(frame),
+            SerialMode::Ascii => String::from_utf8_lossy(frame).trim_end().to_string(),
+        }
+    }
+}
+
+/// 串口参数, 数据位固定为 8
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerialConfig {
+    /// 串口名称, 如 /dev/ttyUSB0, COM3
+    pub port: String,
+    pub baud_rate: u32,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    /// 帧间隔, 单位: 微秒, None 时按 3.5 个字符时间计算
+    pub frame_gap_us: Option<u64>,
+    #[serde(default)]
+    pub mode: SerialMode,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            port: if cfg!(windows) {
+                "COM1".into()
+            } else {
+                "/dev/ttyUSB0".into()
+            },
+            baud_rate: 115200,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            frame_gap_us: None,
+            mode: SerialMode::Rtu,
+        }
+    }
+}
+
+impl SerialConfig {
+    /// 传输一个字符需要的位数: 起始位 + 8 数据位 + 校验位 + 停止位
+    fn char_bits(&self) -> u64 {
+        let parity = if self.parity == Parity::None { 0 } else { 1 };
+        let stop = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        1 + 8 + parity + stop
+    }
+
+    /// 如 "115200 8N1", ascii 模式时加上 " ASCII"
+    pub fn line_label(&self) -> String {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        let stop = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        let mut label = format!("{} 8{}{}", self.baud_rate, parity, stop);
+        if self.mode == SerialMode::Ascii {
+            label.push_str(" ASCII");
+        }
+        label
+    }
+
+    /// 帧间隔 t3.5, 波特率大于 19200 时固定为 1750us
+    pub fn frame_gap(&self) -> Duration {
+        if let Some(us) = self.frame_gap_us {
+            return Duration::from_micros(us);
+        }
+        if self.baud_rate > 19200 {
+            Duration::from_micros(1750)
+        } else {
+            Duration::from_micros(self.char_bits() * 3_500_000 / self.baud_rate.max(1) as u64)
+        }
+    }
+}
+
+/// 系统中可用的串口
+pub fn available_ports() -> Vec<String> {
+    serialport::available_ports()
+        .map(|ports| ports.into_iter().map(|p| p.port_name).collect())
+        .unwrap_or_default()
+}
+
+/// Modbus CRC16, 多项式 0xA001, 初始值 0xFFFF, 低字节在前发送
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0xFFFF, |crc, byte| crc16_update(crc, *byte))
+}
+
+/// 逐字节计算 crc, 用于在字节流中查找帧边界
+pub(super) fn crc16_update(mut crc: u16, byte: u8) -> u16 {
+    crc ^= byte as u16;
+    for _ in 0..8 {
+        if crc & 1 != 0 {
+            crc = (crc >> 1) ^ 0xA001;
+        } else {
+            crc >>= 1;
+        }
+    }
+    crc
+}
+
+/// 组装 rtu 帧: 从站地址 + pdu + crc
+pub fn encode_frame(slave: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(pdu.len() + 3);
+    frame.push(slave);
+    frame.extend_from_slice(pdu);
+    frame.extend_from_slice(&crc16(&frame).to_le_bytes());
+    frame
+}
+
+/// 检查 crc, 返回 (从站地址, pdu)
+pub fn decode_frame(frame: &[u8]) -> Result<(u8, &[u8])> {
+    if frame.len() < 4 {
+        return Err(AppError::ModbusInvalidResponse(format!(
+            "帧长度错误: {}",
+            frame.len()
+        )));
+    }
+    let (body, crc) = frame.split_at(frame.len() - 2);
+    let expected = crc16(body);
+    let actual = u16::from_le_bytes([crc[0], crc[1]]);
+    if expected != actual {
+        return Err(AppError::ModbusCrc { expected, actual });
+    }
+    Ok((body[0], &body[1..]))
+}
+
+/// 根据已收到的帧头, 计算响应帧的总长度(包括 crc)
+/// 返回 None 表示还需要更多字节
+fn expected_frame_len(header: &[u8]) -> Result<Option<usize>> {
+    let function = match header.get(1) {
+        Some(function) => *function,
+        None => return Ok(None),
+    };
+    if function & 0x80 != 0 {
+        return Ok(Some(5));
+    }
+    match function {
+        function::READ_COILS
+        | function::READ_DISCRETE_INPUTS
+        | function::READ_HOLDING_REGISTERS
+        | function::READ_INPUT_REGISTERS
+        | function::REPORT_SERVER_ID => Ok(header.get(2).map(|count| 3 + *count as usize + 2)),
+        function::WRITE_SINGLE_COIL
+        | function::WRITE_SINGLE_REGISTER
+        | function::WRITE_MULTIPLE_COILS
+        | function::WRITE_MULTIPLE_REGISTERS => Ok(Some(8)),
+        function => Err(AppError::ModbusInvalidResponse(format!(
+            "不支持的功能码 0x{:02X}",
+            function
+        ))),
+    }
+}
+
+/// rtu 帧的从站地址范围, 不支持广播
+pub(super) fn check_slave(slave: u8) -> Result<()> {
+    if !(1..=247).contains(&slave) {
+        return Err(AppError::ModbusInvalidRequest(format!(
+            "从站地址 {} 超出范围 1..=247",
+            slave
+        )));
+    }
+    Ok(())
+}
+
+/// 检查响应帧的 crc 和从站地址, 返回响应的 pdu
+pub(super) fn check_response(slave: u8, frame: &[u8]) -> Result<Vec<u8>> {
+    let (address, pdu) = decode_frame(frame)?;
+    check_address(slave, address)?;
+    Ok(pdu.to_vec())
+}
+
+fn check_address(slave: u8, address: u8) -> Result<()> {
+    if address != slave {
+        return Err(AppError::ModbusInvalidResponse(format!(
+            "从站地址不匹配: 请求 {}, 响应 {}",
+            slave, address
+        )));
+    }
+    Ok(())
+}
+
+/// 读取一个完整的 rtu 响应帧
+///
+/// read 的第二个参数为剩余的超时时间, 超时时返回 TimedOut 或 WouldBlock
+pub(super) fn read_frame<F>(mut read: F, timeout: Duration) -> Result<Vec<u8>>
+where
+    F: FnMut(&mut [u8], Duration) -> io::Result<usize>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut frame = Vec::with_capacity(256);
+    let mut buf = [0u8; 256];
+    loop {
+        if let Some(len) = expected_frame_len(&frame)? {
+            if frame.len() >= len {
+                frame.truncate(len);
+                return Ok(frame);
+            }
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            if !frame.is_empty() {
+                tracing::debug!("rtu rx (不完整): {}", hex(&frame));
+            }
+            return Err(AppError::ModbusTimeout);
+        }
+        match read(&mut buf, remaining) {
+            Ok(n) => frame.extend_from_slice(&buf[..n]),
+            Err(e) if is_timeout(&e) || e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+pub(crate) fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+}
+
+/// 串口上的 rtu 或 ascii 传输层
+///
+/// linux 下可以用 `socat -d -d pty,raw,echo=0 pty,raw,echo=0` 创建一对虚拟串口进行测试
+pub struct RtuTransport {
+    config: SerialConfig,
+    port: Box<dyn SerialPort>,
+    /// 上一帧结束的时间, 用于保证帧间隔
+    last_frame: Instant,
+}
+
+impl RtuTransport {
+    pub fn open(config: SerialConfig) -> Result<Self> {
+        let port = serialport::new(&config.port, config.baud_rate)
+            .data_bits(serialport::DataBits::Eight)
+            .parity(config.parity.into())
+            .stop_bits(config.stop_bits.into())
+            .flow_control(serialport::FlowControl::None)
+            .timeout(Duration::from_millis(100))
+            .open()?;
+        tracing::info!("打开串口 {} {}", config.port, config.baud_rate);
+        Ok(Self {
+            config,
+            port,
+            last_frame: Instant::now(),
+        })
+    }
+
+    pub fn config(&self) -> &SerialConfig {
+        &self.config
+    }
+}
+
+impl Transport for RtuTransport {
+    fn transact(&mut self, slave: u8, pdu: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        check_slave(slave)?;
+        let gap = self.config.frame_gap();
+        let elapsed = self.last_frame.elapsed();
+        if elapsed < gap {
+            thread::sleep(gap - elapsed);
+        }
+
+        // 丢弃上一次请求超时后才到达的数据
+        self.port.clear(ClearBuffer::Input)?;
+
+        let mode = self.config.mode;
+        let frame = mode.encode_frame(slave, pdu);
+        tracing::debug!("{} tx: {}", mode.label(), mode.format_frame(&frame));
+        self.port.write_all(&frame)?;
+        self.port.flush()?;
+
+        let port = &mut self.port;
+        let read = |buf: &mut [u8], remaining| {
+            port.set_timeout(remaining)?;
+            port.read(buf)
+        };
+        let result = match mode {
+            SerialMode::Rtu => read_frame(read, timeout),
+            SerialMode::Ascii => ascii::read_frame(read, timeout),
+        };
+        self.last_frame = Instant::now();
+        let response = result?;
+        tracing::debug!("{} rx: {}", mode.label(), mode.format_frame(&response));
+        match mode {
+            SerialMode::Rtu => check_response(slave, &response),
+            SerialMode::Ascii => {
+                let (address, pdu) = ascii::decode_frame(&response)?;
+                check_address(slave, address)?;
+                Ok(pdu)
+            }
+        }
+    }
+
+    fn label(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.config.mode.label().to_lowercase(),
+            self.config.port,
+            self.config.baud_rate
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    #[test]
+    fn crc16_known_vectors() {
+        // CRC-16/MODBUS 的校验值
+        assert_eq!(crc16(b"123456789"), 0x4B37);
+        assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x02]), 0x0BC4);
+        assert_eq!(crc16(&[0x11, 0x03, 0x00, 0x6B, 0x00, 0x03]), 0x8776);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn encode_and_decode_frames() {
+        let frame = encode_frame(0x01, &[0x03, 0x00, 0x00, 0x00, 0x02]);
+        assert_eq!(frame, vec![0x01, 0x03, 0x00, 0x00, 0x00, 0x02, 0xC4, 0x0B]);
+        assert_eq!(
+            decode_frame(&frame).unwrap(),
+            (0x01, &[0x03, 0x00, 0x00, 0x00, 0x02][..])
+        );
+
+        let mut corrupted = frame.clone();
+        corrupted[3] ^= 0x01;
+        assert!(matches!(
+            decode_frame(&corrupted),
+            Err(AppError::ModbusCrc { .. })
+        ));
+        assert!(matches!(
+            decode_frame(&frame[..3]),
+            Err(AppError::ModbusInvalidResponse(_))
+        ));
+        assert!(check_response(0x02, &frame).is_err());
+        assert!(check_slave(0).is_err());
+        assert!(check_slave(248).is_err());
+        assert!(check_slave(247).is_ok());
+    }
+
+    /// 按给定的分段返回数据, 分段用完后超时
+    fn reader(chunks: Vec<Vec<u8>>) -> impl FnMut(&mut [u8], Duration) -> io::Result<usize> {
+        let mut chunks: VecDeque<Vec<u8>> = chunks.into();
+        move |buf: &mut [u8], _| match chunks.pop_front() {
+            Some(chunk) => {
+                buf[..chunk.len()].copy_from_slice(&chunk);
+                Ok(chunk.len())
+            }
+            None => Err(io::Error::new(ErrorKind::TimedOut, "timeout")),
+        }
+    }
+
+    #[test]
+    fn read_frame_by_length() {
+        let response = encode_frame(0x11, &[0x03, 0x06, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64]);
+        let chunks = vec![
+            response[..1].to_vec(),
+            response[1..4].to_vec(),
+            response[4..].to_vec(),
+        ];
+        let frame = read_frame(reader(chunks), Duration::from_millis(100)).unwrap();
+        assert_eq!(frame, response);
+
+        // 异常响应固定 5 个字节, 多余的字节丢弃
+        let exception = encode_frame(0x11, &[0x83, 0x02]);
+        let mut chunk = exception.clone();
+        chunk.push(0xAA);
+        let frame = read_frame(reader(vec![chunk]), Duration::from_millis(100)).unwrap();
+        assert_eq!(frame, exception);
+
+        let write = encode_frame(0x11, &[0x10, 0x00, 0x01, 0x00, 0x02]);
+        let frame = read_frame(reader(vec![write.clone()]), Duration::from_millis(100)).unwrap();
+        assert_eq!(frame, write);
+    }
+
+    #[test]
+    fn read_frame_timeout_and_invalid_function() {
+        let partial = vec![vec![0x11, 0x03, 0x06, 0x02]];
+        assert_eq!(
+            read_frame(reader(partial), Duration::from_millis(20)),
+            Err(AppError::ModbusTimeout)
+        );
+        assert!(matches!(
+            read_frame(reader(vec![vec![0x11, 0x2B]]), Duration::from_millis(20)),
+            Err(AppError::ModbusInvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn frame_gap() {
+        let config = SerialConfig {
+            baud_rate: 9600,
+            ..Default::default()
+        };
+        // 10 位一个字符, 3.5 个字符
+        assert_eq!(config.frame_gap(), Duration::from_micros(3645));
+        let config = SerialConfig::default();
+        assert_eq!(config.frame_gap(), Duration::from_micros(1750));
+    }
+
+    /// 主站通过虚拟串口与 slave.rs 中的从站通信
+    #[cfg(unix)]
+    #[test]
+    fn pty_round_trip() {
+        use std::sync::Arc;
+
+        use parking_lot::Mutex;
+
+        use crate::{
+            data::register_map::{Area, RegisterMap},
+            service::modbus::{
+                pdu::{ExceptionCode, Request},
+                slave::{ModbusSlave, RegisterImage, SlaveListen},
+                ModbusMaster,
+            },
+        };
+
+        let map = RegisterMap::modbus_rtu_example();
+        let image = Arc::new(Mutex::new(RegisterImage::from_map(&map)));
+        let slave = ModbusSlave::start(&SlaveListen::Pty, 1, image.clone()).unwrap();
+        let port = slave.label().trim_start_matches("pty ").to_string();
+        let transport = RtuTransport::open(SerialConfig {
+            port,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut master = ModbusMaster::new(Box::new(transport));
+        master.timeout = Duration::from_secs(1);
+
+        let (_, field) = map.find("holding_data0").unwrap();
+        let raw = master.read_holding_registers(1, 0, 2).unwrap();
+        assert_eq!(field.decode(&raw).unwrap(), vec![1.34f32 as f64]);
+
+        assert_eq!(
+            master.read_coils(1, 0, 8).unwrap(),
+            vec![true, false, true, false, true, false, true, false]
+        );
+        master.write_single_coil(1, 1, true).unwrap();
+        master.write_multiple_registers(1, 8, &[1, 2, 3]).unwrap();
+        assert_eq!(
+            master.read_holding_registers(1, 8, 3).unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            image.lock().read(Area::Coils, 0, 2),
+            Ok(vec![1, 1]),
+            "写入的值保存在从站的寄存器镜像中"
+        );
+
+        // 寄存器表中没有定义的地址
+        let request = Request::ReadHoldingRegisters {
+            address: 200,
+            quantity: 1,
+        };
+        assert_eq!(
+            master.request_with(1, &request, Duration::from_secs(1), 0),
+            Err(AppError::ModbusException {
+                function: 0x03,
+                code: ExceptionCode::IllegalDataAddress,
+            })
+        );
+        // 其它从站地址的请求没有响应
+        assert_eq!(
+            master.request_with(2, &request, Duration::from_millis(200), 0),
+            Err(AppError::ModbusTimeout)
+        );
+    }
+}
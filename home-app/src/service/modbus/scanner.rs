@@ -0,0 +1,328 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    resource::error::{AppError, Result},
+    service::RepaintSignal,
+};
+
+use super::{
+    pdu::{function, ExceptionCode},
+    rtu::{Parity, RtuTransport, SerialConfig, SerialMode, StopBits},
+    Transport,
+};
+
+/// 扫描的波特率, modbus-rtu-example 的 Kconfig 中 MB_UART_BAUD_RATE 的范围为 1200..=115200
+pub const SCAN_BAUD_RATES: [u32; 8] = [1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+/// 发现从站使用的请求: 读保持寄存器 0, 不支持的从站也会返回异常响应
+const DISCOVERY: [u8; 5] = [function::READ_HOLDING_REGISTERS, 0x00, 0x00, 0x00, 0x01];
+
+/// 探测功能码使用的请求
+/// 写功能码使用非法的值或数量, 符合规范的从站返回非法数据值, 不会写入
+const PROBES: [(u8, &[u8], bool); 8] = [
+    (function::READ_COILS, &[0x00, 0x00, 0x00, 0x01], false),
+    (
+        function::READ_DISCRETE_INPUTS,
+        &[0x00, 0x00, 0x00, 0x01],
+        false,
+    ),
+    (
+        function::READ_HOLDING_REGISTERS,
+        &[0x00, 0x00, 0x00, 0x01],
+        false,
+    ),
+    (
+        function::READ_INPUT_REGISTERS,
+        &[0x00, 0x00, 0x00, 0x01],
+        false,
+    ),
+    (function::REPORT_SERVER_ID, &[], false),
+    (function::WRITE_SINGLE_COIL, &[0x00, 0x00, 0x12, 0x34], true),
+    (
+        function::WRITE_MULTIPLE_COILS,
+        &[0x00, 0x00, 0x00, 0x00, 0x00],
+        true,
+    ),
+    (
+        function::WRITE_MULTIPLE_REGISTERS,
+        &[0x00, 0x00, 0x00, 0x00, 0x00],
+        true,
+    ),
+];
+
+/// 扫描参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanConfig {
+    pub port: String,
+    pub baud_rates: Vec<u32>,
+    pub parities: Vec<Parity>,
+    pub stop_bits: StopBits,
+    pub mode: SerialMode,
+    pub first: u8,
+    pub last: u8,
+    /// 每个地址的响应超时, 单位: 毫秒, 低波特率时会加上传输时间
+    pub timeout: u64,
+    /// 发现从站后探测支持的功能码
+    pub probe_functions: bool,
+    /// 同时探测写功能码
+    pub probe_writes: bool,
+    /// 某个串口参数下发现从站后, 不再尝试其它参数
+    pub stop_on_found: bool,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            port: SerialConfig::default().port,
+            baud_rates: SCAN_BAUD_RATES.to_vec(),
+            parities: vec![Parity::None, Parity::Even, Parity::Odd],
+            stop_bits: StopBits::One,
+            mode: SerialMode::Rtu,
+            first: 1,
+            last: 247,
+            timeout: 100,
+            probe_functions: true,
+            probe_writes: false,
+            stop_on_found: true,
+        }
+    }
+}
+
+impl ScanConfig {
+    fn settings(&self) -> Vec<SerialConfig> {
+        let mut settings = vec![];
+        for baud_rate in self.baud_rates.iter() {
+            for parity in self.parities.iter() {
+                settings.push(SerialConfig {
+                    port: self.port.clone(),
+                    baud_rate: *baud_rate,
+                    parity: *parity,
+                    stop_bits: self.stop_bits,
+                    frame_gap_us: None,
+                    mode: self.mode,
+                });
+            }
+        }
+        settings
+    }
+
+    fn addresses(&self) -> std::ops::RangeInclusive<u8> {
+        self.first.max(1)..=self.last.min(247)
+    }
+
+    /// 需要发送的发现请求总数
+    pub fn total(&self) -> usize {
+        self.settings().len() * self.addresses().count()
+    }
+}
+
+/// 一个功能码的探测结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub function: u8,
+    /// None 表示没有响应
+    pub response: Option<std::result::Result<(), ExceptionCode>>,
+}
+
+impl Probe {
+    /// 正常响应或非 "非法功能码" 的异常都说明从站支持该功能码
+    pub fn supported(&self) -> bool {
+        match self.response {
+            Some(Ok(())) => true,
+            Some(Err(code)) => code != ExceptionCode::IllegalFunction,
+            None => false,
+        }
+    }
+}
+
+/// 一个应答的从站
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub serial: SerialConfig,
+    pub slave: u8,
+    /// 发现请求的往返时间
+    pub latency: Duration,
+    pub probes: Vec<Probe>,
+}
+
+impl ScanResult {
+    pub fn supported_functions(&self) -> Vec<u8> {
+        self.probes
+            .iter()
+            .filter(|p| p.supported())
+            .map(|p| p.function)
+            .collect()
+    }
+
+    /// 探测过程中收到的异常码
+    pub fn exceptions(&self) -> Vec<ExceptionCode> {
+        let mut exceptions = vec![];
+        for probe in self.probes.iter() {
+            if let Some(Err(code)) = probe.response {
+                if !exceptions.contains(&code) {
+                    exceptions.push(code);
+                }
+            }
+        }
+        exceptions
+    }
+}
+
+/// 扫描进度和结果
+#[derive(Debug, Default)]
+pub struct ScanState {
+    pub done: usize,
+    pub total: usize,
+    /// 正在扫描的串口参数
+    pub current: String,
+    pub results: Vec<ScanResult>,
+    /// crc 错误等无效响应的次数, 较多时说明串口参数不对或线路有干扰
+    pub invalid: u64,
+    pub finished: bool,
+    pub error: Option<String>,
+}
+
+/// 在后台线程中扫描串口总线上的从站
+pub struct BusScanner {
+    state: Arc<Mutex<ScanState>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl BusScanner {
+    pub fn start(config: ScanConfig, repaint: RepaintSignal) -> Result<Self> {
+        let state = Arc::new(Mutex::new(ScanState {
+            total: config.total(),
+            ..Default::default()
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let thread_state = state.clone();
+        let thread_cancel = cancel.clone();
+        thread::Builder::new()
+            .name("modbus-scanner".to_string())
+            .spawn(move || {
+                let result = scan(&config, &thread_state, &thread_cancel, &repaint);
+                let mut state = thread_state.lock();
+                state.finished = true;
+                state.error = result.err().map(|e| e.to_string());
+                drop(state);
+                repaint();
+            })?;
+        Ok(Self { state, cancel })
+    }
+
+    pub fn state(&self) -> MutexGuard<ScanState> {
+        self.state.lock()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for BusScanner {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn scan(
+    config: &ScanConfig,
+    state: &Mutex<ScanState>,
+    cancel: &AtomicBool,
+    repaint: &RepaintSignal,
+) -> Result<()> {
+    for serial in config.settings() {
+        let label = format!("{} {}", serial.port, serial.line_label());
+        state.lock().current = label.clone();
+        tracing::info!("扫描 {}", label);
+
+        // 一个请求和响应最多约 16 个字符 (ascii 模式约 35 个), 低波特率时需要加上传输时间
+        let chars = match serial.mode {
+            SerialMode::Rtu => 16,
+            SerialMode::Ascii => 35,
+        };
+        let char_time = Duration::from_micros(11 * 1_000_000 / serial.baud_rate.max(1) as u64);
+        let timeout = Duration::from_millis(config.timeout) + char_time * chars;
+        let mut transport = RtuTransport::open(serial.clone())?;
+        let mut found = false;
+        for slave in config.addresses() {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let start = Instant::now();
+            let response = probe(&mut transport, slave, &DISCOVERY, timeout, state)?;
+            let latency = start.elapsed();
+            if response.is_some() {
+                let mut probes = vec![];
+                if config.probe_functions {
+                    for (function, data, write) in PROBES {
+                        if write && !config.probe_writes {
+                            continue;
+                        }
+                        let mut pdu = vec![function];
+                        pdu.extend_from_slice(data);
+                        probes.push(Probe {
+                            function,
+                            response: probe(&mut transport, slave, &pdu, timeout, state)?,
+                        });
+                    }
+                }
+                tracing::info!("发现从站 #{}: {}, {:?}", slave, label, latency);
+                state.lock().results.push(ScanResult {
+                    serial: serial.clone(),
+                    slave,
+                    latency,
+                    probes,
+                });
+                found = true;
+            }
+            state.lock().done += 1;
+            repaint();
+        }
+        if found && config.stop_on_found {
+            state.lock().done = config.total();
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 发送一个原始请求, 返回是否为异常响应, 没有有效响应时返回 None
+/// 串口本身出错时停止扫描
+fn probe(
+    transport: &mut RtuTransport,
+    slave: u8,
+    pdu: &[u8],
+    timeout: Duration,
+    state: &Mutex<ScanState>,
+) -> Result<Option<std::result::Result<(), ExceptionCode>>> {
+    Ok(match transport.transact(slave, pdu, timeout) {
+        Ok(response) if response.first() == Some(&(pdu[0] | 0x80)) => {
+            Some(Err(response.get(1).copied().unwrap_or_default().into()))
+        }
+        Ok(response) if response.first() == Some(&pdu[0]) => Some(Ok(())),
+        Ok(_) => {
+            state.lock().invalid += 1;
+            None
+        }
+        Err(AppError::ModbusTimeout) => None,
+        Err(e @ (AppError::Serial(_) | AppError::Error(_))) => return Err(e),
+        Err(e) => {
+            tracing::debug!("从站 #{} 无效响应: {}", slave, e);
+            state.lock().invalid += 1;
+            None
+        }
+    })
+}
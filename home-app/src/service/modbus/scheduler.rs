@@ -0,0 +1,1000 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::register_map::{Area, ReadBlock},
+    resource::error::{AppError, Result},
+    service::RepaintSignal,
+};
+
+use super::{
+    pdu::{Request, Response},
+    poller::PollState,
+    ModbusMaster, TransportConfig,
+};
+
+/// 计算总线占用率的时间窗口
+const UTILISATION_WINDOW: Duration = Duration::from_secs(10);
+
+/// 请求的优先级, 排在前面的先发送
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// 操作员的写请求
+    Write,
+    /// 其它一次性请求, 如网关转发的请求
+    Request,
+    /// 周期轮询
+    Poll,
+}
+
+/// 一条总线的调度参数
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BusConfig {
+    /// 收到响应后到下一个请求的最小间隔, 单位: 毫秒
+    /// 用于处理慢的从站和 rs485 收发切换慢的转换器, 串口的 t3.5 由传输层保证
+    pub gap_ms: u64,
+    /// 每秒最多发送的请求数, 0 表示不限制
+    pub max_rate: f64,
+    /// 响应超时, 单位: 毫秒
+    pub timeout_ms: u64,
+    /// 重试次数, 不包括第一次请求
+    pub retries: u32,
+}
+
+impl Default for BusConfig {
+    fn default() -> Self {
+        Self {
+            gap_ms: 0,
+            max_rate: 0.0,
+            timeout_ms: 500,
+            retries: 2,
+        }
+    }
+}
+
+/// 一个从站的请求统计, 每次重试单独计数
+#[derive(Debug, Clone, Default)]
+pub struct SlaveStats {
+    pub requests: u64,
+    pub timeouts: u64,
+    /// 超时以外的失败, 包括异常响应
+    pub failures: u64,
+    /// 成功请求的平均耗时
+    pub latency: Option<Duration>,
+    pub last_error: Option<String>,
+    pub last_request: Option<DateTime<Local>>,
+    /// 最后一次收到响应的时间, 异常响应也算作从站在线
+    pub last_response: Option<DateTime<Local>>,
+}
+
+impl SlaveStats {
+    /// 超时的请求占比
+    pub fn timeout_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.timeouts as f64 / self.requests as f64
+        }
+    }
+
+    /// 最后一次请求是否收到了响应, 还没有请求时返回 None
+    pub fn online(&self) -> Option<bool> {
+        let request = self.last_request?;
+        Some(
+            self.last_response
+                .map_or(false, |response| response >= request),
+        )
+    }
+
+    fn record(&mut self, result: &Result<Response>, elapsed: Duration) {
+        self.requests += 1;
+        let now = Local::now();
+        self.last_request = Some(now);
+        if matches!(result, Ok(_) | Err(AppError::ModbusException { .. })) {
+            self.last_response = Some(now);
+        }
+        match result {
+            Ok(_) => {
+                // 指数平均, 新的值占 1/8
+                self.latency = Some(match self.latency {
+                    Some(latency) => (latency * 7 + elapsed) / 8,
+                    None => elapsed,
+                });
+            }
+            Err(e) => {
+                if *e == AppError::ModbusTimeout {
+                    self.timeouts += 1;
+                } else {
+                    self.failures += 1;
+                }
+                self.last_error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+/// 一条总线的统计
+#[derive(Debug, Clone, Default)]
+pub struct BusStats {
+    pub label: String,
+    /// 发送的请求数, 包括重试
+    pub requests: u64,
+    /// 合并后少发送的读请求数
+    pub merged: u64,
+    /// 等待发送的一次性请求
+    pub queued: usize,
+    /// 周期轮询的数量
+    pub groups: usize,
+    pub slaves: BTreeMap<u8, SlaveStats>,
+    /// 最近的请求 (结束时间, 占用总线的时间)
+    window: VecDeque<(Instant, Duration)>,
+}
+
+impl BusStats {
+    /// 最近 10 秒内总线被占用的比例, 等待响应的时间也算作占用
+    pub fn utilisation(&self) -> f64 {
+        let now = Instant::now();
+        let busy: Duration = self
+            .window
+            .iter()
+            .filter(|(end, _)| now.duration_since(*end) < UTILISATION_WINDOW)
+            .map(|(_, busy)| *busy)
+            .sum();
+        (busy.as_secs_f64() / UTILISATION_WINDOW.as_secs_f64()).min(1.0)
+    }
+
+    fn record(&mut self, slave: u8, result: &Result<Response>, start: Instant) {
+        let end = Instant::now();
+        self.requests += 1;
+        self.slaves
+            .entry(slave)
+            .or_default()
+            .record(result, end - start);
+        self.window.push_back((end, end - start));
+        while let Some((first, _)) = self.window.front() {
+            if end.duration_since(*first) < UTILISATION_WINDOW {
+                break;
+            }
+            self.window.pop_front();
+        }
+    }
+}
+
+type Callback = Box<dyn FnOnce(Result<Response>) + Send>;
+
+/// 一次性请求, 相同的读请求合并后共用一个结果
+struct Job {
+    slave: u8,
+    request: Request,
+    priority: Priority,
+    seq: u64,
+    callbacks: Vec<Callback>,
+}
+
+/// 周期轮询的一组读请求
+struct Group {
+    id: u64,
+    slave: u8,
+    blocks: Vec<ReadBlock>,
+    interval: Duration,
+    next_poll: Instant,
+    state: Arc<Mutex<PollState>>,
+    repaint: RepaintSignal,
+}
+
+/// 合并后的一个读请求
+struct Read {
+    slave: u8,
+    block: ReadBlock,
+}
+
+#[derive(Default)]
+struct BusQueue {
+    jobs: Vec<Job>,
+    groups: Vec<Group>,
+    next_id: u64,
+    stop: bool,
+}
+
+/// 总线线程和所有句柄共享的数据
+struct BusShared {
+    transport: TransportConfig,
+    queue: Mutex<BusQueue>,
+    wakeup: Condvar,
+    config: Mutex<BusConfig>,
+    stats: Mutex<BusStats>,
+}
+
+impl BusShared {
+    fn notify(&self) {
+        self.wakeup.notify_one();
+    }
+}
+
+/// 最后一个句柄释放时停止总线线程
+struct BusOwner {
+    shared: Arc<BusShared>,
+}
+
+impl Drop for BusOwner {
+    fn drop(&mut self) {
+        self.shared.queue.lock().stop = true;
+        self.shared.notify();
+    }
+}
+
+/// 一条总线的句柄, 所有使用这条总线的页面和服务通过它排队访问总线
+#[derive(Clone)]
+pub struct BusHandle {
+    owner: Arc<BusOwner>,
+}
+
+impl BusHandle {
+    fn shared(&self) -> &BusShared {
+        &self.owner.shared
+    }
+
+    pub fn label(&self) -> String {
+        self.shared().stats.lock().label.clone()
+    }
+
+    /// 请求加入队列, 完成后在总线线程中调用 done
+    /// 队列中已有相同的轮询读请求时共用一次请求
+    pub fn submit(
+        &self,
+        slave: u8,
+        request: Request,
+        priority: Priority,
+        done: impl FnOnce(Result<Response>) + Send + 'static,
+    ) {
+        let mut queue = self.shared().queue.lock();
+        if priority == Priority::Poll && request.is_read() {
+            let same = queue
+                .jobs
+                .iter_mut()
+                .find(|job| job.slave == slave && job.request == request);
+            if let Some(job) = same {
+                job.callbacks.push(Box::new(done));
+                self.shared().stats.lock().merged += 1;
+                return;
+            }
+        }
+        queue.next_id += 1;
+        let seq = queue.next_id;
+        queue.jobs.push(Job {
+            slave,
+            request,
+            priority,
+            seq,
+            callbacks: vec![Box::new(done)],
+        });
+        drop(queue);
+        self.shared().notify();
+    }
+
+    /// 发送请求并等待结果
+    pub fn request(&self, slave: u8, request: &Request, priority: Priority) -> Result<Response> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.submit(slave, request.clone(), priority, move |result| {
+            tx.send(result).ok();
+        });
+        rx.recv()
+            .map_err(|_| AppError::ModbusConnection("总线已关闭".to_string()))?
+    }
+
+    /// 按间隔读取 blocks, 结果写入 state, 返回用于修改和删除的 id
+    pub fn add_group(
+        &self,
+        slave: u8,
+        blocks: Vec<ReadBlock>,
+        interval: Duration,
+        state: Arc<Mutex<PollState>>,
+        repaint: RepaintSignal,
+    ) -> u64 {
+        let mut queue = self.shared().queue.lock();
+        queue.next_id += 1;
+        let id = queue.next_id;
+        queue.groups.push(Group {
+            id,
+            slave,
+            blocks,
+            interval,
+            next_poll: Instant::now(),
+            state,
+            repaint,
+        });
+        drop(queue);
+        self.shared().notify();
+        id
+    }
+
+    pub fn remove_group(&self, id: u64) {
+        self.shared().queue.lock().groups.retain(|g| g.id != id);
+    }
+
+    pub fn set_group_interval(&self, id: u64, interval: Duration) {
+        let mut queue = self.shared().queue.lock();
+        if let Some(group) = queue.groups.iter_mut().find(|g| g.id == id) {
+            group.next_poll = group
+                .next_poll
+                .checked_sub(group.interval)
+                .map_or_else(Instant::now, |last| last + interval);
+            group.interval = interval;
+        }
+        drop(queue);
+        self.shared().notify();
+    }
+
+    /// 立即读取一次, 如写入后刷新界面上的值
+    pub fn refresh_group(&self, id: u64) {
+        let mut queue = self.shared().queue.lock();
+        if let Some(group) = queue.groups.iter_mut().find(|g| g.id == id) {
+            group.next_poll = Instant::now();
+        }
+        drop(queue);
+        self.shared().notify();
+    }
+
+    pub fn config(&self) -> BusConfig {
+        *self.shared().config.lock()
+    }
+
+    pub fn stats(&self) -> BusStats {
+        let mut stats = self.shared().stats.lock().clone();
+        let queue = self.shared().queue.lock();
+        stats.queued = queue.jobs.len();
+        stats.groups = queue.groups.len();
+        stats
+    }
+}
+
+/// 总线调度器, 每条物理总线一个后台线程, 合并所有页面和服务的请求
+///
+/// 一次性请求按优先级发送, 写请求优先; 周期轮询中同一从站相邻或重叠的读请求合并为一个.
+/// 每个请求之前保证配置的帧间隔和请求速率, 半双工的 rs485 总线上不会出现两个请求同时发送
+#[derive(Clone, Default)]
+pub struct ModbusScheduler {
+    inner: Arc<Mutex<SchedulerInner>>,
+}
+
+#[derive(Default)]
+struct SchedulerInner {
+    buses: BTreeMap<String, Weak<BusOwner>>,
+    configs: BTreeMap<String, BusConfig>,
+}
+
+impl ModbusScheduler {
+    pub fn new(configs: BTreeMap<String, BusConfig>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SchedulerInner {
+                buses: BTreeMap::new(),
+                configs,
+            })),
+        }
+    }
+
+    /// 打开总线, 已打开时返回同一条总线
+    /// 同一个串口不能同时使用不同的参数
+    pub fn bus(&self, transport: &TransportConfig) -> Result<BusHandle> {
+        let key = transport.bus_key();
+        let mut inner = self.inner.lock();
+        if let Some(owner) = inner.buses.get(&key).and_then(Weak::upgrade) {
+            if owner.shared.transport != *transport {
+                return Err(AppError::ModbusConnection(format!(
+                    "{} 正在以 {} 使用",
+                    key,
+                    owner.shared.transport.label()
+                )));
+            }
+            return Ok(BusHandle { owner });
+        }
+
+        let master = ModbusMaster::open(transport)?;
+        let shared = Arc::new(BusShared {
+            transport: transport.clone(),
+            queue: Mutex::new(BusQueue::default()),
+            wakeup: Condvar::new(),
+            config: Mutex::new(inner.configs.get(&key).copied().unwrap_or_default()),
+            stats: Mutex::new(BusStats {
+                label: master.label(),
+                ..Default::default()
+            }),
+        });
+        let thread_shared = shared.clone();
+        thread::Builder::new()
+            .name("modbus-bus".to_string())
+            .spawn(move || BusThread::new(master, thread_shared).run())?;
+        tracing::info!("打开 modbus 总线 {}", transport.label());
+
+        let owner = Arc::new(BusOwner { shared });
+        inner.buses.insert(key, Arc::downgrade(&owner));
+        Ok(BusHandle { owner })
+    }
+
+    /// 正在使用的总线, 按标识排序
+    pub fn buses(&self) -> Vec<(String, BusHandle)> {
+        let mut inner = self.inner.lock();
+        inner.buses.retain(|_, owner| owner.strong_count() > 0);
+        inner
+            .buses
+            .iter()
+            .filter_map(|(key, owner)| {
+                Some((
+                    key.clone(),
+                    BusHandle {
+                        owner: owner.upgrade()?,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    pub fn configs(&self) -> BTreeMap<String, BusConfig> {
+        self.inner.lock().configs.clone()
+    }
+
+    /// 修改总线的参数, 正在使用的总线立即生效
+    pub fn set_config(&self, key: &str, config: BusConfig) {
+        let mut inner = self.inner.lock();
+        inner.configs.insert(key.to_string(), config);
+        if let Some(owner) = inner.buses.get(key).and_then(Weak::upgrade) {
+            *owner.shared.config.lock() = config;
+        }
+    }
+}
+
+/// 总线的后台线程, 独占传输层
+struct BusThread {
+    master: ModbusMaster,
+    shared: Arc<BusShared>,
+    last_start: Option<Instant>,
+    last_end: Option<Instant>,
+}
+
+impl BusThread {
+    fn new(master: ModbusMaster, shared: Arc<BusShared>) -> Self {
+        Self {
+            master,
+            shared,
+            last_start: None,
+            last_end: None,
+        }
+    }
+
+    fn run(mut self) {
+        loop {
+            let mut queue = self.shared.queue.lock();
+            if queue.stop {
+                break;
+            }
+            if let Some(job) = Self::take_job(&mut queue) {
+                drop(queue);
+                let result = self.transact(job.slave, &job.request);
+                for callback in job.callbacks {
+                    callback(result.clone());
+                }
+                continue;
+            }
+
+            let now = Instant::now();
+            if queue.groups.iter().any(|g| g.next_poll <= now) {
+                let due: Vec<u64> = queue
+                    .groups
+                    .iter()
+                    .filter(|g| g.next_poll <= now)
+                    .map(|g| g.id)
+                    .collect();
+                let reads = Self::merge(&queue.groups, &due);
+                drop(queue);
+                self.poll(&due, reads);
+                continue;
+            }
+
+            match queue.groups.iter().map(|g| g.next_poll).min() {
+                Some(next) => {
+                    self.shared.wakeup.wait_until(&mut queue, next);
+                }
+                None => self.shared.wakeup.wait(&mut queue),
+            }
+        }
+        tracing::info!("关闭 modbus 总线 {}", self.master.label());
+    }
+
+    /// 优先级最高, 最早加入的请求
+    fn take_job(queue: &mut BusQueue) -> Option<Job> {
+        let index = queue
+            .jobs
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, job)| (job.priority, job.seq))
+            .map(|(i, _)| i)?;
+        Some(queue.jobs.remove(index))
+    }
+
+    /// 合并到期的轮询中同一从站, 同一数据区相邻或重叠的读请求
+    fn merge(groups: &[Group], due: &[u64]) -> Vec<Read> {
+        let mut ranges: Vec<(u8, Area, u32, u32)> = groups
+            .iter()
+            .filter(|g| due.contains(&g.id))
+            .flat_map(|g| {
+                g.blocks.iter().map(move |b| {
+                    let addresses = b.addresses();
+                    (g.slave, b.area, addresses.start, addresses.end)
+                })
+            })
+            .collect();
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(u8, Area, u32, u32)> = vec![];
+        for (slave, area, start, end) in ranges {
+            match merged.last_mut() {
+                Some(last)
+                    if last.0 == slave
+                        && last.1 == area
+                        && start <= last.3
+                        && end.max(last.3) - last.2 <= area.max_read() as u32 =>
+                {
+                    last.3 = last.3.max(end)
+                }
+                _ => merged.push((slave, area, start, end)),
+            }
+        }
+        merged
+            .into_iter()
+            .map(|(slave, area, start, end)| Read {
+                slave,
+                block: ReadBlock {
+                    area,
+                    address: start as u16,
+                    quantity: (end - start) as u16,
+                },
+            })
+            .collect()
+    }
+
+    /// 发送合并后的读请求, 把结果分给每个到期的轮询
+    /// 每个读请求之前先发送等待中的一次性请求, 写请求不需要等待整轮轮询结束
+    fn poll(&mut self, due: &[u64], reads: Vec<Read>) {
+        let requested: usize = {
+            let queue = self.shared.queue.lock();
+            queue
+                .groups
+                .iter()
+                .filter(|g| due.contains(&g.id))
+                .map(|g| g.blocks.len())
+                .sum()
+        };
+        self.shared.stats.lock().merged += requested.saturating_sub(reads.len()) as u64;
+
+        let mut results = vec![];
+        for read in reads {
+            loop {
+                let job = Self::take_job(&mut self.shared.queue.lock());
+                match job {
+                    Some(job) => {
+                        let result = self.transact(job.slave, &job.request);
+                        for callback in job.callbacks {
+                            callback(result.clone());
+                        }
+                    }
+                    None => break,
+                }
+            }
+            if self.shared.queue.lock().stop {
+                return;
+            }
+            let result = self
+                .transact(read.slave, &read.block.request())
+                .map(|response| match response {
+                    Response::Bits(bits) => bits.into_iter().map(u16::from).collect(),
+                    Response::Registers(registers) => registers,
+                    _ => vec![],
+                });
+            results.push((read, result));
+        }
+
+        let mut repaints = vec![];
+        let mut queue = self.shared.queue.lock();
+        for group in queue.groups.iter_mut().filter(|g| due.contains(&g.id)) {
+            let mut state = group.state.lock();
+            for block in group.blocks.iter() {
+                let found = results.iter().find(|(read, _)| {
+                    read.slave == group.slave
+                        && read.block.area == block.area
+                        && read.block.address <= block.address
+                        && read.block.addresses().end >= block.addresses().end
+                });
+                let result = match found {
+                    Some((read, Ok(values))) => {
+                        let offset = (block.address - read.block.address) as usize;
+                        Ok(values
+                            .iter()
+                            .skip(offset)
+                            .take(block.quantity as usize)
+                            .copied()
+                            .collect())
+                    }
+                    Some((_, Err(e))) => Err(e.clone()),
+                    None => continue,
+                };
+                state.update(block, result);
+            }
+            drop(state);
+            group.next_poll = Instant::now() + group.interval;
+            repaints.push(group.repaint.clone());
+        }
+        drop(queue);
+        for repaint in repaints {
+            repaint();
+        }
+    }
+
+    /// 按配置的间隔和速率发送请求, 失败时重试, 每次发送都计入统计
+    fn transact(&mut self, slave: u8, request: &Request) -> Result<Response> {
+        let config = *self.shared.config.lock();
+        let timeout = Duration::from_millis(config.timeout_ms.max(1));
+        let mut attempt = 0;
+        loop {
+            self.wait_turn(&config);
+            let start = Instant::now();
+            self.last_start = Some(start);
+            let result = self.master.request_with(slave, request, timeout, 0);
+            self.last_end = Some(Instant::now());
+            self.shared.stats.lock().record(slave, &result, start);
+            match result {
+                Err(e) if attempt < config.retries && ModbusMaster::retryable(&e) => {
+                    attempt += 1;
+                    tracing::warn!("从站 {} 请求失败, 第 {} 次重试: {}", slave, attempt, e);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn wait_turn(&self, config: &BusConfig) {
+        let mut ready = Instant::now();
+        if let Some(end) = self.last_end {
+            ready = ready.max(end + Duration::from_millis(config.gap_ms));
+        }
+        if let (Some(start), true) = (self.last_start, config.max_rate > 0.0) {
+            ready = ready.max(start + Duration::from_secs_f64(1.0 / config.max_rate));
+        }
+        let wait = ready.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::register_map::RegisterMap,
+        service::modbus::{
+            rtu::SerialConfig,
+            simulator::{Simulator, SimulatorConfig},
+            slave::SlaveListen,
+        },
+    };
+
+    fn group(id: u64, slave: u8, blocks: &[(Area, u16, u16)]) -> Group {
+        Group {
+            id,
+            slave,
+            blocks: blocks
+                .iter()
+                .map(|&(area, address, quantity)| ReadBlock {
+                    area,
+                    address,
+                    quantity,
+                })
+                .collect(),
+            interval: Duration::from_secs(1),
+            next_poll: Instant::now(),
+            state: Arc::default(),
+            repaint: Arc::new(|| {}),
+        }
+    }
+
+    fn reads(groups: &[Group], due: &[u64]) -> Vec<(u8, Area, u16, u16)> {
+        BusThread::merge(groups, due)
+            .into_iter()
+            .map(|r| (r.slave, r.block.area, r.block.address, r.block.quantity))
+            .collect()
+    }
+
+    #[test]
+    fn merge_reads() {
+        use Area::*;
+        let groups = [
+            group(1, 1, &[(HoldingRegisters, 0, 10), (InputRegisters, 0, 4)]),
+            // 相邻和重叠的合并, 有空隙的不合并
+            group(
+                2,
+                1,
+                &[(HoldingRegisters, 10, 10), (HoldingRegisters, 5, 10)],
+            ),
+            group(3, 1, &[(HoldingRegisters, 21, 4), (Coils, 0, 8)]),
+            // 其它从站
+            group(4, 2, &[(HoldingRegisters, 0, 10)]),
+            // 没有到期
+            group(5, 1, &[(HoldingRegisters, 20, 1)]),
+        ];
+        assert_eq!(
+            reads(&groups, &[1, 2, 3, 4]),
+            vec![
+                (1, Coils, 0, 8),
+                (1, InputRegisters, 0, 4),
+                (1, HoldingRegisters, 0, 20),
+                (1, HoldingRegisters, 21, 4),
+                (2, HoldingRegisters, 0, 10),
+            ]
+        );
+        assert_eq!(
+            reads(&groups, &[1, 2, 3, 4, 5]),
+            vec![
+                (1, Coils, 0, 8),
+                (1, InputRegisters, 0, 4),
+                (1, HoldingRegisters, 0, 25),
+                (2, HoldingRegisters, 0, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_up_to_max_read() {
+        use Area::*;
+        let groups = [
+            group(
+                1,
+                1,
+                &[(HoldingRegisters, 0, 100), (HoldingRegisters, 100, 25)],
+            ),
+            group(2, 1, &[(HoldingRegisters, 125, 1)]),
+            group(3, 1, &[(InputRegisters, 0, 100), (InputRegisters, 50, 100)]),
+            group(
+                4,
+                1,
+                &[(Coils, 0, 1000), (Coils, 1000, 1000), (Coils, 2000, 8)],
+            ),
+        ];
+        let reads = reads(&groups, &[1, 2, 3, 4]);
+        assert_eq!(
+            reads,
+            vec![
+                (1, Coils, 0, 2000),
+                (1, Coils, 2000, 8),
+                (1, InputRegisters, 0, 100),
+                (1, InputRegisters, 50, 100),
+                (1, HoldingRegisters, 0, 125),
+                (1, HoldingRegisters, 125, 1),
+            ]
+        );
+        assert!(reads.iter().all(|r| r.3 <= r.1.max_read()));
+    }
+
+    #[test]
+    fn take_job_by_priority() {
+        let mut queue = BusQueue::default();
+        for (seq, priority) in [
+            Priority::Poll,
+            Priority::Request,
+            Priority::Write,
+            Priority::Poll,
+            Priority::Write,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            queue.jobs.push(Job {
+                slave: 1,
+                request: Request::ReadCoils {
+                    address: seq as u16,
+                    quantity: 1,
+                },
+                priority,
+                seq: seq as u64,
+                callbacks: vec![],
+            });
+        }
+        let order: Vec<u64> = std::iter::from_fn(|| BusThread::take_job(&mut queue))
+            .map(|job| job.seq)
+            .collect();
+        assert_eq!(order, vec![2, 4, 1, 0, 3]);
+    }
+
+    /// pty 上的模拟器 (modbus-rtu-example, 从站 1) 和连接它的总线
+    fn simulator_bus(config: BusConfig) -> (Simulator, BusHandle) {
+        let simulator = Simulator::start(
+            &SimulatorConfig {
+                listen: SlaveListen::Pty,
+                ..Default::default()
+            },
+            RegisterMap::modbus_rtu_example(),
+        )
+        .unwrap();
+        let transport = TransportConfig::Rtu(SerialConfig {
+            port: simulator.label().trim_start_matches("pty ").to_string(),
+            ..Default::default()
+        });
+        let scheduler = ModbusScheduler::new(BTreeMap::from([(transport.bus_key(), config)]));
+        let bus = scheduler.bus(&transport).unwrap();
+        // 主站打开之前模拟器每 100ms 检查一次, 等它开始读取, 避免第一个请求超时
+        thread::sleep(Duration::from_millis(150));
+        (simulator, bus)
+    }
+
+    fn read(address: u16) -> Request {
+        Request::ReadHoldingRegisters {
+            address,
+            quantity: 1,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_before_queued_polls() {
+        let (_simulator, bus) = simulator_bus(BusConfig {
+            timeout_ms: 200,
+            retries: 0,
+            ..Default::default()
+        });
+        let done = Arc::new(Mutex::new(vec![]));
+        let submit = |name: &'static str, slave: u8, request: Request, priority: Priority| {
+            let done = done.clone();
+            bus.submit(slave, request, priority, move |result| {
+                done.lock().push((name, result.is_ok()));
+            });
+        };
+        // 从站 2 不存在, 等待超时期间其它请求排队
+        submit("missing", 2, read(0), Priority::Request);
+        while bus.stats().queued > 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        submit("poll 1", 1, read(0), Priority::Poll);
+        submit("poll 2", 1, read(2), Priority::Poll);
+        // 与排队中的轮询相同, 共用一次请求
+        submit("poll 1 again", 1, read(0), Priority::Poll);
+        submit("request", 1, read(4), Priority::Request);
+        let write = Request::WriteSingleRegister {
+            address: 8,
+            value: 42,
+        };
+        submit("write", 1, write, Priority::Write);
+
+        let response = bus.request(1, &read(8), Priority::Poll).unwrap();
+        assert_eq!(response, Response::Registers(vec![42]));
+        assert_eq!(
+            *done.lock(),
+            vec![
+                ("missing", false),
+                ("write", true),
+                ("request", true),
+                ("poll 1", true),
+                ("poll 1 again", true),
+                ("poll 2", true),
+            ]
+        );
+        let stats = bus.stats();
+        assert_eq!(stats.merged, 1);
+        assert_eq!(stats.requests, 6);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_during_poll_round() {
+        let (_simulator, bus) = simulator_bus(BusConfig {
+            timeout_ms: 100,
+            retries: 0,
+            ..Default::default()
+        });
+        // 不相邻的三个读请求, 从站不存在, 一轮轮询至少需要 300 毫秒
+        let state = Arc::new(Mutex::new(PollState::default()));
+        let blocks = [0, 10, 20]
+            .map(|address| ReadBlock {
+                area: Area::HoldingRegisters,
+                address,
+                quantity: 1,
+            })
+            .to_vec();
+        bus.add_group(
+            2,
+            blocks,
+            Duration::from_secs(10),
+            state.clone(),
+            Arc::new(|| {}),
+        );
+        thread::sleep(Duration::from_millis(20));
+
+        let write = Request::WriteSingleRegister {
+            address: 8,
+            value: 7,
+        };
+        assert!(bus.request(1, &write, Priority::Write).is_ok());
+        // 轮询结果在一轮结束后才写入
+        assert_eq!(state.lock().polls, 0);
+        while state.lock().polls < 3 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(state.lock().failures, 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn gap_and_rate_limit() {
+        let (_simulator, bus) = simulator_bus(BusConfig {
+            gap_ms: 50,
+            ..Default::default()
+        });
+        let mut last = None;
+        for _ in 0..3 {
+            bus.request(1, &read(0), Priority::Request).unwrap();
+            let now = Instant::now();
+            if let Some(last) = last {
+                assert!(now - last >= Duration::from_millis(50));
+            }
+            last = Some(now);
+        }
+
+        let (_simulator, bus) = simulator_bus(BusConfig {
+            max_rate: 10.0,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        for _ in 0..4 {
+            bus.request(1, &read(0), Priority::Request).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(bus.stats().utilisation() > 0.0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slave_stats() {
+        let (_simulator, bus) = simulator_bus(BusConfig {
+            timeout_ms: 100,
+            retries: 1,
+            ..Default::default()
+        });
+        assert!(bus.request(1, &read(0), Priority::Request).is_ok());
+        assert_eq!(
+            bus.request(2, &read(0), Priority::Request),
+            Err(AppError::ModbusTimeout)
+        );
+        // 异常响应不重试
+        assert!(matches!(
+            bus.request(1, &read(200), Priority::Request),
+            Err(AppError::ModbusException { .. })
+        ));
+
+        let stats = bus.stats();
+        assert_eq!(stats.requests, 4);
+        let online = &stats.slaves[&1];
+        assert_eq!(
+            (online.requests, online.timeouts, online.failures),
+            (2, 0, 1)
+        );
+        assert!(online.latency.is_some());
+        assert_eq!(online.online(), Some(true));
+        assert_eq!(online.timeout_rate(), 0.0);
+        let missing = &stats.slaves[&2];
+        assert_eq!(
+            (missing.requests, missing.timeouts, missing.failures),
+            (2, 2, 0)
+        );
+        assert_eq!(missing.latency, None);
+        assert_eq!(missing.online(), Some(false));
+        assert_eq!(missing.timeout_rate(), 1.0);
+        assert!(missing.last_error.is_some());
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    f64::consts::PI,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::register_map::RegisterMap,
+    resource::error::{AppError, Result},
+};
+
+use super::slave::{ModbusSlave, RegisterImage, SlaveListen};
+
+/// 字段值的变化方式
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Waveform {
+    /// offset + amplitude * sin(2π t / period), period 单位: 秒
+    Sine {
+        amplitude: f64,
+        offset: f64,
+        period: f64,
+    },
+    /// 每次更新随机变化 ±step, 限制在 min..=max
+    RandomWalk { step: f64, min: f64, max: f64 },
+    /// 在 period 秒内从 min 线性增加到 max, 然后重新开始
+    Ramp { min: f64, max: f64, period: f64 },
+    /// 复制另一个字段的值, 如把主站写入的保持寄存器回显到输入寄存器
+    Echo { source: String },
+}
+
+impl Waveform {
+    pub const ALL: [&'static str; 4] = ["正弦", "随机游走", "锯齿", "回显"];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Waveform::Sine { .. } => Self::ALL[0],
+            Waveform::RandomWalk { .. } => Self::ALL[1],
+            Waveform::Ramp { .. } => Self::ALL[2],
+            Waveform::Echo { .. } => Self::ALL[3],
+        }
+    }
+
+    /// 按 ALL 中的序号创建默认参数
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            0 => Waveform::Sine {
+                amplitude: 1.0,
+                offset: 0.0,
+                period: 10.0,
+            },
+            1 => Waveform::RandomWalk {
+                step: 0.1,
+                min: 0.0,
+                max: 10.0,
+            },
+            2 => Waveform::Ramp {
+                min: 0.0,
+                max: 10.0,
+                period: 10.0,
+            },
+            _ => Waveform::Echo {
+                source: String::new(),
+            },
+        }
+    }
+}
+
+/// 一个字段的脚本
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldScript {
+    pub field: String,
+    #[serde(flatten)]
+    pub waveform: Waveform,
+}
+
+/// 模拟器配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulatorConfig {
+    /// 寄存器表名称
+    pub map: String,
+    pub slave: u8,
+    pub listen: SlaveListen,
+    /// 脚本的更新间隔, 单位: 毫秒
+    pub interval: u64,
+    pub scripts: Vec<FieldScript>,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        Self {
+            map: "modbus-rtu-example".into(),
+            slave: 1,
+            listen: SlaveListen::default(),
+            interval: 100,
+            scripts: vec![],
+        }
+    }
+}
+
+impl SimulatorConfig {
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| AppError::Error(format!("模拟器配置错误: {}", e)))
+    }
+}
+
+/// Modbus 从站模拟器, 按寄存器表应答主站, 并按脚本更新字段的值
+pub struct Simulator {
+    slave: ModbusSlave,
+    map: RegisterMap,
+    image: Arc<Mutex<RegisterImage>>,
+    running: Arc<AtomicBool>,
+}
+
+impl Simulator {
+    pub fn start(config: &SimulatorConfig, map: RegisterMap) -> Result<Self> {
+        map.validate()?;
+        for script in config.scripts.iter() {
+            if map.find(&script.field).is_none() {
+                return Err(AppError::RegisterMap(format!(
+                    "脚本的字段不存在: {}",
+                    script.field
+                )));
+            }
+            if let Waveform::Echo { source } = &script.waveform {
+                if map.find(source).is_none() {
+                    return Err(AppError::RegisterMap(format!(
+                        "回显的字段不存在: {}",
+                        source
+                    )));
+                }
+            }
+        }
+
+        let image = Arc::new(Mutex::new(RegisterImage::from_map(&map)));
+        let slave = ModbusSlave::start(&config.listen, config.slave, image.clone())?;
+        let running = Arc::new(AtomicBool::new(true));
+        if !config.scripts.is_empty() {
+            spawn_scripts(
+                config.scripts.clone(),
+                map.clone(),
+                image.clone(),
+                Duration::from_millis(config.interval.max(10)),
+                running.clone(),
+            )?;
+        }
+        Ok(Self {
+            slave,
+            map,
+            image,
+            running,
+        })
+    }
+
+    /// 主站连接的地址
+    pub fn label(&self) -> &str {
+        self.slave.label()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.slave.is_running()
+    }
+
+    pub fn map(&self) -> &RegisterMap {
+        &self.map
+    }
+
+    pub fn image(&self) -> MutexGuard<RegisterImage> {
+        self.image.lock()
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn spawn_scripts(
+    scripts: Vec<FieldScript>,
+    map: RegisterMap,
+    image: Arc<Mutex<RegisterImage>>,
+    interval: Duration,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    thread::Builder::new()
+        .name("modbus-simulator".to_string())
+        .spawn(move || {
+            let start = Instant::now();
+            let mut random = Random::new();
+            while running.load(Ordering::Relaxed) {
+                let t = start.elapsed().as_secs_f64();
+                let mut image = image.lock();
+                for script in scripts.iter() {
+                    if let Err(e) = run_script(script, &map, &mut image, t, &mut random) {
+                        tracing::warn!("模拟器脚本 {} 失败: {}", script.field, e);
+                    }
+                }
+                drop(image);
+                thread::sleep(interval);
+            }
+        })?;
+    Ok(())
+}
+
+fn run_script(
+    script: &FieldScript,
+    map: &RegisterMap,
+    image: &mut RegisterImage,
+    t: f64,
+    random: &mut Random,
+) -> Result<()> {
+    let (area, field) = match map.find(&script.field) {
+        Some(found) => found,
+        None => return Ok(()),
+    };
+    let count = field.count as usize;
+    let values = match &script.waveform {
+        Waveform::Sine {
+            amplitude,
+            offset,
+            period,
+        } => vec![offset + amplitude * (2.0 * PI * t / period.max(0.001)).sin(); count],
+        Waveform::RandomWalk { step, min, max } => image
+            .get(area, field)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|v| (v + step * (random.next() * 2.0 - 1.0)).clamp(*min, *max))
+            .collect(),
+        Waveform::Ramp { min, max, period } => {
+            let period = period.max(0.001);
+            vec![min + (max - min) * (t % period) / period; count]
+        }
+        Waveform::Echo { source } => {
+            let mut values = match map.find(source) {
+                Some((source_area, source)) => image.get(source_area, source).unwrap_or_default(),
+                None => return Ok(()),
+            };
+            values.resize(count, values.last().copied().unwrap_or_default());
+            values
+        }
+    };
+    image.set(area, field, &values)
+}
+
+/// xorshift 伪随机数, 只用于生成模拟数据
+struct Random(u64);
+
+impl Random {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self(seed | 1)
+    }
+
+    /// 0..1 之间的随机数
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Write},
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+
+use crate::{
+    data::register_map::{Area, Field, Initial, RegisterMap},
+    resource::error::{AppError, Result},
+};
+
+use super::{
+    pdu::{exception_pdu, ExceptionCode, Request, Response},
+    rtu::{is_timeout, SerialConfig, SerialMode},
+    tcp::serve_mbap,
+};
+
+/// 从站的寄存器镜像, 只有寄存器表中定义的地址可以访问
+#[derive(Debug, Clone, Default)]
+pub struct RegisterImage {
+    values: BTreeMap<(Area, u16), u16>,
+    /// 处理的请求数
+    pub requests: u64,
+    /// 返回异常响应的请求数
+    pub exceptions: u64,
+}
+
+impl RegisterImage {
+    /// 按寄存器表分配地址, 并写入字段的初始值
+    pub fn from_map(map: &RegisterMap) -> Self {
+        let mut image = Self::default();
+        for area in Area::ALL {
+            for field in map.fields(area) {
+                for address in field.address as u32..field.end() {
+                    image.values.insert((area, address as u16), 0);
+                }
+            }
+            for field in map.fields(area) {
+                let result = match &field.initial {
+                    Some(Initial::Text(text)) if field.is_text() => {
+                        image.set_text(area, field, &vec![text.clone(); field.count as usize])
+                    }
+                    Some(initial) if !field.is_text() => {
+                        image.set(area, field, &initial.values(field.count))
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(e) = result {
+                    tracing::warn!("初始值无效: {}", e);
+                }
+            }
+        }
+        image
+    }
+
+    /// 连续地址的原始值, 有未定义的地址时返回非法数据地址
+    pub fn read(
+        &self,
+        area: Area,
+        address: u16,
+        quantity: u16,
+    ) -> std::result::Result<Vec<u16>, ExceptionCode> {
+        (address as u32..address as u32 + quantity as u32)
+            .map(|a| {
+                self.values
+                    .get(&(area, a as u16))
+                    .copied()
+                    .ok_or(ExceptionCode::IllegalDataAddress)
+            })
+            .collect()
+    }
+
+    pub fn write(
+        &mut self,
+        area: Area,
+        address: u16,
+        values: &[u16],
+    ) -> std::result::Result<(), ExceptionCode> {
+        self.read(area, address, values.len() as u16)?;
+        for (i, value) in values.iter().enumerate() {
+            self.values.insert((area, address + i as u16), *value);
+        }
+        Ok(())
+    }
+
+    /// 字段的工程值, 每个数组元素一个值
+    pub fn get(&self, area: Area, field: &Field) -> Option<Vec<f64>> {
+        let raw = self.read(area, field.address, field.len()).ok()?;
+        field.decode(&raw).ok()
+    }
+
+    /// 字段显示用的值, 字符串字段也可以使用
+    pub fn display(&self, area: Area, field: &Field) -> Option<Vec<String>> {
+        let raw = self.read(area, field.address, field.len()).ok()?;
+        field.display(&raw).ok()
+    }
+
+    /// 写入字段的工程值
+    pub fn set(&mut self, area: Area, field: &Field, values: &[f64]) -> Result<()> {
+        let current = self.current(area, field)?;
+        let raw = field.encode(values, &current)?;
+        self.write_field(area, field, &raw)
+    }
+
+    /// 写入字符串字段
+    pub fn set_text(&mut self, area: Area, field: &Field, texts: &[String]) -> Result<()> {
+        self.current(area, field)?;
+        let raw = field.encode_text(texts)?;
+        self.write_field(area, field, &raw)
+    }
+
+    fn current(&self, area: Area, field: &Field) -> Result<Vec<u16>> {
+        self.read(area, field.address, field.len())
+            .map_err(|code| Self::address_error(field, code))
+    }
+
+    fn write_field(&mut self, area: Area, field: &Field, raw: &[u16]) -> Result<()> {
+        self.write(area, field.address, raw)
+            .map_err(|code| Self::address_error(field, code))
+    }
+
+    fn address_error(field: &Field, code: ExceptionCode) -> AppError {
+        AppError::RegisterMap(format!("{} 地址 {}: {}", field.name, field.address, code))
+    }
+
+    /// 处理主站的请求 pdu, 返回响应 pdu
+    pub fn handle(&mut self, pdu: &[u8]) -> Vec<u8> {
+        let function = pdu.first().copied().unwrap_or_default();
+        self.requests += 1;
+        match Request::decode(pdu).and_then(|request| self.execute(&request)) {
+            Ok(response) => response.encode(function),
+            Err(code) => {
+                self.exceptions += 1;
+                tracing::debug!("从站异常响应 0x{:02X}: {}", function, code);
+                exception_pdu(function, code)
+            }
+        }
+    }
+
+    fn execute(&mut self, request: &Request) -> std::result::Result<Response, ExceptionCode> {
+        let bits = |raw: Vec<u16>| Response::Bits(raw.into_iter().map(|v| v != 0).collect());
+        Ok(match request {
+            Request::ReadCoils { address, quantity } => {
+                bits(self.read(Area::Coils, *address, *quantity)?)
+            }
+            Request::ReadDiscreteInputs { address, quantity } => {
+                bits(self.read(Area::DiscreteInputs, *address, *quantity)?)
+            }
+            Request::ReadHoldingRegisters { address, quantity } => {
+                Response::Registers(self.read(Area::HoldingRegisters, *address, *quantity)?)
+            }
+            Request::ReadInputRegisters { address, quantity } => {
+                Response::Registers(self.read(Area::InputRegisters, *address, *quantity)?)
+            }
+            Request::WriteSingleCoil { address, value } => {
+                self.write(Area::Coils, *address, &[*value as u16])?;
+                Response::WriteSingleCoil {
+                    address: *address,
+                    value: *value,
+                }
+            }
+            Request::WriteSingleRegister { address, value } => {
+                self.write(Area::HoldingRegisters, *address, &[*value])?;
+                Response::WriteSingleRegister {
+                    address: *address,
+                    value: *value,
+                }
+            }
+            Request::WriteMultipleCoils { address, values } => {
+                let raw: Vec<u16> = values.iter().map(|v| *v as u16).collect();
+                self.write(Area::Coils, *address, &raw)?;
+                Response::WriteMultiple {
+                    address: *address,
+                    quantity: values.len() as u16,
+                }
+            }
+            Request::WriteMultipleRegisters { address, values } => {
+                self.write(Area::HoldingRegisters, *address, values)?;
+                Response::WriteMultiple {
+                    address: *address,
+                    quantity: values.len() as u16,
+                }
+            }
+        })
+    }
+}
+
+/// 从站的监听方式
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SlaveListen {
+    /// 创建一对虚拟串口, 主站打开另一端, 只支持 linux 和 macos
+    Pty,
+    /// 同 Pty, 使用 ascii 帧格式
+    PtyAscii,
+    /// 在已有的串口上应答
+    Serial(SerialConfig),
+    /// Modbus TCP, 监听所有网卡的端口
+    Tcp { port: u16 },
+}
+
+impl Default for SlaveListen {
+    fn default() -> Self {
+        if cfg!(unix) {
+            SlaveListen::Pty
+        } else {
+            SlaveListen::Tcp { port: 5020 }
+        }
+    }
+}
+
+/// 在后台线程中应答主站请求的从站
+pub struct ModbusSlave {
+    label: String,
+    running: Arc<AtomicBool>,
+}
+
+impl ModbusSlave {
+    pub fn start(
+        listen: &SlaveListen,
+        slave: u8,
+        image: Arc<Mutex<RegisterImage>>,
+    ) -> Result<Self> {
+        let running = Arc::new(AtomicBool::new(true));
+        let label = match listen {
+            SlaveListen::Pty => start_pty(SerialMode::Rtu, slave, image, running.clone())?,
+            SlaveListen::PtyAscii => start_pty(SerialMode::Ascii, slave, image, running.clone())?,
+            SlaveListen::Serial(config) => {
+                let port = serialport::new(&config.port, config.baud_rate)
+                    .data_bits(serialport::DataBits::Eight)
+                    .parity(config.parity.into())
+                    .stop_bits(config.stop_bits.into())
+                    .flow_control(serialport::FlowControl::None)
+                    .open()?;
+                spawn_serial(port, false, config.clone(), slave, image, running.clone())?;
+                format!(
+                    "{} {} {}",
+                    config.mode.label().to_lowercase(),
+                    config.port,
+                    config.baud_rate
+                )
+            }
+            SlaveListen::Tcp { port } => {
+                let listener = TcpListener::bind(("0.0.0.0", *port))?;
+                listener.set_nonblocking(true)?;
+                spawn_tcp(listener, slave, image, running.clone())?;
+                format!("tcp 0.0.0.0:{}", port)
+            }
+        };
+        tracing::info!("modbus 从站 #{} 已启动: {}", slave, label);
+        Ok(Self { label, running })
+    }
+
+    /// 主站连接的地址, pty 为主站需要打开的串口
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// 后台线程出错退出后返回 false
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ModbusSlave {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(unix)]
+fn start_pty(
+    mode: SerialMode,
+    slave: u8,
+    image: Arc<Mutex<RegisterImage>>,
+    running: Arc<AtomicBool>,
+) -> Result<String> {
+    let (master, peer) = serialport::TTYPort::pair()?;
+    let name = peer
+        .name()
+        .ok_or_else(|| AppError::Serial("无法获取虚拟串口名称".to_string()))?;
+    // 关闭这一端, 留给主站打开
+    drop(peer);
+    let config = SerialConfig {
+        mode,
+        ..Default::default()
+    };
+    spawn_serial(Box::new(master), true, config, slave, image, running)?;
+    Ok(format!("pty {}", name))
+}
+
+#[cfg(not(unix))]
+fn start_pty(
+    _: SerialMode,
+    _: u8,
+    _: Arc<Mutex<RegisterImage>>,
+    _: Arc<AtomicBool>,
+) -> Result<String> {
+    Err(AppError::Serial("当前系统不支持虚拟串口".to_string()))
+}
+
+/// rtu 模式下串口上静默超过 t3.5 认为一帧结束, ascii 模式下收到 LF 时一帧结束
+///
+/// pty 的另一端没有打开时读取会返回 EIO, 等待主站打开
+fn spawn_serial(
+    mut port: Box<dyn SerialPort>,
+    pty: bool,
+    config: SerialConfig,
+    slave: u8,
+    image: Arc<Mutex<RegisterImage>>,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    let mode = config.mode;
+    // 系统的串口超时精度有限, 至少等待 2ms
+    port.set_timeout(config.frame_gap().max(Duration::from_millis(2)))?;
+    thread::Builder::new()
+        .name("modbus-slave".to_string())
+        .spawn(move || {
+            let mut frame = Vec::with_capacity(256);
+            let mut buf = [0u8; 256];
+            while running.load(Ordering::Relaxed) {
+                let mut requests = vec![];
+                match port.read(&mut buf) {
+                    Ok(n) if n > 0 => {
+                        frame.extend_from_slice(&buf[..n]);
+                        if mode == SerialMode::Rtu {
+                            continue;
+                        }
+                        while let Some(end) = frame.iter().position(|b| *b == b'\n') {
+                            requests.push(frame.drain(..=end).collect::<Vec<u8>>());
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) if is_timeout(&e) || e.kind() == ErrorKind::Interrupted => {}
+                    Err(_) if pty => {
+                        frame.clear();
+                        thread::sleep(Duration::from_millis(100));
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::error!("从站串口读取失败: {}", e);
+                        break;
+                    }
+                }
+                if mode == SerialMode::Rtu && !frame.is_empty() {
+                    requests.push(std::mem::take(&mut frame));
+                }
+                for request in requests {
+                    if let Some(response) = handle_frame(mode, slave, &request, &image) {
+                        if let Err(e) = port.write_all(&response).and_then(|_| port.flush()) {
+                            tracing::error!("从站串口发送失败: {}", e);
+                            running.store(false, Ordering::Relaxed);
+                        }
+                    }
+                }
+            }
+            running.store(false, Ordering::Relaxed);
+        })?;
+    Ok(())
+}
+
+/// 处理一个 rtu 或 ascii 帧, 不是发给本站的帧和广播不应答
+fn handle_frame(
+    mode: SerialMode,
+    slave: u8,
+    frame: &[u8],
+    image: &Mutex<RegisterImage>,
+) -> Option<Vec<u8>> {
+    // ascii 帧从最后一个 ':' 开始, 之前的是干扰或不完整的帧
+    let frame = match mode {
+        SerialMode::Ascii => &frame[frame.iter().rposition(|b| *b == b':').unwrap_or(0)..],
+        SerialMode::Rtu => frame,
+    };
+    tracing::debug!("从站 rx: {}", mode.format_frame(frame));
+    let (address, pdu) = match mode.decode_frame(frame) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            tracing::debug!("丢弃无效帧: {}", e);
+            return None;
+        }
+    };
+    if address != slave && address != 0 {
+        return None;
+    }
+    let response = image.lock().handle(&pdu);
+    if address == 0 {
+        return None;
+    }
+    let response = mode.encode_frame(slave, &response);
+    tracing::debug!("从站 tx: {}", mode.format_frame(&response));
+    Some(response)
+}
+
+fn spawn_tcp(
+    listener: TcpListener,
+    slave: u8,
+    image: Arc<Mutex<RegisterImage>>,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    thread::Builder::new()
+        .name("modbus-slave".to_string())
+        .spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        tracing::info!("modbus 主站已连接: {}", addr);
+                        let image = image.clone();
+                        let running = running.clone();
+                        thread::spawn(move || {
+                            // 单元号为本站地址, 0 或 255 时应答
+                            let result = serve_mbap(stream, &running, |unit, pdu| {
+                                (unit == slave || unit == 0 || unit == 0xFF)
+                                    .then(|| image.lock().handle(pdu))
+                            });
+                            if let Err(e) = result {
+                                tracing::info!("modbus 主站 {} 断开: {}", addr, e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(e) => {
+                        tracing::error!("modbus 从站监听失败: {}", e);
+                        break;
+                    }
+                }
+            }
+            running.store(false, Ordering::Relaxed);
+        })?;
+    Ok(())
+}
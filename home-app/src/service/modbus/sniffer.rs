@@ -0,0 +1,1066 @@
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::register_map::{Area, RegisterMap},
+    resource::error::{AppError, Result},
+    service::RepaintSignal,
+};
+
+use super::{
+    ascii, hex,
+    pdu::{function, ExceptionCode, Request, Response},
+    rtu::{crc16, crc16_update, is_timeout, SerialConfig, SerialMode},
+};
+
+/// rtu 帧的最大长度
+const MAX_FRAME: usize = 256;
+
+/// 超过后停止监听, 避免长时间运行占满内存
+const MAX_FRAMES: usize = 200_000;
+
+/// crc 错误的帧等待与下一帧合并的时间, 系统调度延迟可能把一帧拆成两段
+const HOLD_US: u64 = 50_000;
+
+/// 注释中最多显示的字段数
+const MAX_ANNOTATIONS: usize = 8;
+
+/// 内容中最多显示的值
+const MAX_VALUES: usize = 16;
+
+/// 监听参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnifferConfig {
+    pub serial: SerialConfig,
+    /// 用于标注字段的寄存器表, 空表示不标注
+    pub map: String,
+    /// 抓包文件, 扩展名为 bin 时按原始字节读取
+    pub path: String,
+}
+
+impl Default for SnifferConfig {
+    fn default() -> Self {
+        Self {
+            serial: SerialConfig::default(),
+            map: "modbus-rtu-example".into(),
+            path: String::new(),
+        }
+    }
+}
+
+/// 串口上收到的一段数据
+///
+/// 抓包文件每行一段: "<相对开始的微秒数> <十六进制字节>", 如 "1520 01 03 00 00 00 02 C4 0B"
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub time_us: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// 按帧间隔切分出的一帧, 还没有检查 crc
+#[derive(Debug, Clone)]
+pub struct RawFrame {
+    pub time_us: u64,
+    pub end_us: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// ascii 模式的字符间超时, 超过后丢弃不完整的帧
+const ASCII_TIMEOUT_US: u64 = 1_000_000;
+
+/// rtu 模式按 3.5 个字符的静默时间切分字节流, ascii 模式按 ':' 和 LF 切分
+pub struct FrameSplitter {
+    mode: SerialMode,
+    gap_us: u64,
+    buffer: Vec<u8>,
+    start_us: u64,
+    last_us: u64,
+}
+
+impl FrameSplitter {
+    pub fn new(serial: &SerialConfig) -> Self {
+        let gap_us = match serial.mode {
+            SerialMode::Rtu => serial.frame_gap().as_micros() as u64,
+            SerialMode::Ascii => ASCII_TIMEOUT_US,
+        };
+        Self {
+            mode: serial.mode,
+            gap_us,
+            buffer: Vec::with_capacity(MAX_FRAME),
+            start_us: 0,
+            last_us: 0,
+        }
+    }
+
+    /// 收到一段数据, 返回已经结束的帧
+    ///
+    /// rtu 模式与上一段的间隔超过 t3.5 时上一帧结束, ascii 模式收到 LF 时一帧结束
+    pub fn push(&mut self, time_us: u64, bytes: &[u8]) -> Vec<RawFrame> {
+        let mut frames: Vec<RawFrame> = self.flush(time_us).into_iter().collect();
+        for byte in bytes {
+            // ascii 模式下 ':' 总是新帧的开始
+            if self.mode == SerialMode::Ascii && *byte == b':' {
+                frames.extend(self.finish());
+            }
+            if self.buffer.is_empty() {
+                self.start_us = time_us;
+            }
+            self.buffer.push(*byte);
+            self.last_us = time_us;
+            if self.mode == SerialMode::Ascii && *byte == b'\n' {
+                frames.extend(self.finish());
+            }
+        }
+        frames
+    }
+
+    /// 静默超过 t3.5 (ascii 模式为 1 秒) 时结束当前帧
+    pub fn flush(&mut self, now_us: u64) -> Option<RawFrame> {
+        if now_us.saturating_sub(self.last_us) > self.gap_us {
+            self.finish()
+        } else {
+            None
+        }
+    }
+
+    /// 结束当前帧, 用于数据结束时
+    pub fn finish(&mut self) -> Option<RawFrame> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        Some(RawFrame {
+            time_us: self.start_us,
+            end_us: self.last_us,
+            bytes: std::mem::take(&mut self.buffer),
+        })
+    }
+}
+
+/// 没有时间信息的字节流按 crc 查找帧边界, 找不到有效帧的字节作为一个无效帧
+pub fn split_by_crc(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut frames = vec![];
+    let mut garbage = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match frame_len_at(&bytes[pos..]) {
+            Some(len) => {
+                if !garbage.is_empty() {
+                    frames.push(std::mem::take(&mut garbage));
+                }
+                frames.push(bytes[pos..pos + len].to_vec());
+                pos += len;
+            }
+            None => {
+                garbage.push(bytes[pos]);
+                pos += 1;
+            }
+        }
+    }
+    if !garbage.is_empty() {
+        frames.push(garbage);
+    }
+    frames
+}
+
+/// 从头开始最短的 crc 正确且功能码合理的帧长度
+fn frame_len_at(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 4 || !plausible(bytes[0], bytes[1]) {
+        return None;
+    }
+    let mut crc = crc16_update(crc16_update(0xFFFF, bytes[0]), bytes[1]);
+    for body in 2..(MAX_FRAME - 2).min(bytes.len() - 2) + 1 {
+        let actual = u16::from_le_bytes([bytes[body], bytes[body + 1]]);
+        if crc == actual {
+            return Some(body + 2);
+        }
+        crc = crc16_update(crc, bytes[body]);
+    }
+    None
+}
+
+fn plausible(slave: u8, function: u8) -> bool {
+    slave <= 247
+        && matches!(
+            function & 0x7F,
+            function::READ_COILS
+                | function::READ_DISCRETE_INPUTS
+                | function::READ_HOLDING_REGISTERS
+                | function::READ_INPUT_REGISTERS
+                | function::WRITE_SINGLE_COIL
+                | function::WRITE_SINGLE_REGISTER
+                | function::WRITE_MULTIPLE_COILS
+                | function::WRITE_MULTIPLE_REGISTERS
+                | function::REPORT_SERVER_ID
+        )
+}
+
+fn crc_ok(frame: &[u8]) -> bool {
+    frame.len() >= 4
+        && crc16(&frame[..frame.len() - 2])
+            == u16::from_le_bytes([frame[frame.len() - 2], frame[frame.len() - 1]])
+}
+
+/// 帧的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Request,
+    Response,
+    Exception,
+    /// 校验错误或无法解析
+    Invalid,
+}
+
+impl FrameKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FrameKind::Request => "请求",
+            FrameKind::Response => "响应",
+            FrameKind::Exception => "异常",
+            FrameKind::Invalid => "无效",
+        }
+    }
+}
+
+/// 解码后的一帧
+#[derive(Debug, Clone)]
+pub struct SniffedFrame {
+    pub index: usize,
+    /// 相对开始的时间, 单位: 微秒
+    pub time_us: u64,
+    /// 与上一帧结束之间的静默时间
+    pub idle_us: u64,
+    pub bytes: Vec<u8>,
+    pub kind: FrameKind,
+    /// rtu 的 crc 或 ascii 的 lrc 是否正确
+    pub checksum_ok: bool,
+    pub slave: Option<u8>,
+    pub function: Option<u8>,
+    /// 功能码、地址、数量、值等
+    pub summary: String,
+    /// 寄存器表中对应的字段
+    pub fields: String,
+    /// 配对的请求或响应的序号
+    pub peer: Option<usize>,
+    /// 响应相对请求开始的时间
+    pub latency_us: Option<u64>,
+    /// 如 "无响应"
+    pub note: String,
+}
+
+/// 解码帧并配对请求和响应
+pub struct Decoder {
+    mode: SerialMode,
+    map: Option<RegisterMap>,
+    frames: Vec<SniffedFrame>,
+    /// 等待响应的请求
+    pending: Option<(usize, Request)>,
+    /// 等待与下一帧合并的 crc 错误帧
+    held: Option<RawFrame>,
+    last_end_us: u64,
+}
+
+impl Decoder {
+    pub fn new(mode: SerialMode, map: Option<RegisterMap>) -> Self {
+        Self {
+            mode,
+            map,
+            frames: vec![],
+            pending: None,
+            held: None,
+            last_end_us: 0,
+        }
+    }
+
+    pub fn frames(&self) -> &[SniffedFrame] {
+        &self.frames
+    }
+
+    pub fn push(&mut self, raw: RawFrame) {
+        if self.mode == SerialMode::Ascii {
+            match ascii::decode_frame(&raw.bytes) {
+                Ok((slave, pdu)) => self.decode_pdu(&raw, slave, &pdu),
+                Err(e) => {
+                    let mut frame = self.new_frame(&raw);
+                    frame.note = e.to_string();
+                    self.frames.push(frame);
+                }
+            }
+            return;
+        }
+        if let Some(held) = self.held.take() {
+            let mut merged = held.bytes.clone();
+            merged.extend_from_slice(&raw.bytes);
+            if crc_ok(&merged) {
+                self.decode(RawFrame {
+                    time_us: held.time_us,
+                    end_us: raw.end_us,
+                    bytes: merged,
+                });
+                return;
+            }
+            self.decode_invalid(held);
+        }
+        if crc_ok(&raw.bytes) {
+            self.decode(raw);
+        } else {
+            self.held = Some(raw);
+        }
+    }
+
+    /// 超过等待时间后输出暂存的 crc 错误帧
+    pub fn flush(&mut self, now_us: u64) {
+        if let Some(held) = &self.held {
+            if now_us.saturating_sub(held.end_us) > HOLD_US {
+                self.finish();
+            }
+        }
+    }
+
+    /// 数据结束时输出暂存的帧
+    pub fn finish(&mut self) {
+        if let Some(held) = self.held.take() {
+            self.decode_invalid(held);
+        }
+    }
+
+    /// 多帧粘在一起时按 crc 拆开, 否则作为无效帧
+    fn decode_invalid(&mut self, raw: RawFrame) {
+        let parts = split_by_crc(&raw.bytes);
+        if parts.len() > 1 && parts.iter().all(|part| crc_ok(part)) {
+            for bytes in parts {
+                self.decode(RawFrame {
+                    time_us: raw.time_us,
+                    end_us: raw.end_us,
+                    bytes,
+                });
+            }
+            return;
+        }
+        let mut frame = self.new_frame(&raw);
+        frame.slave = raw.bytes.first().copied();
+        frame.function = raw.bytes.get(1).copied();
+        frame.note = if raw.bytes.len() < 4 {
+            format!("帧长度错误: {}", raw.bytes.len())
+        } else {
+            "CRC 错误".to_string()
+        };
+        self.frames.push(frame);
+    }
+
+    fn new_frame(&mut self, raw: &RawFrame) -> SniffedFrame {
+        let frame = SniffedFrame {
+            index: self.frames.len(),
+            time_us: raw.time_us,
+            idle_us: raw.time_us.saturating_sub(self.last_end_us),
+            bytes: raw.bytes.clone(),
+            kind: FrameKind::Invalid,
+            checksum_ok: false,
+            slave: None,
+            function: None,
+            summary: String::new(),
+            fields: String::new(),
+            peer: None,
+            latency_us: None,
+            note: String::new(),
+        };
+        self.last_end_us = raw.end_us;
+        frame
+    }
+
+    /// crc 正确的 rtu 帧
+    fn decode(&mut self, raw: RawFrame) {
+        let pdu = &raw.bytes[1..raw.bytes.len() - 2];
+        self.decode_pdu(&raw, raw.bytes[0], pdu);
+    }
+
+    fn decode_pdu(&mut self, raw: &RawFrame, slave: u8, pdu: &[u8]) {
+        let mut frame = self.new_frame(raw);
+        frame.checksum_ok = true;
+        frame.slave = Some(slave);
+        frame.function = pdu.first().copied();
+
+        // 先作为等待中的请求的响应解析, 写单个线圈/寄存器的响应与请求相同
+        if let Some((request_index, request)) = self.pending.take() {
+            let request_frame = &self.frames[request_index];
+            if request_frame.slave == Some(slave)
+                && pdu.first().map(|f| f & 0x7F) == Some(request.function_code())
+            {
+                match request.decode_response(pdu) {
+                    Ok(response) => {
+                        frame.kind = FrameKind::Response;
+                        frame.summary = response_summary(&response);
+                        frame.fields = self.annotate_response(&request, &response);
+                    }
+                    Err(AppError::ModbusException { code, .. }) => {
+                        frame.kind = FrameKind::Exception;
+                        frame.summary = format!("{}: {}", function::name(pdu[0]), code);
+                    }
+                    Err(e) => frame.note = e.to_string(),
+                }
+                if frame.kind != FrameKind::Invalid {
+                    let latency = frame.time_us.saturating_sub(request_frame.time_us);
+                    frame.peer = Some(request_index);
+                    frame.latency_us = Some(latency);
+                    if let Some(request_frame) = self.frames.get_mut(request_index) {
+                        request_frame.peer = Some(frame.index);
+                        request_frame.latency_us = Some(latency);
+                    }
+                    self.frames.push(frame);
+                    return;
+                }
+            } else if let Some(request_frame) = self.frames.get_mut(request_index) {
+                request_frame.note = "无响应".to_string();
+            }
+        }
+
+        match Request::decode(pdu) {
+            Ok(request) => {
+                frame.kind = FrameKind::Request;
+                frame.summary = request_summary(&request);
+                frame.fields = self.annotate_request(&request);
+                frame.note.clear();
+                // 广播请求没有响应
+                if slave != 0 {
+                    self.pending = Some((frame.index, request));
+                }
+            }
+            Err(_) if pdu.len() == 2 && pdu[0] & 0x80 != 0 => {
+                frame.kind = FrameKind::Exception;
+                frame.summary = format!(
+                    "{}: {}",
+                    function::name(pdu[0]),
+                    ExceptionCode::from(pdu[1])
+                );
+                frame.note = "没有对应的请求".to_string();
+            }
+            Err(_) if frame.note.is_empty() => frame.note = "无法解析".to_string(),
+            Err(_) => {}
+        }
+        self.frames.push(frame);
+    }
+
+    fn annotate_request(&self, request: &Request) -> String {
+        let map = match &self.map {
+            Some(map) => map,
+            None => return String::new(),
+        };
+        let (area, address, quantity, raw) = match request {
+            Request::ReadCoils { address, quantity } => (Area::Coils, *address, *quantity, None),
+            Request::ReadDiscreteInputs { address, quantity } => {
+                (Area::DiscreteInputs, *address, *quantity, None)
+            }
+            Request::ReadHoldingRegisters { address, quantity } => {
+                (Area::HoldingRegisters, *address, *quantity, None)
+            }
+            Request::ReadInputRegisters { address, quantity } => {
+                (Area::InputRegisters, *address, *quantity, None)
+            }
+            Request::WriteSingleCoil { address, value } => {
+                (Area::Coils, *address, 1, Some(vec![*value as u16]))
+            }
+            Request::WriteSingleRegister { address, value } => {
+                (Area::HoldingRegisters, *address, 1, Some(vec![*value]))
+            }
+            Request::WriteMultipleCoils { address, values } => (
+                Area::Coils,
+                *address,
+                values.len() as u16,
+                Some(values.iter().map(|v| *v as u16).collect()),
+            ),
+            Request::WriteMultipleRegisters { address, values } => (
+                Area::HoldingRegisters,
+                *address,
+                values.len() as u16,
+                Some(values.clone()),
+            ),
+        };
+        annotate(map, area, address, quantity, raw.as_deref())
+    }
+
+    /// 读响应按请求的地址标注字段值
+    fn annotate_response(&self, request: &Request, response: &Response) -> String {
+        let map = match &self.map {
+            Some(map) => map,
+            None => return String::new(),
+        };
+        let (area, address) = match request {
+            Request::ReadCoils { address, .. } => (Area::Coils, *address),
+            Request::ReadDiscreteInputs { address, .. } => (Area::DiscreteInputs, *address),
+            Request::ReadHoldingRegisters { address, .. } => (Area::HoldingRegisters, *address),
+            Request::ReadInputRegisters { address, .. } => (Area::InputRegisters, *address),
+            _ => return String::new(),
+        };
+        let raw: Vec<u16> = match response {
+            Response::Bits(bits) => bits.iter().map(|b| *b as u16).collect(),
+            Response::Registers(registers) => registers.clone(),
+            _ => return String::new(),
+        };
+        annotate(map, area, address, raw.len() as u16, Some(&raw))
+    }
+}
+
+/// 地址范围内的字段, 有原始值时显示解码后的值
+fn annotate(
+    map: &RegisterMap,
+    area: Area,
+    address: u16,
+    quantity: u16,
+    raw: Option<&[u16]>,
+) -> String {
+    let end = address as u32 + quantity as u32;
+    let mut items = vec![];
+    for field in map.fields(area) {
+        if field.end() <= address as u32 || field.address as u32 >= end {
+            continue;
+        }
+        let raw = match raw {
+            Some(raw) => raw,
+            None => {
+                items.push(field.name.clone());
+                continue;
+            }
+        };
+        for element in field.elements() {
+            if element.end() <= address as u32 || element.address as u32 >= end {
+                continue;
+            }
+            // 只覆盖了一部分的元素无法解码, 只显示名称
+            if element.address < address || element.end() > end {
+                items.push(element.name.clone());
+                continue;
+            }
+            let offset = (element.address - address) as usize;
+            if let Ok(values) = element.display(&raw[offset..offset + element.len() as usize]) {
+                items.push(format!("{}={}{}", element.name, values[0], element.unit));
+            }
+        }
+    }
+    join_limited(items, MAX_ANNOTATIONS, ", ")
+}
+
+fn join_limited(items: Vec<String>, limit: usize, separator: &str) -> String {
+    if items.len() <= limit {
+        return items.join(separator);
+    }
+    format!("{} 等 {} 项", items[..limit].join(separator), items.len())
+}
+
+fn bits_text(bits: &[bool]) -> String {
+    join_limited(
+        bits.iter().map(|b| (*b as u8).to_string()).collect(),
+        MAX_VALUES,
+        " ",
+    )
+}
+
+fn words_text(words: &[u16]) -> String {
+    join_limited(
+        words.iter().map(|w| format!("{:04X}", w)).collect(),
+        MAX_VALUES,
+        " ",
+    )
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+fn request_summary(request: &Request) -> String {
+    let name = function::name(request.function_code());
+    match request {
+        Request::ReadCoils { address, quantity }
+        | Request::ReadDiscreteInputs { address, quantity }
+        | Request::ReadHoldingRegisters { address, quantity }
+        | Request::ReadInputRegisters { address, quantity } => {
+            format!("{} 地址 {} 数量 {}", name, address, quantity)
+        }
+        Request::WriteSingleCoil { address, value } => {
+            format!("{} 地址 {} = {}", name, address, on_off(*value))
+        }
+        Request::WriteSingleRegister { address, value } => {
+            format!("{} 地址 {} = {:04X}", name, address, value)
+        }
+        Request::WriteMultipleCoils { address, values } => format!(
+            "{} 地址 {} 数量 {}: {}",
+            name,
+            address,
+            values.len(),
+            bits_text(values)
+        ),
+        Request::WriteMultipleRegisters { address, values } => format!(
+            "{} 地址 {} 数量 {}: {}",
+            name,
+            address,
+            values.len(),
+            words_text(values)
+        ),
+    }
+}
+
+fn response_summary(response: &Response) -> String {
+    match response {
+        Response::Bits(bits) => format!("{} 位: {}", bits.len(), bits_text(bits)),
+        Response::Registers(registers) => {
+            format!("{} 个寄存器: {}", registers.len(), words_text(registers))
+        }
+        Response::WriteSingleCoil { address, value } => {
+            format!("确认 地址 {} = {}", address, on_off(*value))
+        }
+        Response::WriteSingleRegister { address, value } => {
+            format!("确认 地址 {} = {:04X}", address, value)
+        }
+        Response::WriteMultiple { address, quantity } => {
+            format!("确认 地址 {} 数量 {}", address, quantity)
+        }
+    }
+}
+
+/// 一次抓包的原始数据和解码结果
+pub struct Capture {
+    pub chunks: Vec<Chunk>,
+    pub decoder: Decoder,
+    splitter: FrameSplitter,
+    /// 收到的总字节数
+    pub bytes: u64,
+    pub error: Option<String>,
+}
+
+impl Capture {
+    pub fn new(serial: &SerialConfig, map: Option<RegisterMap>) -> Self {
+        Self {
+            chunks: vec![],
+            decoder: Decoder::new(serial.mode, map),
+            splitter: FrameSplitter::new(serial),
+            bytes: 0,
+            error: None,
+        }
+    }
+
+    pub fn push(&mut self, chunk: Chunk) {
+        for frame in self.splitter.push(chunk.time_us, &chunk.bytes) {
+            self.decoder.push(frame);
+        }
+        self.bytes += chunk.bytes.len() as u64;
+        self.chunks.push(chunk);
+    }
+
+    /// 没有新数据时检查帧是否结束
+    pub fn flush(&mut self, now_us: u64) {
+        if let Some(frame) = self.splitter.flush(now_us) {
+            self.decoder.push(frame);
+        }
+        self.decoder.flush(now_us);
+    }
+
+    pub fn finish(&mut self) {
+        if let Some(frame) = self.splitter.finish() {
+            self.decoder.push(frame);
+        }
+        self.decoder.finish();
+    }
+
+    pub fn frames(&self) -> &[SniffedFrame] {
+        self.decoder.frames()
+    }
+
+    /// 读取抓包文件, 扩展名为 bin 时按原始字节读取, rtu 模式按 crc 查找帧边界
+    pub fn load(
+        path: impl AsRef<Path>,
+        serial: &SerialConfig,
+        map: Option<RegisterMap>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let mut capture = Self::new(serial, map);
+        let binary = path.extension().map_or(false, |ext| ext == "bin");
+        if binary && serial.mode == SerialMode::Ascii {
+            capture.push(Chunk {
+                time_us: 0,
+                bytes: fs::read(path)?,
+            });
+        } else if binary {
+            let bytes = fs::read(path)?;
+            for frame in split_by_crc(&bytes) {
+                capture.decoder.push(RawFrame {
+                    time_us: 0,
+                    end_us: 0,
+                    bytes: frame,
+                });
+            }
+            capture.bytes = bytes.len() as u64;
+            capture.chunks.push(Chunk { time_us: 0, bytes });
+        } else {
+            for chunk in parse_log(&fs::read_to_string(path)?)? {
+                capture.push(chunk);
+            }
+        }
+        capture.finish();
+        Ok(capture)
+    }
+
+    /// 保存为抓包文件
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut content = String::from("# 相对开始的微秒数 十六进制字节\n");
+        for chunk in self.chunks.iter() {
+            content.push_str(&format!("{} {}\n", chunk.time_us, hex(&chunk.bytes)));
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// 解析抓包文件, "#" 开始的行为注释
+pub fn parse_log(text: &str) -> Result<Vec<Chunk>> {
+    let mut chunks = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = || AppError::Error(format!("抓包文件第 {} 行格式错误: {}", i + 1, line));
+        let mut parts = line.split_whitespace();
+        let time_us = parts
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(error)?;
+        let bytes = parts
+            .map(|b| u8::from_str_radix(b, 16))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| error())?;
+        chunks.push(Chunk { time_us, bytes });
+    }
+    Ok(chunks)
+}
+
+/// 导出为 csv, 返回导出的帧数
+pub fn export_csv(frames: &[SniffedFrame], path: impl AsRef<Path>) -> Result<usize> {
+    let escape = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    let ms = |us: u64| format!("{:.3}", us as f64 / 1000.0);
+    let mut file = File::create(path)?;
+    let mut content = String::from(
+        "序号,时间(ms),间隔(ms),类型,从站,功能码,内容,字段,校验,配对,响应时间(ms),备注,报文\n",
+    );
+    for frame in frames {
+        content.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            frame.index,
+            ms(frame.time_us),
+            ms(frame.idle_us),
+            frame.kind.label(),
+            frame.slave.map_or_else(String::new, |s| s.to_string()),
+            frame
+                .function
+                .map_or_else(String::new, |f| format!("{:02X}", f)),
+            escape(&frame.summary),
+            escape(&frame.fields),
+            if frame.checksum_ok { "OK" } else { "错误" },
+            frame.peer.map_or_else(String::new, |p| p.to_string()),
+            frame.latency_us.map_or_else(String::new, ms),
+            escape(&frame.note),
+            hex(&frame.bytes),
+        ));
+    }
+    file.write_all(content.as_bytes())?;
+    Ok(frames.len())
+}
+
+/// 被动监听串口上的 rtu 或 ascii 报文, 只读不写, 需要单独的串口接在总线上
+pub struct Sniffer {
+    capture: Arc<Mutex<Capture>>,
+    running: Arc<AtomicBool>,
+    label: String,
+}
+
+impl Sniffer {
+    pub fn start(
+        serial: &SerialConfig,
+        map: Option<RegisterMap>,
+        repaint: RepaintSignal,
+    ) -> Result<Self> {
+        let gap = serial.frame_gap();
+        let mut port = serialport::new(&serial.port, serial.baud_rate)
+            .data_bits(serialport::DataBits::Eight)
+            .parity(serial.parity.into())
+            .stop_bits(serial.stop_bits.into())
+            .flow_control(serialport::FlowControl::None)
+            .open()?;
+        // 系统的串口超时精度有限, 至少等待 1ms
+        port.set_timeout(gap.max(Duration::from_millis(1)))?;
+
+        let capture = Arc::new(Mutex::new(Capture::new(serial, map)));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_capture = capture.clone();
+        let thread_running = running.clone();
+        thread::Builder::new()
+            .name("modbus-sniffer".to_string())
+            .spawn(move || {
+                let start = Instant::now();
+                let mut buf = [0u8; MAX_FRAME];
+                while thread_running.load(Ordering::Relaxed) {
+                    let result = port.read(&mut buf);
+                    let now_us = start.elapsed().as_micros() as u64;
+                    let mut capture = thread_capture.lock();
+                    let frames = capture.frames().len();
+                    match result {
+                        Ok(n) if n > 0 => capture.push(Chunk {
+                            time_us: now_us,
+                            bytes: buf[..n].to_vec(),
+                        }),
+                        Ok(_) => capture.flush(now_us),
+                        Err(e) if is_timeout(&e) || e.kind() == ErrorKind::Interrupted => {
+                            capture.flush(now_us)
+                        }
+                        Err(e) => {
+                            capture.error = Some(format!("串口读取失败: {}", e));
+                            break;
+                        }
+                    }
+                    if capture.frames().len() >= MAX_FRAMES {
+                        capture.error = Some(format!("报文超过 {} 帧, 已停止", MAX_FRAMES));
+                        break;
+                    }
+                    if capture.frames().len() != frames {
+                        repaint();
+                    }
+                }
+                thread_capture.lock().finish();
+                thread_running.store(false, Ordering::Relaxed);
+                repaint();
+            })?;
+        tracing::info!("开始监听 {} {}", serial.port, serial.line_label());
+        Ok(Self {
+            capture,
+            running,
+            label: format!("{} {}", serial.port, serial.line_label()),
+        })
+    }
+
+    /// 打开抓包文件, 不启动监听
+    pub fn load(path: &str, serial: &SerialConfig, map: Option<RegisterMap>) -> Result<Self> {
+        Ok(Self {
+            capture: Arc::new(Mutex::new(Capture::load(path, serial, map)?)),
+            running: Arc::new(AtomicBool::new(false)),
+            label: path.to_string(),
+        })
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn capture(&self) -> MutexGuard<Capture> {
+        self.capture.lock()
+    }
+}
+
+impl Drop for Sniffer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::modbus::rtu::encode_frame;
+
+    fn read_request() -> Vec<u8> {
+        encode_frame(1, &[0x03, 0x00, 0x00, 0x00, 0x02])
+    }
+
+    fn read_response() -> Vec<u8> {
+        // 1.34f32, CDAB
+        encode_frame(1, &[0x03, 0x04, 0x85, 0x1F, 0x3F, 0xAB])
+    }
+
+    fn decode_all(map: Option<RegisterMap>, chunks: &[(u64, Vec<u8>)]) -> Capture {
+        let mut capture = Capture::new(&SerialConfig::default(), map);
+        for (time_us, bytes) in chunks {
+            capture.push(Chunk {
+                time_us: *time_us,
+                bytes: bytes.clone(),
+            });
+        }
+        capture.finish();
+        capture
+    }
+
+    #[test]
+    fn split_by_silent_interval() {
+        // 115200 波特率的 t3.5 为 1750 微秒
+        let mut splitter = FrameSplitter::new(&SerialConfig::default());
+        let request = read_request();
+        assert!(splitter.push(0, &request[..3]).is_empty());
+        assert!(splitter.push(1000, &request[3..]).is_empty());
+
+        let response = read_response();
+        let frames = splitter.push(5000, &response);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].bytes, request);
+        assert_eq!((frames[0].time_us, frames[0].end_us), (0, 1000));
+
+        assert!(splitter.flush(6500).is_none());
+        let frame = splitter.flush(7000).unwrap();
+        assert_eq!(frame.bytes, response);
+        assert_eq!(frame.time_us, 5000);
+        assert!(splitter.finish().is_none());
+    }
+
+    #[test]
+    fn split_ascii_by_delimiters() {
+        let serial = SerialConfig {
+            mode: SerialMode::Ascii,
+            ..Default::default()
+        };
+        let mut splitter = FrameSplitter::new(&serial);
+        let request = ascii::encode_frame(1, &[0x03, 0x00, 0x00, 0x00, 0x02]);
+        // 不完整的帧在收到下一个 ':' 时结束
+        let mut bytes = b":0103".to_vec();
+        bytes.extend_from_slice(&request);
+        let frames = splitter.push(0, &bytes);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].bytes, b":0103");
+        assert_eq!(frames[1].bytes, request);
+    }
+
+    #[test]
+    fn split_back_to_back_frames_by_crc() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(read_request());
+        bytes.extend(read_response());
+        bytes.push(0x01);
+        assert_eq!(
+            split_by_crc(&bytes),
+            vec![
+                vec![0xFF, 0xFE],
+                read_request(),
+                read_response(),
+                vec![0x01]
+            ]
+        );
+        assert!(split_by_crc(&[]).is_empty());
+    }
+
+    #[test]
+    fn pair_requests_and_responses() {
+        let map = RegisterMap::modbus_rtu_example();
+        let exception = encode_frame(1, &[0x83, 0x02]);
+        let no_response = encode_frame(2, &[0x03, 0x00, 0x00, 0x00, 0x01]);
+        let capture = decode_all(
+            Some(map),
+            &[
+                (0, read_request()),
+                (3000, read_response()),
+                (10_000, no_response),
+                (20_000, read_request()),
+                (22_000, exception),
+            ],
+        );
+        let frames = capture.frames();
+        let kinds: Vec<FrameKind> = frames.iter().map(|f| f.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                FrameKind::Request,
+                FrameKind::Response,
+                FrameKind::Request,
+                FrameKind::Request,
+                FrameKind::Exception,
+            ]
+        );
+        assert_eq!(frames[0].peer, Some(1));
+        assert_eq!(frames[1].peer, Some(0));
+        assert_eq!(frames[1].latency_us, Some(3000));
+        assert_eq!(frames[1].idle_us, 3000);
+        assert!(
+            frames[1].fields.contains("holding_data0=1.340"),
+            "{}",
+            frames[1].fields
+        );
+        assert_eq!(frames[2].note, "无响应");
+        assert_eq!(frames[4].peer, Some(3));
+        assert_eq!(frames[4].latency_us, Some(2000));
+    }
+
+    #[test]
+    fn decode_frames_without_gap_and_bad_crc() {
+        // 响应紧跟请求, 中间没有 t3.5 的静默
+        let mut bytes = read_request();
+        bytes.extend(read_response());
+        let capture = decode_all(None, &[(0, bytes)]);
+        let frames = capture.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].kind, FrameKind::Request);
+        assert_eq!(frames[1].kind, FrameKind::Response);
+        assert_eq!(frames[1].peer, Some(0));
+
+        // 系统调度把一帧拆成两段时合并
+        let request = read_request();
+        let capture = decode_all(
+            None,
+            &[(0, request[..4].to_vec()), (5000, request[4..].to_vec())],
+        );
+        assert_eq!(capture.frames().len(), 1);
+        assert_eq!(capture.frames()[0].bytes, request);
+
+        let mut corrupted = read_request();
+        corrupted[7] ^= 0xFF;
+        let capture = decode_all(None, &[(0, corrupted)]);
+        let frame = &capture.frames()[0];
+        assert_eq!(frame.kind, FrameKind::Invalid);
+        assert!(!frame.checksum_ok);
+        assert_eq!(frame.note, "CRC 错误");
+    }
+
+    #[test]
+    fn parse_capture_log() {
+        let text = "# 相对开始的微秒数 十六进制字节\n\n0 01 03 00 00 00 02 C4 0B\n  1520 01 83 02 c0 f1  \n";
+        let chunks = parse_log(text).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].time_us, 0);
+        assert_eq!(chunks[0].bytes, read_request());
+        assert_eq!(chunks[1].time_us, 1520);
+        assert_eq!(chunks[1].bytes, vec![0x01, 0x83, 0x02, 0xC0, 0xF1]);
+
+        let error = parse_log("0 01 03\nabc 01\n").unwrap_err();
+        assert!(error.to_string().contains("第 2 行"), "{}", error);
+        assert!(parse_log("0 01 0G").is_err());
+        assert!(parse_log("0 100").is_err());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let capture = decode_all(None, &[(0, read_request()), (3000, read_response())]);
+        let path = std::env::temp_dir().join(format!("sniffer-test-{}.log", std::process::id()));
+        capture.save(&path).unwrap();
+        let loaded = Capture::load(&path, &SerialConfig::default(), None).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.bytes, capture.bytes);
+        assert_eq!(loaded.frames().len(), 2);
+        assert_eq!(loaded.frames()[1].latency_us, Some(3000));
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::resource::error::{AppError, Result};
+
+use super::{
+    hex,
+    rtu::{check_response, check_slave, encode_frame, is_timeout, read_frame},
+    Transport,
+};
+
+/// MBAP 报文头长度: 事务号(2) + 协议号(2) + 长度(2) + 单元号(1)
+const MBAP_HEADER_LEN: usize = 7;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TcpConfig {
+    pub host: String,
+    pub port: u16,
+    /// 连接超时, 单位: 毫秒
+    pub connect_timeout: u64,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".into(),
+            port: 502,
+            connect_timeout: 3000,
+        }
+    }
+}
+
+impl TcpConfig {
+    fn connect(&self) -> Result<TcpStream> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(connection_error)?
+            .next()
+            .ok_or_else(|| AppError::ModbusConnection(format!("无法解析地址 {}", self.host)))?;
+        let stream = TcpStream::connect_timeout(&addr, Duration::from_millis(self.connect_timeout))
+            .map_err(connection_error)?;
+        stream.set_nodelay(true).ok();
+        tracing::info!("连接 modbus 从站 {}", addr);
+        Ok(stream)
+    }
+}
+
+fn connection_error(e: std::io::Error) -> AppError {
+    AppError::ModbusConnection(e.to_string())
+}
+
+/// 连接断开后, 下一次请求时重新连接
+struct Connection {
+    config: TcpConfig,
+    stream: Option<TcpStream>,
+}
+
+impl Connection {
+    fn stream(&mut self) -> Result<&mut TcpStream> {
+        if self.stream.is_none() {
+            self.stream = Some(self.config.connect()?);
+        }
+        Ok(self.stream.as_mut().unwrap())
+    }
+
+    /// 连接错误时断开, 超时等协议错误保留连接
+    ///
+    /// partial: 超时时已经收到了部分响应, 剩余的数据会被下一次请求当成新的一帧, 同样断开
+    fn check<T>(&mut self, result: Result<T>, partial: bool) -> Result<T> {
+        match result {
+            Err(AppError::ModbusConnection(_)) => self.stream = None,
+            Err(AppError::ModbusTimeout) if partial => {
+                tracing::debug!("响应不完整, 断开连接");
+                self.stream = None;
+            }
+            _ => {}
+        }
+        result
+    }
+}
+
+/// 读取数据, 对端关闭连接时返回连接错误
+fn read_some(stream: &mut TcpStream, buf: &mut [u8], timeout: Duration) -> std::io::Result<usize> {
+    stream.set_read_timeout(Some(timeout))?;
+    match stream.read(buf)? {
+        0 => Err(ErrorKind::ConnectionAborted.into()),
+        n => Ok(n),
+    }
+}
+
+fn io_error(e: std::io::Error) -> AppError {
+    if is_timeout(&e) {
+        AppError::ModbusTimeout
+    } else {
+        connection_error(e)
+    }
+}
+
+/// Modbus TCP 传输层, 使用 MBAP 报文头
+pub struct TcpTransport {
+    connection: Connection,
+    transaction_id: u16,
+}
+
+impl TcpTransport {
+    pub fn new(config: TcpConfig) -> Self {
+        Self {
+            connection: Connection {
+                config,
+                stream: None,
+            },
+            transaction_id: 0,
+        }
+    }
+
+    fn exchange(
+        &mut self,
+        unit: u8,
+        pdu: &[u8],
+        timeout: Duration,
+        partial: &mut bool,
+    ) -> Result<Vec<u8>> {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let transaction_id = self.transaction_id;
+
+        let mut frame = Vec::with_capacity(MBAP_HEADER_LEN + pdu.len());
+        frame.extend_from_slice(&transaction_id.to_be_bytes());
+        frame.extend_from_slice(&0u16.to_be_bytes());
+        frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        frame.push(unit);
+        frame.extend_from_slice(pdu);
+
+        let stream = self.connection.stream()?;
+        tracing::debug!("tcp tx: {}", hex(&frame));
+        stream.write_all(&frame).map_err(connection_error)?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut header = [0u8; MBAP_HEADER_LEN];
+            read_exact(stream, &mut header, deadline, partial)?;
+            let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+            if !(2..=254).contains(&length) {
+                // 长度错误时无法再找到下一帧的开头, 只能断开重连
+                return Err(AppError::ModbusConnection(format!(
+                    "MBAP 长度错误: {}",
+                    length
+                )));
+            }
+            let mut body = vec![0u8; length - 1];
+            read_exact(stream, &mut body, deadline, partial)?;
+            *partial = false;
+            tracing::debug!("tcp rx: {} {}", hex(&header), hex(&body));
+
+            let response_id = u16::from_be_bytes([header[0], header[1]]);
+            if response_id != transaction_id {
+                // 上一次超时的请求的响应, 丢弃
+                tracing::warn!(
+                    "丢弃事务号不匹配的响应: 期望 {}, 实际 {}",
+                    transaction_id,
+                    response_id
+                );
+                continue;
+            }
+            let protocol = u16::from_be_bytes([header[2], header[3]]);
+            if protocol != 0 {
+                return Err(AppError::ModbusInvalidResponse(format!(
+                    "协议号错误: {}",
+                    protocol
+                )));
+            }
+            if header[6] != unit {
+                return Err(AppError::ModbusInvalidResponse(format!(
+                    "单元号不匹配: 请求 {}, 响应 {}",
+                    unit, header[6]
+                )));
+            }
+            return Ok(body);
+        }
+    }
+}
+
+/// 读满 buf, 收到数据后把 partial 置为 true, 读完一帧后由调用者清除
+fn read_exact(
+    stream: &mut TcpStream,
+    buf: &mut [u8],
+    deadline: Instant,
+    partial: &mut bool,
+) -> Result<()> {
+    let mut read = 0;
+    while read < buf.len() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(AppError::ModbusTimeout);
+        }
+        match read_some(stream, &mut buf[read..], remaining) {
+            Ok(n) => {
+                read += n;
+                *partial = true;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(io_error(e)),
+        }
+    }
+    Ok(())
+}
+
+impl Transport for TcpTransport {
+    fn transact(&mut self, slave: u8, pdu: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        let mut partial = false;
+        let result = self.exchange(slave, pdu, timeout, &mut partial);
+        self.connection.check(result, partial)
+    }
+
+    fn label(&self) -> String {
+        let config = &self.connection.config;
+        format!("tcp {}:{}", config.host, config.port)
+    }
+}
+
+/// 从站一侧: 按顺序应答一个客户端的请求, 直到连接断开或 running 为 false
+///
+/// handler 的参数为单元号和请求的 pdu, 返回响应的 pdu, None 时不应答.
+/// 响应使用请求的事务号和单元号, 协议号不为 0 的请求丢弃
+pub(super) fn serve_mbap<F>(
+    mut stream: TcpStream,
+    running: &AtomicBool,
+    mut handler: F,
+) -> std::io::Result<()>
+where
+    F: FnMut(u8, &[u8]) -> Option<Vec<u8>>,
+{
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+    let mut received = Vec::with_capacity(260);
+    let mut buf = [0u8; 260];
+    while running.load(Ordering::Relaxed) {
+        match stream.read(&mut buf) {
+            Ok(0) => return Err(ErrorKind::ConnectionAborted.into()),
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(e) if is_timeout(&e) => continue,
+            Err(e) => return Err(e),
+        }
+        while received.len() >= MBAP_HEADER_LEN {
+            let len = u16::from_be_bytes([received[4], received[5]]) as usize;
+            if !(2..=254).contains(&len) {
+                return Err(ErrorKind::InvalidData.into());
+            }
+            if received.len() < 6 + len {
+                break;
+            }
+            let adu: Vec<u8> = received.drain(..6 + len).collect();
+            if adu[2..4] != [0, 0] {
+                continue;
+            }
+            let unit = adu[6];
+            let pdu = match handler(unit, &adu[MBAP_HEADER_LEN..]) {
+                Some(pdu) => pdu,
+                None => continue,
+            };
+            let mut response = Vec::with_capacity(MBAP_HEADER_LEN + pdu.len());
+            response.extend_from_slice(&adu[..4]);
+            response.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+            response.push(unit);
+            response.extend_from_slice(&pdu);
+            stream.write_all(&response)?;
+        }
+    }
+    Ok(())
+}
+
+/// rtu over tcp, 通过串口服务器(如 rs485 转以太网模块)透传 rtu 帧
+pub struct RtuOverTcpTransport {
+    connection: Connection,
+}
+
+impl RtuOverTcpTransport {
+    pub fn new(config: TcpConfig) -> Self {
+        Self {
+            connection: Connection {
+                config,
+                stream: None,
+            },
+        }
+    }
+
+    fn exchange(
+        &mut self,
+        slave: u8,
+        pdu: &[u8],
+        timeout: Duration,
+        partial: &mut bool,
+    ) -> Result<Vec<u8>> {
+        check_slave(slave)?;
+        let stream = self.connection.stream()?;
+        discard_pending(stream)?;
+
+        let frame = encode_frame(slave, pdu);
+        tracing::debug!("rtu/tcp tx: {}", hex(&frame));
+        stream.write_all(&frame).map_err(connection_error)?;
+
+        let read = |buf: &mut [u8], remaining| {
+            let n = read_some(stream, buf, remaining)?;
+            *partial = true;
+            Ok(n)
+        };
+        let response = read_frame(read, timeout).map_err(|e| match e {
+            AppError::ModbusTimeout
+            | AppError::ModbusCrc { .. }
+            | AppError::ModbusInvalidResponse(_) => e,
+            e => AppError::ModbusConnection(e.to_string()),
+        })?;
+        tracing::debug!("rtu/tcp rx: {}", hex(&response));
+        check_response(slave, &response)
+    }
+}
+
+/// 丢弃上一次请求超时后才到达的数据
+fn discard_pending(stream: &mut TcpStream) -> Result<()> {
+    stream.set_nonblocking(true).map_err(connection_error)?;
+    let mut buf = [0u8; 256];
+    let result = loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break Err(AppError::ModbusConnection("连接已关闭".to_string())),
+            Ok(n) => tracing::debug!("丢弃 {} 字节: {}", n, hex(&buf[..n])),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+            Err(e) => break Err(connection_error(e)),
+        }
+    };
+    stream.set_nonblocking(false).map_err(connection_error)?;
+    result
+}
+
+impl Transport for RtuOverTcpTransport {
+    fn transact(&mut self, slave: u8, pdu: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        let mut partial = false;
+        let result = self.exchange(slave, pdu, timeout, &mut partial);
+        self.connection.check(result, partial)
+    }
+
+    fn label(&self) -> String {
+        let config = &self.connection.config;
+        format!("rtu over tcp {}:{}", config.host, config.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::*;
+
+    const REQUEST: [u8; 5] = [0x03, 0x00, 0x00, 0x00, 0x01];
+    const RESPONSE: [u8; 4] = [0x03, 0x02, 0x12, 0x34];
+
+    /// 按脚本应答的从站, 返回配置和已接受的连接数
+    ///
+    /// script 的参数为第几个请求和请求报文, 返回依次发送的 (延时, 数据)
+    fn server<F>(mut script: F) -> (TcpConfig, Arc<AtomicUsize>)
+    where
+        F: FnMut(usize, &[u8]) -> Vec<(u64, Vec<u8>)> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = TcpConfig {
+            port: listener.local_addr().unwrap().port(),
+            ..Default::default()
+        };
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        thread::spawn(move || {
+            let mut requests = 0;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0u8; 256];
+                'connection: while let Ok(n @ 1..) = stream.read(&mut buf) {
+                    for (delay, bytes) in script(requests, &buf[..n]) {
+                        thread::sleep(Duration::from_millis(delay));
+                        if stream.write_all(&bytes).is_err() {
+                            break 'connection;
+                        }
+                    }
+                    requests += 1;
+                }
+            }
+        });
+        (config, connections)
+    }
+
+    /// 与请求相同的 MBAP 报文头
+    fn mbap_response(request: &[u8]) -> Vec<u8> {
+        let mut frame = request[..4].to_vec();
+        frame.extend_from_slice(&(RESPONSE.len() as u16 + 1).to_be_bytes());
+        frame.push(request[6]);
+        frame.extend_from_slice(&RESPONSE);
+        frame
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn tcp_reconnect_after_partial_response() {
+        let (config, connections) = server(|n, request| {
+            let response = mbap_response(request);
+            match n {
+                // 只发送报文头和一个字节, 其余的在超时以后才到达
+                0 => vec![(0, response[..8].to_vec()), (300, response[8..].to_vec())],
+                // 完整的响应在超时以后才到达
+                2 => vec![(300, response)],
+                _ => vec![(0, response)],
+            }
+        });
+        let mut transport = TcpTransport::new(config);
+        assert_eq!(
+            transport.transact(1, &REQUEST, ms(100)),
+            Err(AppError::ModbusTimeout)
+        );
+        assert_eq!(
+            transport.transact(1, &REQUEST, ms(1000)),
+            Ok(RESPONSE.to_vec())
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+        // 一个字节都没有收到时保留连接, 迟到的响应按事务号丢弃
+        assert_eq!(
+            transport.transact(1, &REQUEST, ms(100)),
+            Err(AppError::ModbusTimeout)
+        );
+        assert_eq!(
+            transport.transact(1, &REQUEST, ms(1000)),
+            Ok(RESPONSE.to_vec())
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn rtu_over_tcp_reconnect_after_partial_response() {
+        let (config, connections) = server(|n, request| {
+            assert_eq!(request, encode_frame(1, &REQUEST));
+            let response = encode_frame(1, &RESPONSE);
+            match n {
+                0 => vec![(0, response[..3].to_vec()), (300, response[3..].to_vec())],
+                2 => vec![],
+                _ => vec![(0, response)],
+            }
+        });
+        let mut transport = RtuOverTcpTransport::new(config);
+        assert_eq!(
+            transport.transact(1, &REQUEST, ms(100)),
+            Err(AppError::ModbusTimeout)
+        );
+        assert_eq!(
+            transport.transact(1, &REQUEST, ms(1000)),
+            Ok(RESPONSE.to_vec())
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+        assert_eq!(
+            transport.transact(1, &REQUEST, ms(100)),
+            Err(AppError::ModbusTimeout)
+        );
+        assert_eq!(
+            transport.transact(1, &REQUEST, ms(1000)),
+            Ok(RESPONSE.to_vec())
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn simulator_over_tcp() {
+        use crate::{
+            data::register_map::RegisterMap,
+            service::modbus::{
+                simulator::{Simulator, SimulatorConfig},
+                slave::SlaveListen,
+            },
+        };
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let _simulator = Simulator::start(
+            &SimulatorConfig {
+                listen: SlaveListen::Tcp { port },
+                ..Default::default()
+            },
+            RegisterMap::modbus_rtu_example(),
+        )
+        .unwrap();
+        let mut transport = TcpTransport::new(TcpConfig {
+            port,
+            ..Default::default()
+        });
+        let write = [0x06, 0x00, 0x08, 0x00, 0x2A];
+        assert_eq!(transport.transact(1, &write, ms(1000)), Ok(write.to_vec()));
+        // 单元号 0 和 255 也应答
+        for unit in [0, 0xFF] {
+            assert_eq!(
+                transport.transact(unit, &[0x03, 0x00, 0x08, 0x00, 0x01], ms(1000)),
+                Ok(vec![0x03, 0x02, 0x00, 0x2A])
+            );
+        }
+        // 其它单元号不应答
+        assert_eq!(
+            transport.transact(2, &REQUEST, ms(100)),
+            Err(AppError::ModbusTimeout)
+        );
+        assert_eq!(
+            transport.transact(1, &[0x03, 0x00, 0xC8, 0x00, 0x01], ms(1000)),
+            Ok(vec![0x83, 0x02])
+        );
+    }
+}
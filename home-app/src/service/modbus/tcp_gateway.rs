@@ -0,0 +1,402 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    resource::error::{AppError, Result},
+    service::RepaintSignal,
+};
+
+use super::{
+    pdu::{exception_pdu, ExceptionCode, Request},
+    scheduler::{BusHandle, ModbusScheduler, Priority},
+    tcp::serve_mbap,
+    TransportConfig,
+};
+
+/// 保留的已断开客户端数量
+const CLOSED_CLIENTS: usize = 20;
+
+/// 单元号到从站地址的映射
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnitRoute {
+    pub unit: u8,
+    pub slave: u8,
+}
+
+/// Modbus TCP 到串口总线的网关参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TcpGatewayConfig {
+    /// 监听所有网卡的端口
+    pub port: u16,
+    /// 转发请求的总线
+    pub transport: TransportConfig,
+    pub routes: Vec<UnitRoute>,
+    /// 没有映射的单元号 1..=247 直接作为从站地址
+    pub passthrough: bool,
+    /// 所有客户端等待转发的请求数上限, 超出时返回从站忙
+    pub max_pending: usize,
+    pub auto_start: bool,
+}
+
+impl Default for TcpGatewayConfig {
+    fn default() -> Self {
+        Self {
+            port: 5502,
+            transport: TransportConfig::default(),
+            routes: vec![],
+            passthrough: true,
+            max_pending: 32,
+            auto_start: false,
+        }
+    }
+}
+
+impl TcpGatewayConfig {
+    /// 单元号对应的从站地址, 映射表优先
+    pub fn route(&self, unit: u8) -> Option<u8> {
+        match self.routes.iter().find(|r| r.unit == unit) {
+            Some(route) => Some(route.slave),
+            None if self.passthrough && (1..=247).contains(&unit) => Some(unit),
+            None => None,
+        }
+    }
+}
+
+/// 一个 tcp 客户端
+#[derive(Debug, Clone)]
+pub struct ClientStats {
+    pub addr: SocketAddr,
+    pub connected: DateTime<Local>,
+    pub requests: u64,
+    /// 返回的异常响应, 包括从站的异常
+    pub exceptions: u64,
+    pub closed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct TcpGatewayState {
+    pub clients: Vec<ClientStats>,
+    pub requests: u64,
+    /// 目标从站无响应, 返回了异常码 0x0B
+    pub timeouts: u64,
+    pub exceptions: u64,
+    /// 正在等待总线的请求
+    pub pending: usize,
+    pub last_error: Option<String>,
+}
+
+impl TcpGatewayState {
+    /// 已连接的客户端
+    fn client(&mut self, addr: SocketAddr) -> Option<&mut ClientStats> {
+        self.clients
+            .iter_mut()
+            .rev()
+            .find(|c| c.addr == addr && !c.closed)
+    }
+}
+
+struct Shared {
+    config: TcpGatewayConfig,
+    bus: BusHandle,
+    state: Mutex<TcpGatewayState>,
+    running: AtomicBool,
+    repaint: RepaintSignal,
+}
+
+/// 把 Modbus TCP 客户端的请求转发到串口总线上的从站
+///
+/// 请求由总线调度器排队, 与其它页面的轮询共用总线.
+/// 单元号没有映射时返回网关路径不可用 (0x0A), 从站无响应时返回网关目标设备无响应 (0x0B)
+pub struct TcpGateway {
+    label: String,
+    shared: Arc<Shared>,
+}
+
+impl TcpGateway {
+    pub fn start(
+        config: &TcpGatewayConfig,
+        scheduler: &ModbusScheduler,
+        repaint: RepaintSignal,
+    ) -> Result<Self> {
+        let bus = scheduler.bus(&config.transport)?;
+        let listener = TcpListener::bind(("0.0.0.0", config.port))?;
+        listener.set_nonblocking(true)?;
+        let label = format!("tcp 0.0.0.0:{} -> {}", config.port, bus.label());
+        let shared = Arc::new(Shared {
+            config: config.clone(),
+            bus,
+            state: Mutex::new(TcpGatewayState::default()),
+            running: AtomicBool::new(true),
+            repaint,
+        });
+        let thread_shared = shared.clone();
+        thread::Builder::new()
+            .name("modbus-tcp-gateway".to_string())
+            .spawn(move || listen(listener, thread_shared))?;
+        tracing::info!("modbus tcp 网关已启动: {}", label);
+        Ok(Self { label, shared })
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn config(&self) -> &TcpGatewayConfig {
+        &self.shared.config
+    }
+
+    pub fn state(&self) -> MutexGuard<TcpGatewayState> {
+        self.shared.state.lock()
+    }
+
+    /// 监听线程出错退出后返回 false
+    pub fn is_running(&self) -> bool {
+        self.shared.running.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for TcpGateway {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn listen(listener: TcpListener, shared: Arc<Shared>) {
+    while shared.running.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                tracing::info!("modbus tcp 网关客户端已连接: {}", addr);
+                {
+                    let mut state = shared.state.lock();
+                    let closed = state.clients.iter().filter(|c| c.closed).count();
+                    if closed >= CLOSED_CLIENTS {
+                        if let Some(i) = state.clients.iter().position(|c| c.closed) {
+                            state.clients.remove(i);
+                        }
+                    }
+                    state.clients.push(ClientStats {
+                        addr,
+                        connected: Local::now(),
+                        requests: 0,
+                        exceptions: 0,
+                        closed: false,
+                    });
+                }
+                (shared.repaint)();
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let result = serve_mbap(stream, &shared.running, |unit, pdu| {
+                        Some(forward(&shared, addr, unit, pdu))
+                    });
+                    if let Err(e) = result {
+                        tracing::info!("modbus tcp 网关客户端 {} 断开: {}", addr, e);
+                    }
+                    if let Some(client) = shared.state.lock().client(addr) {
+                        client.closed = true;
+                    }
+                    (shared.repaint)();
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => {
+                tracing::error!("modbus tcp 网关监听失败: {}", e);
+                shared.state.lock().last_error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+    shared.running.store(false, Ordering::Relaxed);
+    (shared.repaint)();
+}
+
+/// 转发一个请求, 返回响应或异常响应的 pdu
+fn forward(shared: &Shared, addr: SocketAddr, unit: u8, pdu: &[u8]) -> Vec<u8> {
+    let function = pdu.first().copied().unwrap_or(0);
+    let result = route(shared, unit, pdu);
+    let mut state = shared.state.lock();
+    state.requests += 1;
+    let exception = result.as_ref().err().copied();
+    if let Some(code) = exception {
+        if code == ExceptionCode::GatewayTargetFailed {
+            state.timeouts += 1;
+        }
+        state.exceptions += 1;
+    }
+    if let Some(client) = state.client(addr) {
+        client.requests += 1;
+        client.exceptions += exception.is_some() as u64;
+    }
+    drop(state);
+    (shared.repaint)();
+    result.unwrap_or_else(|code| exception_pdu(function, code))
+}
+
+fn route(shared: &Shared, unit: u8, pdu: &[u8]) -> std::result::Result<Vec<u8>, ExceptionCode> {
+    let slave = shared
+        .config
+        .route(unit)
+        .ok_or(ExceptionCode::GatewayPathUnavailable)?;
+    let request = Request::decode(pdu)?;
+    {
+        let mut state = shared.state.lock();
+        if state.pending >= shared.config.max_pending.max(1) {
+            return Err(ExceptionCode::ServerDeviceBusy);
+        }
+        state.pending += 1;
+    }
+    let result = shared.bus.request(slave, &request, Priority::Request);
+    let mut state = shared.state.lock();
+    state.pending -= 1;
+    match result {
+        Ok(response) => Ok(response.encode(request.function_code())),
+        Err(e) => {
+            tracing::debug!("modbus tcp 网关转发到从站 {} 失败: {}", slave, e);
+            state.last_error = Some(format!("从站 {}: {}", slave, e));
+            Err(exception_code(&e))
+        }
+    }
+}
+
+/// 转发失败时返回给客户端的异常码, 从站的异常响应原样返回
+fn exception_code(e: &AppError) -> ExceptionCode {
+    match e {
+        AppError::ModbusException { code, .. } => *code,
+        AppError::ModbusConnection(_) | AppError::Serial(_) => {
+            ExceptionCode::GatewayPathUnavailable
+        }
+        _ => ExceptionCode::GatewayTargetFailed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::{
+        data::register_map::RegisterMap,
+        service::modbus::{
+            rtu::SerialConfig,
+            scheduler::BusConfig,
+            simulator::{Simulator, SimulatorConfig},
+            slave::SlaveListen,
+        },
+    };
+
+    /// 发送一个请求, 返回 MBAP 头和 pdu
+    fn exchange(
+        stream: &mut TcpStream,
+        transaction: u16,
+        unit: u8,
+        pdu: &[u8],
+    ) -> (Vec<u8>, Vec<u8>) {
+        let mut request = transaction.to_be_bytes().to_vec();
+        request.extend_from_slice(&[0, 0]);
+        request.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        request.push(unit);
+        request.extend_from_slice(pdu);
+        stream.write_all(&request).unwrap();
+
+        let mut header = vec![0u8; 7];
+        stream.read_exact(&mut header).unwrap();
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let mut pdu = vec![0u8; len - 1];
+        stream.read_exact(&mut pdu).unwrap();
+        (header, pdu)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn forward_to_simulator() {
+        let simulator = Simulator::start(
+            &SimulatorConfig {
+                listen: SlaveListen::Pty,
+                ..Default::default()
+            },
+            RegisterMap::modbus_rtu_example(),
+        )
+        .unwrap();
+        let serial = SerialConfig {
+            port: simulator.label().trim_start_matches("pty ").to_string(),
+            ..Default::default()
+        };
+        let transport = TransportConfig::Rtu(serial);
+        let scheduler = ModbusScheduler::new(BTreeMap::from([(
+            transport.bus_key(),
+            BusConfig {
+                timeout_ms: 200,
+                retries: 0,
+                ..Default::default()
+            },
+        )]));
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = TcpGatewayConfig {
+            port,
+            transport,
+            routes: vec![UnitRoute { unit: 9, slave: 1 }],
+            ..Default::default()
+        };
+        let gateway = TcpGateway::start(&config, &scheduler, Arc::new(|| {})).unwrap();
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(3)))
+            .unwrap();
+
+        // 读保持寄存器 0..2, 响应使用请求的事务号和单元号
+        let (header, pdu) = exchange(&mut stream, 0x1234, 1, &[0x03, 0x00, 0x00, 0x00, 0x02]);
+        assert_eq!(header, vec![0x12, 0x34, 0, 0, 0, 7, 1]);
+        assert_eq!(pdu[..2], [0x03, 0x04]);
+        assert_eq!(pdu.len(), 6);
+
+        // 映射的单元号
+        let (header, pdu) = exchange(&mut stream, 0x1235, 9, &[0x06, 0x00, 0x08, 0x00, 0x2A]);
+        assert_eq!(header, vec![0x12, 0x35, 0, 0, 0, 6, 9]);
+        assert_eq!(pdu, vec![0x06, 0x00, 0x08, 0x00, 0x2A]);
+        let (_, pdu) = exchange(&mut stream, 0x1236, 1, &[0x03, 0x00, 0x08, 0x00, 0x01]);
+        assert_eq!(pdu, vec![0x03, 0x02, 0x00, 0x2A]);
+
+        // 从站的异常响应原样返回
+        let (header, pdu) = exchange(&mut stream, 0x1237, 1, &[0x03, 0x00, 0xC8, 0x00, 0x01]);
+        assert_eq!(header, vec![0x12, 0x37, 0, 0, 0, 3, 1]);
+        assert_eq!(pdu, vec![0x83, 0x02]);
+
+        // 从站 2 不存在, 超时后返回网关目标设备无响应
+        let (header, pdu) = exchange(&mut stream, 0x1238, 2, &[0x03, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(header, vec![0x12, 0x38, 0, 0, 0, 3, 2]);
+        assert_eq!(pdu, vec![0x83, 0x0B]);
+
+        // 单元号 0 没有映射
+        let (header, pdu) = exchange(&mut stream, 0x1239, 0, &[0x01, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(header, vec![0x12, 0x39, 0, 0, 0, 3, 0]);
+        assert_eq!(pdu, vec![0x81, 0x0A]);
+
+        let state = gateway.state();
+        assert_eq!(state.requests, 6);
+        assert_eq!(state.timeouts, 1);
+        assert_eq!(state.exceptions, 3);
+    }
+}
@@ -1,7 +1,174 @@
-#[derive(Default, Serialize, Deserialize)]
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, TryRecvError},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+
+use crate::resource::error::{AppError, Result};
+
+use super::RepaintSignal;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MqttConfig {
-    config: Config,
-    #[serde(skip)]
+    pub client_id: String,
+    pub host: String,
+    pub port: u16,
+    /// 心跳间隔, 单位: 秒
+    pub keep_alive: u64,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            client_id: "home-app".into(),
+            host: "127.0.0.1".into(),
+            port: 1883,
+            keep_alive: 5,
+        }
+    }
+}
+
+/// 从 broker 收到的消息
+#[derive(Clone, Debug)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// mqtt 客户端, 收到的消息在后台线程中放入队列, 由 ui 线程通过 try_recv 取出
+pub struct MqttClient {
+    config: MqttConfig,
+    client: Option<Client>,
+    rx: Option<Receiver<MqttMessage>>,
     jh: Option<JoinHandle<()>>,
-    stop: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+}
+
+impl MqttClient {
+    pub fn new(config: MqttConfig) -> Self {
+        Self {
+            config,
+            client: None,
+            rx: None,
+            jh: None,
+            connected: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn config(&self) -> &MqttConfig {
+        &self.config
+    }
+
+    /// 是否已连接到 broker
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// 启动客户端, 每次连接成功后都会重新订阅 subscriptions
+    pub fn start(&mut self, subscriptions: Vec<String>, repaint: RepaintSignal) -> Result<()> {
+        if self.client.is_some() {
+            return Ok(());
+        }
+
+        let mut options = MqttOptions::new(
+            self.config.client_id.clone(),
+            self.config.host.clone(),
+            self.config.port,
+        );
+        options.set_keep_alive(Duration::from_secs(self.config.keep_alive));
+
+        let (client, mut connection) = Client::new(options, 64);
+        let (tx, rx) = channel();
+        let connected = self.connected.clone();
+        let mut sub_client = client.clone();
+
+        let builder = std::thread::Builder::new().name("mqtt-client".to_string());
+        let jh = builder
+            .spawn(move || {
+                for notification in connection.iter() {
+                    match notification {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            tracing::info!("mqtt 已连接");
+                            connected.store(true, Ordering::Relaxed);
+                            for topic in subscriptions.iter() {
+                                if let Err(e) = sub_client.try_subscribe(topic, QoS::AtLeastOnce) {
+                                    tracing::error!("订阅 {} 失败: {}", topic, e);
+                                }
+                            }
+                            repaint();
+                        }
+                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                            let message = MqttMessage {
+                                topic: publish.topic,
+                                payload: publish.payload.to_vec(),
+                            };
+                            if tx.send(message).is_err() {
+                                break;
+                            }
+                            repaint();
+                        }
+                        Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) => break,
+                        Ok(_) => {}
+                        Err(e) => {
+                            if connected.swap(false, Ordering::Relaxed) {
+                                repaint();
+                            }
+                            tracing::warn!("mqtt 连接错误: {}", e);
+                            // 连接失败时, 避免立即重连
+                            std::thread::sleep(Duration::from_secs(1));
+                        }
+                    }
+                }
+                connected.store(false, Ordering::Relaxed);
+            })
+            .map_err(|e| AppError::Error(e.to_string()))?;
+
+        self.client = Some(client);
+        self.rx = Some(rx);
+        self.jh = Some(jh);
+        Ok(())
+    }
+
+    /// 断开连接
+    pub fn stop(&mut self) {
+        if let Some(mut client) = self.client.take() {
+            client.disconnect().ok();
+        }
+        self.rx = None;
+        self.jh = None;
+    }
+
+    /// 发布消息, 不会阻塞 ui 线程
+    pub fn publish(
+        &mut self,
+        topic: &str,
+        payload: impl Into<Vec<u8>>,
+        retain: bool,
+    ) -> Result<()> {
+        let client = self.client.as_mut().ok_or(AppError::MqttClientNotStarted)?;
+        client.try_publish(topic, QoS::AtLeastOnce, retain, payload)?;
+        Ok(())
+    }
+
+    /// 取出一条收到的消息
+    pub fn try_recv(&self) -> Option<MqttMessage> {
+        let rx = self.rx.as_ref()?;
+        match rx.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
+
+impl Drop for MqttClient {
+    fn drop(&mut self) {
+        self.stop();
+    }
 }
@@ -16,6 +16,7 @@ pub struct MqttServer {
     config: Config,
     #[serde(skip)]
     jh: Option<JoinHandle<()>>,
+    #[serde(skip)]
     stop: Arc<AtomicBool>,
 }
 
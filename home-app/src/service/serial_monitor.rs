@@ -0,0 +1,292 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs::File,
+    io::{ErrorKind, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+
+use crate::{
+    data::idf_log::{LineSplitter, LogLine},
+    resource::error::{AppError, Result},
+    service::{modbus::rtu::is_timeout, RepaintSignal},
+};
+
+/// 内存中保留的行数, 超出时丢弃最早的行
+const MAX_LINES: usize = 100_000;
+/// 没有换行的输出超过这个时间后作为一行显示
+const PARTIAL_LINE_TIMEOUT: Duration = Duration::from_millis(200);
+/// 串口断开后重新打开的间隔
+const REOPEN_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 串口监视器参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SerialMonitorConfig {
+    /// ESP32-C3 的 USB-Serial-JTAG 在 linux 上是 /dev/ttyACM0, UART 转 USB 通常是 /dev/ttyUSB0
+    pub port: String,
+    pub baud_rate: u32,
+    /// 打开串口时通过 RTS 复位芯片, 与 idf.py monitor 相同
+    pub reset_on_open: bool,
+    /// 固件的 ELF 文件, 用于解析 panic 回溯中的地址
+    pub elf: String,
+}
+
+impl Default for SerialMonitorConfig {
+    fn default() -> Self {
+        Self {
+            port: if cfg!(windows) {
+                "COM3".into()
+            } else {
+                "/dev/ttyACM0".into()
+            },
+            baud_rate: 115200,
+            reset_on_open: false,
+            elf: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MonitorState {
+    pub lines: VecDeque<LogLine>,
+    /// 出现过的标签
+    pub tags: BTreeSet<String>,
+    pub bytes: u64,
+    pub connected: bool,
+    pub error: Option<String>,
+    next_index: u64,
+}
+
+impl MonitorState {
+    fn push(&mut self, text: &str) {
+        let line = LogLine::parse(self.next_index, Local::now(), text);
+        self.next_index += 1;
+        if !line.tag.is_empty() && !self.tags.contains(&line.tag) {
+            self.tags.insert(line.tag.clone());
+        }
+        self.lines.push_back(line);
+        if self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+
+/// 打开 ESP32 的串口, 把输出解析为 ESP-IDF 日志行
+///
+/// USB-Serial-JTAG 在芯片复位时会断开, 断开后每隔一段时间尝试重新打开
+pub struct SerialMonitor {
+    label: String,
+    state: Arc<Mutex<MonitorState>>,
+    /// 读线程持有的串口的副本, 用于发送和复位, 断开时为 None
+    port: Arc<Mutex<Option<Box<dyn SerialPort>>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl SerialMonitor {
+    pub fn start(config: &SerialMonitorConfig, repaint: RepaintSignal) -> Result<Self> {
+        let port = open(config)?;
+        if config.reset_on_open {
+            reset(port.as_ref())?;
+        }
+        let label = format!("{} {}", config.port, config.baud_rate);
+        let state = Arc::new(Mutex::new(MonitorState {
+            connected: true,
+            ..Default::default()
+        }));
+        let writer = Arc::new(Mutex::new(Some(port.try_clone()?)));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread_state = state.clone();
+        let thread_writer = writer.clone();
+        let thread_running = running.clone();
+        let config = config.clone();
+        thread::Builder::new()
+            .name("serial-monitor".to_string())
+            .spawn(move || {
+                read_loop(
+                    port,
+                    &config,
+                    &thread_state,
+                    &thread_writer,
+                    &thread_running,
+                    &repaint,
+                );
+                thread_state.lock().connected = false;
+                repaint();
+            })?;
+        tracing::info!("串口监视器已打开: {}", label);
+        Ok(Self {
+            label,
+            state,
+            port: writer,
+            running,
+        })
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn state(&self) -> MutexGuard<MonitorState> {
+        self.state.lock()
+    }
+
+    /// 发送一行文本, 以 \r\n 结尾
+    pub fn send(&self, text: &str) -> Result<()> {
+        match self.port.lock().as_mut() {
+            Some(port) => {
+                port.write_all(format!("{}\r\n", text).as_bytes())?;
+                Ok(())
+            }
+            None => Err(AppError::Error("串口未连接".to_string())),
+        }
+    }
+
+    /// 通过 RTS 拉低 EN 复位芯片, USB-Serial-JTAG 也支持
+    pub fn reset(&self) -> Result<()> {
+        match self.port.lock().as_mut() {
+            Some(port) => reset(port.as_ref()),
+            None => Err(AppError::Error("串口未连接".to_string())),
+        }
+    }
+}
+
+impl Drop for SerialMonitor {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn open(config: &SerialMonitorConfig) -> Result<Box<dyn SerialPort>> {
+    let mut port = serialport::new(&config.port, config.baud_rate)
+        .data_bits(serialport::DataBits::Eight)
+        .parity(serialport::Parity::None)
+        .stop_bits(serialport::StopBits::One)
+        .flow_control(serialport::FlowControl::None)
+        .timeout(Duration::from_millis(50))
+        .open()?;
+    // 打开后保持 DTR 和 RTS 无效, 避免芯片停在复位或下载模式
+    // 虚拟串口不支持时忽略
+    port.write_data_terminal_ready(false).ok();
+    port.write_request_to_send(false).ok();
+    Ok(port)
+}
+
+fn reset(port: &dyn SerialPort) -> Result<()> {
+    let mut port = port.try_clone()?;
+    port.write_data_terminal_ready(false)?;
+    port.write_request_to_send(true)?;
+    thread::sleep(Duration::from_millis(100));
+    port.write_request_to_send(false)?;
+    Ok(())
+}
+
+fn read_loop(
+    mut port: Box<dyn SerialPort>,
+    config: &SerialMonitorConfig,
+    state: &Mutex<MonitorState>,
+    writer: &Mutex<Option<Box<dyn SerialPort>>>,
+    running: &AtomicBool,
+    repaint: &RepaintSignal,
+) {
+    let mut splitter = LineSplitter::default();
+    let mut last_data = Instant::now();
+    let mut buf = [0u8; 1024];
+    while running.load(Ordering::Relaxed) {
+        match port.read(&mut buf) {
+            Ok(n) if n > 0 => {
+                last_data = Instant::now();
+                let lines = splitter.push(&buf[..n]);
+                let mut state = state.lock();
+                state.bytes += n as u64;
+                for line in lines.iter() {
+                    state.push(line);
+                }
+                drop(state);
+                if !lines.is_empty() {
+                    repaint();
+                }
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) if is_timeout(&e) || e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => {
+                tracing::info!("串口监视器 {} 断开: {}", config.port, e);
+                if let Some(line) = splitter.flush() {
+                    state.lock().push(&line);
+                }
+                *writer.lock() = None;
+                {
+                    let mut state = state.lock();
+                    state.connected = false;
+                    state.error = Some(format!("串口断开: {}", e));
+                }
+                repaint();
+                port = match reopen(config, running) {
+                    Some(port) => port,
+                    None => return,
+                };
+                *writer.lock() = port.try_clone().ok();
+                {
+                    let mut state = state.lock();
+                    state.connected = true;
+                    state.error = None;
+                }
+                repaint();
+                continue;
+            }
+        }
+        if last_data.elapsed() >= PARTIAL_LINE_TIMEOUT {
+            if let Some(line) = splitter.flush() {
+                state.lock().push(&line);
+                repaint();
+            }
+        }
+    }
+}
+
+/// 等待串口重新出现, 停止时返回 None
+fn reopen(config: &SerialMonitorConfig, running: &AtomicBool) -> Option<Box<dyn SerialPort>> {
+    while running.load(Ordering::Relaxed) {
+        thread::sleep(REOPEN_INTERVAL);
+        if let Ok(port) = open(config) {
+            tracing::info!("串口监视器 {} 已重新打开", config.port);
+            return Some(port);
+        }
+    }
+    None
+}
+
+/// 保存为文本文件, 每行前面加上主机时间
+pub fn save_log<'a>(
+    lines: impl Iterator<Item = &'a LogLine>,
+    path: impl AsRef<Path>,
+) -> Result<usize> {
+    let mut content = String::new();
+    let mut count = 0;
+    for line in lines {
+        content.push_str(&format!(
+            "[{}] {}\n",
+            line.host_time.format("%Y-%m-%d %H:%M:%S%.3f"),
+            line.text()
+        ));
+        count += 1;
+    }
+    File::create(path)?.write_all(content.as_bytes())?;
+    Ok(count)
+}
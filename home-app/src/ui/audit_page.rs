@@ -0,0 +1,204 @@
+use chrono::{Local, NaiveDate};
+use epi::egui::{self, Color32};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{
+    data::{
+        app_data::AppData,
+        audit::{export_csv, AuditAction, AuditFilter},
+        storage::persistence::app_dir,
+    },
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{navigation::PageKind, titlebar::MainTitlebar};
+
+/// 操作审计日志, 支持搜索, 按日期过滤和导出
+pub struct AuditPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    filter: AuditFilter,
+    /// 日期输入框的内容, 格式: 2022-04-05
+    from: String,
+    to: String,
+    /// 导出结果
+    message: Option<Result<String, String>>,
+}
+
+impl AuditPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            filter: AuditFilter::default(),
+            from: String::new(),
+            to: String::new(),
+            message: None,
+        }
+    }
+
+    fn parse_date(text: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok()
+    }
+
+    fn filter_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("搜索");
+            ui.text_edit_singleline(&mut self.filter.query);
+
+            ui.label("操作");
+            egui::ComboBox::from_id_source("audit_action")
+                .selected_text(self.filter.action.map_or("全部", |a| a.label()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.action, None, "全部");
+                    for action in AuditAction::ALL {
+                        ui.selectable_value(&mut self.filter.action, Some(action), action.label());
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("日期");
+            let dates: [(&mut String, &mut Option<NaiveDate>); 2] = [
+                (&mut self.from, &mut self.filter.from),
+                (&mut self.to, &mut self.filter.to),
+            ];
+            for (text, date) in dates {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut *text)
+                        .hint_text("YYYY-MM-DD")
+                        .desired_width(90.0),
+                );
+                if response.changed() {
+                    *date = Self::parse_date(text);
+                }
+                if !text.trim().is_empty() && date.is_none() {
+                    ui.colored_label(Color32::RED, "格式错误");
+                }
+            }
+            if ui.button("今天").clicked() {
+                let today = Local::now().date_naive();
+                self.from = today.format("%Y-%m-%d").to_string();
+                self.to = self.from.clone();
+                self.filter.from = Some(today);
+                self.filter.to = Some(today);
+            }
+            if ui.button("清除").clicked() {
+                self.from.clear();
+                self.to.clear();
+                self.filter = AuditFilter::default();
+            }
+        });
+    }
+}
+
+impl BasePage for AuditPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Audit {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("操作审计");
+            ui.horizontal(|ui| {
+                ui.label("操作人");
+                ui.text_edit_singleline(&mut app_data.audit.user);
+                ui.separator();
+                ui.label(format!("日志文件: {}", app_data.audit.path().display()));
+            });
+            self.filter_ui(ui);
+
+            let count = app_data.audit.search(&self.filter).count();
+            ui.horizontal(|ui| {
+                ui.label(format!("共 {} 条记录", count));
+                if ui.button("导出 csv").clicked() {
+                    let path = app_dir().join(format!(
+                        "audit-{}.csv",
+                        Local::now().format("%Y%m%d-%H%M%S")
+                    ));
+                    self.message = Some(
+                        export_csv(app_data.audit.search(&self.filter), &path)
+                            .map(|n| format!("已导出 {} 条记录到 {}", n, path.display()))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+                match &self.message {
+                    Some(Ok(message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(Color32::RED, e);
+                    }
+                    None => {}
+                }
+            });
+            ui.separator();
+
+            let entries: Vec<_> = app_data.audit.search(&self.filter).collect();
+            let row_height = ui.text_style_height(&egui::TextStyle::Body);
+            // 最新的记录在前面
+            egui::ScrollArea::vertical().show_rows(ui, row_height, entries.len(), |ui, rows| {
+                egui::Grid::new("audit_entries")
+                    .num_columns(6)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("时间");
+                        ui.strong("操作人");
+                        ui.strong("操作");
+                        ui.strong("对象");
+                        ui.strong("原值");
+                        ui.strong("新值");
+                        ui.end_row();
+
+                        for entry in entries.iter().rev().skip(rows.start).take(rows.len()) {
+                            ui.label(entry.time.format("%Y-%m-%d %H:%M:%S").to_string());
+                            ui.label(&entry.user);
+                            ui.label(entry.action.label());
+                            ui.label(&entry.target);
+                            ui.label(entry.old_value.as_deref().unwrap_or("-"));
+                            ui.label(entry.new_value.as_deref().unwrap_or("-"));
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
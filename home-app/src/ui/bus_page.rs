@@ -0,0 +1,228 @@
+use epi::egui::{self, Color32, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use crate::{
+    data::app_data::AppData,
+    service::modbus::scheduler::{BusConfig, BusStats},
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{config_audit::ConfigAudit, navigation::PageKind, titlebar::MainTitlebar};
+
+/// 总线调度器的参数和统计: 请求速率, 帧间隔, 总线占用率和每个从站的超时率
+pub struct BusPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    /// 记录总线参数的修改
+    config_audit: ConfigAudit,
+}
+
+impl BusPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            config_audit: ConfigAudit::default(),
+        }
+    }
+
+    /// 返回修改后的参数
+    fn config_ui(ui: &mut Ui, config: &BusConfig) -> Option<BusConfig> {
+        let mut config = *config;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("帧间隔");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut config.gap_ms)
+                        .clamp_range(0..=5000)
+                        .suffix(" ms"),
+                )
+                .on_hover_text("收到响应后到下一个请求的最小间隔, 串口的 t3.5 另外保证")
+                .changed();
+            ui.label("最大速率");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut config.max_rate)
+                        .clamp_range(0.0..=1000.0)
+                        .speed(0.5)
+                        .suffix(" 次/秒"),
+                )
+                .on_hover_text("0 表示不限制")
+                .changed();
+            ui.label("超时");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut config.timeout_ms)
+                        .clamp_range(10..=10_000)
+                        .speed(10)
+                        .suffix(" ms"),
+                )
+                .changed();
+            ui.label("重试");
+            changed |= ui
+                .add(egui::DragValue::new(&mut config.retries).clamp_range(0..=10))
+                .changed();
+        });
+        changed.then_some(config)
+    }
+
+    fn stats_ui(ui: &mut Ui, key: &str, stats: &BusStats) {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "请求 {} 次, 合并 {} 次, 排队 {}, 轮询 {}",
+                stats.requests, stats.merged, stats.queued, stats.groups
+            ));
+            let utilisation = stats.utilisation();
+            ui.add(
+                egui::ProgressBar::new(utilisation as f32)
+                    .desired_width(160.0)
+                    .text(format!("占用率 {:.0}%", utilisation * 100.0)),
+            );
+        });
+        if stats.slaves.is_empty() {
+            return;
+        }
+        egui::Grid::new(("bus_slaves", key))
+            .num_columns(8)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("从站");
+                ui.strong("请求");
+                ui.strong("超时");
+                ui.strong("超时率");
+                ui.strong("其它失败");
+                ui.strong("平均耗时");
+                ui.strong("最后请求");
+                ui.strong("最后错误");
+                ui.end_row();
+
+                for (slave, slave_stats) in stats.slaves.iter() {
+                    ui.label(slave.to_string());
+                    ui.label(slave_stats.requests.to_string());
+                    ui.label(slave_stats.timeouts.to_string());
+                    let rate = slave_stats.timeout_rate();
+                    let text = format!("{:.1}%", rate * 100.0);
+                    if rate > 0.1 {
+                        ui.colored_label(Color32::RED, text);
+                    } else {
+                        ui.label(text);
+                    }
+                    ui.label(slave_stats.failures.to_string());
+                    ui.label(slave_stats.latency.map_or_else(String::new, |l| {
+                        format!("{:.1} ms", l.as_secs_f64() * 1000.0)
+                    }));
+                    ui.label(
+                        slave_stats
+                            .last_request
+                            .map_or_else(String::new, |t| t.format("%H:%M:%S").to_string()),
+                    );
+                    ui.label(slave_stats.last_error.as_deref().unwrap_or(""));
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+impl BasePage for BusPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Buses {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let app_data = app_data.read();
+        let buses = app_data.modbus.buses();
+        let configs = app_data.modbus.configs();
+        // 正在使用的总线和设备列表中的总线
+        let mut keys: BTreeSet<String> = buses.iter().map(|(key, _)| key.clone()).collect();
+        let mut labels = BTreeMap::new();
+        for device in app_data.modbus_devices.iter() {
+            let key = device.transport.bus_key();
+            labels
+                .entry(key.clone())
+                .or_insert_with(|| device.transport.label());
+            keys.insert(key);
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Modbus 总线");
+            ui.label("同一条总线上的所有请求由调度器排队发送, 写请求优先, 相邻的轮询读请求合并");
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for key in keys.iter() {
+                    let bus = buses.iter().find(|(k, _)| k == key).map(|(_, bus)| bus);
+                    let stats = bus.map(|bus| bus.stats());
+                    let title = match &stats {
+                        Some(stats) => format!("{} ({})", key, stats.label),
+                        None => format!(
+                            "{} ({}, 未使用)",
+                            key,
+                            labels.get(key).map_or("", String::as_str)
+                        ),
+                    };
+                    egui::CollapsingHeader::new(title)
+                        .id_source(("bus", key))
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            let config = bus.map_or_else(
+                                || configs.get(key).copied().unwrap_or_default(),
+                                |bus| bus.config(),
+                            );
+                            if let Some(config) = Self::config_ui(ui, &config) {
+                                app_data.modbus.set_config(key, config);
+                            }
+                            if let Some(stats) = &stats {
+                                Self::stats_ui(ui, key, stats);
+                            }
+                        });
+                }
+            });
+        });
+
+        let configs = app_data.modbus.configs();
+        drop(app_data);
+        if let Some((old, new)) = self.config_audit.changes(ctx, &configs) {
+            self.app_data
+                .write()
+                .audit_config("modbus_buses", Some(old), Some(new));
+        }
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeSet;
+
+use epi::egui;
+use serde::Serialize;
+use serde_json::Value;
+
+/// 跟踪页面上一个配置的修改, 输入框失去焦点并且松开鼠标后才产生一条审计记录
+/// 旧值为开始修改之前的值, 连续的输入和拖动只记录一次
+#[derive(Default)]
+pub struct ConfigAudit {
+    saved: Option<Value>,
+}
+
+impl ConfigAudit {
+    /// 在绘制配置之后调用, 返回修改了的字段的 (旧值, 新值)
+    pub fn changes(
+        &mut self,
+        ctx: &egui::Context,
+        config: &impl Serialize,
+    ) -> Option<(String, String)> {
+        let current = serde_json::to_value(config).ok()?;
+        let saved = match &self.saved {
+            Some(saved) => saved,
+            None => {
+                self.saved = Some(current);
+                return None;
+            }
+        };
+        if *saved == current || ctx.memory().focus().is_some() || ctx.input().pointer.any_down() {
+            return None;
+        }
+        let mut old = vec![];
+        let mut new = vec![];
+        diff("", saved, &current, &mut old, &mut new);
+        self.saved = Some(current);
+        Some((old.join(", "), new.join(", ")))
+    }
+}
+
+/// 比较两个 json 值, 对象按字段递归比较, 其它类型整体比较
+fn diff(path: &str, old: &Value, new: &Value, olds: &mut Vec<String>, news: &mut Vec<String>) {
+    if old == new {
+        return;
+    }
+    if let (Value::Object(old), Value::Object(new)) = (old, new) {
+        let empty = Value::Null;
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for key in keys {
+            let path = match path {
+                "" => key.clone(),
+                path => format!("{}.{}", path, key),
+            };
+            diff(
+                &path,
+                old.get(key).unwrap_or(&empty),
+                new.get(key).unwrap_or(&empty),
+                olds,
+                news,
+            );
+        }
+        return;
+    }
+    let text = |value: &Value| match (path, value) {
+        ("", value) => value.to_string(),
+        (path, value) => format!("{}={}", path, value),
+    };
+    olds.push(text(old));
+    news.push(text(new));
+}
@@ -1,34 +1,380 @@
-use epi::egui;
+use epi::egui::{self, Color32, Id, RichText, Ui};
 use parking_lot::RwLock;
 use winit::window::Window;
 
 use std::sync::Arc;
 
-use crate::window::{BasePage, Page, PageAction, StatusBar, TitleBar};
+use crate::{
+    data::{
+        app_data::AppData,
+        command_queue::CommandTarget,
+        device::{Device, DeviceCommand, DeviceKind, GroupState, RoomFilter},
+        shadow::SyncState,
+    },
+    window::{BasePage, PageAction, StatusBar, TitleBar},
+};
 
-use super::titlebar::MainTitlebar;
+use super::{
+    dnd::{drag_source, drop_target, dropped},
+    navigation::PageKind,
+    titlebar::MainTitlebar,
+};
 
 pub struct DevicePage {
     id: usize,
     pid: usize,
-    open_test_window: bool,
     title_bar: MainTitlebar,
     status_bar: DeviceListBar,
     window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    /// 设备列表按房间过滤
+    room_filter: RoomFilter,
+    new_room: String,
+    new_group: String,
+    /// 正在拖拽的设备id
+    dragging: Option<String>,
+    /// 离线设备的命令排队有效期, 单位: 秒
+    queue_ttl: u64,
+    error: Option<String>,
 }
 
 impl DevicePage {
-    pub fn new(window_handle: Arc<RwLock<Window>>) -> Self {
-        let title_bar = MainTitlebar::new(window_handle.clone());
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        let queue_ttl = app_data.read().command_queue.default_ttl;
         Self {
             id: 0,
             pid: 0,
-            open_test_window: false,
             title_bar,
-            status_bar: DeviceListBar::default(),
+            status_bar: DeviceListBar::new(app_data.clone()),
             window_handle,
+            app_data,
+            room_filter: RoomFilter::All,
+            new_room: String::new(),
+            new_group: String::new(),
+            dragging: None,
+            queue_ttl,
+            error: None,
         }
     }
+
+    /// 房间列表, 设备可以拖拽到房间上
+    fn rooms_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        ui.heading("房间");
+
+        let can_accept = self.dragging.is_some();
+
+        ui.selectable_value(&mut self.room_filter, RoomFilter::All, "全部设备");
+
+        let response = drop_target(ui, can_accept, |ui| {
+            ui.selectable_value(&mut self.room_filter, RoomFilter::Unassigned, "未分配");
+        })
+        .response;
+        if dropped(ui, &response) {
+            if let Some(id) = self.dragging.take() {
+                let old = app_data.devices.get(&id).and_then(|d| d.room.clone());
+                app_data.devices.assign_room(&id, None);
+                app_data.audit_config(format!("{}.room", id), old, Some("未分配".to_string()));
+            }
+        }
+
+        let mut remove_room = None;
+        for room in app_data.devices.rooms().to_vec() {
+            let response = drop_target(ui, can_accept, |ui| {
+                ui.horizontal(|ui| {
+                    let count = app_data
+                        .devices
+                        .devices()
+                        .filter(|d| d.room.as_ref() == Some(&room))
+                        .count();
+                    ui.selectable_value(
+                        &mut self.room_filter,
+                        RoomFilter::Room(room.clone()),
+                        format!("{} ({})", room, count),
+                    );
+                    if ui.small_button("🗑").on_hover_text("删除房间").clicked() {
+                        remove_room = Some(room.clone());
+                    }
+                });
+            })
+            .response;
+            if dropped(ui, &response) {
+                if let Some(id) = self.dragging.take() {
+                    let old = app_data.devices.get(&id).and_then(|d| d.room.clone());
+                    app_data.devices.assign_room(&id, Some(&room));
+                    app_data.audit_config(format!("{}.room", id), old, Some(room.clone()));
+                }
+            }
+        }
+        if let Some(room) = remove_room {
+            if self.room_filter == RoomFilter::Room(room.clone()) {
+                self.room_filter = RoomFilter::All;
+            }
+            app_data.devices.remove_room(&room);
+            app_data.audit_config("rooms", Some(room), None);
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_room).desired_width(100.0));
+            if ui.button("添加房间").clicked() {
+                if app_data.devices.add_room(&self.new_room) {
+                    let room = self.new_room.trim().to_string();
+                    app_data.audit_config("rooms", None, Some(room));
+                    self.new_room.clear();
+                } else {
+                    self.error = Some(format!("房间 \"{}\" 无效或已存在", self.new_room));
+                }
+            }
+        });
+    }
+
+    /// 分组列表, 设备可以拖拽到分组上, 分组命令会发送给所有成员
+    fn groups_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        ui.heading("分组");
+
+        let can_accept = self.dragging.is_some();
+        let mut remove_group = None;
+        let mut command = None;
+
+        let groups = app_data.devices.groups().to_vec();
+        for group in groups.iter() {
+            let state = app_data.devices.group_state(&group.name);
+            let response = drop_target(ui, can_accept, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong(&group.name);
+                    ui.colored_label(group_state_color(state), state.label());
+                });
+                ui.horizontal(|ui| {
+                    if ui.small_button("全开").clicked() {
+                        command = Some((group.name.clone(), DeviceCommand::Power(true)));
+                    }
+                    if ui.small_button("全关").clicked() {
+                        command = Some((group.name.clone(), DeviceCommand::Power(false)));
+                    }
+                    if ui.small_button("🗑").on_hover_text("删除分组").clicked() {
+                        remove_group = Some(group.name.clone());
+                    }
+                });
+                ui.collapsing(format!("成员 ({})", group.members.len()), |ui| {
+                    for member in group.members.iter() {
+                        ui.horizontal(|ui| {
+                            let name = app_data
+                                .devices
+                                .get(member)
+                                .map(|d| d.name.clone())
+                                .unwrap_or_else(|| member.clone());
+                            ui.label(name);
+                            if ui.small_button("✖").on_hover_text("移出分组").clicked() {
+                                app_data.devices.remove_from_group(&group.name, member);
+                                app_data.audit_config(
+                                    format!("groups.{}", group.name),
+                                    Some(member.clone()),
+                                    None,
+                                );
+                            }
+                        });
+                    }
+                });
+            })
+            .response;
+            if dropped(ui, &response) {
+                if let Some(id) = self.dragging.take() {
+                    app_data.devices.add_to_group(&group.name, &id);
+                    app_data.audit_config(format!("groups.{}", group.name), None, Some(id));
+                }
+            }
+        }
+
+        if let Some((group, command)) = command {
+            let failed = app_data.send_group_command(&group, &command, Some(self.queue_ttl));
+            if !failed.is_empty() {
+                self.error = Some(format!("以下设备命令发送失败: {}", failed.join(", ")));
+            }
+        }
+        if let Some(group) = remove_group {
+            app_data.devices.remove_group(&group);
+            app_data.audit_config("groups", Some(group), None);
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_group).desired_width(100.0));
+            if ui.button("添加分组").clicked() {
+                if app_data.devices.add_group(&self.new_group) {
+                    let group = self.new_group.trim().to_string();
+                    app_data.audit_config("groups", None, Some(group));
+                    self.new_group.clear();
+                } else {
+                    self.error = Some(format!("分组 \"{}\" 无效或已存在", self.new_group));
+                }
+            }
+        });
+    }
+
+    /// 设备列表
+    fn devices_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let title = match &self.room_filter {
+            RoomFilter::All => "全部设备".to_string(),
+            RoomFilter::Unassigned => "未分配房间的设备".to_string(),
+            RoomFilter::Room(room) => room.clone(),
+        };
+        ui.heading(title);
+        ui.horizontal(|ui| {
+            ui.label("拖动设备到左侧的房间或分组");
+            ui.separator();
+            ui.label("离线命令有效期");
+            ui.add(
+                egui::DragValue::new(&mut self.queue_ttl)
+                    .clamp_range(10..=7 * 24 * 3600)
+                    .suffix(" 秒"),
+            );
+        });
+        ui.separator();
+
+        let devices: Vec<Device> = app_data
+            .devices
+            .filtered(&self.room_filter)
+            .cloned()
+            .collect();
+        if devices.is_empty() {
+            ui.label("没有设备");
+            return;
+        }
+
+        let mut command = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("device_list")
+                .num_columns(7)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("名称");
+                    ui.strong("类型");
+                    ui.strong("房间");
+                    ui.strong("状态");
+                    ui.strong("开关");
+                    ui.strong("同步");
+                    ui.strong("上报数据");
+                    ui.end_row();
+
+                    for device in devices.iter() {
+                        let drag_id = Id::new("device_drag").with(&device.id);
+                        let is_dragging = drag_source(ui, drag_id, |ui| {
+                            ui.label(format!("☰ {}", device.name))
+                                .on_hover_text(&device.id);
+                        });
+                        if is_dragging {
+                            self.dragging = Some(device.id.clone());
+                        }
+
+                        let mut kind = device.kind;
+                        egui::ComboBox::from_id_source(Id::new("device_kind").with(&device.id))
+                            .selected_text(kind.label())
+                            .show_ui(ui, |ui| {
+                                for k in DeviceKind::ALL {
+                                    ui.selectable_value(&mut kind, k, k.label());
+                                }
+                            });
+                        if kind != device.kind {
+                            if let Some(d) = app_data.devices.get_mut(&device.id) {
+                                d.kind = kind;
+                            }
+                            app_data.audit_config(
+                                format!("{}.kind", device.id),
+                                Some(device.kind.label().to_string()),
+                                Some(kind.label().to_string()),
+                            );
+                        }
+
+                        ui.label(device.room.as_deref().unwrap_or("-"));
+
+                        let queued = app_data
+                            .command_queue
+                            .queue(&CommandTarget::Mqtt(device.id.clone()))
+                            .count();
+                        if device.online {
+                            ui.colored_label(Color32::GREEN, "在线");
+                        } else if queued > 0 {
+                            ui.colored_label(Color32::GRAY, format!("离线 ({}条命令排队)", queued));
+                        } else {
+                            ui.colored_label(Color32::GRAY, "离线");
+                        }
+
+                        if device.switchable() {
+                            let on = device.power().unwrap_or(false);
+                            let text = if on { "关闭" } else { "打开" };
+                            if ui.button(text).clicked() {
+                                command = Some((device.id.clone(), DeviceCommand::Power(!on)));
+                            }
+                        } else {
+                            ui.label("-");
+                        }
+
+                        self.shadow_ui(ui, app_data, &device.id);
+
+                        let fields: Vec<String> = device
+                            .reported
+                            .iter()
+                            .map(|(k, v)| format!("{}: {}", k, v))
+                            .collect();
+                        ui.label(fields.join(", "));
+                        ui.end_row();
+                    }
+                });
+        });
+
+        if let Some((id, command)) = command {
+            if let Err(e) = app_data.send_command(&id, &command, Some(self.queue_ttl)) {
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// 设备影子的同步状态, 显示同步中和未同步的字段
+    fn shadow_ui(&mut self, ui: &mut Ui, app_data: &mut AppData, id: &str) {
+        let out_of_sync = app_data.shadows.out_of_sync(id);
+        let document = app_data
+            .shadows
+            .get(id)
+            .map(|s| serde_json::to_string_pretty(&s.document()).unwrap_or_default())
+            .unwrap_or_default();
+
+        ui.horizontal(|ui| {
+            if out_of_sync.is_empty() {
+                ui.colored_label(Color32::GREEN, "✔")
+                    .on_hover_text(&document);
+                return;
+            }
+            for (field, state) in out_of_sync.iter() {
+                let color = match state {
+                    SyncState::InSync => Color32::GREEN,
+                    SyncState::Pending(_) => Color32::YELLOW,
+                    SyncState::Failed => Color32::RED,
+                };
+                ui.colored_label(color, format!("{} {}", field, state.label()))
+                    .on_hover_text(&document);
+            }
+            if ui
+                .small_button("✖")
+                .on_hover_text("放弃未同步的命令")
+                .clicked()
+            {
+                let delta = app_data
+                    .shadows
+                    .get(id)
+                    .map(|s| serde_json::Value::Object(s.delta()).to_string());
+                app_data.shadows.discard_delta(id);
+                app_data.audit_config(format!("{}.shadow.desired", id), delta, None);
+            }
+        });
+    }
+}
+
+fn group_state_color(state: GroupState) -> Color32 {
+    match state {
+        GroupState::AllOn => Color32::GREEN,
+        GroupState::PartiallyOn => Color32::YELLOW,
+        GroupState::AllOff => Color32::GRAY,
+        GroupState::Unknown => Color32::DARK_GRAY,
+    }
 }
 
 impl BasePage for DevicePage {
@@ -40,39 +386,47 @@ impl BasePage for DevicePage {
         self.status_bar.draw(ctx, frame);
     }
 
-    fn content(&mut self, ctx: &egui::Context, frame: &epi::Frame) -> PageAction {
-        let mut res = PageAction::None;
-        egui::CentralPanel::default().show(ctx, |ui| {
-            tracing::debug!(
-                "ui.available_rect_before_wrap(): {:?}",
-                ui.available_rect_before_wrap()
-            );
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Devices {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
 
-            ui.label("mqtt设备");
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
 
-            ui.horizontal(|ui| {
-                if ui.button("弹出窗口").clicked() {
-                    self.open_test_window = true;
-                }
+        egui::SidePanel::left("device_page_rooms")
+            .resizable(true)
+            .default_width(200.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.rooms_ui(ui, &mut app_data);
+                    ui.separator();
+                    self.groups_ui(ui, &mut app_data);
+                });
+            });
 
-                if self.open_test_window {
-                    egui::Window::new("测试").show(ui.ctx(), |ui| {
-                        ui.label("hello");
-                        if ui.button("关闭窗口").clicked() {
-                            self.open_test_window = false;
-                        }
-                    });
-                    if !self.open_test_window {
-                        res = PageAction::CloseWindow;
-                        return;
-                    }
-                    res = PageAction::OpenWindow(false);
-                    return;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if let Some(error) = &self.error {
+                let mut close = false;
+                ui.horizontal(|ui| {
+                    ui.colored_label(Color32::RED, error);
+                    close = ui.small_button("✖").clicked();
+                });
+                if close {
+                    self.error = None;
                 }
-            });
+            }
+            self.devices_ui(ui, &mut app_data);
         });
 
-        res
+        if !ctx.memory().is_anything_being_dragged() {
+            self.dragging = None;
+        }
+
+        PageAction::None
     }
 
     fn set_id(&mut self, id: usize) {
@@ -80,7 +434,7 @@ impl BasePage for DevicePage {
     }
 
     fn get_id(&self) -> usize {
-        todo!()
+        self.id
     }
 
     fn set_pid(&mut self, pid: usize) {
@@ -92,18 +446,36 @@ impl BasePage for DevicePage {
     }
 }
 
-#[derive(Default, Clone)]
-struct DeviceListBar {}
+struct DeviceListBar {
+    app_data: Arc<RwLock<AppData>>,
+}
+
+impl DeviceListBar {
+    fn new(app_data: Arc<RwLock<AppData>>) -> Self {
+        Self { app_data }
+    }
+}
 
 impl StatusBar for DeviceListBar {
-    fn draw(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
-        // .min_height(25.0)
+    fn draw(&mut self, ctx: &egui::Context, _frame: &epi::Frame) {
+        let app_data = self.app_data.read();
         egui::TopBottomPanel::bottom("device_page_status_bar").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                ui.label("状态栏");
-                if ui.button("点击").clicked() {
-                    ui.label("点击了");
+                let client = &app_data.mqtt_client;
+                if client.is_connected() {
+                    ui.colored_label(Color32::GREEN, "●");
+                } else {
+                    ui.colored_label(Color32::RED, "●");
                 }
+                ui.label(format!(
+                    "mqtt: {}:{}",
+                    client.config().host,
+                    client.config().port
+                ));
+                ui.separator();
+                let total = app_data.devices.devices().count();
+                let online = app_data.devices.devices().filter(|d| d.online).count();
+                ui.label(RichText::new(format!("设备: {} / 在线: {}", total, online)));
             });
         });
     }
@@ -0,0 +1,80 @@
+use epi::egui::{
+    self, epaint, CursorIcon, Id, InnerResponse, LayerId, Order, Rect, Sense, Shape, Ui, Vec2,
+};
+
+/// 可拖拽的组件, 返回 true 表示正在被拖拽
+pub fn drag_source(ui: &mut Ui, id: Id, body: impl FnOnce(&mut Ui)) -> bool {
+    let is_being_dragged = ui.memory().is_being_dragged(id);
+
+    if !is_being_dragged {
+        let response = ui.scope(body).response;
+        let response = ui.interact(response.rect, id, Sense::drag());
+        if response.hovered() {
+            ui.output().cursor_icon = CursorIcon::Grab;
+        }
+    } else {
+        ui.output().cursor_icon = CursorIcon::Grabbing;
+
+        // 拖拽时, 在最上层绘制, 并跟随鼠标移动
+        let layer_id = LayerId::new(Order::Tooltip, id);
+        let response = ui.with_layer_id(layer_id, body).response;
+        if let Some(pointer_pos) = ui.input().pointer.interact_pos() {
+            let delta = pointer_pos - response.rect.center();
+            ui.ctx().translate_layer(layer_id, delta);
+        }
+    }
+
+    is_being_dragged
+}
+
+/// 放置区域, 返回的 Response 配合 dropped 判断是否放下
+pub fn drop_target<R>(
+    ui: &mut Ui,
+    can_accept_what_is_being_dragged: bool,
+    body: impl FnOnce(&mut Ui) -> R,
+) -> InnerResponse<R> {
+    let is_being_dragged = ui.memory().is_anything_being_dragged();
+
+    let margin = Vec2::splat(4.0);
+
+    let outer_rect_bounds = ui.available_rect_before_wrap();
+    let inner_rect = outer_rect_bounds.shrink2(margin);
+    let where_to_put_background = ui.painter().add(Shape::Noop);
+    let mut content_ui = ui.child_ui(inner_rect, *ui.layout());
+    let ret = body(&mut content_ui);
+    let outer_rect = Rect::from_min_max(outer_rect_bounds.min, content_ui.min_rect().max + margin);
+    let (rect, response) = ui.allocate_at_least(outer_rect.size(), Sense::hover());
+
+    let style = if is_being_dragged && can_accept_what_is_being_dragged && response.hovered() {
+        ui.visuals().widgets.active
+    } else {
+        ui.visuals().widgets.inactive
+    };
+
+    let mut fill = style.bg_fill;
+    let mut stroke = style.bg_stroke;
+    if is_being_dragged && !can_accept_what_is_being_dragged {
+        // 不能放置时, 变暗
+        fill = egui::color::tint_color_towards(fill, ui.visuals().window_fill());
+        stroke.color = egui::color::tint_color_towards(stroke.color, ui.visuals().window_fill());
+    }
+
+    ui.painter().set(
+        where_to_put_background,
+        epaint::RectShape {
+            rounding: style.rounding,
+            fill,
+            stroke,
+            rect,
+        },
+    );
+
+    InnerResponse::new(ret, response)
+}
+
+/// 拖拽的组件是否在 response 上被放下
+pub fn dropped(ui: &Ui, response: &egui::Response) -> bool {
+    ui.memory().is_anything_being_dragged()
+        && response.hovered()
+        && ui.input().pointer.any_released()
+}
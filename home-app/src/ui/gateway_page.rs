@@ -0,0 +1,235 @@
+use epi::egui::{self, Color32, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{
+    data::app_data::AppData,
+    service::modbus::mqtt_gateway::{topic_id, MqttGateway, PublishFormat, PublishMode},
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{config_audit::ConfigAudit, navigation::PageKind, titlebar::MainTitlebar};
+
+/// 把 Modbus 从站桥接到内置 broker 的网关, 显示每个从站的轮询统计
+pub struct GatewayPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    error: Option<String>,
+    /// 记录配置的修改
+    config_audit: ConfigAudit,
+}
+
+impl GatewayPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            error: None,
+            config_audit: ConfigAudit::default(),
+        }
+    }
+
+    /// 网关参数, 启动后不能修改
+    fn config_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let running = app_data.gateway.is_some();
+        let names: Vec<String> = app_data
+            .modbus_devices
+            .iter()
+            .map(|d| d.name.clone())
+            .collect();
+        let config = &mut app_data.gateway_config;
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("客户端 id");
+                ui.add(egui::TextEdit::singleline(&mut config.id).desired_width(160.0));
+                ui.label("格式");
+                egui::ComboBox::from_id_source("gateway_format")
+                    .selected_text(config.format.label())
+                    .show_ui(ui, |ui| {
+                        for format in [PublishFormat::Json, PublishFormat::PerField] {
+                            ui.selectable_value(&mut config.format, format, format.label());
+                        }
+                    });
+                ui.label("发布");
+                egui::ComboBox::from_id_source("gateway_mode")
+                    .selected_text(config.mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in [PublishMode::OnChange, PublishMode::Periodic] {
+                            ui.selectable_value(&mut config.mode, mode, mode.label());
+                        }
+                    });
+                if config.mode == PublishMode::Periodic {
+                    ui.add(
+                        egui::DragValue::new(&mut config.publish_interval)
+                            .clamp_range(1..=3600)
+                            .suffix(" s"),
+                    );
+                }
+                ui.checkbox(&mut config.retain, "保留消息");
+            });
+            ui.horizontal_wrapped(|ui| {
+                ui.label("设备");
+                // 未选择时桥接所有设备
+                for name in names.iter() {
+                    let mut checked = config.devices.is_empty() || config.devices.contains(name);
+                    if ui.checkbox(&mut checked, name).changed() {
+                        if config.devices.is_empty() {
+                            config.devices = names.clone();
+                        }
+                        if checked {
+                            config.devices.push(name.clone());
+                        } else {
+                            config.devices.retain(|d| d != name);
+                        }
+                    }
+                }
+            });
+        });
+        ui.checkbox(&mut config.auto_start, "应用启动时自动启动");
+
+        let mut start = false;
+        let mut stop = false;
+        ui.horizontal(|ui| {
+            if running {
+                stop = ui.button("停止").clicked();
+            } else {
+                start = ui.button("启动").clicked();
+            }
+            if let Some(gateway) = &app_data.gateway {
+                let state = gateway.state();
+                if state.connected {
+                    ui.label(format!(
+                        "已连接 broker, 订阅 home/+/set, 在线状态: home/{}/availability",
+                        topic_id(&app_data.gateway_config.id)
+                    ));
+                } else {
+                    ui.colored_label(Color32::RED, "未连接 broker");
+                }
+                for e in state.errors.iter() {
+                    ui.colored_label(Color32::RED, e);
+                }
+            }
+        });
+
+        if start {
+            self.error = app_data.start_gateway().err().map(|e| e.to_string());
+        } else if stop {
+            app_data.gateway = None;
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+    }
+
+    fn stats_ui(&mut self, ui: &mut Ui, gateway: &MqttGateway) {
+        let state = gateway.state();
+        egui::ScrollArea::both().show(ui, |ui| {
+            egui::Grid::new("gateway_stats")
+                .num_columns(11)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("设备");
+                    ui.strong("连接");
+                    ui.strong("状态");
+                    ui.strong("轮询");
+                    ui.strong("失败");
+                    ui.strong("连续失败");
+                    ui.strong("耗时");
+                    ui.strong("最后轮询");
+                    ui.strong("发布");
+                    ui.strong("写入 / 失败");
+                    ui.strong("最后错误");
+                    ui.end_row();
+
+                    for stats in state.slaves.iter() {
+                        ui.label(&stats.device);
+                        ui.label(&stats.label);
+                        if stats.polls == 0 {
+                            ui.label("-");
+                        } else if stats.online {
+                            ui.colored_label(Color32::GREEN, "在线");
+                        } else {
+                            ui.colored_label(Color32::RED, "离线");
+                        }
+                        ui.label(stats.polls.to_string());
+                        ui.label(stats.failures.to_string());
+                        ui.label(stats.consecutive_failures.to_string());
+                        ui.label(stats.latency.map_or_else(String::new, |l| {
+                            format!("{:.1} ms", l.as_secs_f64() * 1000.0)
+                        }));
+                        ui.label(
+                            stats
+                                .last_poll
+                                .map_or_else(String::new, |t| t.format("%H:%M:%S").to_string()),
+                        );
+                        ui.label(stats.publishes.to_string());
+                        ui.label(format!("{} / {}", stats.writes, stats.write_failures));
+                        ui.label(stats.last_error.as_deref().unwrap_or(""));
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+impl BasePage for GatewayPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Gateway {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::TopBottomPanel::top("gateway_config").show(ctx, |ui| {
+            ui.heading("Modbus MQTT 网关");
+            self.config_ui(ui, &mut app_data);
+            ui.add_space(4.0);
+        });
+
+        if let Some((old, new)) = self.config_audit.changes(ctx, &app_data.gateway_config) {
+            app_data.audit_config("mqtt_gateway", Some(old), Some(new));
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| match &app_data.gateway {
+            Some(gateway) => self.stats_ui(ui, gateway),
+            None => {
+                ui.label("未启动");
+            }
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -0,0 +1,277 @@
+use chrono::Local;
+use epi::egui::{self, Color32, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{
+    data::{
+        app_data::AppData,
+        esp_image::{EspImage, FirmwareFile},
+        storage::persistence::app_dir,
+    },
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{navigation::PageKind, titlebar::MainTitlebar};
+
+/// 查看 ESP-IDF 应用镜像或合并镜像的内容, 推送 OTA 前确认版本
+pub struct ImagePage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    path: String,
+    file: Option<FirmwareFile>,
+    message: Option<Result<String, String>>,
+}
+
+impl ImagePage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            path: String::new(),
+            file: None,
+            message: None,
+        }
+    }
+
+    fn open_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.path)
+                    .hint_text("build/<项目>.bin 或合并的 flash 镜像")
+                    .desired_width(360.0),
+            );
+            if ui.button("打开").clicked() {
+                match FirmwareFile::open(self.path.trim()) {
+                    Ok(file) => {
+                        self.file = Some(file);
+                        self.message = None;
+                    }
+                    Err(e) => {
+                        self.file = None;
+                        self.message = Some(Err(e.to_string()));
+                    }
+                }
+            }
+            if let Some(file) = &self.file {
+                if ui.button("导出 json").clicked() {
+                    let path = app_dir().join(format!(
+                        "image-{}.json",
+                        Local::now().format("%Y%m%d-%H%M%S")
+                    ));
+                    self.message = Some(
+                        file.export_json(&path)
+                            .map(|_| format!("已导出到 {}", path.display()))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+            }
+            match &self.message {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(Color32::RED, e);
+                }
+                None => {}
+            }
+        });
+    }
+
+    fn file_ui(&mut self, ui: &mut Ui) {
+        let file = match &self.file {
+            Some(file) => file,
+            None => {
+                ui.label("未打开");
+                return;
+            }
+        };
+        ui.label(format!(
+            "{}: {} 字节, {}, {} 个镜像",
+            file.path,
+            file.file_size,
+            if file.merged {
+                "合并镜像"
+            } else {
+                "应用镜像"
+            },
+            file.images.len()
+        ));
+        for error in file.errors.iter() {
+            ui.colored_label(Color32::RED, error);
+        }
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                for image in file.images.iter() {
+                    let title = match (&image.app, image.name.as_str()) {
+                        (Some(app), "") => format!("{} {}", app.project_name, app.version),
+                        (Some(app), name) => {
+                            format!("{}: {} {}", name, app.project_name, app.version)
+                        }
+                        (None, "") => "镜像".to_string(),
+                        (None, name) => name.to_string(),
+                    };
+                    let title = if image.is_valid() {
+                        title
+                    } else {
+                        format!("{} (校验失败)", title)
+                    };
+                    egui::CollapsingHeader::new(format!("0x{:x} {}", image.offset, title))
+                        .id_source(image.offset)
+                        .default_open(true)
+                        .show(ui, |ui| image_ui(ui, image));
+                }
+            });
+    }
+}
+
+fn check_label(ui: &mut Ui, valid: bool, text: &str) {
+    if valid {
+        ui.colored_label(Color32::GREEN, format!("{} 正确", text));
+    } else {
+        ui.colored_label(Color32::RED, format!("{} 错误", text));
+    }
+}
+
+fn image_ui(ui: &mut Ui, image: &EspImage) {
+    egui::Grid::new(("image_header", image.offset))
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("芯片");
+            ui.label(format!(
+                "{} (id {}), 版本 {} - {}",
+                image.chip, image.chip_id, image.min_chip_revision, image.max_chip_revision
+            ));
+            ui.end_row();
+            ui.label("flash");
+            ui.label(format!(
+                "{} {} {}",
+                image.flash_mode, image.flash_size, image.flash_frequency
+            ));
+            ui.end_row();
+            ui.label("入口地址");
+            ui.monospace(format!("0x{:08x}", image.entry_point));
+            ui.end_row();
+            ui.label("长度");
+            ui.label(format!("{} 字节", image.size));
+            ui.end_row();
+            ui.label("校验");
+            ui.horizontal(|ui| {
+                check_label(
+                    ui,
+                    image.checksum_valid,
+                    &format!("校验和 0x{:02x}", image.checksum),
+                );
+                match image.hash_valid {
+                    Some(valid) => check_label(ui, valid, "SHA256"),
+                    None => {
+                        ui.label("没有附加 SHA256");
+                    }
+                }
+            });
+            ui.end_row();
+            if let Some(hash) = &image.hash {
+                ui.label("SHA256");
+                ui.monospace(hash);
+                ui.end_row();
+            }
+            if let Some(app) = &image.app {
+                ui.label("项目");
+                ui.label(&app.project_name);
+                ui.end_row();
+                ui.label("版本");
+                ui.label(&app.version);
+                ui.end_row();
+                ui.label("ESP-IDF");
+                ui.label(&app.idf_version);
+                ui.end_row();
+                ui.label("编译时间");
+                ui.label(&app.compile_time);
+                ui.end_row();
+                ui.label("安全版本");
+                ui.label(app.secure_version.to_string());
+                ui.end_row();
+                ui.label("ELF SHA256");
+                ui.monospace(&app.elf_sha256);
+                ui.end_row();
+            }
+        });
+
+    ui.add_space(4.0);
+    egui::Grid::new(("image_segments", image.offset))
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("段");
+            ui.strong("加载地址");
+            ui.strong("长度");
+            ui.strong("文件位置");
+            ui.strong("区域");
+            ui.end_row();
+            for (i, segment) in image.segments.iter().enumerate() {
+                ui.label(i.to_string());
+                ui.monospace(format!("0x{:08x}", segment.load_address));
+                ui.monospace(format!("0x{:x} ({})", segment.length, segment.length));
+                ui.monospace(format!("0x{:08x}", segment.file_offset));
+                ui.label(if segment.region.is_empty() {
+                    "-"
+                } else {
+                    segment.region
+                });
+                ui.end_row();
+            }
+        });
+    ui.add_space(8.0);
+}
+
+impl BasePage for ImagePage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Image {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        egui::TopBottomPanel::top("image_open").show(ctx, |ui| {
+            ui.heading("ESP32 固件镜像");
+            self.open_ui(ui);
+            ui.add_space(4.0);
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.file_ui(ui);
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
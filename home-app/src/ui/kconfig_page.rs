@@ -0,0 +1,457 @@
+use chrono::Local;
+use epi::egui::{self, Color32, RichText, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    data::{
+        app_data::AppData,
+        kconfig::{format_number, parse_number, Issue, Kconfig, Node, Symbol, SymbolType},
+        sdkconfig::{Sdkconfig, CONFIG_PREFIX},
+        storage::persistence::app_dir,
+    },
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{navigation::PageKind, titlebar::MainTitlebar};
+
+const KCONFIG_FILE: &str = "main/Kconfig.projbuild";
+const DEFAULTS_FILE: &str = "sdkconfig.defaults";
+
+/// 按 Kconfig.projbuild 编辑 sdkconfig.defaults, 不需要 menuconfig
+pub struct KconfigPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    /// ESP-IDF 项目目录
+    project: String,
+    kconfig: Kconfig,
+    config: Sdkconfig,
+    message: Option<Result<String, String>>,
+}
+
+impl KconfigPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            project: ".".to_string(),
+            kconfig: Kconfig::default(),
+            config: Sdkconfig::default(),
+            message: None,
+        }
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        PathBuf::from(self.project.trim()).join(file)
+    }
+
+    /// 读取 Kconfig.projbuild, 没有 sdkconfig.defaults 时从空文件开始
+    fn open(&mut self) -> Result<String, String> {
+        let kconfig = Kconfig::load(self.path(KCONFIG_FILE))
+            .map_err(|e| format!("{}: {}", KCONFIG_FILE, e))?;
+        let defaults = self.path(DEFAULTS_FILE);
+        let config = if defaults.exists() {
+            Sdkconfig::load(&defaults).map_err(|e| format!("{}: {}", DEFAULTS_FILE, e))?
+        } else {
+            Sdkconfig::default()
+        };
+        let message = format!(
+            "{} 个选项, {} 中设置了 {} 项",
+            kconfig.symbols.len(),
+            DEFAULTS_FILE,
+            config.names().count()
+        );
+        self.kconfig = kconfig;
+        self.config = config;
+        Ok(message)
+    }
+
+    fn file_ui(&mut self, ui: &mut Ui, issues: &[Issue]) {
+        ui.horizontal(|ui| {
+            ui.label("项目目录");
+            ui.add(egui::TextEdit::singleline(&mut self.project).desired_width(280.0));
+            if ui
+                .button("打开")
+                .on_hover_text(format!("读取 {} 和 {}", KCONFIG_FILE, DEFAULTS_FILE))
+                .clicked()
+            {
+                self.message = Some(self.open());
+            }
+            let has_error = issues.iter().any(|issue| issue.error);
+            let save = ui
+                .add_enabled(!has_error, egui::Button::new("保存"))
+                .on_hover_text(format!("写回 {}", DEFAULTS_FILE))
+                .on_disabled_hover_text("有错误时不能保存");
+            if save.clicked() {
+                let path = self.path(DEFAULTS_FILE);
+                self.message = Some(
+                    self.config
+                        .save(&path)
+                        .map(|_| format!("已保存到 {}", path.display()))
+                        .map_err(|e| e.to_string()),
+                );
+            }
+            let export = ui
+                .add_enabled(!has_error, egui::Button::new("导出"))
+                .on_hover_text("另存一份, 用于其它现场的编译");
+            if export.clicked() {
+                let path = app_dir().join(format!(
+                    "sdkconfig-{}.defaults",
+                    Local::now().format("%Y%m%d-%H%M%S")
+                ));
+                self.message = Some(
+                    self.config
+                        .save(&path)
+                        .map(|_| format!("已导出到 {}", path.display()))
+                        .map_err(|e| e.to_string()),
+                );
+            }
+        });
+
+        match &self.message {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(Color32::RED, e);
+            }
+            None => {}
+        }
+    }
+
+    /// Kconfig 中没有的设置, 例如 ESP-IDF 组件的选项, 原样编辑
+    fn others_ui(&mut self, ui: &mut Ui) {
+        let names: Vec<String> = self
+            .config
+            .names()
+            .filter(|name| self.kconfig.symbol(name).is_none())
+            .map(|name| name.to_string())
+            .collect();
+        if names.is_empty() {
+            return;
+        }
+        egui::CollapsingHeader::new("其它设置")
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::Grid::new("kconfig_others").show(ui, |ui| {
+                    for name in names {
+                        ui.label(format!("{}{}", CONFIG_PREFIX, name));
+                        let mut value = self.config.get_raw(&name).unwrap_or_default();
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut value).desired_width(160.0))
+                            .changed()
+                        {
+                            self.config.set_raw(&name, value.trim());
+                        }
+                        if ui.small_button("✖").on_hover_text("删除").clicked() {
+                            self.config.remove(&name);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    fn issues_ui(&self, ui: &mut Ui, issues: &[Issue]) {
+        for warning in self.kconfig.warnings.iter() {
+            ui.colored_label(Color32::YELLOW, warning);
+        }
+        if issues.is_empty() {
+            ui.colored_label(Color32::GREEN, "检查通过");
+        }
+        for issue in issues {
+            let color = if issue.error {
+                Color32::RED
+            } else {
+                Color32::YELLOW
+            };
+            ui.colored_label(color, &issue.message);
+        }
+    }
+}
+
+/// 菜单树的表单, 借用页面中的 Kconfig 和正在编辑的文件
+struct Form<'a> {
+    kconfig: &'a Kconfig,
+    config: &'a mut Sdkconfig,
+    issues: &'a [Issue],
+}
+
+impl<'a> Form<'a> {
+    fn nodes_ui(&mut self, ui: &mut Ui, nodes: &[Node]) {
+        let kconfig = self.kconfig;
+        for (i, node) in nodes.iter().enumerate() {
+            match node {
+                Node::Menu {
+                    title,
+                    depends,
+                    children,
+                } => {
+                    let active = kconfig.is_active(depends, self.config);
+                    let header = if active {
+                        RichText::new(title)
+                    } else {
+                        RichText::new(title).weak()
+                    };
+                    egui::CollapsingHeader::new(header)
+                        .id_source((title, i))
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            if let Some(depends) = depends {
+                                depends_label(ui, &depends.to_string(), active);
+                            }
+                            self.nodes_ui(ui, children);
+                        });
+                }
+                Node::Symbol(index) => self.symbol_ui(ui, &kconfig.symbols[*index]),
+                Node::Choice(index) => self.choice_ui(ui, *index),
+                Node::Comment(text) => {
+                    ui.weak(text);
+                }
+            }
+        }
+    }
+
+    fn symbol_ui(&mut self, ui: &mut Ui, symbol: &Symbol) {
+        let name = symbol.name.as_str();
+        let active = self.kconfig.is_active(&symbol.depends, self.config);
+        let has_error = self
+            .issues
+            .iter()
+            .any(|issue| issue.error && issue.symbol == name);
+        ui.horizontal(|ui| {
+            let label = RichText::new(symbol.prompt.as_deref().unwrap_or(name));
+            let label = match (has_error, active) {
+                (true, _) => label.color(Color32::RED),
+                (false, false) => label.weak(),
+                _ => label,
+            };
+            let mut hover = format!("{}{}", CONFIG_PREFIX, name);
+            if !symbol.help.is_empty() {
+                hover = format!("{}\n\n{}", hover, symbol.help);
+            }
+            ui.label(label).on_hover_text(hover);
+
+            // 没有提示的选项只显示计算出的值
+            let editable = active && symbol.prompt.is_some();
+            ui.add_enabled_ui(editable, |ui| self.value_ui(ui, symbol));
+
+            if let Some((min, max)) = self.kconfig.range(symbol, self.config) {
+                let text = format!(
+                    "范围 {} - {}",
+                    format_number(symbol.kind, min),
+                    format_number(symbol.kind, max)
+                );
+                if has_error {
+                    ui.colored_label(Color32::RED, text);
+                } else {
+                    ui.weak(text);
+                }
+            }
+            if self.config.contains(name) {
+                if ui
+                    .small_button("↺")
+                    .on_hover_text(format!(
+                        "删除设置, 恢复默认值 {}",
+                        self.kconfig.default_of(symbol, self.config)
+                    ))
+                    .clicked()
+                {
+                    self.config.remove(name);
+                }
+            } else if editable {
+                ui.weak("默认");
+            }
+            if let Some(depends) = &symbol.depends {
+                depends_label(ui, &depends.to_string(), active);
+            }
+        });
+    }
+
+    fn value_ui(&mut self, ui: &mut Ui, symbol: &Symbol) {
+        let name = symbol.name.as_str();
+        match symbol.kind {
+            SymbolType::Bool => {
+                let mut checked = self.kconfig.value(name, self.config) == "y";
+                if ui.checkbox(&mut checked, "").changed() {
+                    self.config.set_raw(name, if checked { "y" } else { "n" });
+                }
+            }
+            SymbolType::Int | SymbolType::Hex => {
+                let mut text = self
+                    .config
+                    .get_raw(name)
+                    .filter(|_| symbol.prompt.is_some())
+                    .unwrap_or_else(|| self.kconfig.value(name, self.config));
+                let valid = text.is_empty() || parse_number(symbol.kind, &text).is_some();
+                let mut edit = egui::TextEdit::singleline(&mut text).desired_width(100.0);
+                if !valid {
+                    edit = edit.text_color(Color32::RED);
+                }
+                if ui.add(edit).changed() {
+                    self.config.set_raw(name, text.trim());
+                }
+            }
+            SymbolType::String => {
+                let mut text = self.kconfig.value(name, self.config);
+                if ui
+                    .add(egui::TextEdit::singleline(&mut text).desired_width(200.0))
+                    .changed()
+                {
+                    self.config.set_string(name, &text);
+                }
+            }
+        }
+    }
+
+    /// choice 用下拉框选择, 文件中只写入选中的选项
+    fn choice_ui(&mut self, ui: &mut Ui, index: usize) {
+        let kconfig = self.kconfig;
+        let choice = &kconfig.choices[index];
+        let active = kconfig.is_active(&choice.depends, self.config);
+        let selected = kconfig.selected(index, self.config);
+        let prompt = |name: &str| {
+            kconfig
+                .symbol(name)
+                .and_then(|s| s.prompt.clone())
+                .unwrap_or_else(|| name.to_string())
+        };
+        ui.horizontal(|ui| {
+            let label = if active {
+                RichText::new(&choice.prompt)
+            } else {
+                RichText::new(&choice.prompt).weak()
+            };
+            let label = ui.label(label);
+            if !choice.help.is_empty() {
+                label.on_hover_text(&choice.help);
+            }
+            let mut choose = None;
+            ui.add_enabled_ui(active, |ui| {
+                egui::ComboBox::from_id_source(("kconfig_choice", index))
+                    .selected_text(selected.as_deref().map(prompt).unwrap_or_default())
+                    .show_ui(ui, |ui| {
+                        for option in choice.options.iter() {
+                            let available = kconfig
+                                .symbol(option)
+                                .map_or(false, |s| kconfig.is_active(&s.depends, self.config));
+                            let response = ui.add_enabled(
+                                available,
+                                egui::SelectableLabel::new(
+                                    selected.as_ref() == Some(option),
+                                    prompt(option),
+                                ),
+                            );
+                            if response.on_hover_text(option).clicked() {
+                                choose = Some(option);
+                            }
+                        }
+                    });
+            });
+            if let Some(option) = choose {
+                for other in choice.options.iter() {
+                    self.config.remove(other);
+                }
+                self.config.set_raw(option, "y");
+            }
+            if choice.options.iter().any(|o| self.config.contains(o)) {
+                if ui.small_button("↺").on_hover_text("恢复默认选择").clicked() {
+                    for option in choice.options.iter() {
+                        self.config.remove(option);
+                    }
+                }
+            } else if active {
+                ui.weak("默认");
+            }
+            if let Some(depends) = &choice.depends {
+                depends_label(ui, &depends.to_string(), active);
+            }
+        });
+    }
+}
+
+/// 依赖满足时为灰色, 不满足时为红色
+fn depends_label(ui: &mut Ui, depends: &str, met: bool) {
+    let text = RichText::new(format!("依赖 {}", depends)).small();
+    if met {
+        ui.label(text.weak());
+    } else {
+        ui.label(text.color(Color32::RED))
+            .on_hover_text("依赖不满足, 设置不会生效");
+    }
+}
+
+impl BasePage for KconfigPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Kconfig {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let issues = self.kconfig.validate(&self.config);
+        egui::TopBottomPanel::top("kconfig_file").show(ctx, |ui| {
+            ui.heading("ESP32 sdkconfig");
+            self.file_ui(ui, &issues);
+            ui.add_space(4.0);
+        });
+
+        egui::TopBottomPanel::bottom("kconfig_issues")
+            .resizable(true)
+            .default_height(120.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| self.issues_ui(ui, &issues));
+            });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .show(ui, |ui| {
+                    if self.kconfig.nodes.is_empty() {
+                        ui.weak(format!("打开项目目录读取 {}", KCONFIG_FILE));
+                    }
+                    let mut form = Form {
+                        kconfig: &self.kconfig,
+                        config: &mut self.config,
+                        issues: &issues,
+                    };
+                    form.nodes_ui(ui, &self.kconfig.nodes);
+                    self.others_ui(ui);
+                });
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -1,5 +1,25 @@
+pub mod audit_page;
+pub mod bus_page;
+pub mod config_audit;
 pub mod device_page;
+pub mod dnd;
 pub mod error;
+pub mod gateway_page;
+pub mod image_page;
+pub mod kconfig_page;
+pub mod modbus_widgets;
+pub mod monitor_page;
+pub mod navigation;
+pub mod notification_center;
+pub mod partition_page;
+pub mod queue_page;
+pub mod recipe_page;
+pub mod register_page;
+pub mod scan_page;
+pub mod simulator_page;
+pub mod sniffer_page;
+pub mod tcp_gateway_page;
 pub mod titlebar;
+pub mod trend_page;
 // pub mod titlebar_ui;
 pub mod ui_state;
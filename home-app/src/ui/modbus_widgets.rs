@@ -0,0 +1,201 @@
+use epi::egui::{self, Ui};
+
+use crate::{
+    data::register_map::Order,
+    service::modbus::{
+        rtu::{available_ports, Parity, SerialConfig, SerialMode, StopBits},
+        slave::SlaveListen,
+        tcp::TcpConfig,
+        TransportConfig,
+    },
+};
+
+/// 常用的波特率
+pub const BAUD_RATES: [u32; 8] = [2400, 4800, 9600, 19200, 38400, 57600, 115200, 230400];
+
+/// 寄存器原始值, 如 "851F 3FAB"
+pub fn hex_words(raw: &[u16]) -> String {
+    raw.iter()
+        .map(|r| format!("{:04X}", r))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 选择多寄存器数值的字节顺序, 返回是否修改
+pub fn order_combo(ui: &mut Ui, id: &str, order: &mut Order) -> bool {
+    let mut changed = false;
+    egui::ComboBox::from_id_source(id)
+        .selected_text(order.label())
+        .show_ui(ui, |ui| {
+            for value in Order::ALL {
+                changed |= ui.selectable_value(order, value, value.label()).changed();
+            }
+        });
+    changed
+}
+
+/// 编辑连接方式, 返回是否修改
+pub fn transport_ui(ui: &mut Ui, id: &str, config: &mut TransportConfig) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        let kinds = [
+            ("串口", TransportConfig::Rtu(SerialConfig::default())),
+            ("Modbus TCP", TransportConfig::Tcp(TcpConfig::default())),
+            (
+                "RTU over TCP",
+                TransportConfig::RtuOverTcp(TcpConfig::default()),
+            ),
+        ];
+        let current = std::mem::discriminant(config);
+        egui::ComboBox::from_id_source(format!("{}_kind", id))
+            .selected_text(
+                kinds
+                    .iter()
+                    .find(|(_, k)| std::mem::discriminant(k) == current)
+                    .map_or("", |(label, _)| label),
+            )
+            .show_ui(ui, |ui| {
+                for (label, kind) in kinds {
+                    let selected = std::mem::discriminant(&kind) == current;
+                    if ui.selectable_label(selected, label).clicked() && !selected {
+                        *config = kind;
+                        changed = true;
+                    }
+                }
+            });
+
+        match config {
+            TransportConfig::Rtu(serial) => changed |= serial_ui(ui, id, serial),
+            TransportConfig::Tcp(tcp) | TransportConfig::RtuOverTcp(tcp) => {
+                changed |= tcp_ui(ui, tcp)
+            }
+        }
+    });
+    changed
+}
+
+/// 编辑模拟从站的监听方式, 返回是否修改
+pub fn listen_ui(ui: &mut Ui, id: &str, listen: &mut SlaveListen) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        let kinds = [
+            ("虚拟串口", SlaveListen::Pty),
+            ("虚拟串口 ASCII", SlaveListen::PtyAscii),
+            ("串口", SlaveListen::Serial(SerialConfig::default())),
+            ("Modbus TCP", SlaveListen::Tcp { port: 5020 }),
+        ];
+        let current = std::mem::discriminant(listen);
+        egui::ComboBox::from_id_source(format!("{}_kind", id))
+            .selected_text(
+                kinds
+                    .iter()
+                    .find(|(_, k)| std::mem::discriminant(k) == current)
+                    .map_or("", |(label, _)| label),
+            )
+            .show_ui(ui, |ui| {
+                for (label, kind) in kinds {
+                    let selected = std::mem::discriminant(&kind) == current;
+                    if ui.selectable_label(selected, label).clicked() && !selected {
+                        *listen = kind;
+                        changed = true;
+                    }
+                }
+            });
+
+        match listen {
+            SlaveListen::Pty | SlaveListen::PtyAscii => {}
+            SlaveListen::Serial(serial) => changed |= serial_ui(ui, id, serial),
+            SlaveListen::Tcp { port } => {
+                ui.label("端口");
+                changed |= ui.add(egui::DragValue::new(port)).changed();
+            }
+        }
+    });
+    changed
+}
+
+/// 编辑串口参数
+pub fn serial_ui(ui: &mut Ui, id: &str, config: &mut SerialConfig) -> bool {
+    let mut changed = false;
+    let response = ui.add(egui::TextEdit::singleline(&mut config.port).desired_width(110.0));
+    changed |= response.changed();
+    egui::ComboBox::from_id_source(format!("{}_port", id))
+        .selected_text("选择")
+        .width(50.0)
+        .show_ui(ui, |ui| {
+            for port in available_ports() {
+                if ui.selectable_label(config.port == port, &port).clicked() {
+                    config.port = port;
+                    changed = true;
+                }
+            }
+        });
+
+    egui::ComboBox::from_id_source(format!("{}_baud", id))
+        .selected_text(config.baud_rate.to_string())
+        .show_ui(ui, |ui| {
+            for baud in BAUD_RATES {
+                changed |= ui
+                    .selectable_value(&mut config.baud_rate, baud, baud.to_string())
+                    .changed();
+            }
+        });
+
+    egui::ComboBox::from_id_source(format!("{}_parity", id))
+        .selected_text(parity_label(config.parity))
+        .width(60.0)
+        .show_ui(ui, |ui| {
+            for parity in [Parity::None, Parity::Even, Parity::Odd] {
+                changed |= ui
+                    .selectable_value(&mut config.parity, parity, parity_label(parity))
+                    .changed();
+            }
+        });
+
+    egui::ComboBox::from_id_source(format!("{}_stop", id))
+        .selected_text(stop_bits_label(config.stop_bits))
+        .width(60.0)
+        .show_ui(ui, |ui| {
+            for stop_bits in [StopBits::One, StopBits::Two] {
+                changed |= ui
+                    .selectable_value(&mut config.stop_bits, stop_bits, stop_bits_label(stop_bits))
+                    .changed();
+            }
+        });
+
+    egui::ComboBox::from_id_source(format!("{}_mode", id))
+        .selected_text(config.mode.label())
+        .width(60.0)
+        .show_ui(ui, |ui| {
+            for mode in [SerialMode::Rtu, SerialMode::Ascii] {
+                changed |= ui
+                    .selectable_value(&mut config.mode, mode, mode.label())
+                    .changed();
+            }
+        });
+    changed
+}
+
+fn tcp_ui(ui: &mut Ui, config: &mut TcpConfig) -> bool {
+    let mut changed = ui
+        .add(egui::TextEdit::singleline(&mut config.host).desired_width(120.0))
+        .changed();
+    ui.label(":");
+    changed |= ui.add(egui::DragValue::new(&mut config.port)).changed();
+    changed
+}
+
+pub fn parity_label(parity: Parity) -> &'static str {
+    match parity {
+        Parity::None => "无校验",
+        Parity::Even => "偶校验",
+        Parity::Odd => "奇校验",
+    }
+}
+
+pub fn stop_bits_label(stop_bits: StopBits) -> &'static str {
+    match stop_bits {
+        StopBits::One => "1 停止位",
+        StopBits::Two => "2 停止位",
+    }
+}
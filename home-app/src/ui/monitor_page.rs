@@ -0,0 +1,534 @@
+use chrono::Local;
+use epi::egui::{
+    self,
+    text::{LayoutJob, TextFormat},
+    Color32, Sense, TextStyle, Ui,
+};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use crate::{
+    data::{
+        app_data::AppData,
+        elf_symbols::FirmwareSymbols,
+        idf_log::{address_label, is_return_address, last_panic, LogLevel, LogLine},
+        storage::persistence::app_dir,
+    },
+    service::{modbus::rtu::available_ports, serial_monitor::save_log},
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{config_audit::ConfigAudit, navigation::PageKind, titlebar::MainTitlebar};
+
+/// 74880 是 ESP32 ROM 启动信息的波特率
+const MONITOR_BAUD_RATES: [u32; 6] = [74880, 115200, 230400, 460800, 921600, 2000000];
+
+/// ESP32 串口日志, 代替 idf.py monitor
+pub struct MonitorPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    /// 显示这个级别及更严重的日志
+    level: LogLevel,
+    hidden_tags: BTreeSet<String>,
+    /// 显示没有级别和标签的输出, 如 ROM 启动信息和 panic
+    show_other: bool,
+    query: String,
+    /// 暂停时只显示这一行及之前的行
+    paused: Option<u64>,
+    send_text: String,
+    /// 点击选择的行, 解析它的地址; 没有选择时解析最近一次 panic
+    selected: Option<u64>,
+    message: Option<Result<String, String>>,
+    /// 记录配置的修改
+    config_audit: ConfigAudit,
+}
+
+impl MonitorPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            level: LogLevel::Verbose,
+            hidden_tags: BTreeSet::new(),
+            show_other: true,
+            query: String::new(),
+            paused: None,
+            send_text: String::new(),
+            selected: None,
+            message: None,
+            config_audit: ConfigAudit::default(),
+        }
+    }
+
+    fn config_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let running = app_data.serial_monitor.is_some();
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!running, |ui| {
+                let config = &mut app_data.serial_monitor_config;
+                ui.label("串口");
+                ui.add(egui::TextEdit::singleline(&mut config.port).desired_width(110.0));
+                egui::ComboBox::from_id_source("monitor_port")
+                    .selected_text("选择")
+                    .width(50.0)
+                    .show_ui(ui, |ui| {
+                        for port in available_ports() {
+                            if ui.selectable_label(config.port == port, &port).clicked() {
+                                config.port = port;
+                            }
+                        }
+                    });
+                egui::ComboBox::from_id_source("monitor_baud")
+                    .selected_text(config.baud_rate.to_string())
+                    .show_ui(ui, |ui| {
+                        for baud in MONITOR_BAUD_RATES {
+                            ui.selectable_value(&mut config.baud_rate, baud, baud.to_string());
+                        }
+                    });
+                ui.checkbox(&mut config.reset_on_open, "打开时复位");
+            });
+
+            if running {
+                if ui.button("关闭").clicked() {
+                    app_data.serial_monitor = None;
+                }
+            } else if ui.button("打开").clicked() {
+                self.message = app_data
+                    .start_serial_monitor()
+                    .err()
+                    .map(|e| Err(e.to_string()));
+            }
+            if let Some(monitor) = &app_data.serial_monitor {
+                if ui
+                    .button("复位")
+                    .on_hover_text("通过 RTS 拉低 EN 引脚")
+                    .clicked()
+                {
+                    self.message = monitor.reset().err().map(|e| Err(e.to_string()));
+                }
+                let state = monitor.state();
+                if state.connected {
+                    ui.colored_label(
+                        Color32::GREEN,
+                        format!("{}: {} 字节", monitor.label(), state.bytes),
+                    );
+                } else {
+                    ui.colored_label(
+                        Color32::RED,
+                        state.error.as_deref().unwrap_or("已断开, 等待重新连接"),
+                    );
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("固件 ELF");
+            ui.add(
+                egui::TextEdit::singleline(&mut app_data.serial_monitor_config.elf)
+                    .hint_text("build/<项目>.elf")
+                    .desired_width(320.0),
+            );
+            if ui.button("加载").clicked() {
+                self.message = Some(
+                    app_data
+                        .load_firmware_symbols()
+                        .map(|_| "已加载固件符号".to_string())
+                        .map_err(|e| e.to_string()),
+                );
+            }
+            match &app_data.firmware_symbols {
+                Some(symbols) if symbols.is_outdated() => {
+                    ui.colored_label(Color32::YELLOW, "ELF 已更新, 请重新加载");
+                }
+                Some(symbols) => {
+                    ui.label(format!("已加载 {}", symbols.path().display()));
+                }
+                None => {
+                    ui.label("未加载, 回溯只显示地址");
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let send = ui.add_enabled(
+                running,
+                egui::TextEdit::singleline(&mut self.send_text)
+                    .hint_text("发送到设备, 回车发送")
+                    .desired_width(320.0),
+            );
+            if send.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+                if let Some(monitor) = &app_data.serial_monitor {
+                    match monitor.send(&self.send_text) {
+                        Ok(()) => self.send_text.clear(),
+                        Err(e) => self.message = Some(Err(e.to_string())),
+                    }
+                }
+                send.request_focus();
+            }
+            match &self.message {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(Color32::RED, e);
+                }
+                None => {}
+            }
+        });
+    }
+
+    fn filter_ui(&mut self, ui: &mut Ui, app_data: &AppData) {
+        let monitor = match &app_data.serial_monitor {
+            Some(monitor) => monitor,
+            None => return,
+        };
+        ui.horizontal(|ui| {
+            ui.label("级别");
+            egui::ComboBox::from_id_source("monitor_level")
+                .selected_text(self.level.label())
+                .show_ui(ui, |ui| {
+                    for level in LogLevel::ALL {
+                        ui.selectable_value(&mut self.level, level, level.label());
+                    }
+                });
+            let tags: Vec<String> = monitor.state().tags.iter().cloned().collect();
+            let title = if self.hidden_tags.is_empty() {
+                "标签: 全部".to_string()
+            } else {
+                format!("标签: 隐藏 {}", self.hidden_tags.len())
+            };
+            ui.menu_button(title, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("全部显示").clicked() {
+                        self.hidden_tags.clear();
+                    }
+                    if ui.button("全部隐藏").clicked() {
+                        self.hidden_tags = tags.iter().cloned().collect();
+                    }
+                });
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for tag in tags.iter() {
+                            let mut shown = !self.hidden_tags.contains(tag);
+                            if ui.checkbox(&mut shown, tag).changed() {
+                                if shown {
+                                    self.hidden_tags.remove(tag);
+                                } else {
+                                    self.hidden_tags.insert(tag.clone());
+                                }
+                            }
+                        }
+                    });
+            });
+            ui.checkbox(&mut self.show_other, "其它输出");
+            ui.label("搜索");
+            ui.add(egui::TextEdit::singleline(&mut self.query).desired_width(160.0));
+
+            match self.paused {
+                Some(_) => {
+                    if ui.button("继续").clicked() {
+                        self.paused = None;
+                    }
+                }
+                None => {
+                    if ui.button("暂停").clicked() {
+                        self.paused = monitor.state().lines.back().map(|l| l.index);
+                    }
+                }
+            }
+            if ui.button("清空").clicked() {
+                monitor.state().clear();
+                self.selected = None;
+            }
+            if ui.button("保存").clicked() {
+                let path = app_dir().join(format!(
+                    "monitor-{}.log",
+                    Local::now().format("%Y%m%d-%H%M%S")
+                ));
+                let state = monitor.state();
+                self.message = Some(
+                    save_log(state.lines.iter().filter(|l| self.visible(l)), &path)
+                        .map(|n| format!("已保存 {} 行到 {}", n, path.display()))
+                        .map_err(|e| e.to_string()),
+                );
+            }
+        });
+    }
+
+    fn visible(&self, line: &LogLine) -> bool {
+        if self.paused.map_or(false, |index| line.index > index) {
+            return false;
+        }
+        let shown = match line.level {
+            Some(level) => level <= self.level && !self.hidden_tags.contains(&line.tag),
+            None => self.show_other,
+        };
+        shown && (self.query.is_empty() || line.contains(&self.query))
+    }
+
+    fn lines_ui(&mut self, ui: &mut Ui, app_data: &AppData) {
+        let monitor = match &app_data.serial_monitor {
+            Some(monitor) => monitor,
+            None => {
+                ui.label("未打开");
+                return;
+            }
+        };
+        let state = monitor.state();
+        let rows: Vec<&LogLine> = state.lines.iter().filter(|l| self.visible(l)).collect();
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let mut scroll = egui::ScrollArea::both().auto_shrink([false, false]);
+        if self.paused.is_none() {
+            scroll = scroll.stick_to_bottom();
+        }
+        scroll.show_rows(ui, row_height, rows.len(), |ui, range| {
+            for line in rows.iter().skip(range.start).take(range.len()) {
+                let mut job = line_job(ui, line);
+                if self.selected == Some(line.index) {
+                    for section in job.sections.iter_mut() {
+                        section.format.underline = egui::Stroke::new(1.0, Color32::GRAY);
+                    }
+                }
+                let response = ui.add(egui::Label::new(job).sense(Sense::click()));
+                if !line.addresses.is_empty() && response.clicked() {
+                    self.selected = Some(line.index);
+                }
+            }
+        });
+    }
+
+    /// 解析选择的行或最近一次 panic 中的代码地址
+    fn backtrace_ui(&mut self, ui: &mut Ui, app_data: &AppData) {
+        let monitor = match &app_data.serial_monitor {
+            Some(monitor) => monitor,
+            None => return,
+        };
+        let state = monitor.state();
+        let selected = self
+            .selected
+            .and_then(|index| state.lines.iter().find(|l| l.index == index));
+        let lines = match selected {
+            Some(line) => vec![line],
+            None => last_panic(&state.lines),
+        };
+        ui.horizontal(|ui| {
+            ui.strong("回溯解析");
+            match selected {
+                Some(line) => {
+                    ui.label(format!("第 {} 行", line.index + 1));
+                    if ui.button("最近的 panic").clicked() {
+                        self.selected = None;
+                    }
+                }
+                None if lines.is_empty() => {
+                    ui.label("没有 panic, 点击带有地址的行进行解析");
+                }
+                None => {
+                    ui.label("最近的 panic");
+                }
+            }
+            if !lines.is_empty() && ui.button("复制").clicked() {
+                ui.output().copied_text =
+                    backtrace_text(&lines, app_data.firmware_symbols.as_ref());
+            }
+        });
+        if lines.is_empty() {
+            return;
+        }
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                egui::Grid::new("monitor_backtrace")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (label, address, return_address) in line_addresses(&lines) {
+                            ui.label(label);
+                            ui.monospace(format!("0x{:08x}", address));
+                            let frames = app_data
+                                .firmware_symbols
+                                .as_ref()
+                                .map(|symbols| symbols.resolve(address, return_address))
+                                .unwrap_or_default();
+                            if frames.is_empty() {
+                                ui.label("??");
+                                ui.end_row();
+                            }
+                            for (i, frame) in frames.iter().enumerate() {
+                                if i > 0 {
+                                    ui.label("");
+                                    ui.label("");
+                                }
+                                let function = frame.function.as_deref().unwrap_or("??");
+                                if frame.inlined {
+                                    ui.monospace(format!("{} (内联)", function));
+                                } else {
+                                    ui.monospace(function);
+                                }
+                                ui.monospace(frame.location());
+                                ui.end_row();
+                            }
+                        }
+                    });
+            });
+    }
+}
+
+/// 行中的代码地址, 前面的寄存器名称和是否为返回地址
+fn line_addresses<'a>(lines: &[&'a LogLine]) -> Vec<(&'a str, u32, bool)> {
+    lines
+        .iter()
+        .flat_map(|line| {
+            line.addresses.iter().filter_map(move |range| {
+                let address = u32::from_str_radix(&line.message[range.start + 2..range.end], 16);
+                Some((
+                    address_label(&line.message, range),
+                    address.ok()?,
+                    is_return_address(&line.message, range),
+                ))
+            })
+        })
+        .collect()
+}
+
+/// 与 addr2line -pfia 相同格式的文本, 用于复制到问题报告
+fn backtrace_text(lines: &[&LogLine], symbols: Option<&FirmwareSymbols>) -> String {
+    let mut text = String::new();
+    for (label, address, return_address) in line_addresses(lines) {
+        let frames = symbols
+            .map(|symbols| symbols.resolve(address, return_address))
+            .unwrap_or_default();
+        text.push_str(&format!("{:<10}0x{:08x}: ", label, address));
+        if frames.is_empty() {
+            text.push_str("?? ??:0\n");
+        }
+        for (i, frame) in frames.iter().enumerate() {
+            if i > 0 {
+                text.push_str("                (inlined by) ");
+            }
+            text.push_str(&format!(
+                "{} at {}\n",
+                frame.function.as_deref().unwrap_or("??"),
+                frame.location()
+            ));
+        }
+    }
+    text
+}
+
+/// 日志级别的颜色, 与 ESP-IDF 的 ANSI 颜色相同
+fn level_color(level: Option<LogLevel>) -> Option<Color32> {
+    match level? {
+        LogLevel::Error => Some(Color32::from_rgb(0xe0, 0x50, 0x50)),
+        LogLevel::Warn => Some(Color32::from_rgb(0xd0, 0xb0, 0x30)),
+        LogLevel::Info => Some(Color32::from_rgb(0x50, 0xc0, 0x50)),
+        LogLevel::Debug | LogLevel::Verbose => None,
+    }
+}
+
+/// 一行日志: 主机时间, 级别, 设备时间戳, 标签, 消息, 代码地址高亮显示
+fn line_job(ui: &Ui, line: &LogLine) -> LayoutJob {
+    let font = TextStyle::Monospace.resolve(ui.style());
+    let text_color = ui.visuals().text_color();
+    let format = |color: Color32| TextFormat::simple(font.clone(), color);
+    let mut job = LayoutJob::default();
+    job.append(
+        &line.host_time.format("%H:%M:%S%.3f ").to_string(),
+        0.0,
+        format(Color32::GRAY),
+    );
+    let color = level_color(line.level).unwrap_or(text_color);
+    if let Some(level) = line.level {
+        job.append(
+            &format!("{} ({}) {}: ", level.letter(), line.timestamp, line.tag),
+            0.0,
+            format(color),
+        );
+    }
+    let mut start = 0;
+    for range in line.addresses.iter() {
+        job.append(&line.message[start..range.start], 0.0, format(color));
+        job.append(
+            &line.message[range.clone()],
+            0.0,
+            TextFormat {
+                background: Color32::from_rgb(0x60, 0x40, 0x00),
+                ..format(Color32::from_rgb(0xff, 0xd0, 0x60))
+            },
+        );
+        start = range.end;
+    }
+    job.append(&line.message[start..], 0.0, format(color));
+    job
+}
+
+impl BasePage for MonitorPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Monitor {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::TopBottomPanel::top("monitor_config").show(ctx, |ui| {
+            ui.heading("ESP32 串口日志");
+            self.config_ui(ui, &mut app_data);
+            self.filter_ui(ui, &app_data);
+            ui.add_space(4.0);
+        });
+
+        if let Some((old, new)) = self
+            .config_audit
+            .changes(ctx, &app_data.serial_monitor_config)
+        {
+            app_data.audit_config("serial_monitor", Some(old), Some(new));
+        }
+
+        if app_data.serial_monitor.is_some() {
+            egui::TopBottomPanel::bottom("monitor_backtrace")
+                .resizable(true)
+                .default_height(180.0)
+                .show(ctx, |ui| {
+                    self.backtrace_ui(ui, &app_data);
+                });
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.lines_ui(ui, &app_data);
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
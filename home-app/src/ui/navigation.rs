@@ -0,0 +1,107 @@
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{data::app_data::AppData, window::Page};
+
+use super::{
+    audit_page::AuditPage, bus_page::BusPage, device_page::DevicePage, gateway_page::GatewayPage,
+    image_page::ImagePage, kconfig_page::KconfigPage, monitor_page::MonitorPage,
+    partition_page::PartitionPage, queue_page::QueuePage, recipe_page::RecipePage,
+    register_page::RegisterPage, scan_page::ScanPage, simulator_page::SimulatorPage,
+    sniffer_page::SnifferPage, tcp_gateway_page::TcpGatewayPage, trend_page::TrendPage,
+};
+
+/// 可以从标题栏菜单打开的页面
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    Devices,
+    CommandQueue,
+    Audit,
+    Registers,
+    Simulator,
+    Scanner,
+    Sniffer,
+    Gateway,
+    Buses,
+    Recipes,
+    TcpGateway,
+    Trends,
+    Monitor,
+    Image,
+    Partitions,
+    Kconfig,
+}
+
+impl PageKind {
+    pub const ALL: [PageKind; 16] = [
+        PageKind::Devices,
+        PageKind::CommandQueue,
+        PageKind::Audit,
+        PageKind::Registers,
+        PageKind::Simulator,
+        PageKind::Scanner,
+        PageKind::Sniffer,
+        PageKind::Gateway,
+        PageKind::Buses,
+        PageKind::Recipes,
+        PageKind::TcpGateway,
+        PageKind::Trends,
+        PageKind::Monitor,
+        PageKind::Image,
+        PageKind::Partitions,
+        PageKind::Kconfig,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PageKind::Devices => "设备",
+            PageKind::CommandQueue => "离线命令队列",
+            PageKind::Audit => "操作审计",
+            PageKind::Registers => "Modbus 寄存器",
+            PageKind::Simulator => "Modbus 模拟从站",
+            PageKind::Scanner => "Modbus 总线扫描",
+            PageKind::Sniffer => "Modbus 报文监听",
+            PageKind::Gateway => "Modbus MQTT 网关",
+            PageKind::Buses => "Modbus 总线调度",
+            PageKind::Recipes => "Modbus 参数配方",
+            PageKind::TcpGateway => "Modbus TCP 网关",
+            PageKind::Trends => "Modbus 趋势",
+            PageKind::Monitor => "ESP32 串口日志",
+            PageKind::Image => "ESP32 固件镜像",
+            PageKind::Partitions => "ESP32 分区表",
+            PageKind::Kconfig => "ESP32 sdkconfig",
+        }
+    }
+
+    /// 创建页面
+    pub fn build(
+        &self,
+        window_handle: Arc<RwLock<Window>>,
+        app_data: Arc<RwLock<AppData>>,
+    ) -> Page {
+        let mut page = Page::default();
+        match self {
+            PageKind::Devices => page.add(Box::new(DevicePage::new(window_handle, app_data))),
+            PageKind::CommandQueue => page.add(Box::new(QueuePage::new(window_handle, app_data))),
+            PageKind::Audit => page.add(Box::new(AuditPage::new(window_handle, app_data))),
+            PageKind::Registers => page.add(Box::new(RegisterPage::new(window_handle, app_data))),
+            PageKind::Simulator => page.add(Box::new(SimulatorPage::new(window_handle, app_data))),
+            PageKind::Scanner => page.add(Box::new(ScanPage::new(window_handle, app_data))),
+            PageKind::Sniffer => page.add(Box::new(SnifferPage::new(window_handle, app_data))),
+            PageKind::Gateway => page.add(Box::new(GatewayPage::new(window_handle, app_data))),
+            PageKind::Buses => page.add(Box::new(BusPage::new(window_handle, app_data))),
+            PageKind::Recipes => page.add(Box::new(RecipePage::new(window_handle, app_data))),
+            PageKind::TcpGateway => {
+                page.add(Box::new(TcpGatewayPage::new(window_handle, app_data)))
+            }
+            PageKind::Trends => page.add(Box::new(TrendPage::new(window_handle, app_data))),
+            PageKind::Monitor => page.add(Box::new(MonitorPage::new(window_handle, app_data))),
+            PageKind::Image => page.add(Box::new(ImagePage::new(window_handle, app_data))),
+            PageKind::Partitions => page.add(Box::new(PartitionPage::new(window_handle, app_data))),
+            PageKind::Kconfig => page.add(Box::new(KconfigPage::new(window_handle, app_data))),
+        }
+        page
+    }
+}
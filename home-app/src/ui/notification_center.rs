@@ -0,0 +1,324 @@
+use epi::egui::{self, Align2, Color32, RichText, Ui};
+
+use crate::data::{
+    alert::{AlertCondition, AlertManager, AlertRule, Severity},
+    app_data::AppData,
+};
+
+#[derive(PartialEq)]
+enum Tab {
+    Notifications,
+    Rules,
+}
+
+/// 通知中心, 从标题栏打开
+pub struct NotificationCenter {
+    pub open: bool,
+    tab: Tab,
+    /// 新规则的编辑内容
+    new_rule: AlertRule,
+}
+
+impl Default for NotificationCenter {
+    fn default() -> Self {
+        Self {
+            open: false,
+            tab: Tab::Notifications,
+            new_rule: AlertRule {
+                id: 0,
+                device_id: String::new(),
+                field: String::new(),
+                condition: AlertCondition::Above(0.0),
+                severity: Severity::Warning,
+                hysteresis: 0.0,
+                cooldown: 60,
+                enabled: true,
+            },
+        }
+    }
+}
+
+pub fn severity_color(severity: Severity) -> Color32 {
+    match severity {
+        Severity::Info => Color32::LIGHT_BLUE,
+        Severity::Warning => Color32::YELLOW,
+        Severity::Critical => Color32::RED,
+    }
+}
+
+impl NotificationCenter {
+    pub fn trigger(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn draw(&mut self, ctx: &egui::Context, app_data: &mut AppData) {
+        let before = (
+            app_data.alerts.history().len(),
+            app_data.alerts.unacknowledged(),
+        );
+        self.toasts(ctx, &mut app_data.alerts);
+
+        let mut open = self.open;
+        egui::Window::new("通知中心")
+            .open(&mut open)
+            .default_width(520.0)
+            .anchor(Align2::RIGHT_TOP, [-10.0, 40.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.tab, Tab::Notifications, "通知");
+                    ui.selectable_value(&mut self.tab, Tab::Rules, "告警规则");
+                });
+                ui.separator();
+                match self.tab {
+                    Tab::Notifications => self.notifications_ui(ui, &mut app_data.alerts),
+                    Tab::Rules => self.rules_ui(ui, app_data),
+                }
+            });
+        self.open = open;
+
+        // 确认和清除通知后需要保存历史
+        let after = (
+            app_data.alerts.history().len(),
+            app_data.alerts.unacknowledged(),
+        );
+        if after != before {
+            app_data.mark_changed();
+        }
+    }
+
+    /// 右下角弹出新通知
+    fn toasts(&mut self, ctx: &egui::Context, alerts: &mut AlertManager) {
+        let toasts = alerts.toasts();
+        if toasts.is_empty() {
+            return;
+        }
+        egui::Area::new("alert_toasts")
+            .anchor(Align2::RIGHT_BOTTOM, [-10.0, -40.0])
+            .show(ctx, |ui| {
+                for toast in toasts.iter() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(
+                                severity_color(toast.severity),
+                                toast.severity.label(),
+                            );
+                            ui.label(&toast.message);
+                            if ui.small_button("确认").clicked() {
+                                alerts.acknowledge(toast.id);
+                            }
+                        });
+                    });
+                }
+            });
+    }
+
+    fn notifications_ui(&mut self, ui: &mut Ui, alerts: &mut AlertManager) {
+        ui.horizontal(|ui| {
+            if ui.button("全部确认").clicked() {
+                alerts.acknowledge_all();
+            }
+            if ui.button("清除已确认").clicked() {
+                alerts.clear_acknowledged();
+            }
+        });
+
+        let mut acknowledge = None;
+        let mut clear = None;
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                if alerts.history().is_empty() {
+                    ui.label("没有通知");
+                }
+                // 最新的在前面
+                for n in alerts.history().iter().rev() {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(severity_color(n.severity), n.severity.label());
+                        ui.label(n.time.format("%Y-%m-%d %H:%M:%S").to_string());
+                        let text = RichText::new(&n.message);
+                        if n.acknowledged {
+                            ui.label(text.weak());
+                        } else {
+                            ui.label(text.strong());
+                            if ui.small_button("确认").clicked() {
+                                acknowledge = Some(n.id);
+                            }
+                        }
+                        if ui.small_button("清除").clicked() {
+                            clear = Some(n.id);
+                        }
+                    });
+                }
+            });
+
+        if let Some(id) = acknowledge {
+            alerts.acknowledge(id);
+        }
+        if let Some(id) = clear {
+            alerts.clear(id);
+        }
+    }
+
+    fn rules_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let mut remove = None;
+        let mut toggled = None;
+        egui::Grid::new("alert_rules")
+            .num_columns(6)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("启用");
+                ui.strong("设备.字段");
+                ui.strong("条件");
+                ui.strong("级别");
+                ui.strong("状态");
+                ui.label("");
+                ui.end_row();
+
+                let alerts = &mut app_data.alerts;
+                let active: Vec<bool> = alerts
+                    .rules()
+                    .iter()
+                    .map(|r| alerts.is_active(r.id))
+                    .collect();
+                for (rule, active) in alerts.rules_mut().iter_mut().zip(active) {
+                    if ui.checkbox(&mut rule.enabled, "").changed() {
+                        toggled = Some((rule.id, rule.enabled));
+                    }
+                    ui.label(format!("{}.{}", rule.device_id, rule.field));
+                    ui.label(rule.condition.describe());
+                    ui.colored_label(severity_color(rule.severity), rule.severity.label());
+                    if active {
+                        ui.colored_label(Color32::RED, "告警中");
+                    } else {
+                        ui.label("正常");
+                    }
+                    if ui.small_button("🗑").clicked() {
+                        remove = Some(rule.id);
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some((id, enabled)) = toggled {
+            app_data.audit_config(
+                format!("alerts.{}.enabled", id),
+                Some((!enabled).to_string()),
+                Some(enabled.to_string()),
+            );
+        }
+        if let Some(id) = remove {
+            let old = app_data
+                .alerts
+                .rules()
+                .iter()
+                .find(|r| r.id == id)
+                .and_then(|r| serde_json::to_string(r).ok());
+            app_data.alerts.remove_rule(id);
+            app_data.audit_config("alerts", old, None);
+        }
+
+        ui.separator();
+        ui.strong("添加规则");
+
+        let rule = &mut self.new_rule;
+        egui::Grid::new("new_alert_rule")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("设备");
+                egui::ComboBox::from_id_source("new_rule_device")
+                    .selected_text(&rule.device_id)
+                    .show_ui(ui, |ui| {
+                        for device in app_data.devices.devices() {
+                            ui.selectable_value(
+                                &mut rule.device_id,
+                                device.id.clone(),
+                                &device.name,
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("字段");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut rule.field);
+                    // 提示设备已上报的字段
+                    if let Some(device) = app_data.devices.get(&rule.device_id) {
+                        ui.menu_button("⏷", |ui| {
+                            for key in device.reported.keys() {
+                                if ui.button(key).clicked() {
+                                    rule.field = key.clone();
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    }
+                });
+                ui.end_row();
+
+                ui.label("条件");
+                ui.horizontal(|ui| {
+                    let conditions = [
+                        AlertCondition::Above(0.0),
+                        AlertCondition::Below(0.0),
+                        AlertCondition::OutsideRange(0.0, 100.0),
+                        AlertCondition::NoUpdate(10),
+                        AlertCondition::RateOfChange(1.0),
+                    ];
+                    egui::ComboBox::from_id_source("new_rule_condition")
+                        .selected_text(rule.condition.label())
+                        .show_ui(ui, |ui| {
+                            for c in conditions {
+                                let selected = std::mem::discriminant(&c)
+                                    == std::mem::discriminant(&rule.condition);
+                                if ui.selectable_label(selected, c.label()).clicked() && !selected {
+                                    rule.condition = c;
+                                }
+                            }
+                        });
+                    match &mut rule.condition {
+                        AlertCondition::Above(v)
+                        | AlertCondition::Below(v)
+                        | AlertCondition::RateOfChange(v) => {
+                            ui.add(egui::DragValue::new(v).speed(0.1));
+                        }
+                        AlertCondition::OutsideRange(min, max) => {
+                            ui.add(egui::DragValue::new(min).speed(0.1).prefix("min: "));
+                            ui.add(egui::DragValue::new(max).speed(0.1).prefix("max: "));
+                        }
+                        AlertCondition::NoUpdate(minutes) => {
+                            ui.add(egui::DragValue::new(minutes).suffix(" 分钟"));
+                        }
+                    }
+                });
+                ui.end_row();
+
+                ui.label("级别");
+                ui.horizontal(|ui| {
+                    for s in Severity::ALL {
+                        ui.selectable_value(&mut rule.severity, s, s.label());
+                    }
+                });
+                ui.end_row();
+
+                ui.label("回差");
+                ui.add(egui::DragValue::new(&mut rule.hysteresis).speed(0.1));
+                ui.end_row();
+
+                ui.label("冷却时间");
+                ui.add(egui::DragValue::new(&mut rule.cooldown).suffix(" 秒"));
+                ui.end_row();
+            });
+
+        let valid = !rule.device_id.is_empty() && !rule.field.trim().is_empty();
+        if ui.add_enabled(valid, egui::Button::new("添加")).clicked() {
+            let mut new_rule = rule.clone();
+            new_rule.field = new_rule.field.trim().to_string();
+            app_data.alerts.add_rule(new_rule);
+            let new = app_data
+                .alerts
+                .rules()
+                .last()
+                .and_then(|r| serde_json::to_string(r).ok());
+            app_data.audit_config("alerts", None, new);
+        }
+    }
+}
@@ -0,0 +1,489 @@
+use chrono::Local;
+use epi::egui::{self, Align2, Color32, Sense, Stroke, TextStyle, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::{fs, sync::Arc};
+
+use crate::{
+    data::{
+        app_data::AppData,
+        partition_table::{
+            align_up, format_size, parse_size, subtype_name, subtypes, type_name, FlashLayout,
+            Issue, Partition, PartitionTable, SUBTYPE_NVS, SUBTYPE_OTADATA, TEMPLATES, TYPE_APP,
+            TYPE_DATA,
+        },
+        storage::persistence::app_dir,
+    },
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{navigation::PageKind, titlebar::MainTitlebar};
+
+const FLASH_SIZES: [u32; 6] = [1, 2, 4, 8, 16, 32];
+const MAP_HEIGHT: f32 = 48.0;
+
+/// 对分区的操作, 在表格绘制完成后执行
+enum RowAction {
+    Up(usize),
+    Down(usize),
+    Remove(usize),
+}
+
+/// ESP-IDF 分区表编辑, 按 sdkconfig 中的 flash 大小检查
+pub struct PartitionPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    path: String,
+    sdkconfig_path: String,
+    layout: FlashLayout,
+    table: PartitionTable,
+    /// 正在编辑的地址和大小文本
+    offsets: Vec<String>,
+    sizes: Vec<String>,
+    selected: Option<usize>,
+    message: Option<Result<String, String>>,
+}
+
+impl PartitionPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        let mut page = Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            path: "partitions.csv".to_string(),
+            sdkconfig_path: "sdkconfig".to_string(),
+            layout: FlashLayout::default(),
+            table: PartitionTable::default(),
+            offsets: vec![],
+            sizes: vec![],
+            selected: None,
+            message: None,
+        };
+        page.set_table(PartitionTable::from_csv(TEMPLATES[1].1, &page.layout).unwrap_or_default());
+        page
+    }
+
+    fn set_table(&mut self, table: PartitionTable) {
+        self.table = table;
+        self.selected = None;
+        self.sync_text();
+    }
+
+    fn sync_text(&mut self) {
+        let partitions = &self.table.partitions;
+        self.offsets = partitions
+            .iter()
+            .map(|p| format!("0x{:x}", p.offset))
+            .collect();
+        self.sizes = partitions.iter().map(|p| format_size(p.size)).collect();
+    }
+
+    fn file_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("分区表");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.path)
+                    .hint_text("partitions.csv 或 partition-table.bin")
+                    .desired_width(280.0),
+            );
+            if ui.button("打开").clicked() {
+                match PartitionTable::load(self.path.trim(), &self.layout) {
+                    Ok(table) => {
+                        self.message =
+                            Some(Ok(format!("已打开 {} 个分区", table.partitions.len())));
+                        self.set_table(table);
+                    }
+                    Err(e) => self.message = Some(Err(e.to_string())),
+                }
+            }
+            if ui
+                .button("保存")
+                .on_hover_text("扩展名为 .bin 时保存为二进制, 否则为 csv")
+                .clicked()
+            {
+                self.message = Some(
+                    self.table
+                        .save(self.path.trim())
+                        .map(|_| format!("已保存到 {}", self.path.trim()))
+                        .map_err(|e| e.to_string()),
+                );
+            }
+            for extension in ["csv", "bin"] {
+                if ui.button(format!("导出 {}", extension)).clicked() {
+                    let path = app_dir().join(format!(
+                        "partitions-{}.{}",
+                        Local::now().format("%Y%m%d-%H%M%S"),
+                        extension
+                    ));
+                    self.message = Some(
+                        self.table
+                            .save(&path)
+                            .map(|_| format!("已导出到 {}", path.display()))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+            }
+            ui.menu_button("模板", |ui| {
+                for (name, csv) in TEMPLATES {
+                    if ui.button(name).clicked() {
+                        match PartitionTable::from_csv(csv, &self.layout) {
+                            Ok(table) => self.set_table(table),
+                            Err(e) => self.message = Some(Err(e.to_string())),
+                        }
+                        ui.close_menu();
+                    }
+                }
+            });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("sdkconfig");
+            ui.add(egui::TextEdit::singleline(&mut self.sdkconfig_path).desired_width(280.0));
+            if ui
+                .button("读取")
+                .on_hover_text("读取 flash 大小和分区表地址")
+                .clicked()
+            {
+                match fs::read_to_string(self.sdkconfig_path.trim()) {
+                    Ok(text) => {
+                        self.layout = FlashLayout::from_sdkconfig(&text);
+                        self.message = Some(Ok(format!(
+                            "flash {}, 分区表在 0x{:x}",
+                            format_size(self.layout.flash_size),
+                            self.layout.table_offset
+                        )));
+                    }
+                    Err(e) => self.message = Some(Err(e.to_string())),
+                }
+            }
+            ui.label("flash 大小");
+            egui::ComboBox::from_id_source("partition_flash_size")
+                .selected_text(format_size(self.layout.flash_size))
+                .show_ui(ui, |ui| {
+                    for size in FLASH_SIZES {
+                        let size = size * 1024 * 1024;
+                        ui.selectable_value(&mut self.layout.flash_size, size, format_size(size));
+                    }
+                });
+            ui.label(format!("分区表地址 0x{:x}", self.layout.table_offset));
+        });
+
+        match &self.message {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(Color32::RED, e);
+            }
+            None => {}
+        }
+    }
+
+    /// flash 的使用情况, 每个分区一个色块, 有错误的分区红色描边
+    fn map_ui(&mut self, ui: &mut Ui, issues: &[Issue]) {
+        let size = egui::vec2(ui.available_width(), MAP_HEIGHT);
+        let (response, painter) = ui.allocate_painter(size, Sense::click());
+        let rect = response.rect;
+        let flash_size = self.layout.flash_size.max(1) as f32;
+        let x = |address: u32| rect.left() + rect.width() * (address as f32 / flash_size).min(1.0);
+        let block = |start: u32, end: u32| {
+            egui::Rect::from_x_y_ranges(x(start)..=x(end).max(x(start) + 1.0), rect.y_range())
+        };
+        let font = TextStyle::Small.resolve(ui.style());
+        let label = |r: egui::Rect, text: &str, color: Color32| {
+            let galley = painter.layout_no_wrap(text.to_string(), font.clone(), color);
+            if galley.size().x + 4.0 < r.width() {
+                painter.text(r.center(), Align2::CENTER_CENTER, text, font.clone(), color);
+            }
+        };
+
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let table_end = self.layout.first_free();
+        let bootloader = block(0, self.layout.table_offset);
+        painter.rect_filled(bootloader, 0.0, Color32::from_gray(70));
+        label(bootloader, "bootloader", Color32::WHITE);
+        painter.rect_filled(
+            block(self.layout.table_offset, table_end),
+            0.0,
+            Color32::from_gray(110),
+        );
+
+        let mut hovered = None;
+        for (i, p) in self.table.partitions.iter().enumerate() {
+            let r = block(p.offset, p.end()).shrink2(egui::vec2(0.5, 0.0));
+            painter.rect_filled(r, 0.0, partition_color(p));
+            label(r, &p.name, Color32::BLACK);
+            if issues
+                .iter()
+                .any(|issue| issue.error && issue.partition == Some(i))
+            {
+                painter.rect_stroke(r, 0.0, Stroke::new(2.0, Color32::RED));
+            }
+            if self.selected == Some(i) {
+                painter.rect_stroke(r.shrink(1.0), 0.0, Stroke::new(2.0, Color32::WHITE));
+            }
+            if response.hover_pos().map_or(false, |pos| r.contains(pos)) {
+                hovered = Some(i);
+            }
+        }
+        painter.rect_stroke(rect, 2.0, ui.visuals().widgets.noninteractive.bg_stroke);
+
+        if response.clicked() {
+            self.selected = hovered;
+        }
+        if let Some(p) = hovered.map(|i| &self.table.partitions[i]) {
+            response.on_hover_text_at_pointer(format!(
+                "{}: {} {}\n0x{:x} - 0x{:x}, {}",
+                p.name,
+                type_name(p.kind),
+                subtype_name(p.kind, p.subtype),
+                p.offset,
+                p.end(),
+                format_size(p.size)
+            ));
+        }
+
+        let used = self
+            .table
+            .partitions
+            .iter()
+            .map(|p| p.end())
+            .max()
+            .unwrap_or(table_end);
+        ui.label(format!(
+            "flash {}, 已用到 0x{:x}, 剩余 {}",
+            format_size(self.layout.flash_size),
+            used,
+            format_size(self.layout.flash_size.saturating_sub(used))
+        ));
+    }
+
+    fn table_ui(&mut self, ui: &mut Ui, issues: &[Issue]) {
+        let mut action = None;
+        egui::Grid::new("partition_table")
+            .striped(true)
+            .show(ui, |ui| {
+                for title in ["", "名称", "类型", "子类型", "地址", "大小", "标志", ""]
+                {
+                    ui.strong(title);
+                }
+                ui.end_row();
+                for (i, p) in self.table.partitions.iter_mut().enumerate() {
+                    let has_error = issues
+                        .iter()
+                        .any(|issue| issue.error && issue.partition == Some(i));
+                    let number = egui::RichText::new((i + 1).to_string());
+                    let number = if has_error {
+                        number.color(Color32::RED)
+                    } else {
+                        number
+                    };
+                    if ui
+                        .selectable_label(self.selected == Some(i), number)
+                        .clicked()
+                    {
+                        self.selected = Some(i);
+                    }
+                    ui.add(egui::TextEdit::singleline(&mut p.name).desired_width(100.0));
+                    let kind = p.kind;
+                    egui::ComboBox::from_id_source(("partition_type", i))
+                        .selected_text(type_name(p.kind))
+                        .width(60.0)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut p.kind, TYPE_APP, "app");
+                            ui.selectable_value(&mut p.kind, TYPE_DATA, "data");
+                        });
+                    if p.kind != kind {
+                        p.subtype = subtypes(p.kind).first().map_or(0, |(_, value)| *value);
+                    }
+                    egui::ComboBox::from_id_source(("partition_subtype", i))
+                        .selected_text(subtype_name(p.kind, p.subtype))
+                        .width(90.0)
+                        .show_ui(ui, |ui| {
+                            for (name, value) in subtypes(p.kind) {
+                                ui.selectable_value(&mut p.subtype, *value, *name);
+                            }
+                        });
+                    size_edit(ui, &mut self.offsets[i], &mut p.offset);
+                    size_edit(ui, &mut self.sizes[i], &mut p.size);
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut p.encrypted, "加密");
+                        ui.checkbox(&mut p.readonly, "只读");
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.small_button("⬆").clicked() {
+                            action = Some(RowAction::Up(i));
+                        }
+                        if ui.small_button("⬇").clicked() {
+                            action = Some(RowAction::Down(i));
+                        }
+                        if ui.small_button("✖").clicked() {
+                            action = Some(RowAction::Remove(i));
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+
+        let partitions = &mut self.table.partitions;
+        match action {
+            Some(RowAction::Up(i)) if i > 0 => partitions.swap(i, i - 1),
+            Some(RowAction::Down(i)) if i + 1 < partitions.len() => partitions.swap(i, i + 1),
+            Some(RowAction::Remove(i)) => {
+                partitions.remove(i);
+                self.selected = None;
+            }
+            _ => {}
+        }
+        if action.is_some() {
+            self.sync_text();
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("添加分区").clicked() {
+                let end = self
+                    .table
+                    .partitions
+                    .iter()
+                    .map(|p| p.end())
+                    .max()
+                    .unwrap_or_else(|| self.layout.first_free());
+                match align_up(end, TYPE_DATA) {
+                    Some(offset) => {
+                        self.table.partitions.push(Partition {
+                            name: format!("data{}", self.table.partitions.len()),
+                            kind: TYPE_DATA,
+                            subtype: SUBTYPE_NVS,
+                            offset,
+                            size: 0x6000,
+                            encrypted: false,
+                            readonly: false,
+                        });
+                        self.sync_text();
+                    }
+                    None => self.message = Some(Err("最后一个分区之后没有空间".to_string())),
+                }
+            }
+            if ui
+                .button("重新排列地址")
+                .on_hover_text("按当前顺序从分区表之后依次排列, 满足对齐要求")
+                .clicked()
+            {
+                match self.table.arrange(&self.layout) {
+                    Ok(()) => self.sync_text(),
+                    Err(e) => self.message = Some(Err(e.to_string())),
+                }
+            }
+        });
+    }
+
+    fn issues_ui(&mut self, ui: &mut Ui, issues: &[Issue]) {
+        if issues.is_empty() {
+            ui.colored_label(Color32::GREEN, "检查通过");
+            return;
+        }
+        for issue in issues {
+            let color = if issue.error {
+                Color32::RED
+            } else {
+                Color32::YELLOW
+            };
+            let text = egui::RichText::new(&issue.message).color(color);
+            if ui
+                .add(egui::Label::new(text).sense(Sense::click()))
+                .clicked()
+            {
+                self.selected = issue.partition;
+            }
+        }
+    }
+}
+
+/// 编辑地址或大小, 文本无效时显示为红色并保持原值
+fn size_edit(ui: &mut Ui, text: &mut String, value: &mut u32) {
+    let valid = parse_size(text).is_some();
+    let mut edit = egui::TextEdit::singleline(text).desired_width(80.0);
+    if !valid {
+        edit = edit.text_color(Color32::RED);
+    }
+    if ui.add(edit).changed() {
+        if let Some(parsed) = parse_size(text) {
+            *value = parsed;
+        }
+    }
+}
+
+fn partition_color(partition: &Partition) -> Color32 {
+    match (partition.kind, partition.subtype) {
+        (TYPE_APP, _) if partition.ota_slot().is_some() => Color32::from_rgb(0x70, 0xa0, 0xe0),
+        (TYPE_APP, _) => Color32::from_rgb(0x50, 0x80, 0xd0),
+        (TYPE_DATA, SUBTYPE_OTADATA) => Color32::from_rgb(0xe0, 0xa0, 0x50),
+        (TYPE_DATA, SUBTYPE_NVS) => Color32::from_rgb(0x70, 0xc0, 0x70),
+        (TYPE_DATA, _) => Color32::from_rgb(0xa0, 0xc0, 0x90),
+        _ => Color32::from_rgb(0xb0, 0x90, 0xd0),
+    }
+}
+
+impl BasePage for PartitionPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Partitions {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        egui::TopBottomPanel::top("partition_file").show(ctx, |ui| {
+            ui.heading("ESP32 分区表");
+            self.file_ui(ui);
+            ui.add_space(4.0);
+        });
+
+        let issues = self.table.validate(&self.layout);
+        egui::TopBottomPanel::bottom("partition_issues")
+            .resizable(true)
+            .default_height(120.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| self.issues_ui(ui, &issues));
+            });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.map_ui(ui, &issues);
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .show(ui, |ui| self.table_ui(ui, &issues));
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
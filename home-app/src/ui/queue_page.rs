@@ -0,0 +1,197 @@
+use chrono::Local;
+use epi::egui::{self, Color32};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{
+    data::app_data::AppData,
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{navigation::PageKind, titlebar::MainTitlebar};
+
+/// 离线命令队列, 可以查看和取消排队的命令
+pub struct QueuePage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    /// 开始修改时的默认有效期, 修改结束后记录审计
+    old_default_ttl: Option<u64>,
+    /// 正在修改的命令的 (序号, 开始修改时的剩余有效期, 修改后的有效期)
+    editing_entry: Option<(u64, i64, Option<i64>)>,
+}
+
+impl QueuePage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            old_default_ttl: None,
+            editing_entry: None,
+        }
+    }
+}
+
+impl BasePage for QueuePage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::CommandQueue {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let mut app_data = self.app_data.write();
+        let app_data = &mut *app_data;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("离线命令队列");
+            ui.horizontal(|ui| {
+                ui.label("默认有效期");
+                let ttl = app_data.command_queue.default_ttl;
+                let response = ui.add(
+                    egui::DragValue::new(&mut app_data.command_queue.default_ttl)
+                        .clamp_range(10..=7 * 24 * 3600)
+                        .suffix(" 秒"),
+                );
+                if response.drag_started() || response.gained_focus() {
+                    self.old_default_ttl.get_or_insert(ttl);
+                }
+                if response.drag_released() || response.lost_focus() {
+                    let new_ttl = app_data.command_queue.default_ttl;
+                    match self.old_default_ttl.take() {
+                        Some(old_ttl) if old_ttl != new_ttl => app_data.audit_config(
+                            "command_queue.default_ttl",
+                            Some(old_ttl.to_string()),
+                            Some(new_ttl.to_string()),
+                        ),
+                        _ => {}
+                    }
+                }
+                ui.separator();
+                ui.label(format!("共 {} 条命令", app_data.command_queue.len()));
+            });
+            ui.separator();
+
+            if app_data.command_queue.is_empty() {
+                ui.label("没有排队的命令");
+                return;
+            }
+
+            let now = Local::now();
+            let mut cancel = None;
+            let mut cancel_all = None;
+            let mut set_ttl = None;
+            let mut edit_finished = false;
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for target in app_data.command_queue.targets() {
+                    ui.horizontal(|ui| {
+                        ui.strong(target.label());
+                        if ui.small_button("全部取消").clicked() {
+                            cancel_all = Some(target.clone());
+                        }
+                    });
+                    egui::Grid::new(format!("queue_{}", target.label()))
+                        .num_columns(5)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong("序号");
+                            ui.strong("命令");
+                            ui.strong("入队时间");
+                            ui.strong("剩余有效期");
+                            ui.label("");
+                            ui.end_row();
+
+                            for queued in app_data.command_queue.queue(&target) {
+                                ui.label(queued.id.to_string());
+                                ui.label(queued.command.label());
+                                ui.label(queued.created.format("%Y-%m-%d %H:%M:%S").to_string());
+                                let remaining = (queued.expires - now).num_seconds();
+                                if remaining > 0 {
+                                    let mut ttl = remaining;
+                                    let response = ui.add(
+                                        egui::DragValue::new(&mut ttl)
+                                            .clamp_range(10..=7 * 24 * 3600)
+                                            .suffix(" 秒"),
+                                    );
+                                    if response.drag_started() || response.gained_focus() {
+                                        self.editing_entry
+                                            .get_or_insert((queued.id, remaining, None));
+                                    }
+                                    if ttl != remaining {
+                                        set_ttl = Some((queued.id, ttl));
+                                    }
+                                    if response.drag_released() || response.lost_focus() {
+                                        edit_finished = true;
+                                    }
+                                } else {
+                                    ui.colored_label(Color32::RED, "已过期");
+                                }
+                                if ui.small_button("取消").clicked() {
+                                    cancel = Some(queued.id);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    ui.separator();
+                }
+            });
+
+            if let Some((id, ttl)) = set_ttl {
+                if app_data.command_queue.set_ttl(id, ttl as u64).is_some() {
+                    app_data.mark_changed();
+                    if let Some((editing, _, new)) = &mut self.editing_entry {
+                        if *editing == id {
+                            *new = Some(ttl);
+                        }
+                    }
+                }
+            }
+            if edit_finished {
+                if let Some((id, old, Some(new))) = self.editing_entry.take() {
+                    app_data.audit_config(
+                        format!("command_queue #{}", id),
+                        Some(format!("剩余 {} 秒", old)),
+                        Some(format!("{} 秒", new)),
+                    );
+                }
+            }
+            if let Some(id) = cancel {
+                app_data.cancel_queued(id);
+            }
+            if let Some(target) = cancel_all {
+                app_data.cancel_all_queued(&target);
+            }
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -0,0 +1,694 @@
+use chrono::Local;
+use epi::egui::{self, Color32, RichText, Ui};
+use parking_lot::RwLock;
+use serde_json::Value;
+use winit::window::Window;
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use crate::{
+    data::{
+        app_data::AppData,
+        command_queue::Command,
+        recipe::{recipe_fields, Recipe, RecipeItem},
+        register_map::{Field, RegisterMap},
+        storage::persistence::app_dir,
+    },
+    resource::error::Result,
+    service::modbus::recipe::{RecipeJob, RecipeTarget, RecipeTask},
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{config_audit::ConfigAudit, navigation::PageKind, titlebar::MainTitlebar};
+
+/// 参数配方: 编辑, 导入导出, 从从站上传, 预览差异后批量下载到多个从站
+pub struct RecipePage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    selected: Option<usize>,
+    /// 值的编辑文本, 与选择的配方的 items 一一对应
+    texts: Vec<String>,
+    /// 选择的从站名称
+    targets: BTreeSet<String>,
+    only_changed: bool,
+    /// 新建配方使用的寄存器表
+    new_map: String,
+    import_path: String,
+    job: Option<RecipeJob>,
+    /// 正在下载的配方, 结束后记录审计
+    downloading: Option<String>,
+    confirm_download: bool,
+    /// 离线从站的配方排队有效期, 单位: 秒
+    queue_ttl: u64,
+    /// 记录选择的配方的修改, 切换配方时重新开始
+    recipe_audit: ConfigAudit,
+    message: Option<std::result::Result<String, String>>,
+}
+
+impl RecipePage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        let new_map = app_data
+            .read()
+            .register_maps
+            .first()
+            .map(|m| m.name.clone())
+            .unwrap_or_default();
+        let queue_ttl = app_data.read().command_queue.default_ttl;
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            selected: None,
+            texts: vec![],
+            targets: BTreeSet::new(),
+            only_changed: true,
+            new_map,
+            import_path: String::new(),
+            job: None,
+            downloading: None,
+            confirm_download: false,
+            queue_ttl,
+            recipe_audit: ConfigAudit::default(),
+            message: None,
+        }
+    }
+
+    fn select(&mut self, index: Option<usize>, recipes: &[Recipe]) {
+        self.selected = index;
+        self.recipe_audit = ConfigAudit::default();
+        self.texts = index
+            .and_then(|i| recipes.get(i))
+            .map(|recipe| recipe.items.iter().map(|i| value_text(&i.value)).collect())
+            .unwrap_or_default();
+        self.job = None;
+    }
+
+    fn running(&self) -> bool {
+        self.job.as_ref().map_or(false, |job| !job.state().finished)
+    }
+
+    /// 配方列表, 新建和导入
+    fn list_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let mut select = None;
+        egui::ScrollArea::vertical()
+            .max_height(ui.available_height() - 120.0)
+            .show(ui, |ui| {
+                for (i, recipe) in app_data.recipes.iter().enumerate() {
+                    let text = format!("{} ({})", recipe.name, recipe.map);
+                    if ui
+                        .selectable_label(self.selected == Some(i), text)
+                        .on_hover_text(&recipe.description)
+                        .clicked()
+                    {
+                        select = Some(i);
+                    }
+                }
+            });
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("recipe_new_map")
+                .selected_text(&self.new_map)
+                .show_ui(ui, |ui| {
+                    for map in app_data.register_maps.iter() {
+                        ui.selectable_value(&mut self.new_map, map.name.clone(), &map.name);
+                    }
+                });
+            if ui.button("新建").clicked() {
+                let name = unique_name(&app_data.recipes, "recipe");
+                app_data.audit_config("recipes", None, Some(name.clone()));
+                app_data.recipes.push(Recipe::new(name, &self.new_map));
+                select = Some(app_data.recipes.len() - 1);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.import_path)
+                    .hint_text("配方 json 文件路径")
+                    .desired_width(160.0),
+            );
+            if ui.button("导入").clicked() {
+                match Recipe::load(self.import_path.trim()) {
+                    Ok(mut recipe) => {
+                        if app_data.recipes.iter().any(|r| r.name == recipe.name) {
+                            recipe.name = unique_name(&app_data.recipes, &recipe.name);
+                        }
+                        self.message = Some(Ok(format!("已导入 {}", recipe.name)));
+                        app_data.audit_config("recipes", None, Some(recipe.name.clone()));
+                        app_data.recipes.push(recipe);
+                        select = Some(app_data.recipes.len() - 1);
+                    }
+                    Err(e) => self.message = Some(Err(e.to_string())),
+                }
+            }
+        });
+
+        if let Some(index) = select {
+            if !self.running() {
+                self.select(Some(index), &app_data.recipes);
+            }
+        }
+    }
+
+    /// 配方的名称, 说明和每个字段的值
+    fn editor_ui(&mut self, ui: &mut Ui, recipe: &mut Recipe, map: Option<&RegisterMap>) {
+        ui.horizontal(|ui| {
+            ui.label("名称");
+            ui.add(egui::TextEdit::singleline(&mut recipe.name).desired_width(160.0));
+            ui.label("说明");
+            ui.add(egui::TextEdit::singleline(&mut recipe.description).desired_width(280.0));
+            ui.label(format!(
+                "寄存器表: {}, 创建于 {}",
+                recipe.map,
+                recipe.created.format("%Y-%m-%d %H:%M")
+            ));
+        });
+        if self.texts.len() != recipe.items.len() {
+            self.texts = recipe.items.iter().map(|i| value_text(&i.value)).collect();
+        }
+        let map = match map {
+            Some(map) => map,
+            None => {
+                ui.colored_label(Color32::RED, format!("找不到寄存器表: {}", recipe.map));
+                return;
+            }
+        };
+
+        let mut swap = None;
+        let mut remove = None;
+        egui::Grid::new("recipe_items")
+            .num_columns(6)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("顺序");
+                ui.strong("字段");
+                ui.strong("地址");
+                ui.strong("值");
+                ui.strong("");
+                ui.strong("");
+                ui.end_row();
+
+                let count = recipe.items.len();
+                for (i, item) in recipe.items.iter_mut().enumerate() {
+                    ui.label((i + 1).to_string());
+                    let found = map.find(&item.field);
+                    match found {
+                        Some((area, field)) => {
+                            ui.label(&item.field).on_hover_text(format!(
+                                "{} {}",
+                                area.label(),
+                                field.data_type.label()
+                            ));
+                            ui.label(field.address.to_string());
+                        }
+                        None => {
+                            ui.colored_label(Color32::RED, &item.field);
+                            ui.label("-");
+                        }
+                    }
+                    let text = &mut self.texts[i];
+                    let response = ui.add(egui::TextEdit::singleline(text).desired_width(160.0));
+                    if let Some((_, field)) = found {
+                        if response.changed() {
+                            item.value = parse_value(text, field);
+                        }
+                        let zeros = vec![0; field.len() as usize];
+                        match field.from_json(&item.value, &zeros) {
+                            Ok(_) => {
+                                ui.label(&field.unit);
+                            }
+                            Err(e) => {
+                                ui.colored_label(Color32::RED, e.to_string());
+                            }
+                        }
+                    } else {
+                        ui.label("");
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(i > 0, egui::Button::new("⬆")).clicked() {
+                            swap = Some(i - 1);
+                        }
+                        if ui
+                            .add_enabled(i + 1 < count, egui::Button::new("⬇"))
+                            .clicked()
+                        {
+                            swap = Some(i);
+                        }
+                        if ui.button("✖").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        if let Some(i) = swap {
+            recipe.items.swap(i, i + 1);
+            self.texts.swap(i, i + 1);
+        }
+        if let Some(i) = remove {
+            recipe.items.remove(i);
+            self.texts.remove(i);
+        }
+
+        // 同一个字段只需要写入一次, 已添加的字段不再列出
+        let available: Vec<&Field> = recipe_fields(map)
+            .into_iter()
+            .map(|(_, field)| field)
+            .filter(|field| !recipe.items.iter().any(|item| item.field == field.name))
+            .collect();
+        ui.horizontal(|ui| {
+            let mut add = vec![];
+            egui::ComboBox::from_id_source("recipe_add_field")
+                .selected_text("添加字段")
+                .show_ui(ui, |ui| {
+                    for field in available.iter() {
+                        if ui.selectable_label(false, &field.name).clicked() {
+                            add.push(*field);
+                        }
+                    }
+                });
+            if ui
+                .add_enabled(!available.is_empty(), egui::Button::new("添加全部"))
+                .clicked()
+            {
+                add = available.clone();
+            }
+            for field in add {
+                let value = field
+                    .to_json(&vec![0; field.len() as usize])
+                    .unwrap_or(Value::Null);
+                self.texts.push(value_text(&value));
+                recipe.items.push(RecipeItem {
+                    field: field.name.clone(),
+                    value,
+                });
+            }
+        });
+    }
+
+    /// 选择从站, 上传, 预览和下载
+    fn targets_ui(&mut self, ui: &mut Ui, app_data: &mut AppData, index: usize) {
+        let recipe = &app_data.recipes[index].clone();
+        let running = self.running();
+        ui.horizontal_wrapped(|ui| {
+            ui.label("从站");
+            let mut count = 0;
+            for device in app_data.modbus_devices.iter() {
+                if device.map != recipe.map {
+                    continue;
+                }
+                count += 1;
+                let mut checked = self.targets.contains(&device.name);
+                let text = format!("{} #{}", device.name, device.slave);
+                if ui
+                    .checkbox(&mut checked, text)
+                    .on_hover_text(device.transport.label())
+                    .changed()
+                {
+                    if checked {
+                        self.targets.insert(device.name.clone());
+                    } else {
+                        self.targets.remove(&device.name);
+                    }
+                }
+            }
+            if count == 0 {
+                ui.label(format!("没有使用寄存器表 {} 的从站", recipe.map));
+            }
+        });
+
+        let mut task = None;
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!running, |ui| {
+                if ui
+                    .button("上传")
+                    .on_hover_text("读取第一个选择的从站的所有可写字段, 生成新的配方")
+                    .clicked()
+                {
+                    let name = unique_name(&app_data.recipes, &format!("{}-upload", recipe.map));
+                    task = Some(RecipeTask::Upload { name });
+                }
+                if ui.button("预览差异").clicked() {
+                    task = Some(RecipeTask::Preview(recipe.clone()));
+                }
+                if ui.button("下载").clicked() {
+                    self.confirm_download = true;
+                }
+                ui.checkbox(&mut self.only_changed, "只写入有变化的值");
+                if ui.button("导出").clicked() {
+                    let path = app_dir().join(format!(
+                        "recipe-{}-{}.json",
+                        recipe.name,
+                        Local::now().format("%Y%m%d-%H%M%S")
+                    ));
+                    self.message = Some(
+                        recipe
+                            .save(&path)
+                            .map(|_| format!("已导出到 {}", path.display()))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+            });
+            if running && ui.button("取消").clicked() {
+                if let Some(job) = &self.job {
+                    job.cancel();
+                }
+            }
+            match &self.message {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(Color32::RED, e);
+                }
+                None => {}
+            }
+        });
+
+        if self.confirm_download {
+            let mut open = true;
+            let mut confirm = false;
+            egui::Window::new("确认下载")
+                .collapsible(false)
+                .resizable(false)
+                .open(&mut open)
+                .show(ui.ctx(), |ui| {
+                    ui.label(format!(
+                        "将配方 {} 的 {} 个字段依次写入 {} 个从站",
+                        recipe.name,
+                        recipe.items.len(),
+                        self.targets.len()
+                    ));
+                    ui.label("每个字段写入后读回校验, 失败时恢复该从站已写入的字段");
+                    ui.horizontal(|ui| {
+                        ui.label("离线的从站排队等待上线, 有效期");
+                        ui.add(
+                            egui::DragValue::new(&mut self.queue_ttl)
+                                .clamp_range(10..=7 * 24 * 3600)
+                                .suffix(" 秒"),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        confirm = ui.button("下载").clicked();
+                        if ui.button("取消").clicked() {
+                            self.confirm_download = false;
+                        }
+                    });
+                });
+            if confirm {
+                self.downloading = Some(recipe.name.clone());
+                task = Some(RecipeTask::Download {
+                    recipe: recipe.clone(),
+                    only_changed: self.only_changed,
+                });
+            }
+            if confirm || !open {
+                self.confirm_download = false;
+            }
+        }
+
+        if let Some(task) = task {
+            let queued = match &task {
+                RecipeTask::Download {
+                    recipe,
+                    only_changed,
+                } => self.queue_offline(app_data, recipe, *only_changed),
+                _ => vec![],
+            };
+            let map = recipe.map.clone();
+            let result = match self.build_targets(app_data, &map, &queued) {
+                Ok(targets) if targets.is_empty() && !queued.is_empty() => {
+                    self.downloading = None;
+                    self.message = Some(Ok(format!("{} 离线, 已排队", queued.join(", "))));
+                    return;
+                }
+                Ok(targets) => RecipeJob::start(task, targets, app_data.repaint_signal()),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(job) => {
+                    self.job = Some(job);
+                    self.message = if queued.is_empty() {
+                        None
+                    } else {
+                        Some(Ok(format!("{} 离线, 已排队", queued.join(", "))))
+                    };
+                }
+                Err(e) => {
+                    self.downloading = None;
+                    self.message = Some(Err(e.to_string()));
+                }
+            }
+        }
+    }
+
+    /// 离线的从站不参与本次下载, 配方进入离线队列, 返回这些从站的名称
+    fn queue_offline(
+        &mut self,
+        app_data: &mut AppData,
+        recipe: &Recipe,
+        only_changed: bool,
+    ) -> Vec<String> {
+        let offline: Vec<String> = app_data
+            .modbus_devices
+            .iter()
+            .filter(|d| d.map == recipe.map && self.targets.contains(&d.name))
+            .filter(|d| app_data.modbus_online(d) == Some(false))
+            .map(|d| d.name.clone())
+            .collect();
+        for device in offline.iter() {
+            let command = Command::Recipe {
+                recipe: recipe.clone(),
+                only_changed,
+            };
+            app_data.queue_modbus_command(device, command, None, Some(self.queue_ttl));
+        }
+        offline
+    }
+
+    /// 选择的从站, 不包括 skip 中已排队的从站
+    fn build_targets(
+        &self,
+        app_data: &AppData,
+        map: &str,
+        skip: &[String],
+    ) -> Result<Vec<RecipeTarget>> {
+        app_data
+            .modbus_devices
+            .iter()
+            .filter(|d| d.map == map && self.targets.contains(&d.name))
+            .filter(|d| !skip.contains(&d.name))
+            .map(|device| {
+                Ok(RecipeTarget {
+                    device: device.name.clone(),
+                    slave: device.slave,
+                    map: app_data.device_map(device)?,
+                    bus: app_data.modbus.bus(&device.transport)?,
+                })
+            })
+            .collect()
+    }
+
+    /// 上传的配方加入列表, 下载结束后记录审计
+    fn finish_job(&mut self, app_data: &mut AppData) {
+        let job = match &self.job {
+            Some(job) => job,
+            None => return,
+        };
+        let mut state = job.state();
+        if !state.finished {
+            return;
+        }
+        if let Some(recipe) = state.uploaded.take() {
+            drop(state);
+            app_data.audit_config("recipes", None, Some(recipe.name.clone()));
+            app_data.recipes.push(recipe);
+            self.select(Some(app_data.recipes.len() - 1), &app_data.recipes);
+            return;
+        }
+        let name = match self.downloading.take() {
+            Some(name) => name,
+            None => return,
+        };
+        app_data.audit_recipe(&name, &state.targets);
+    }
+
+    /// 每个从站的差异和执行结果
+    fn result_ui(&mut self, ui: &mut Ui) {
+        let job = match &self.job {
+            Some(job) => job,
+            None => return,
+        };
+        let state = job.state();
+        egui::ScrollArea::vertical()
+            .id_source("recipe_results")
+            .show(ui, |ui| {
+                for target in state.targets.iter() {
+                    let changed = target.changes.iter().filter(|c| c.changed()).count();
+                    let mut title = RichText::new(format!(
+                        "{}: {}, {} 个字段有变化",
+                        target.device,
+                        target.status.label(),
+                        changed
+                    ));
+                    if target.status.is_error() {
+                        title = title.color(Color32::RED);
+                    }
+                    egui::CollapsingHeader::new(title)
+                        .id_source(("recipe_target", &target.device))
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            if target.changes.is_empty() {
+                                return;
+                            }
+                            egui::Grid::new(("recipe_diff", &target.device))
+                                .num_columns(4)
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    ui.strong("字段");
+                                    ui.strong("当前值");
+                                    ui.strong("配方值");
+                                    ui.strong("状态");
+                                    ui.end_row();
+                                    for change in target.changes.iter() {
+                                        ui.label(&change.field);
+                                        ui.label(&change.old_text);
+                                        if change.changed() {
+                                            ui.colored_label(Color32::YELLOW, &change.new_text);
+                                        } else {
+                                            ui.label(&change.new_text);
+                                        }
+                                        if change.written {
+                                            ui.colored_label(Color32::GREEN, "已写入");
+                                        } else if change.changed() {
+                                            ui.label("未写入");
+                                        } else {
+                                            ui.label("相同");
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                }
+            });
+    }
+}
+
+/// 编辑框中的文本, 字符串不带引号
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// 编辑框的文本转换为 json, 不是合法的 json 时作为字符串
+fn parse_value(text: &str, field: &Field) -> Value {
+    if field.is_text() && field.count == 1 {
+        return Value::String(text.to_string());
+    }
+    serde_json::from_str(text.trim()).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+fn unique_name(recipes: &[Recipe], base: &str) -> String {
+    let exists = |name: &str| recipes.iter().any(|r| r.name == name);
+    if !exists(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|i| format!("{}-{}", base, i))
+        .find(|name| !exists(name))
+        .unwrap()
+}
+
+impl BasePage for RecipePage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Recipes {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+        self.finish_job(&mut app_data);
+
+        egui::SidePanel::left("recipe_list")
+            .resizable(true)
+            .default_width(260.0)
+            .show(ctx, |ui| {
+                ui.heading("配方");
+                self.list_ui(ui, &mut app_data);
+            });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let index = match self.selected.filter(|i| *i < app_data.recipes.len()) {
+                Some(index) => index,
+                None => {
+                    ui.label("选择或新建一个配方");
+                    return;
+                }
+            };
+            let map = app_data.register_map(&app_data.recipes[index].map).cloned();
+            let running = self.running();
+            ui.add_enabled_ui(!running, |ui| {
+                egui::ScrollArea::vertical()
+                    .id_source("recipe_editor")
+                    .max_height(ui.available_height() * 0.5)
+                    .show(ui, |ui| {
+                        self.editor_ui(ui, &mut app_data.recipes[index], map.as_ref());
+                    });
+            });
+            if let Some((old, new)) = self.recipe_audit.changes(ctx, &app_data.recipes[index]) {
+                let target = format!("recipes.{}", app_data.recipes[index].name);
+                app_data.audit_config(target, Some(old), Some(new));
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!running, egui::Button::new("删除配方"))
+                    .clicked()
+                {
+                    let recipe = app_data.recipes.remove(index);
+                    app_data.audit_config("recipes", Some(recipe.name), None);
+                    self.select(None, &app_data.recipes);
+                }
+            });
+            if self.selected.is_none() {
+                return;
+            }
+            ui.separator();
+            self.targets_ui(ui, &mut app_data, index);
+            ui.separator();
+            self.result_ui(ui);
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -0,0 +1,731 @@
+use epi::egui::{self, Color32, RichText, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    data::{
+        app_data::AppData,
+        audit::AuditAction,
+        command_queue::Command,
+        modbus_device::ModbusDevice,
+        register_map::{Area, DataType, Field, Order, OrderGuess, RegisterMap},
+    },
+    service::modbus::{
+        pdu::Request,
+        poller::{ModbusPoller, PollState},
+    },
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{
+    config_audit::ConfigAudit,
+    modbus_widgets::{hex_words, order_combo, transport_ui},
+    navigation::PageKind,
+    titlebar::MainTitlebar,
+};
+
+/// 正在编辑的值
+struct Editing {
+    area: Area,
+    field: String,
+    text: String,
+}
+
+/// 等待确认的写入, 值为显示用的文本
+struct PendingWrite {
+    area: Area,
+    field: Field,
+    old: Option<String>,
+    new: String,
+    request: Request,
+    raw: Vec<u16>,
+}
+
+/// Modbus 寄存器浏览, 按寄存器表显示从站的数据, 可以修改线圈和保持寄存器
+pub struct RegisterPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    /// 当前选择的从站
+    selected: usize,
+    poller: Option<ModbusPoller>,
+    /// 连接时使用的寄存器表
+    map: Option<RegisterMap>,
+    editing: Option<Editing>,
+    pending: Option<PendingWrite>,
+    /// 从站离线时写请求的排队有效期, 单位: 秒
+    queue_ttl: u64,
+    /// 记录选择的从站的设置修改, 切换从站时重新开始
+    device_audit: (usize, ConfigAudit),
+    /// 显示猜测字节序的窗口
+    guessing: bool,
+    error: Option<String>,
+}
+
+impl RegisterPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        let queue_ttl = app_data.read().command_queue.default_ttl;
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            selected: 0,
+            poller: None,
+            map: None,
+            editing: None,
+            pending: None,
+            queue_ttl,
+            device_audit: (0, ConfigAudit::default()),
+            guessing: false,
+            error: None,
+        }
+    }
+
+    fn connect(&mut self, app_data: &AppData) {
+        let device = match app_data.modbus_devices.get(self.selected) {
+            Some(device) => device,
+            None => return,
+        };
+        let map = match app_data.device_map(device) {
+            Ok(map) => map,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        match app_data.modbus.bus(&device.transport) {
+            Ok(bus) => {
+                self.poller = Some(ModbusPoller::start(
+                    bus,
+                    device.slave,
+                    map.all_read_blocks(),
+                    Duration::from_millis(device.poll_interval),
+                    app_data.repaint_signal(),
+                ));
+                self.map = Some(map);
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.poller = None;
+        self.map = None;
+        self.editing = None;
+        self.pending = None;
+    }
+
+    /// 从站选择和连接参数
+    fn connection_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let connected = self.poller.is_some();
+        ui.horizontal(|ui| {
+            ui.label("从站");
+            ui.add_enabled_ui(!connected, |ui| {
+                let selected_text = app_data
+                    .modbus_devices
+                    .get(self.selected)
+                    .map_or("", |d| d.name.as_str());
+                egui::ComboBox::from_id_source("register_device")
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        for (i, device) in app_data.modbus_devices.iter().enumerate() {
+                            ui.selectable_value(&mut self.selected, i, &device.name);
+                        }
+                    });
+                if ui.button("新建").clicked() {
+                    let name = format!("modbus-{}", app_data.modbus_devices.len() + 1);
+                    app_data.audit_config("modbus_devices", None, Some(name.clone()));
+                    app_data.modbus_devices.push(ModbusDevice {
+                        name,
+                        ..Default::default()
+                    });
+                    self.selected = app_data.modbus_devices.len() - 1;
+                    self.device_audit.0 = usize::MAX;
+                }
+                if ui.button("删除").clicked() && self.selected < app_data.modbus_devices.len() {
+                    let device = app_data.modbus_devices.remove(self.selected);
+                    app_data.audit_config("modbus_devices", Some(device.name), None);
+                    self.selected = self.selected.saturating_sub(1);
+                    self.device_audit.0 = usize::MAX;
+                }
+            });
+        });
+
+        let maps: Vec<String> = app_data
+            .register_maps
+            .iter()
+            .map(|m| m.name.clone())
+            .collect();
+        let device = match app_data.modbus_devices.get_mut(self.selected) {
+            Some(device) => device,
+            None => return,
+        };
+
+        ui.add_enabled_ui(!connected, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("名称");
+                ui.add(egui::TextEdit::singleline(&mut device.name).desired_width(120.0));
+                ui.label("地址");
+                ui.add(egui::DragValue::new(&mut device.slave).clamp_range(1..=247));
+                ui.label("寄存器表");
+                egui::ComboBox::from_id_source("register_map")
+                    .selected_text(&device.map)
+                    .show_ui(ui, |ui| {
+                        for map in maps {
+                            ui.selectable_value(&mut device.map, map.clone(), map);
+                        }
+                    });
+                ui.label("字节序");
+                order_combo(ui, "register_order", &mut device.order);
+                if !device.field_orders.is_empty() {
+                    ui.label(format!("{} 个字段单独设置", device.field_orders.len()))
+                        .on_hover_text(
+                            device
+                                .field_orders
+                                .iter()
+                                .map(|(name, order)| format!("{}: {}", name, order.label()))
+                                .collect::<Vec<_>>()
+                                .join("\n"),
+                        );
+                    if ui.small_button("清除").clicked() {
+                        device.field_orders.clear();
+                    }
+                }
+            });
+            transport_ui(ui, "register_transport", &mut device.transport);
+        });
+
+        let mut connect = false;
+        ui.horizontal(|ui| {
+            ui.label("轮询间隔");
+            let response = ui.add(
+                egui::DragValue::new(&mut device.poll_interval)
+                    .clamp_range(100..=60_000)
+                    .speed(10)
+                    .suffix(" ms"),
+            );
+            if response.changed() {
+                if let Some(poller) = &self.poller {
+                    poller.set_interval(Duration::from_millis(device.poll_interval));
+                }
+            }
+
+            if connected {
+                if ui.button("断开").clicked() {
+                    self.disconnect();
+                }
+            } else {
+                connect = ui.button("连接").clicked();
+            }
+            if ui
+                .add_enabled(connected, egui::Button::new("猜测字节序"))
+                .on_hover_text("按读取到的值猜测 32 和 64 位字段的字节顺序")
+                .clicked()
+            {
+                self.guessing = true;
+            }
+
+            if let Some(poller) = &self.poller {
+                let state = poller.state();
+                ui.label(format!(
+                    "{}, 请求 {} 次, 失败 {} 次",
+                    poller.label(),
+                    state.polls,
+                    state.failures
+                ));
+                match &state.last_write {
+                    Some(Ok(message)) => {
+                        ui.label(format!("已写入 {}", message));
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(Color32::RED, format!("写入失败: {}", e));
+                    }
+                    None => {}
+                }
+            }
+        });
+
+        if self.device_audit.0 != self.selected {
+            self.device_audit = (self.selected, ConfigAudit::default());
+        }
+        if let Some(device) = app_data.modbus_devices.get(self.selected) {
+            if let Some((old, new)) = self.device_audit.1.changes(ui.ctx(), device) {
+                let target = format!("modbus_devices.{}", device.name);
+                app_data.audit_config(target, Some(old), Some(new));
+            }
+        }
+
+        if connect {
+            self.connect(app_data);
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+    }
+
+    /// 一个数据区的表格
+    fn area_ui(&mut self, ui: &mut Ui, area: Area, map: &RegisterMap, state: &PollState) {
+        let fields = map.fields(area);
+        if fields.is_empty() {
+            return;
+        }
+        egui::CollapsingHeader::new(format!("{} ({})", area.label(), fields.len()))
+            .id_source(area)
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::Grid::new(("register_grid", area))
+                    .num_columns(7)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("名称");
+                        ui.strong("地址");
+                        ui.strong("类型");
+                        ui.strong("值");
+                        ui.strong("原始值");
+                        ui.strong("单位");
+                        ui.strong("读取时间");
+                        ui.end_row();
+
+                        for field in fields.iter().flat_map(|f| f.elements()) {
+                            self.row_ui(ui, area, &field, state);
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    fn row_ui(&mut self, ui: &mut Ui, area: Area, field: &Field, state: &PollState) {
+        let len = field.len();
+        let raw = state.raw(area, field.address, len);
+        let value = raw
+            .as_ref()
+            .and_then(|raw| field.display(raw).ok())
+            .and_then(|values| values.into_iter().next());
+        let error = state.error(area, field.address, len);
+
+        let name = RichText::new(&field.name);
+        let name = if error.is_some() {
+            name.color(Color32::RED)
+        } else {
+            name
+        };
+        let response = ui.label(name);
+        if !field.description.is_empty() {
+            response.on_hover_text(&field.description);
+        }
+        ui.label(field.address.to_string());
+        ui.label(field.data_type.label());
+
+        let editing = self
+            .editing
+            .as_ref()
+            .filter(|e| e.area == area && e.field == field.name)
+            .is_some();
+        if editing {
+            self.edit_ui(ui, area, field, value, raw.as_deref());
+        } else {
+            let text = match (&value, error) {
+                (_, Some(e)) => RichText::new(e.to_string()).color(Color32::RED),
+                (Some(value), None) => RichText::new(value),
+                (None, None) => RichText::new("-"),
+            };
+            if let (true, Some(value)) = (field.writable(area), value) {
+                let response = ui.add(egui::Button::new(text).frame(false));
+                if response.on_hover_text("点击修改").clicked() {
+                    self.begin_edit(area, field, value, raw.as_deref());
+                }
+            } else {
+                ui.label(text);
+            }
+        }
+
+        ui.label(raw.as_deref().map_or_else(String::new, hex_words));
+        ui.label(&field.unit);
+        ui.label(
+            state
+                .read_time(area, field.address, len)
+                .map_or_else(String::new, |t| t.format("%H:%M:%S%.3f").to_string()),
+        );
+    }
+
+    /// 开始修改, bool 类型直接切换并等待确认
+    fn begin_edit(&mut self, area: Area, field: &Field, value: String, raw: Option<&[u16]>) {
+        if field.data_type == DataType::Bool {
+            let new = if value == "ON" { "OFF" } else { "ON" };
+            self.prepare_write(area, field, Some(value), new, raw.unwrap_or_default());
+        } else {
+            self.editing = Some(Editing {
+                area,
+                field: field.name.clone(),
+                text: value,
+            });
+        }
+    }
+
+    fn edit_ui(
+        &mut self,
+        ui: &mut Ui,
+        area: Area,
+        field: &Field,
+        value: Option<String>,
+        raw: Option<&[u16]>,
+    ) {
+        let mut submit = false;
+        let mut cancel = false;
+        let width = if field.is_text() { 160.0 } else { 80.0 };
+        ui.horizontal(|ui| {
+            let editing = self.editing.as_mut().unwrap();
+            let response =
+                ui.add(egui::TextEdit::singleline(&mut editing.text).desired_width(width));
+            if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+                submit = true;
+            }
+            submit |= ui.small_button("✔").clicked();
+            cancel = ui.small_button("✖").clicked();
+        });
+        if cancel {
+            self.editing = None;
+        } else if submit {
+            let editing = self.editing.take().unwrap();
+            // 字符串保留首尾的空格
+            let text = if field.is_text() {
+                editing.text.as_str()
+            } else {
+                editing.text.trim()
+            };
+            if self.prepare_write(area, field, value, text, raw.unwrap_or_default()) {
+                self.editing = None;
+            } else {
+                self.editing = Some(editing);
+            }
+        }
+    }
+
+    /// 编码新值并等待确认, 值无效时返回 false
+    fn prepare_write(
+        &mut self,
+        area: Area,
+        field: &Field,
+        old: Option<String>,
+        new: &str,
+        current: &[u16],
+    ) -> bool {
+        let encoded = if field.is_text() {
+            field
+                .encode_text(&[new.to_string()])
+                .map(|raw| (raw, new.to_string()))
+        } else {
+            let value = match new.to_ascii_uppercase().as_str() {
+                "ON" => Some(1.0),
+                "OFF" => Some(0.0),
+                text => text.parse::<f64>().ok(),
+            };
+            match value {
+                Some(value) => field
+                    .encode(&[value], current)
+                    .map(|raw| (raw, field.format_value(value))),
+                None => {
+                    self.error = Some(format!("{}: 无效的数值 {}", field.name, new));
+                    return false;
+                }
+            }
+        };
+        let result = encoded.and_then(|(raw, new)| {
+            area.write_request(field.address, raw.clone())
+                .map(|request| (request, raw, new))
+                .ok_or_else(|| format!("{} 不可写", area.label()).into())
+        });
+        match result {
+            Ok((request, raw, new)) => {
+                self.pending = Some(PendingWrite {
+                    area,
+                    field: field.clone(),
+                    old,
+                    new,
+                    request,
+                    raw,
+                });
+                true
+            }
+            Err(e) => {
+                self.error = Some(e.to_string());
+                false
+            }
+        }
+    }
+
+    /// 按当前读取的值猜测 32 和 64 位字段的字节顺序, 选择后重新连接
+    fn guess_ui(&mut self, ctx: &egui::Context, app_data: &mut AppData) {
+        if !self.guessing {
+            return;
+        }
+        let (poller, map) = match (&self.poller, &self.map) {
+            (Some(poller), Some(map)) => (poller, map),
+            _ => {
+                self.guessing = false;
+                return;
+            }
+        };
+        let state = poller.state();
+        // 数组只使用第一个元素
+        let guesses: Vec<(Field, Vec<OrderGuess>)> = Area::ALL
+            .into_iter()
+            .flat_map(|area| map.fields(area).iter().map(move |f| (area, f.element(0))))
+            .filter(|(_, field)| field.data_type.is_multi_register())
+            .filter_map(|(area, field)| {
+                let raw = state.raw(area, field.address, field.len())?;
+                let guesses = field.guess_order(&raw);
+                Some((field, guesses))
+            })
+            .collect();
+        drop(state);
+        let mut totals: Vec<(Order, u32)> = Order::ALL
+            .into_iter()
+            .map(|order| {
+                let score = guesses
+                    .iter()
+                    .flat_map(|(_, g)| g.iter())
+                    .filter(|g| g.order == order)
+                    .map(|g| g.score)
+                    .sum();
+                (order, score)
+            })
+            .collect();
+        totals.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+        let mut open = true;
+        let mut apply_device = None;
+        let mut apply_field = None;
+        egui::Window::new("猜测字节序")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                if guesses.is_empty() {
+                    ui.label("没有读取到 32 或 64 位字段的值");
+                    return;
+                }
+                ui.label("按数值是否合理排序, 第一个最可能是正确的字节序");
+                egui::Grid::new("order_guess").striped(true).show(ui, |ui| {
+                    ui.strong("字段");
+                    ui.strong("当前");
+                    for i in 1..=Order::ALL.len() {
+                        ui.strong(format!("候选 {}", i));
+                    }
+                    ui.end_row();
+                    for (field, guesses) in guesses.iter() {
+                        ui.label(&field.name);
+                        ui.label(field.order().label());
+                        for (i, guess) in guesses.iter().enumerate() {
+                            let text = format!(
+                                "{}: {}",
+                                guess.order.label(),
+                                field.format_value(guess.value)
+                            );
+                            let text = if i == 0 {
+                                RichText::new(text).strong()
+                            } else {
+                                RichText::new(text)
+                            };
+                            if ui
+                                .add(egui::Button::new(text).frame(false))
+                                .on_hover_text("只对这个字段使用")
+                                .clicked()
+                            {
+                                apply_field = Some((field.name.clone(), guess.order));
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let (best, _) = totals[0];
+                    ui.label(format!("整个设备最可能是 {}", best.label()));
+                    if ui
+                        .button(format!("设备使用 {}", best.label()))
+                        .on_hover_text("寄存器表中未配置字节序的字段使用, 清除字段的单独设置")
+                        .clicked()
+                    {
+                        apply_device = Some(best);
+                    }
+                });
+            });
+        if !open {
+            self.guessing = false;
+        }
+
+        let device = match app_data.modbus_devices.get_mut(self.selected) {
+            Some(device) => device,
+            None => return,
+        };
+        let target = format!("modbus_devices.{}", device.name);
+        let (old, new) = if let Some(order) = apply_device {
+            let old = device.order.label();
+            device.order = order;
+            device.field_orders.clear();
+            (old.to_string(), order.label().to_string())
+        } else if let Some((name, order)) = apply_field {
+            // 数组元素的名称包含下标, 设置整个数组
+            let name = name.split('[').next().unwrap_or_default().to_string();
+            let old = device.field_orders.insert(name.clone(), order);
+            (
+                format!("{}: {}", name, old.map_or("-", |o| o.label())),
+                format!("{}: {}", name, order.label()),
+            )
+        } else {
+            return;
+        };
+        app_data.audit_config(target, Some(old), Some(new));
+        // 已经记录, 不再作为设置的修改记录一次
+        self.device_audit.1 = ConfigAudit::default();
+        self.disconnect();
+        self.connect(app_data);
+    }
+
+    /// 写入确认对话框, 确认后记录审计日志
+    fn confirm_ui(&mut self, ctx: &egui::Context, app_data: &mut AppData) {
+        let pending = match &self.pending {
+            Some(pending) => pending,
+            None => return,
+        };
+        let device = app_data.modbus_devices.get(self.selected);
+        let offline = device.and_then(|d| app_data.modbus_online(d)) == Some(false);
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("确认写入")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                let field = &pending.field;
+                ui.label(format!(
+                    "{} {} {}",
+                    pending.area.label(),
+                    field.address,
+                    field.name
+                ));
+                ui.label(format!(
+                    "{} → {} {}",
+                    pending.old.as_deref().unwrap_or("-"),
+                    pending.new,
+                    field.unit
+                ));
+                ui.label(format!("原始值: {}", hex_words(&pending.raw)));
+                if offline {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(Color32::YELLOW, "从站离线, 写入排队等待上线, 有效期");
+                        ui.add(
+                            egui::DragValue::new(&mut self.queue_ttl)
+                                .clamp_range(10..=7 * 24 * 3600)
+                                .suffix(" 秒"),
+                        );
+                    });
+                }
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("确认").clicked();
+                    cancelled = ui.button("取消").clicked();
+                });
+            });
+
+        if confirmed {
+            let pending = self.pending.take().unwrap();
+            let device = app_data
+                .modbus_devices
+                .get(self.selected)
+                .map_or_else(String::new, |d| d.name.clone());
+            let field = &pending.field;
+            if offline {
+                let command = Command::Write {
+                    request: pending.request,
+                    description: format!("{} = {} {}", field.name, pending.new, field.unit),
+                };
+                app_data.queue_modbus_command(&device, command, pending.old, Some(self.queue_ttl));
+                return;
+            }
+            app_data.audit.record(
+                AuditAction::RegisterWrite,
+                format!("{}.{}", device, field.name),
+                pending.old.clone(),
+                Some(pending.new.clone()),
+            );
+            if let Some(poller) = &self.poller {
+                poller.write(pending.request, field.name.clone());
+            }
+        } else if cancelled {
+            self.pending = None;
+        }
+    }
+}
+
+impl BasePage for RegisterPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Registers {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::TopBottomPanel::top("register_connection").show(ctx, |ui| {
+            ui.heading("Modbus 寄存器");
+            self.connection_ui(ui, &mut app_data);
+            ui.add_space(4.0);
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let (poller, map) = match (self.poller.take(), self.map.take()) {
+                (Some(poller), Some(map)) => (poller, map),
+                (poller, map) => {
+                    self.poller = poller;
+                    self.map = map;
+                    ui.label("未连接");
+                    return;
+                }
+            };
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let state = poller.state();
+                for area in Area::ALL {
+                    self.area_ui(ui, area, &map, &state);
+                }
+            });
+            self.poller = Some(poller);
+            self.map = Some(map);
+        });
+
+        self.guess_ui(ctx, &mut app_data);
+        self.confirm_ui(ctx, &mut app_data);
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
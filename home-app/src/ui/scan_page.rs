@@ -0,0 +1,319 @@
+use epi::egui::{self, Color32, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{
+    data::{app_data::AppData, modbus_device::ModbusDevice},
+    service::modbus::{
+        rtu::{available_ports, Parity, SerialMode, StopBits},
+        scanner::{BusScanner, ScanResult, SCAN_BAUD_RATES},
+        TransportConfig,
+    },
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{
+    config_audit::ConfigAudit,
+    modbus_widgets::{parity_label, stop_bits_label},
+    navigation::PageKind,
+    titlebar::MainTitlebar,
+};
+
+/// 扫描串口总线上的从站地址和串口参数, 结果可以保存为 Modbus 设备
+pub struct ScanPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    scanner: Option<BusScanner>,
+    error: Option<String>,
+    /// 记录配置的修改
+    config_audit: ConfigAudit,
+}
+
+impl ScanPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            scanner: None,
+            error: None,
+            config_audit: ConfigAudit::default(),
+        }
+    }
+
+    fn scanning(&self) -> bool {
+        self.scanner
+            .as_ref()
+            .map_or(false, |scanner| !scanner.state().finished)
+    }
+
+    fn config_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let scanning = self.scanning();
+        let config = &mut app_data.scan_config;
+        ui.add_enabled_ui(!scanning, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("串口");
+                ui.add(egui::TextEdit::singleline(&mut config.port).desired_width(110.0));
+                egui::ComboBox::from_id_source("scan_port")
+                    .selected_text("选择")
+                    .width(50.0)
+                    .show_ui(ui, |ui| {
+                        for port in available_ports() {
+                            ui.selectable_value(&mut config.port, port.clone(), port);
+                        }
+                    });
+                egui::ComboBox::from_id_source("scan_stop_bits")
+                    .selected_text(stop_bits_label(config.stop_bits))
+                    .width(60.0)
+                    .show_ui(ui, |ui| {
+                        for stop_bits in [StopBits::One, StopBits::Two] {
+                            ui.selectable_value(
+                                &mut config.stop_bits,
+                                stop_bits,
+                                stop_bits_label(stop_bits),
+                            );
+                        }
+                    });
+                egui::ComboBox::from_id_source("scan_mode")
+                    .selected_text(config.mode.label())
+                    .width(60.0)
+                    .show_ui(ui, |ui| {
+                        for mode in [SerialMode::Rtu, SerialMode::Ascii] {
+                            ui.selectable_value(&mut config.mode, mode, mode.label());
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("波特率");
+                for baud in SCAN_BAUD_RATES {
+                    toggle(ui, &mut config.baud_rates, baud, &baud.to_string());
+                }
+                config.baud_rates.sort_unstable();
+            });
+            ui.horizontal(|ui| {
+                ui.label("校验");
+                for parity in [Parity::None, Parity::Even, Parity::Odd] {
+                    toggle(ui, &mut config.parities, parity, parity_label(parity));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("地址");
+                ui.add(egui::DragValue::new(&mut config.first).clamp_range(1..=247));
+                ui.label("-");
+                ui.add(egui::DragValue::new(&mut config.last).clamp_range(1..=247));
+                ui.label("超时");
+                ui.add(
+                    egui::DragValue::new(&mut config.timeout)
+                        .clamp_range(10..=5000)
+                        .suffix(" ms"),
+                );
+                ui.checkbox(&mut config.probe_functions, "探测功能码");
+                ui.add_enabled_ui(config.probe_functions, |ui| {
+                    ui.checkbox(&mut config.probe_writes, "包括写功能码")
+                        .on_hover_text("发送非法的写请求, 符合规范的从站返回异常, 不会写入");
+                });
+                ui.checkbox(&mut config.stop_on_found, "发现从站后停止")
+                    .on_hover_text("某个串口参数下发现从站后, 不再尝试其它参数");
+            });
+        });
+
+        ui.horizontal(|ui| {
+            if scanning {
+                if ui.button("取消").clicked() {
+                    if let Some(scanner) = &self.scanner {
+                        scanner.cancel();
+                    }
+                }
+            } else if ui.button("开始扫描").clicked() {
+                let config = app_data.scan_config.clone();
+                if config.total() == 0 {
+                    self.error = Some("请至少选择一个波特率和校验方式".to_string());
+                } else {
+                    match BusScanner::start(config, app_data.repaint_signal()) {
+                        Ok(scanner) => {
+                            self.scanner = Some(scanner);
+                            self.error = None;
+                        }
+                        Err(e) => self.error = Some(e.to_string()),
+                    }
+                }
+            }
+
+            if let Some(scanner) = &self.scanner {
+                let state = scanner.state();
+                let progress = state.done as f32 / state.total.max(1) as f32;
+                let text = if state.finished {
+                    format!("完成, 发现 {} 个从站", state.results.len())
+                } else {
+                    format!("{}/{} {}", state.done, state.total, state.current)
+                };
+                ui.add(
+                    egui::ProgressBar::new(progress)
+                        .desired_width(360.0)
+                        .text(text),
+                );
+                if state.invalid > 0 {
+                    ui.label(format!("无效响应 {} 次", state.invalid))
+                        .on_hover_text("较多时说明串口参数不对或线路有干扰");
+                }
+                if let Some(e) = &state.error {
+                    ui.colored_label(Color32::RED, e);
+                }
+            }
+        });
+
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+    }
+
+    fn results_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let results = match &self.scanner {
+            Some(scanner) => scanner.state().results.clone(),
+            None => {
+                ui.label("未扫描");
+                return;
+            }
+        };
+        if results.is_empty() {
+            ui.label("没有发现从站");
+            return;
+        }
+
+        let mut save = vec![];
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("scan_results")
+                .num_columns(6)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("地址");
+                    ui.strong("串口参数");
+                    ui.strong("延迟");
+                    ui.strong("支持的功能码");
+                    ui.strong("异常");
+                    ui.strong("");
+                    ui.end_row();
+
+                    for result in results.iter() {
+                        ui.label(result.slave.to_string());
+                        ui.label(result.serial.line_label());
+                        ui.label(format!("{:.1} ms", result.latency.as_secs_f64() * 1000.0));
+                        ui.label(
+                            result
+                                .supported_functions()
+                                .iter()
+                                .map(|f| format!("{:02X}", f))
+                                .collect::<Vec<_>>()
+                                .join(" "),
+                        );
+                        ui.label(
+                            result
+                                .exceptions()
+                                .iter()
+                                .map(|e| e.to_string())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        );
+                        let name = device_name(result);
+                        if app_data.modbus_devices.iter().any(|d| d.name == name) {
+                            ui.label("已保存");
+                        } else if ui.button("保存为设备").clicked() {
+                            save.push(result.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+            if ui.button("全部保存").clicked() {
+                save = results.clone();
+            }
+        });
+
+        for result in save {
+            let name = device_name(&result);
+            if app_data.modbus_devices.iter().any(|d| d.name == name) {
+                continue;
+            }
+            app_data.audit_config("modbus_devices", None, Some(name.clone()));
+            app_data.modbus_devices.push(ModbusDevice {
+                name,
+                transport: TransportConfig::Rtu(result.serial),
+                slave: result.slave,
+                ..Default::default()
+            });
+        }
+    }
+}
+
+/// 勾选时加入列表, 取消时移除
+fn toggle<T: PartialEq + Copy>(ui: &mut Ui, values: &mut Vec<T>, value: T, label: &str) {
+    let mut checked = values.contains(&value);
+    if ui.checkbox(&mut checked, label).changed() {
+        if checked {
+            values.push(value);
+        } else {
+            values.retain(|v| *v != value);
+        }
+    }
+}
+
+fn device_name(result: &ScanResult) -> String {
+    format!("{}-{}", result.serial.port, result.slave)
+}
+
+impl BasePage for ScanPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Scanner {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::TopBottomPanel::top("scan_config").show(ctx, |ui| {
+            ui.heading("Modbus 总线扫描");
+            self.config_ui(ui, &mut app_data);
+            ui.add_space(4.0);
+        });
+
+        if let Some((old, new)) = self.config_audit.changes(ctx, &app_data.scan_config) {
+            app_data.audit_config("bus_scan", Some(old), Some(new));
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.results_ui(ui, &mut app_data);
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -0,0 +1,373 @@
+use epi::egui::{self, Color32, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::{hash::Hash, sync::Arc};
+
+use crate::{
+    data::{
+        app_data::AppData,
+        register_map::{Area, DataType, RegisterMap},
+    },
+    service::modbus::simulator::{FieldScript, Simulator, Waveform},
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{
+    config_audit::ConfigAudit,
+    modbus_widgets::{hex_words, listen_ui},
+    navigation::PageKind,
+    titlebar::MainTitlebar,
+};
+
+/// 应用内的 Modbus 从站模拟器, 用于在没有开发板时调试寄存器浏览等功能
+pub struct SimulatorPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    error: Option<String>,
+    /// 记录配置的修改
+    config_audit: ConfigAudit,
+}
+
+impl SimulatorPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            error: None,
+            config_audit: ConfigAudit::default(),
+        }
+    }
+
+    /// 模拟器参数, 启动后不能修改
+    fn config_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let running = app_data.simulator.is_some();
+        let maps: Vec<String> = app_data
+            .register_maps
+            .iter()
+            .map(|m| m.name.clone())
+            .collect();
+        let config = &mut app_data.simulator_config;
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("寄存器表");
+                egui::ComboBox::from_id_source("simulator_map")
+                    .selected_text(&config.map)
+                    .show_ui(ui, |ui| {
+                        for map in maps {
+                            ui.selectable_value(&mut config.map, map.clone(), map);
+                        }
+                    });
+                ui.label("地址");
+                ui.add(egui::DragValue::new(&mut config.slave).clamp_range(1..=247));
+                ui.label("脚本间隔");
+                ui.add(
+                    egui::DragValue::new(&mut config.interval)
+                        .clamp_range(10..=60_000)
+                        .speed(10)
+                        .suffix(" ms"),
+                );
+            });
+            listen_ui(ui, "simulator_listen", &mut config.listen);
+        });
+
+        let mut start = false;
+        let mut stop = false;
+        ui.horizontal(|ui| {
+            if running {
+                stop = ui.button("停止").clicked();
+            } else {
+                start = ui.button("启动").clicked();
+            }
+            match &app_data.simulator {
+                Some(simulator) if simulator.is_running() => {
+                    let image = simulator.image();
+                    ui.label(format!(
+                        "{}, 请求 {} 次, 异常响应 {} 次",
+                        simulator.label(),
+                        image.requests,
+                        image.exceptions
+                    ));
+                }
+                Some(_) => {
+                    ui.colored_label(Color32::RED, "从站已停止, 详见日志");
+                }
+                None => {}
+            }
+        });
+
+        self.scripts_ui(ui, app_data, running);
+
+        if start {
+            self.error = app_data.start_simulator().err().map(|e| e.to_string());
+        } else if stop {
+            app_data.simulator = None;
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+    }
+
+    fn scripts_ui(&mut self, ui: &mut Ui, app_data: &mut AppData, running: bool) {
+        let fields: Vec<String> = app_data
+            .register_map(&app_data.simulator_config.map)
+            .map(field_names)
+            .unwrap_or_default();
+        let scripts = &mut app_data.simulator_config.scripts;
+        egui::CollapsingHeader::new(format!("脚本 ({})", scripts.len()))
+            .id_source("simulator_scripts")
+            .default_open(true)
+            .show(ui, |ui| {
+                if running {
+                    ui.label("停止后才能修改脚本");
+                }
+                ui.add_enabled_ui(!running, |ui| {
+                    let mut remove = None;
+                    egui::Grid::new("simulator_script_grid")
+                        .num_columns(4)
+                        .show(ui, |ui| {
+                            for (i, script) in scripts.iter_mut().enumerate() {
+                                field_combo(ui, ("script_field", i), &mut script.field, &fields);
+                                waveform_combo(ui, i, &mut script.waveform);
+                                ui.horizontal(|ui| {
+                                    waveform_ui(ui, i, &mut script.waveform, &fields)
+                                });
+                                if ui.small_button("删除").clicked() {
+                                    remove = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    if let Some(i) = remove {
+                        scripts.remove(i);
+                    }
+                    if ui.button("添加脚本").clicked() {
+                        scripts.push(FieldScript {
+                            field: fields.first().cloned().unwrap_or_default(),
+                            waveform: Waveform::from_index(0),
+                        });
+                    }
+                });
+            });
+    }
+
+    /// 从站当前的值, 修改后直接写入寄存器镜像, 模拟设备自身的变化
+    fn values_ui(&mut self, ui: &mut Ui, simulator: &Simulator) {
+        let map = simulator.map();
+        let mut image = simulator.image();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for area in Area::ALL {
+                let fields = map.fields(area);
+                if fields.is_empty() {
+                    continue;
+                }
+                egui::CollapsingHeader::new(format!("{} ({})", area.label(), fields.len()))
+                    .id_source(("simulator", area))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::Grid::new(("simulator_grid", area))
+                            .num_columns(5)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.strong("名称");
+                                ui.strong("地址");
+                                ui.strong("类型");
+                                ui.strong("值");
+                                ui.strong("原始值");
+                                ui.end_row();
+
+                                for field in fields.iter().flat_map(|f| f.elements()) {
+                                    ui.label(&field.name);
+                                    ui.label(field.address.to_string());
+                                    ui.label(field.data_type.label());
+                                    if field.is_text() {
+                                        let mut text = image
+                                            .display(area, &field)
+                                            .and_then(|texts| texts.first().cloned())
+                                            .unwrap_or_default();
+                                        if ui.text_edit_singleline(&mut text).changed() {
+                                            if let Err(e) = image.set_text(area, &field, &[text]) {
+                                                self.error = Some(e.to_string());
+                                            }
+                                        }
+                                        ui.label(
+                                            image
+                                                .read(area, field.address, field.len())
+                                                .map_or_else(
+                                                    |_| String::new(),
+                                                    |raw| hex_words(&raw),
+                                                ),
+                                        );
+                                        ui.end_row();
+                                        continue;
+                                    }
+                                    let mut value = image
+                                        .get(area, &field)
+                                        .and_then(|values| values.first().copied())
+                                        .unwrap_or_default();
+                                    let changed = if field.data_type == DataType::Bool {
+                                        let mut on = value != 0.0;
+                                        let changed = ui.checkbox(&mut on, "").changed();
+                                        value = on as u8 as f64;
+                                        changed
+                                    } else {
+                                        ui.add(egui::DragValue::new(&mut value).speed(0.1))
+                                            .changed()
+                                    };
+                                    if changed {
+                                        if let Err(e) = image.set(area, &field, &[value]) {
+                                            self.error = Some(e.to_string());
+                                        }
+                                    }
+                                    ui.label(
+                                        image
+                                            .read(area, field.address, field.len())
+                                            .map_or_else(|_| String::new(), |raw| hex_words(&raw)),
+                                    );
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            }
+        });
+    }
+}
+
+fn field_names(map: &RegisterMap) -> Vec<String> {
+    Area::ALL
+        .into_iter()
+        .flat_map(|area| {
+            map.fields(area)
+                .iter()
+                .filter(|f| !f.is_text())
+                .map(|f| f.name.clone())
+        })
+        .collect()
+}
+
+fn field_combo(ui: &mut Ui, id: impl Hash, value: &mut String, fields: &[String]) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(value.as_str())
+        .show_ui(ui, |ui| {
+            for field in fields {
+                ui.selectable_value(value, field.clone(), field);
+            }
+        });
+}
+
+fn waveform_combo(ui: &mut Ui, index: usize, waveform: &mut Waveform) {
+    let current = waveform.label();
+    egui::ComboBox::from_id_source(("script_waveform", index))
+        .selected_text(current)
+        .show_ui(ui, |ui| {
+            for (i, label) in Waveform::ALL.iter().enumerate() {
+                if ui.selectable_label(*label == current, *label).clicked() && *label != current {
+                    *waveform = Waveform::from_index(i);
+                }
+            }
+        });
+}
+
+fn period_value(value: &mut f64) -> egui::DragValue {
+    egui::DragValue::new(value)
+        .clamp_range(0.1..=3600.0)
+        .speed(0.1)
+        .suffix(" s")
+}
+
+fn waveform_ui(ui: &mut Ui, index: usize, waveform: &mut Waveform, fields: &[String]) {
+    match waveform {
+        Waveform::Sine {
+            amplitude,
+            offset,
+            period,
+        } => {
+            ui.label("幅值");
+            ui.add(egui::DragValue::new(amplitude).speed(0.1));
+            ui.label("偏移");
+            ui.add(egui::DragValue::new(offset).speed(0.1));
+            ui.label("周期");
+            ui.add(period_value(period));
+        }
+        Waveform::RandomWalk { step, min, max } => {
+            ui.label("步长");
+            ui.add(egui::DragValue::new(step).speed(0.01));
+            ui.label("最小");
+            ui.add(egui::DragValue::new(min).speed(0.1));
+            ui.label("最大");
+            ui.add(egui::DragValue::new(max).speed(0.1));
+        }
+        Waveform::Ramp { min, max, period } => {
+            ui.label("最小");
+            ui.add(egui::DragValue::new(min).speed(0.1));
+            ui.label("最大");
+            ui.add(egui::DragValue::new(max).speed(0.1));
+            ui.label("周期");
+            ui.add(period_value(period));
+        }
+        Waveform::Echo { source } => {
+            ui.label("来源");
+            field_combo(ui, ("script_source", index), source, fields);
+        }
+    }
+}
+
+impl BasePage for SimulatorPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Simulator {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::TopBottomPanel::top("simulator_config").show(ctx, |ui| {
+            ui.heading("Modbus 模拟从站");
+            self.config_ui(ui, &mut app_data);
+            ui.add_space(4.0);
+        });
+
+        if let Some((old, new)) = self.config_audit.changes(ctx, &app_data.simulator_config) {
+            app_data.audit_config("simulator", Some(old), Some(new));
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| match &app_data.simulator {
+            Some(simulator) => self.values_ui(ui, simulator),
+            None => {
+                ui.label("未启动");
+            }
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -0,0 +1,354 @@
+use chrono::Local;
+use epi::egui::{self, Color32, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{
+    data::{app_data::AppData, storage::persistence::app_dir},
+    service::modbus::{
+        hex,
+        sniffer::{export_csv, FrameKind, SniffedFrame, Sniffer},
+    },
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{
+    config_audit::ConfigAudit, modbus_widgets::serial_ui, navigation::PageKind,
+    titlebar::MainTitlebar,
+};
+
+/// 被动监听串口总线上的 rtu 或 ascii 报文, 或打开抓包文件, 按时间顺序显示解码后的帧
+pub struct SnifferPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    sniffer: Option<Sniffer>,
+    /// 只显示异常和无效帧
+    problems_only: bool,
+    /// 自动滚动到最新的帧
+    follow: bool,
+    selected: Option<usize>,
+    message: Option<Result<String, String>>,
+    /// 记录配置的修改
+    config_audit: ConfigAudit,
+}
+
+impl SnifferPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            sniffer: None,
+            problems_only: false,
+            follow: true,
+            selected: None,
+            message: None,
+            config_audit: ConfigAudit::default(),
+        }
+    }
+
+    fn running(&self) -> bool {
+        self.sniffer.as_ref().map_or(false, |s| s.is_running())
+    }
+
+    fn config_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let running = self.running();
+        let maps: Vec<String> = app_data
+            .register_maps
+            .iter()
+            .map(|m| m.name.clone())
+            .collect();
+        let config = &mut app_data.sniffer_config;
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("串口");
+                serial_ui(ui, "sniffer_serial", &mut config.serial);
+                ui.label("寄存器表");
+                egui::ComboBox::from_id_source("sniffer_map")
+                    .selected_text(if config.map.is_empty() {
+                        "无"
+                    } else {
+                        &config.map
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut config.map, String::new(), "无");
+                        for map in maps {
+                            ui.selectable_value(&mut config.map, map.clone(), map);
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("抓包文件");
+                ui.add(egui::TextEdit::singleline(&mut config.path).desired_width(320.0))
+                    .on_hover_text("每行 \"<微秒> <十六进制字节>\", 扩展名为 bin 时按原始字节读取");
+            });
+        });
+
+        ui.horizontal(|ui| {
+            if running {
+                if ui.button("停止").clicked() {
+                    if let Some(sniffer) = &self.sniffer {
+                        sniffer.stop();
+                    }
+                }
+            } else {
+                let map = app_data.register_map(&app_data.sniffer_config.map).cloned();
+                let config = &app_data.sniffer_config;
+                if ui.button("开始监听").clicked() {
+                    self.open(Sniffer::start(
+                        &config.serial,
+                        map.clone(),
+                        app_data.repaint_signal(),
+                    ));
+                }
+                if ui.button("打开文件").clicked() {
+                    self.open(Sniffer::load(&config.path, &config.serial, map));
+                }
+                if ui.button("清空").clicked() {
+                    self.sniffer = None;
+                    self.selected = None;
+                }
+            }
+
+            if let Some(sniffer) = &self.sniffer {
+                let capture = sniffer.capture();
+                let frames = capture.frames();
+                let invalid = frames
+                    .iter()
+                    .filter(|f| f.kind == FrameKind::Invalid)
+                    .count();
+                ui.label(format!(
+                    "{}: {} 字节, {} 帧, 无效 {} 帧",
+                    sniffer.label(),
+                    capture.bytes,
+                    frames.len(),
+                    invalid
+                ));
+                if let Some(e) = &capture.error {
+                    ui.colored_label(Color32::RED, e);
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.problems_only, "只显示异常和无效帧");
+            ui.checkbox(&mut self.follow, "跟随最新");
+            if let Some(sniffer) = &self.sniffer {
+                let time = Local::now().format("%Y%m%d-%H%M%S");
+                if ui.button("导出 csv").clicked() {
+                    let path = app_dir().join(format!("sniffer-{}.csv", time));
+                    self.message = Some(
+                        export_csv(sniffer.capture().frames(), &path)
+                            .map(|n| format!("已导出 {} 帧到 {}", n, path.display()))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+                if ui.button("保存抓包").clicked() {
+                    let path = app_dir().join(format!("sniffer-{}.log", time));
+                    self.message = Some(
+                        sniffer
+                            .capture()
+                            .save(&path)
+                            .map(|_| format!("已保存到 {}", path.display()))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+            }
+            match &self.message {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(Color32::RED, e);
+                }
+                None => {}
+            }
+        });
+    }
+
+    fn open(&mut self, result: crate::resource::error::Result<Sniffer>) {
+        self.selected = None;
+        match result {
+            Ok(sniffer) => {
+                self.sniffer = Some(sniffer);
+                self.message = None;
+            }
+            Err(e) => self.message = Some(Err(e.to_string())),
+        }
+    }
+
+    /// 选中帧的原始报文和配对的帧
+    fn detail_ui(&mut self, ui: &mut Ui, frames: &[SniffedFrame]) {
+        let frame = match self.selected.and_then(|i| frames.get(i)) {
+            Some(frame) => frame,
+            None => {
+                ui.label("点击序号查看报文");
+                return;
+            }
+        };
+        ui.horizontal(|ui| {
+            ui.strong(format!("#{} {}", frame.index, frame.kind.label()));
+            ui.monospace(hex(&frame.bytes));
+        });
+        if let Some(peer) = frame.peer.and_then(|i| frames.get(i)) {
+            ui.horizontal(|ui| {
+                if ui
+                    .small_button(format!("#{} {}", peer.index, peer.kind.label()))
+                    .clicked()
+                {
+                    self.selected = Some(peer.index);
+                }
+                ui.monospace(hex(&peer.bytes));
+            });
+        }
+    }
+
+    fn timeline_ui(&mut self, ui: &mut Ui, frames: &[SniffedFrame]) {
+        let problems_only = self.problems_only;
+        let rows: Vec<&SniffedFrame> = frames
+            .iter()
+            .filter(|f| {
+                !problems_only
+                    || matches!(f.kind, FrameKind::Exception | FrameKind::Invalid)
+                    || !f.note.is_empty()
+            })
+            .collect();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        let mut scroll = egui::ScrollArea::both();
+        if self.follow {
+            scroll = scroll.stick_to_bottom();
+        }
+        scroll.show_rows(ui, row_height, rows.len(), |ui, range| {
+            egui::Grid::new("sniffer_frames")
+                .num_columns(11)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("序号");
+                    ui.strong("时间");
+                    ui.strong("间隔");
+                    ui.strong("类型");
+                    ui.strong("从站");
+                    ui.strong("功能码");
+                    ui.strong("内容");
+                    ui.strong("字段");
+                    ui.strong("校验");
+                    ui.strong("响应时间");
+                    ui.strong("备注");
+                    ui.end_row();
+
+                    for frame in rows.iter().skip(range.start).take(range.len()) {
+                        let selected = self.selected == Some(frame.index);
+                        if ui
+                            .selectable_label(selected, frame.index.to_string())
+                            .clicked()
+                        {
+                            self.selected = Some(frame.index);
+                        }
+                        ui.label(ms(frame.time_us));
+                        ui.label(ms(frame.idle_us));
+                        let color = match frame.kind {
+                            FrameKind::Request => Color32::LIGHT_BLUE,
+                            FrameKind::Response => Color32::LIGHT_GREEN,
+                            FrameKind::Exception => Color32::GOLD,
+                            FrameKind::Invalid => Color32::RED,
+                        };
+                        ui.colored_label(color, frame.kind.label());
+                        ui.label(frame.slave.map_or_else(String::new, |s| s.to_string()));
+                        ui.label(
+                            frame
+                                .function
+                                .map_or_else(String::new, |f| format!("{:02X}", f)),
+                        );
+                        ui.label(&frame.summary);
+                        ui.label(&frame.fields);
+                        if frame.checksum_ok {
+                            ui.label("OK");
+                        } else {
+                            ui.colored_label(Color32::RED, "错误");
+                        }
+                        ui.label(frame.latency_us.map_or_else(String::new, ms));
+                        ui.label(&frame.note);
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+/// 微秒显示为毫秒
+fn ms(us: u64) -> String {
+    format!("{:.3} ms", us as f64 / 1000.0)
+}
+
+impl BasePage for SnifferPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Sniffer {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::TopBottomPanel::top("sniffer_config").show(ctx, |ui| {
+            ui.heading("Modbus 报文监听");
+            self.config_ui(ui, &mut app_data);
+            ui.add_space(4.0);
+        });
+        if let Some((old, new)) = self.config_audit.changes(ctx, &app_data.sniffer_config) {
+            app_data.audit_config("sniffer", Some(old), Some(new));
+        }
+
+        // 绘制时暂时取出, 监听线程会等待界面释放锁, 收到的数据留在串口缓冲区
+        let sniffer = match self.sniffer.take() {
+            Some(sniffer) => sniffer,
+            None => {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.label("未开始");
+                });
+                return PageAction::None;
+            }
+        };
+        let capture = sniffer.capture();
+        egui::TopBottomPanel::bottom("sniffer_detail").show(ctx, |ui| {
+            self.detail_ui(ui, capture.frames());
+        });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.timeline_ui(ui, capture.frames());
+        });
+        drop(capture);
+        self.sniffer = Some(sniffer);
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -0,0 +1,205 @@
+use epi::egui::{self, Color32, Ui};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{
+    data::app_data::AppData,
+    service::modbus::tcp_gateway::{TcpGateway, UnitRoute},
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{
+    config_audit::ConfigAudit, modbus_widgets::transport_ui, navigation::PageKind,
+    titlebar::MainTitlebar,
+};
+
+/// Modbus TCP 客户端通过本机访问串口总线上的从站
+pub struct TcpGatewayPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    error: Option<String>,
+    /// 记录配置的修改
+    config_audit: ConfigAudit,
+}
+
+impl TcpGatewayPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            error: None,
+            config_audit: ConfigAudit::default(),
+        }
+    }
+
+    /// 网关参数, 启动后不能修改
+    fn config_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let running = app_data.tcp_gateway.is_some();
+        let config = &mut app_data.tcp_gateway_config;
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("监听端口");
+                ui.add(egui::DragValue::new(&mut config.port).clamp_range(1..=65535));
+                ui.label("排队上限");
+                ui.add(egui::DragValue::new(&mut config.max_pending).clamp_range(1..=1000))
+                    .on_hover_text("所有客户端等待转发的请求数, 超出时返回从站忙");
+                ui.checkbox(&mut config.passthrough, "未映射的单元号直接作为从站地址");
+            });
+            ui.horizontal(|ui| {
+                ui.label("转发到");
+                transport_ui(ui, "tcp_gateway_transport", &mut config.transport);
+            });
+
+            let mut remove = None;
+            ui.horizontal_wrapped(|ui| {
+                ui.label("单元号映射");
+                for (i, route) in config.routes.iter_mut().enumerate() {
+                    ui.group(|ui| {
+                        ui.add(egui::DragValue::new(&mut route.unit).clamp_range(0..=255));
+                        ui.label("→ 从站");
+                        ui.add(egui::DragValue::new(&mut route.slave).clamp_range(1..=247));
+                        if ui.small_button("✖").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if ui.button("添加").clicked() {
+                    let unit = (1..=255)
+                        .find(|u| !config.routes.iter().any(|r| r.unit == *u))
+                        .unwrap_or(255);
+                    config.routes.push(UnitRoute { unit, slave: 1 });
+                }
+            });
+            if let Some(i) = remove {
+                config.routes.remove(i);
+            }
+        });
+        ui.checkbox(&mut config.auto_start, "应用启动时自动启动");
+
+        let mut start = false;
+        let mut stop = false;
+        ui.horizontal(|ui| {
+            if running {
+                stop = ui.button("停止").clicked();
+            } else {
+                start = ui.button("启动").clicked();
+            }
+            if let Some(gateway) = &app_data.tcp_gateway {
+                if gateway.is_running() {
+                    ui.label(gateway.label());
+                } else {
+                    ui.colored_label(Color32::RED, format!("{} 已停止", gateway.label()));
+                }
+            }
+        });
+
+        if start {
+            self.error = app_data.start_tcp_gateway().err().map(|e| e.to_string());
+        } else if stop {
+            app_data.tcp_gateway = None;
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+    }
+
+    fn stats_ui(&mut self, ui: &mut Ui, gateway: &TcpGateway) {
+        let state = gateway.state();
+        ui.label(format!(
+            "转发 {} 个请求, 异常响应 {} 个, 其中从站无响应 {} 个, 排队 {}",
+            state.requests, state.exceptions, state.timeouts, state.pending
+        ));
+        if let Some(e) = &state.last_error {
+            ui.colored_label(Color32::RED, format!("最后错误: {}", e));
+        }
+        ui.separator();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("tcp_gateway_clients")
+                .num_columns(5)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("客户端");
+                    ui.strong("连接时间");
+                    ui.strong("请求");
+                    ui.strong("异常");
+                    ui.strong("状态");
+                    ui.end_row();
+
+                    for client in state.clients.iter().rev() {
+                        ui.label(client.addr.to_string());
+                        ui.label(client.connected.format("%H:%M:%S").to_string());
+                        ui.label(client.requests.to_string());
+                        ui.label(client.exceptions.to_string());
+                        if client.closed {
+                            ui.label("已断开");
+                        } else {
+                            ui.colored_label(Color32::GREEN, "已连接");
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+impl BasePage for TcpGatewayPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::TcpGateway {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::TopBottomPanel::top("tcp_gateway_config").show(ctx, |ui| {
+            ui.heading("Modbus TCP 网关");
+            self.config_ui(ui, &mut app_data);
+            ui.add_space(4.0);
+        });
+
+        if let Some((old, new)) = self.config_audit.changes(ctx, &app_data.tcp_gateway_config) {
+            app_data.audit_config("tcp_gateway", Some(old), Some(new));
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| match &app_data.tcp_gateway {
+            Some(gateway) => self.stats_ui(ui, gateway),
+            None => {
+                ui.label("未启动");
+            }
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{resource::defines::APP_NAME, window::TitleBar};
+use crate::{data::app_data::AppData, resource::defines::APP_NAME, window::TitleBar};
 
 use epi::egui::{self, Align2, Context, Direction, Layout, ScrollArea, Sense};
 use parking_lot::RwLock;
@@ -6,20 +6,32 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use winit::window::{Fullscreen, Window};
 
-use super::ui_state::UiState;
+use super::{navigation::PageKind, notification_center::NotificationCenter, ui_state::UiState};
 
 pub struct MainTitlebar {
     pub style_ui_open: bool,
     pub window_handle: Arc<RwLock<Window>>,
+    pub app_data: Arc<RwLock<AppData>>,
+    pub notification_center: NotificationCenter,
+    /// 在菜单中选择的页面, 由所在的页面负责切换
+    navigate: Option<PageKind>,
 }
 
 impl MainTitlebar {
-    pub fn new(window_handle: Arc<RwLock<Window>>) -> Self {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
         Self {
             style_ui_open: true,
             window_handle,
+            app_data,
+            notification_center: NotificationCenter::default(),
+            navigate: None,
         }
     }
+
+    /// 取出菜单中选择的页面
+    pub fn take_navigation(&mut self) -> Option<PageKind> {
+        self.navigate.take()
+    }
 }
 
 impl TitleBar for MainTitlebar {
@@ -32,6 +44,14 @@ impl TitleBar for MainTitlebar {
                     // ui.set_style(ui_state.theme.blue_titlebar_style_clone());
 
                     ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        ui.menu_button("☰", |ui| {
+                            for kind in PageKind::ALL {
+                                if ui.button(kind.label()).clicked() {
+                                    self.navigate = Some(kind);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
                         // ui.menu_button("样式", |ui| {
                         //     ui_state.titlebar.trigger_style_ui();
                         //     ui.close_menu();
@@ -60,6 +80,17 @@ impl TitleBar for MainTitlebar {
 
                         // ui.label(format!("{:.1}", ui_state.fps));
 
+                        // 通知中心
+                        let unacknowledged = self.app_data.read().alerts.unacknowledged();
+                        let bell = if unacknowledged > 0 {
+                            format!("🔔 {}", unacknowledged)
+                        } else {
+                            "🔔".to_string()
+                        };
+                        if ui.button(bell).on_hover_text("通知中心").clicked() {
+                            self.notification_center.trigger();
+                        }
+
                         // 设置
                         // if ui.button("⛭").clicked() {
                         //     ui_state.setting_window.trigger_show();
@@ -96,6 +127,9 @@ impl TitleBar for MainTitlebar {
                     });
                 });
             });
+
+        self.notification_center
+            .draw(ctx, &mut self.app_data.write());
     }
 }
 
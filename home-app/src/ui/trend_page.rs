@@ -0,0 +1,563 @@
+use chrono::{Duration, Local, TimeZone};
+use epi::egui::{
+    self,
+    color::Hsva,
+    plot::{Legend, Line, LinkedAxisGroup, Plot, Polygon, VLine, Value, Values},
+    Color32, Ui,
+};
+use parking_lot::RwLock;
+use winit::window::Window;
+
+use std::sync::Arc;
+
+use crate::{
+    data::{
+        app_data::AppData,
+        history::{export_csv, Bucket, HistoryStore},
+        register_map::{Area, Field},
+        storage::persistence::app_dir,
+    },
+    window::{BasePage, PageAction, TitleBar},
+};
+
+use super::{config_audit::ConfigAudit, navigation::PageKind, titlebar::MainTitlebar};
+
+/// 可选的显示时长, 单位: 秒
+const SPANS: [(i64, &str); 6] = [
+    (60, "1 分钟"),
+    (300, "5 分钟"),
+    (900, "15 分钟"),
+    (3600, "1 小时"),
+    (6 * 3600, "6 小时"),
+    (24 * 3600, "24 小时"),
+];
+
+/// 一个序列在当前时间范围内的数据
+struct Trend {
+    name: String,
+    color: Color32,
+    field: Option<Field>,
+    buckets: Vec<Bucket>,
+}
+
+impl Trend {
+    fn format(&self, value: f64) -> String {
+        match &self.field {
+            Some(field) if field.unit.is_empty() => field.format_value(value),
+            Some(field) => format!("{} {}", field.format_value(value), field.unit),
+            None => format!("{:.3}", value),
+        }
+    }
+}
+
+/// 记录字段的轮询值, 多个字段在同一时间轴上显示趋势
+pub struct TrendPage {
+    id: usize,
+    pid: usize,
+    title_bar: MainTitlebar,
+    window_handle: Arc<RwLock<Window>>,
+    app_data: Arc<RwLock<AppData>>,
+    /// 显示的时长, 单位: 秒
+    span: i64,
+    /// 暂停时趋势图停在这个时间, 毫秒时间戳
+    paused: Option<i64>,
+    /// 每个序列一个图, x 轴联动
+    separate: bool,
+    link: LinkedAxisGroup,
+    /// 光标所在的时间, 秒
+    cursor: Option<f64>,
+    /// 上一帧显示的时间范围, 毫秒时间戳
+    visible: Option<(i64, i64)>,
+    error: Option<String>,
+    message: Option<Result<String, String>>,
+    /// 记录配置的修改
+    config_audit: ConfigAudit,
+}
+
+impl TrendPage {
+    pub fn new(window_handle: Arc<RwLock<Window>>, app_data: Arc<RwLock<AppData>>) -> Self {
+        let title_bar = MainTitlebar::new(window_handle.clone(), app_data.clone());
+        Self {
+            id: 0,
+            pid: 0,
+            title_bar,
+            window_handle,
+            app_data,
+            span: 300,
+            paused: None,
+            separate: false,
+            link: LinkedAxisGroup::x(),
+            cursor: None,
+            visible: None,
+            error: None,
+            message: None,
+            config_audit: ConfigAudit::default(),
+        }
+    }
+
+    /// 选择记录的字段, 启动和停止记录
+    fn recorder_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let running = app_data.history_recorder.is_some();
+        let devices: Vec<(String, Vec<(Area, Field)>)> = app_data
+            .modbus_devices
+            .iter()
+            .map(|device| {
+                let fields = app_data
+                    .device_map(device)
+                    .map(|map| {
+                        Area::ALL
+                            .into_iter()
+                            .flat_map(|area| {
+                                map.fields(area)
+                                    .iter()
+                                    .filter(|f| !f.is_text())
+                                    .map(move |f| (area, f.clone()))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (device.name.clone(), fields)
+            })
+            .collect();
+
+        let config = &mut app_data.history_config;
+        egui::CollapsingHeader::new("记录的字段")
+            .default_open(config.fields.is_empty())
+            .show(ui, |ui| {
+                ui.add_enabled_ui(!running, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for (device, fields) in devices.iter() {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.strong(device);
+                                    let selected = config.fields.entry(device.clone()).or_default();
+                                    for (area, field) in fields {
+                                        let mut checked = selected.contains(&field.name);
+                                        if ui
+                                            .checkbox(&mut checked, &field.name)
+                                            .on_hover_text(format!(
+                                                "{} {}",
+                                                area.label(),
+                                                field.address
+                                            ))
+                                            .changed()
+                                        {
+                                            if checked {
+                                                selected.push(field.name.clone());
+                                            } else {
+                                                selected.retain(|f| f != &field.name);
+                                            }
+                                        }
+                                    }
+                                });
+                            }
+                        });
+                });
+                config.fields.retain(|_, fields| !fields.is_empty());
+            });
+
+        let mut start = false;
+        let mut stop = false;
+        ui.horizontal(|ui| {
+            if running {
+                stop = ui.button("停止记录").clicked();
+            } else {
+                start = ui.button("开始记录").clicked();
+            }
+            ui.label("历史文件保留");
+            ui.add(egui::DragValue::new(&mut config.retention_days).clamp_range(1..=3650));
+            ui.label("天");
+            ui.checkbox(&mut config.auto_start, "应用启动时自动记录");
+        });
+        if let Some(recorder) = &app_data.history_recorder {
+            let state = recorder.state();
+            for stats in state.devices.iter() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{}: {} 个字段, 已记录 {} 次",
+                        stats.device, stats.fields, stats.records
+                    ));
+                    if let Some(time) = stats.last_record {
+                        ui.label(format!("最后 {}", time.format("%H:%M:%S%.3f")));
+                    }
+                    if let Some(e) = &stats.last_error {
+                        ui.colored_label(Color32::RED, e);
+                    }
+                });
+            }
+        }
+
+        if start {
+            self.error = app_data
+                .start_history_recorder()
+                .err()
+                .map(|e| e.to_string());
+        } else if stop {
+            app_data.history_recorder = None;
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+    }
+
+    /// 选择显示的序列
+    fn series_ui(&mut self, ui: &mut Ui, app_data: &mut AppData) {
+        let store = app_data.history.lock();
+        let trends = &mut app_data.history_config.trends;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for name in store.series_names() {
+                let mut checked = trends.iter().any(|t| t == name);
+                if ui.checkbox(&mut checked, name).changed() {
+                    if checked {
+                        trends.push(name.to_string());
+                    } else {
+                        trends.retain(|t| t != name);
+                    }
+                }
+            }
+        });
+    }
+
+    fn toolbar_ui(&mut self, ui: &mut Ui, app_data: &AppData) {
+        ui.horizontal(|ui| {
+            ui.label("时长");
+            egui::ComboBox::from_id_source("trend_span")
+                .selected_text(
+                    SPANS
+                        .iter()
+                        .find(|(s, _)| *s == self.span)
+                        .map_or("", |(_, label)| label),
+                )
+                .show_ui(ui, |ui| {
+                    for (span, label) in SPANS {
+                        ui.selectable_value(&mut self.span, span, label);
+                    }
+                });
+            match self.paused {
+                Some(_) => {
+                    if ui.button("继续").clicked() {
+                        self.paused = None;
+                    }
+                }
+                None => {
+                    if ui
+                        .button("暂停")
+                        .on_hover_text("暂停后可以拖动和缩放, 双击恢复")
+                        .clicked()
+                    {
+                        self.paused = Some(Local::now().timestamp_millis());
+                    }
+                }
+            }
+            ui.checkbox(&mut self.separate, "每个字段单独显示");
+            if ui.button("导出当前窗口").clicked() {
+                self.message = Some(self.export(app_data));
+            }
+            match &self.message {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(Color32::RED, e);
+                }
+                None => {}
+            }
+        });
+    }
+
+    fn export(&self, app_data: &AppData) -> Result<String, String> {
+        let trends = &app_data.history_config.trends;
+        if trends.is_empty() {
+            return Err("没有选择字段".to_string());
+        }
+        let (from, to) = self.visible.ok_or_else(|| "没有数据".to_string())?;
+        let path = app_dir().join(format!(
+            "trend-{}.csv",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        export_csv(&app_data.history.lock(), trends, from, to, &path)
+            .map(|count| format!("已导出 {} 行到 {}", count, path.display()))
+            .map_err(|e| e.to_string())
+    }
+
+    /// 读取时间范围内的数据, 点数多于图的宽度时按像素合并
+    fn trends(&self, app_data: &AppData, from: i64, to: i64, count: usize) -> Vec<Trend> {
+        let store = app_data.history.lock();
+        app_data
+            .history_config
+            .trends
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Trend {
+                name: name.clone(),
+                color: Hsva::new((i as f32 * 0.618_034).fract(), 0.85, 0.6, 1.0).into(),
+                field: series_field(app_data, name),
+                buckets: store.buckets(name, from, to, count),
+            })
+            .collect()
+    }
+
+    fn plots_ui(&mut self, ui: &mut Ui, app_data: &AppData) {
+        // 跟随时显示最近的时长; 暂停后读取上一帧显示的范围, 向两边多读一些用于拖动
+        let (window, query) = match self.paused {
+            None => {
+                let to = Local::now().timestamp_millis();
+                let window = (to - self.span * 1000, to);
+                (window, window)
+            }
+            Some(to) => {
+                let window = (to - self.span * 1000, to);
+                let (from, to) = self.visible.unwrap_or(window);
+                let margin = (to - from) / 2;
+                (window, (from - margin, to + margin))
+            }
+        };
+        let count = (ui.available_width() / 2.0).max(50.0) as usize;
+        let trends = self.trends(app_data, query.0, query.1, count);
+        if trends.is_empty() {
+            ui.label("在左侧选择要显示的字段");
+            return;
+        }
+
+        let readout_height = 30.0 + 20.0 * trends.len() as f32;
+        let plot_height = (ui.available_height() - readout_height).max(120.0);
+        let id = format!("trend_{}_{}", self.span, self.paused.is_some());
+        let mut hovered = None;
+        if self.separate {
+            let height = (plot_height / trends.len() as f32).max(80.0);
+            for (i, trend) in trends.iter().enumerate() {
+                let id = format!("{}_{}", id, i);
+                hovered =
+                    hovered.or(self.plot(ui, &id, window, height, std::slice::from_ref(trend)));
+            }
+        } else {
+            hovered = self.plot(ui, &id, window, plot_height, &trends);
+        }
+        self.cursor = hovered;
+        self.readout_ui(ui, app_data, &trends);
+    }
+
+    /// 返回光标所在的时间
+    fn plot(
+        &mut self,
+        ui: &mut Ui,
+        id: &str,
+        window: (i64, i64),
+        height: f32,
+        trends: &[Trend],
+    ) -> Option<f64> {
+        let following = self.paused.is_none();
+        let seconds = |ms: i64| ms as f64 / 1000.0;
+        let response = Plot::new(id)
+            .height(height)
+            .legend(Legend::default())
+            .link_axis(self.link.clone())
+            .allow_drag(!following)
+            .allow_zoom(!following)
+            .allow_boxed_zoom(!following)
+            .include_x(seconds(window.0))
+            .include_x(seconds(window.1))
+            .x_axis_formatter(|x, _| time_label(x, "%H:%M:%S"))
+            .label_formatter(|name, value| {
+                format!(
+                    "{}\n{}\n{:.3}",
+                    name,
+                    time_label(value.x, "%H:%M:%S%.3f"),
+                    value.y
+                )
+            })
+            .show(ui, |plot_ui| {
+                for trend in trends {
+                    let values = trend
+                        .buckets
+                        .iter()
+                        .map(|b| Value::new(seconds(b.time), b.mean));
+                    plot_ui.line(
+                        Line::new(Values::from_values_iter(values))
+                            .color(trend.color)
+                            .name(&trend.name),
+                    );
+                    // 合并的点画出最小到最大值的范围
+                    for pair in trend.buckets.windows(2) {
+                        if pair[0].count == 1 && pair[1].count == 1 {
+                            continue;
+                        }
+                        let (x0, x1) = (seconds(pair[0].time), seconds(pair[1].time));
+                        let band = vec![
+                            Value::new(x0, pair[0].min),
+                            Value::new(x1, pair[1].min),
+                            Value::new(x1, pair[1].max),
+                            Value::new(x0, pair[0].max),
+                        ];
+                        plot_ui.polygon(
+                            Polygon::new(Values::from_values(band))
+                                .color(trend.color)
+                                .fill_alpha(0.2)
+                                .width(0.0)
+                                .name(&trend.name),
+                        );
+                    }
+                }
+                if let Some(x) = self.cursor {
+                    plot_ui.vline(VLine::new(x).color(Color32::GRAY));
+                }
+                let bounds = plot_ui.plot_bounds();
+                let hovered = plot_ui
+                    .plot_hovered()
+                    .then(|| plot_ui.pointer_coordinate())
+                    .flatten()
+                    .map(|p| p.x);
+                (bounds, hovered)
+            });
+        let (bounds, hovered) = response.inner;
+        if bounds.is_finite() {
+            let ms = |x: f64| (x * 1000.0) as i64;
+            self.visible = Some((ms(bounds.min()[0]), ms(bounds.max()[0])));
+        }
+        hovered
+    }
+
+    /// 光标处的值和当前窗口内的统计
+    fn readout_ui(&self, ui: &mut Ui, app_data: &AppData, trends: &[Trend]) {
+        let store = app_data.history.lock();
+        let cursor = self.cursor.map(|x| (x * 1000.0) as i64);
+        egui::Grid::new("trend_readout")
+            .num_columns(5)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("字段");
+                match cursor {
+                    Some(time) => ui.strong(time_label(time as f64 / 1000.0, "%H:%M:%S%.3f")),
+                    None => ui.strong("光标"),
+                };
+                ui.strong("最小");
+                ui.strong("最大");
+                ui.strong("平均");
+                ui.end_row();
+
+                for trend in trends {
+                    ui.colored_label(trend.color, &trend.name);
+                    let value = cursor.and_then(|time| value_near(&store, &trend.name, time));
+                    ui.label(value.map(|v| trend.format(v)).unwrap_or_default());
+                    match window_stats(&trend.buckets, self.visible) {
+                        Some((min, max, mean)) => {
+                            ui.label(trend.format(min));
+                            ui.label(trend.format(max));
+                            ui.label(trend.format(mean));
+                        }
+                        None => {
+                            ui.label("");
+                            ui.label("");
+                            ui.label("");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+/// x 轴的时间, 单位: 秒
+fn time_label(x: f64, format: &str) -> String {
+    Local
+        .timestamp_millis_opt((x * 1000.0) as i64)
+        .single()
+        .map(|time| time.format(format).to_string())
+        .unwrap_or_default()
+}
+
+/// 光标之前最近的值, 超过 1 分钟没有值时不显示
+fn value_near(store: &HistoryStore, series: &str, time: i64) -> Option<f64> {
+    store
+        .value_at(series, time)
+        .filter(|p| time - p.time <= Duration::minutes(1).num_milliseconds())
+        .map(|p| p.value)
+}
+
+/// 显示范围内的最小, 最大和平均值
+fn window_stats(buckets: &[Bucket], visible: Option<(i64, i64)>) -> Option<(f64, f64, f64)> {
+    let (from, to) = visible?;
+    let mut count = 0;
+    let (mut min, mut max, mut sum) = (f64::INFINITY, f64::NEG_INFINITY, 0.0);
+    for bucket in buckets.iter().filter(|b| b.time >= from && b.time <= to) {
+        min = min.min(bucket.min);
+        max = max.max(bucket.max);
+        sum += bucket.mean * bucket.count as f64;
+        count += bucket.count;
+    }
+    (count > 0).then(|| (min, max, sum / count as f64))
+}
+
+/// 序列对应的字段, 用于显示单位
+fn series_field(app_data: &AppData, series: &str) -> Option<Field> {
+    app_data.modbus_devices.iter().find_map(|device| {
+        let name = series.strip_prefix(&device.name)?.strip_prefix('/')?;
+        let map = app_data.register_map(&device.map)?;
+        let (base, index) = match name.split_once('[') {
+            Some((base, index)) => (base, index.trim_end_matches(']').parse().ok()?),
+            None => (name, 0),
+        };
+        let (_, field) = map.find(base)?;
+        Some(field.element(index))
+    })
+}
+
+impl BasePage for TrendPage {
+    fn title_bar(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.title_bar.draw(ctx, frame);
+    }
+
+    fn content(&mut self, ctx: &egui::Context, _frame: &epi::Frame) -> PageAction {
+        if let Some(kind) = self.title_bar.take_navigation() {
+            if kind != PageKind::Trends {
+                let page = kind.build(self.window_handle.clone(), self.app_data.clone());
+                return PageAction::ModifyPage(self.pid, page);
+            }
+        }
+
+        let app_data = self.app_data.clone();
+        let mut app_data = app_data.write();
+
+        egui::TopBottomPanel::top("trend_recorder").show(ctx, |ui| {
+            ui.heading("Modbus 趋势");
+            self.recorder_ui(ui, &mut app_data);
+            ui.add_space(4.0);
+        });
+
+        egui::SidePanel::left("trend_series")
+            .resizable(true)
+            .default_width(220.0)
+            .show(ctx, |ui| {
+                ui.heading("字段");
+                self.series_ui(ui, &mut app_data);
+            });
+
+        if let Some((old, new)) = self.config_audit.changes(ctx, &app_data.history_config) {
+            app_data.audit_config("history", Some(old), Some(new));
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.toolbar_ui(ui, &app_data);
+            self.plots_ui(ui, &app_data);
+        });
+
+        PageAction::None
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn set_pid(&mut self, pid: usize) {
+        self.pid = pid;
+    }
+
+    fn get_pid(&self) -> usize {
+        self.pid
+    }
+}
@@ -9,12 +9,14 @@ use parking_lot::RwLock;
 use winit::window::Window;
 
 use crate::{
+    data::app_data::AppData,
     resource::{defines::APP_NAME, fonts, icons::Icons},
     ui::device_page::DevicePage,
 };
 
 pub struct MainWindow {
     window_handle: Option<Arc<RwLock<Window>>>,
+    app_data: Arc<RwLock<AppData>>,
     pages: Pages,
     ui_enabled: bool,
 }
@@ -23,6 +25,7 @@ impl MainWindow {
     pub fn new() -> Self {
         let window = Self {
             window_handle: None,
+            app_data: Arc::new(RwLock::new(AppData::load())),
             pages: Pages::default(),
             ui_enabled: true,
         };
@@ -55,7 +58,7 @@ impl epi::App for MainWindow {
         APP_NAME
     }
 
-    fn setup(&mut self, ctx: &egui::Context, _frame: &Frame, _storage: Option<&dyn Storage>) {
+    fn setup(&mut self, ctx: &egui::Context, frame: &Frame, _storage: Option<&dyn Storage>) {
         tracing::info!("app setup");
         if let Some(window) = &self.window_handle {
             // 设置图标
@@ -64,17 +67,26 @@ impl epi::App for MainWindow {
 
         ctx.set_fonts(fonts::chinese());
 
+        // 启动后台服务, 收到数据时请求重绘
+        let repaint_frame = frame.clone();
+        self.app_data
+            .write()
+            .start_services(Arc::new(move || repaint_frame.request_repaint()));
+
         // 添加第一个页面
         if self.window_handle.is_some() {
             let mut page = Page::default();
             page.add(Box::new(DevicePage::new(
                 self.window_handle.as_ref().unwrap().clone(),
+                self.app_data.clone(),
             )));
             self.add_page(page);
         }
     }
 
     fn update(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
+        self.app_data.write().update();
+
         for page in self.pages.iter_mut() {
             if let Some(action) = page.draw(ctx, frame) {
                 match action {
@@ -101,6 +113,10 @@ impl epi::App for MainWindow {
             }
         }
     }
+
+    fn on_exit(&mut self) {
+        self.app_data.write().save();
+    }
 }
 
 pub enum PageAction {